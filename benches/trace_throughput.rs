@@ -0,0 +1,206 @@
+//! trace rays 吞吐量基准：在无头设备上用空 raygen 着色器测量 dispatch 的 Mrays/s
+//!
+//! 运行：`cargo bench --bench trace_throughput`。没有 Vulkan loader 或支持光线追踪的设备时打印原因后退出。
+
+use ash::{Entry, khr, vk};
+use vulkan_raytracing::{
+    ApplicationInfo, BufferResource, CommandPoolManager, DeviceConfig, InstanceQueries,
+    TimestampProfiler, TraceDispatch, create_device, create_instance, create_shader_module,
+    measure_trace_throughput, pick_physical_device_and_queue_family_indices,
+};
+
+const RESOLUTIONS: [(u32, u32); 3] = [(640, 480), (1920, 1080), (3840, 2160)];
+const ITERATIONS: u32 = 50;
+
+/// 手工汇编的空 raygen 着色器（SPIR-V 1.4）
+fn empty_raygen_spirv() -> Vec<u32> {
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+    fn string(text: &str) -> Vec<u32> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize(bytes.len() / 4 * 4 + 4, 0);
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+    const CAPABILITY_RAY_TRACING: u32 = 4479;
+    const EXECUTION_MODEL_RAYGEN: u32 = 5313;
+    let (main, void, function_type, label) = (1, 2, 3, 4);
+
+    let mut words = vec![0x0723_0203, 0x0001_0400, 0, 5, 0];
+    words.extend(instruction(17, &[CAPABILITY_RAY_TRACING]));
+    words.extend(instruction(10, &string("SPV_KHR_ray_tracing")));
+    words.extend(instruction(14, &[0, 1]));
+    words.extend(instruction(
+        15,
+        &[[EXECUTION_MODEL_RAYGEN, main].as_slice(), &string("main")].concat(),
+    ));
+    words.extend(instruction(19, &[void]));
+    words.extend(instruction(33, &[function_type, void]));
+    words.extend(instruction(54, &[void, main, 0, function_type]));
+    words.extend(instruction(248, &[label]));
+    words.extend(instruction(253, &[]));
+    words.extend(instruction(56, &[]));
+    words
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let Ok(entry) = (unsafe { Entry::load() }) else {
+        println!("skipping trace_throughput: no Vulkan loader");
+        return Ok(());
+    };
+    let instance = create_instance(&entry, &ApplicationInfo::default(), &[], &[], false, &[])?;
+
+    let Some((physical_device, queue_indices)) = pick_physical_device_and_queue_family_indices(
+        &InstanceQueries::new(&instance, None),
+        None,
+        &[
+            khr::acceleration_structure::NAME,
+            khr::deferred_host_operations::NAME,
+            khr::ray_tracing_pipeline::NAME,
+        ],
+        true,
+    )?
+    else {
+        println!("skipping trace_throughput: no ray tracing capable Vulkan device");
+        unsafe { instance.destroy_instance(None) };
+        return Ok(());
+    };
+    let queue_family_index = queue_indices
+        .graphics_family
+        .ok_or("no graphics queue family")?;
+
+    let device = create_device(
+        &instance,
+        physical_device,
+        &queue_indices,
+        &DeviceConfig::new(true),
+    )?;
+    let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
+    let device_memory_properties =
+        unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let rt_loader = khr::ray_tracing_pipeline::Device::new(&instance, &device);
+    let profiler = TimestampProfiler::new(
+        &InstanceQueries::new(&instance, None),
+        physical_device,
+        queue_family_index,
+    )?;
+    let command_pool = CommandPoolManager::new(&device, queue_family_index)?;
+
+    let mut rt_properties = vk::PhysicalDeviceRayTracingPipelinePropertiesKHR::default();
+    let mut properties2 = vk::PhysicalDeviceProperties2::default().push_next(&mut rt_properties);
+    unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+
+    unsafe {
+        let set_layout = device
+            .create_descriptor_set_layout(&vk::DescriptorSetLayoutCreateInfo::default(), None)?;
+        let set_layouts = [set_layout];
+        let pipeline_layout = device.create_pipeline_layout(
+            &vk::PipelineLayoutCreateInfo::default().set_layouts(&set_layouts),
+            None,
+        )?;
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_IMAGE,
+            descriptor_count: 1,
+        }];
+        let descriptor_pool = device.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::default()
+                .max_sets(1)
+                .pool_sizes(&pool_sizes),
+            None,
+        )?;
+        let descriptor_set = device.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(&set_layouts),
+        )?[0];
+
+        let module = create_shader_module(&device, &empty_raygen_spirv())?;
+        let stages = [vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::RAYGEN_KHR)
+            .module(module)
+            .name(c"main")];
+        let groups = [vk::RayTracingShaderGroupCreateInfoKHR::default()
+            .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
+            .general_shader(0)
+            .closest_hit_shader(vk::SHADER_UNUSED_KHR)
+            .any_hit_shader(vk::SHADER_UNUSED_KHR)
+            .intersection_shader(vk::SHADER_UNUSED_KHR)];
+        let pipeline = rt_loader
+            .create_ray_tracing_pipelines(
+                vk::DeferredOperationKHR::null(),
+                vk::PipelineCache::null(),
+                &[vk::RayTracingPipelineCreateInfoKHR::default()
+                    .stages(&stages)
+                    .groups(&groups)
+                    .max_pipeline_ray_recursion_depth(1)
+                    .layout(pipeline_layout)],
+                None,
+            )
+            .map_err(|(_, err)| err)?[0];
+        device.destroy_shader_module(module, None);
+
+        // raygen 区域的起始地址需要按 shaderGroupBaseAlignment 对齐，多分配一个对齐量后手动偏移
+        let handle_size = rt_properties.shader_group_handle_size as vk::DeviceSize;
+        let base_alignment = rt_properties.shader_group_base_alignment as vk::DeviceSize;
+        let handles =
+            rt_loader.get_ray_tracing_shader_group_handles(pipeline, 0, 1, handle_size as usize)?;
+        let sbt = BufferResource::new(
+            2 * base_alignment.max(handle_size),
+            vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            &device,
+            device_memory_properties,
+        );
+        let address = sbt.device_address(&device);
+        let aligned_address = address.next_multiple_of(base_alignment);
+        sbt.store_from_thread(&handles, aligned_address - address, &device);
+        let stride = handle_size
+            .next_multiple_of(rt_properties.shader_group_handle_alignment as vk::DeviceSize);
+
+        for (width, height) in RESOLUTIONS {
+            let dispatch = TraceDispatch {
+                pipeline,
+                pipeline_layout,
+                descriptor_set,
+                raygen_region: vk::StridedDeviceAddressRegionKHR {
+                    device_address: aligned_address,
+                    stride,
+                    size: stride,
+                },
+                miss_region: vk::StridedDeviceAddressRegionKHR::default(),
+                hit_region: vk::StridedDeviceAddressRegionKHR::default(),
+                callable_region: vk::StridedDeviceAddressRegionKHR::default(),
+                width,
+                height,
+            };
+            let mrays = measure_trace_throughput(
+                &device,
+                &rt_loader,
+                &command_pool,
+                queue,
+                &dispatch,
+                &profiler,
+                ITERATIONS,
+            )?;
+            println!("{}x{}: {:.1} Mrays/s", width, height, mrays);
+        }
+
+        device.destroy_pipeline(pipeline, None);
+        device.destroy_pipeline_layout(pipeline_layout, None);
+        device.destroy_descriptor_pool(descriptor_pool, None);
+        device.destroy_descriptor_set_layout(set_layout, None);
+        sbt.destroy(&device);
+        command_pool.destroy(&device);
+        device.destroy_device(None);
+        instance.destroy_instance(None);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,227 @@
+//! 资源销毁顺序示例：无头创建一套完整的光追资源，再按依赖关系逆序销毁
+//!
+//! 运行：`cargo run --example cleanup`。所有设备子对象都登记在 ResourceTracker 中，
+//! 顺序出错时 safe_destroy_device 会在 debug 构建下 panic 并列出未销毁的对象；
+//! 验证层可用时同时启用，退出时不应出现任何对象泄漏报告。
+
+use ash::{Entry, khr, vk};
+use vulkan_raytracing::*;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let Ok(entry) = (unsafe { Entry::load() }) else {
+        println!("skipping cleanup example: no Vulkan loader");
+        return Ok(());
+    };
+
+    // ========== Instance 与设备 ==========
+    let validation = ValidationLayerConfig::new();
+    let enable_validation = validation.enabled && validation.check_support(&entry)?;
+    let instance_extensions =
+        get_available_instance_extensions(&entry, &get_instance_extensions(true));
+    let enable_debug_messenger = enable_validation && has_debug_utils(&instance_extensions);
+    let validation_layers = if enable_validation {
+        validation.as_ptrs()
+    } else {
+        Vec::new()
+    };
+    let instance = create_instance(
+        &entry,
+        &ApplicationInfo::default(),
+        &validation_layers,
+        &instance_extensions,
+        enable_debug_messenger,
+        &[],
+    )?;
+
+    let Some((physical_device, queue_indices)) = pick_physical_device_and_queue_family_indices(
+        &InstanceQueries::new(&instance, None),
+        None,
+        &[
+            khr::acceleration_structure::NAME,
+            khr::deferred_host_operations::NAME,
+            khr::ray_tracing_pipeline::NAME,
+        ],
+        true,
+    )?
+    else {
+        println!("skipping cleanup example: no ray tracing capable Vulkan device");
+        unsafe { instance.destroy_instance(None) };
+        return Ok(());
+    };
+    let queue_family_index = queue_indices
+        .graphics_family
+        .ok_or("no graphics queue family")?;
+
+    let device = create_device(
+        &instance,
+        physical_device,
+        &queue_indices,
+        &DeviceConfig::new(true),
+    )?;
+    let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
+    let device_memory_properties =
+        unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let as_loader = khr::acceleration_structure::Device::new(&instance, &device);
+    let limits = AccelerationStructureLimits::query(&instance, physical_device);
+
+    let mut tracker = ResourceTracker::new();
+
+    // ========== 创建资源 ==========
+    let command_pool = CommandPoolManager::new(&device, queue_family_index)?;
+    tracker.track(command_pool.pool, "command pool");
+
+    let mut render_target = RenderTargetImage::new(
+        &device,
+        256,
+        256,
+        vk::Format::R32G32B32A32_SFLOAT,
+        RenderTargetUsage::RayTracingOutput,
+        device_memory_properties,
+    )?;
+    tracker.track(render_target.image, "render target");
+    transition_image_to_general(&device, command_pool.pool, queue, &mut render_target)?;
+
+    let (blas, mesh) = BottomLevelAS::from_vertices(
+        &device,
+        &as_loader,
+        &command_pool,
+        queue,
+        &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        &[0, 1, 2],
+        &limits,
+        device_memory_properties,
+    )?;
+    tracker.track(blas.acceleration_structure, "blas");
+    tracker.track(mesh.vertex_buffer.buffer, "vertex buffer");
+    tracker.track(mesh.index_buffer.buffer, "index buffer");
+
+    let instances = [vk::AccelerationStructureInstanceKHR {
+        transform: vk::TransformMatrixKHR {
+            matrix: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        },
+        instance_custom_index_and_mask: vk::Packed24_8::new(0, 0xFF),
+        instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(0, 0),
+        acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+            device_handle: blas.device_address,
+        },
+    }];
+    let tlas = TopLevelAS::new(
+        &device,
+        &as_loader,
+        &command_pool,
+        queue,
+        &instances,
+        vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+        &limits,
+        device_memory_properties,
+    )?;
+    tracker.track(tlas.acceleration_structure, "tlas");
+
+    // 着色器绑定表所在的缓冲
+    let sbt = BufferResource::new(
+        256,
+        vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        &device,
+        device_memory_properties,
+    );
+    tracker.track(sbt.buffer, "sbt");
+
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR),
+    ];
+    let set_layout = unsafe {
+        device.create_descriptor_set_layout(
+            &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+            None,
+        )
+    }?;
+    tracker.track(set_layout, "descriptor set layout");
+    let set_layouts = [set_layout];
+    let pipeline_layout = unsafe {
+        device.create_pipeline_layout(
+            &vk::PipelineLayoutCreateInfo::default().set_layouts(&set_layouts),
+            None,
+        )
+    }?;
+    tracker.track(pipeline_layout, "pipeline layout");
+
+    let pool_sizes = [
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            descriptor_count: 1,
+        },
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_IMAGE,
+            descriptor_count: 1,
+        },
+    ];
+    let descriptor_pool = unsafe {
+        device.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::default()
+                .max_sets(1)
+                .pool_sizes(&pool_sizes),
+            None,
+        )
+    }?;
+    tracker.track(descriptor_pool, "descriptor pool");
+
+    println!("Created {} tracked objects", tracker.outstanding().len());
+
+    // ========== 按依赖关系逆序销毁 ==========
+    // 管线 → SBT/缓冲 → 描述符池 → 加速结构 → 图像 → 命令池 → 逻辑设备 → Instance
+    // 窗口模式下 Swapchain 在命令池之后、逻辑设备之前销毁，Surface 在逻辑设备之后、Instance 之前销毁
+    unsafe {
+        device.device_wait_idle()?;
+
+        // 管线与其布局
+        tracker.untrack(pipeline_layout);
+        device.destroy_pipeline_layout(pipeline_layout, None);
+        tracker.untrack(set_layout);
+        device.destroy_descriptor_set_layout(set_layout, None);
+
+        // SBT 与其他缓冲
+        tracker.untrack(sbt.buffer);
+        sbt.destroy(&device);
+
+        // 描述符池，池中分配的描述符集随之释放
+        tracker.untrack(descriptor_pool);
+        device.destroy_descriptor_pool(descriptor_pool, None);
+
+        // 加速结构：TLAS 引用 BLAS，BLAS 引用网格缓冲
+        tracker.untrack(tlas.acceleration_structure);
+        tlas.destroy(&device, &as_loader);
+        tracker.untrack(blas.acceleration_structure);
+        blas.destroy(&device, &as_loader);
+        tracker.untrack(mesh.vertex_buffer.buffer);
+        tracker.untrack(mesh.index_buffer.buffer);
+        mesh.destroy(&device);
+
+        // 图像
+        tracker.untrack(render_target.image);
+        render_target.destroy(&device);
+
+        // 命令池
+        tracker.untrack(command_pool.pool);
+        command_pool.destroy(&device);
+
+        // 逻辑设备：仍有登记对象时 panic
+        safe_destroy_device(&device, &tracker);
+
+        // Instance
+        instance.destroy_instance(None);
+    }
+
+    println!("All resources destroyed");
+    Ok(())
+}
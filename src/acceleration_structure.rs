@@ -0,0 +1,2459 @@
+use ash::{Device, khr, vk};
+use std::path::Path;
+
+use crate::buffer::{BufferResource, get_buffer_device_address};
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+
+/// 三角形网格的顶点/索引缓冲（顶点格式 R32G32B32_SFLOAT，索引 UINT32）
+pub struct MeshBuffers {
+    pub vertex_buffer: BufferResource,
+    pub index_buffer: BufferResource,
+    pub vertex_count: u32,
+    pub index_count: u32,
+}
+
+impl MeshBuffers {
+    pub fn new(
+        device: &Device,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+        vertices: &[[f32; 3]],
+        indices: &[u32],
+    ) -> Self {
+        let usage = vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+            | vk::BufferUsageFlags::STORAGE_BUFFER;
+        let memory_properties =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+
+        let mut vertex_buffer = BufferResource::new(
+            std::mem::size_of_val(vertices) as vk::DeviceSize,
+            usage,
+            memory_properties,
+            device,
+            device_memory_properties,
+        );
+        vertex_buffer.store(vertices, device);
+
+        let mut index_buffer = BufferResource::new(
+            std::mem::size_of_val(indices) as vk::DeviceSize,
+            usage,
+            memory_properties,
+            device,
+            device_memory_properties,
+        );
+        index_buffer.store(indices, device);
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            vertex_count: vertices.len() as u32,
+            index_count: indices.len() as u32,
+        }
+    }
+
+    /// 通过 staging buffer 把顶点/索引上传到 DEVICE_LOCAL 缓冲，函数返回时拷贝已经完成
+    pub fn new_device_local(
+        device: &Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+        vertices: &[[f32; 3]],
+        indices: &[u32],
+    ) -> Result<Self, RtError> {
+        let usage = vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+            | vk::BufferUsageFlags::STORAGE_BUFFER;
+
+        let vertex_buffer = BufferResource::new_device_local(
+            vertices,
+            usage,
+            device,
+            device_memory_properties,
+            command_pool,
+            queue,
+        )?;
+        let index_buffer = match BufferResource::new_device_local(
+            indices,
+            usage,
+            device,
+            device_memory_properties,
+            command_pool,
+            queue,
+        ) {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                unsafe { vertex_buffer.destroy(device) };
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            vertex_count: vertices.len() as u32,
+            index_count: indices.len() as u32,
+        })
+    }
+
+    pub fn primitive_count(&self) -> u32 {
+        self.index_count / 3
+    }
+
+    pub fn triangle_geometry(&self) -> TriangleGeometry {
+        TriangleGeometry {
+            vertex_buffer: self.vertex_buffer.buffer,
+            index_buffer: self.index_buffer.buffer,
+            vertex_count: self.vertex_count,
+            index_count: self.index_count,
+            vertex_format: TriangleGeometry::DEFAULT_VERTEX_FORMAT,
+            vertex_stride: TriangleGeometry::DEFAULT_VERTEX_STRIDE,
+            position_offset: 0,
+        }
+    }
+
+    /// 生成供 BLAS 构建使用的三角形几何描述
+    pub fn geometry(&self, device: &Device) -> vk::AccelerationStructureGeometryKHR<'static> {
+        self.triangle_geometry().geometry(device)
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            self.vertex_buffer.destroy(device);
+            self.index_buffer.destroy(device);
+        }
+    }
+}
+
+/// 顶点与索引打包在同一个 buffer 中的网格，每个网格只需一次内存分配
+///
+/// 布局为 `[顶点数据 | 对齐填充 | 索引数据]`，索引数据起始于 index_offset。
+pub struct PackedMesh {
+    pub buffer: BufferResource,
+    pub index_offset: vk::DeviceSize,
+    pub vertex_count: u32,
+    pub index_count: u32,
+}
+
+impl PackedMesh {
+    /// 索引数据起始偏移的对齐
+    pub const INDEX_ALIGNMENT: vk::DeviceSize = 16;
+
+    pub fn new(
+        device: &Device,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+        vertices: &[[f32; 3]],
+        indices: &[u32],
+    ) -> Self {
+        let vertex_size = std::mem::size_of_val(vertices) as vk::DeviceSize;
+        let index_size = std::mem::size_of_val(indices) as vk::DeviceSize;
+        let index_offset = vertex_size.next_multiple_of(Self::INDEX_ALIGNMENT);
+
+        let buffer = BufferResource::new(
+            index_offset + index_size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            device_memory_properties,
+        );
+        buffer.store_from_thread(vertices, 0, device);
+        buffer.store_from_thread(indices, index_offset, device);
+
+        Self {
+            buffer,
+            index_offset,
+            vertex_count: vertices.len() as u32,
+            index_count: indices.len() as u32,
+        }
+    }
+
+    pub fn primitive_count(&self) -> u32 {
+        self.index_count / 3
+    }
+
+    /// 顶点数据的设备地址，即 buffer 的基地址
+    pub fn vertex_address(&self, device: &Device) -> u64 {
+        self.buffer.device_address(device)
+    }
+
+    /// 索引数据的设备地址，即基地址加上对齐后的顶点数据大小
+    pub fn index_address(&self, device: &Device) -> u64 {
+        self.buffer.device_address(device) + self.index_offset
+    }
+
+    /// 生成供 BLAS 构建使用的三角形几何描述
+    pub fn geometry(&self, device: &Device) -> vk::AccelerationStructureGeometryKHR<'static> {
+        let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR::default()
+            .vertex_format(vk::Format::R32G32B32_SFLOAT)
+            .vertex_data(vk::DeviceOrHostAddressConstKHR {
+                device_address: self.vertex_address(device),
+            })
+            .vertex_stride(std::mem::size_of::<[f32; 3]>() as vk::DeviceSize)
+            .max_vertex(self.vertex_count.saturating_sub(1))
+            .index_type(vk::IndexType::UINT32)
+            .index_data(vk::DeviceOrHostAddressConstKHR {
+                device_address: self.index_address(device),
+            });
+
+        vk::AccelerationStructureGeometryKHR::default()
+            .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR { triangles })
+            .flags(vk::GeometryFlagsKHR::OPAQUE)
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            self.buffer.destroy(device);
+        }
+    }
+}
+
+/// BLAS 中的一个三角形几何，缓冲区由调用方持有
+///
+/// 默认假设顶点缓冲是紧密排列的 R32G32B32_SFLOAT 位置；位置交错在更大的顶点结构中时，
+/// 用 with_vertex_layout 指定格式、步长和位置在结构中的偏移。
+#[derive(Clone, Copy, Debug)]
+pub struct TriangleGeometry {
+    pub vertex_buffer: vk::Buffer,
+    pub index_buffer: vk::Buffer,
+    pub vertex_count: u32,
+    pub index_count: u32,
+    /// 顶点位置的格式
+    pub vertex_format: vk::Format,
+    /// 相邻两个顶点之间的字节数
+    pub vertex_stride: vk::DeviceSize,
+    /// 位置分量在每个顶点中的字节偏移
+    pub position_offset: vk::DeviceSize,
+}
+
+impl TriangleGeometry {
+    /// BLAS 顶点/索引输入 buffer 必须具备的用途
+    pub const REQUIRED_USAGE: vk::BufferUsageFlags = vk::BufferUsageFlags::from_raw(
+        vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS.as_raw()
+            | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR.as_raw(),
+    );
+
+    /// 紧密排列的位置数组使用的顶点格式
+    pub const DEFAULT_VERTEX_FORMAT: vk::Format = vk::Format::R32G32B32_SFLOAT;
+
+    /// 紧密排列的位置数组使用的顶点步长
+    pub const DEFAULT_VERTEX_STRIDE: vk::DeviceSize = std::mem::size_of::<[f32; 3]>() as _;
+
+    /// 由 BufferResource 创建几何，检查两个 buffer 都带有 REQUIRED_USAGE
+    ///
+    /// 缺少 SHADER_DEVICE_ADDRESS 时 vkGetBufferDeviceAddress 的结果无效，构建会在驱动里失败，
+    /// 这里提前返回 MissingBufferUsage。
+    pub fn from_buffers(
+        vertex_buffer: &BufferResource,
+        index_buffer: &BufferResource,
+        vertex_count: u32,
+        index_count: u32,
+    ) -> Result<Self, RtError> {
+        vertex_buffer.require_usage(Self::REQUIRED_USAGE)?;
+        index_buffer.require_usage(Self::REQUIRED_USAGE)?;
+
+        Ok(Self {
+            vertex_buffer: vertex_buffer.buffer,
+            index_buffer: index_buffer.buffer,
+            vertex_count,
+            index_count,
+            vertex_format: Self::DEFAULT_VERTEX_FORMAT,
+            vertex_stride: Self::DEFAULT_VERTEX_STRIDE,
+            position_offset: 0,
+        })
+    }
+
+    /// 指定交错顶点布局，例如 `Vertex` 数组中位置位于开头时为
+    /// `(R32G32B32_SFLOAT, size_of::<Vertex>(), 0)`
+    ///
+    /// vertex_format 不是加速结构必须支持的顶点格式时返回 RtError::UnsupportedFormat；
+    /// 步长或偏移没有按分量大小对齐、或位置超出单个顶点范围时返回 RtError::InvalidConfiguration。
+    pub fn with_vertex_layout(
+        mut self,
+        vertex_format: vk::Format,
+        vertex_stride: vk::DeviceSize,
+        position_offset: vk::DeviceSize,
+    ) -> Result<Self, RtError> {
+        validate_vertex_layout(vertex_format, vertex_stride, position_offset)?;
+
+        self.vertex_format = vertex_format;
+        self.vertex_stride = vertex_stride;
+        self.position_offset = position_offset;
+        Ok(self)
+    }
+
+    pub fn primitive_count(&self) -> u32 {
+        self.index_count / 3
+    }
+
+    pub fn geometry(&self, device: &Device) -> vk::AccelerationStructureGeometryKHR<'static> {
+        let vertex_address = unsafe { get_buffer_device_address(device, self.vertex_buffer) };
+        let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR::default()
+            .vertex_format(self.vertex_format)
+            .vertex_data(vk::DeviceOrHostAddressConstKHR {
+                device_address: vertex_address + self.position_offset,
+            })
+            .vertex_stride(self.vertex_stride)
+            .max_vertex(self.vertex_count.saturating_sub(1))
+            .index_type(vk::IndexType::UINT32)
+            .index_data(vk::DeviceOrHostAddressConstKHR {
+                device_address: unsafe { get_buffer_device_address(device, self.index_buffer) },
+            });
+
+        vk::AccelerationStructureGeometryKHR::default()
+            .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR { triangles })
+            .flags(vk::GeometryFlagsKHR::OPAQUE)
+    }
+}
+
+/// 加速结构顶点格式的 (单个分量字节数, 位置总字节数)
+///
+/// 只覆盖规范要求所有实现都支持 ACCELERATION_STRUCTURE_VERTEX_BUFFER 的格式。
+fn vertex_format_layout(format: vk::Format) -> Option<(vk::DeviceSize, vk::DeviceSize)> {
+    match format {
+        vk::Format::R32G32_SFLOAT => Some((4, 8)),
+        vk::Format::R32G32B32_SFLOAT => Some((4, 12)),
+        vk::Format::R16G16_SFLOAT | vk::Format::R16G16_SNORM => Some((2, 4)),
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R16G16B16A16_SNORM => Some((2, 8)),
+        _ => None,
+    }
+}
+
+/// 检查交错顶点布局是否满足 VkAccelerationStructureGeometryTrianglesDataKHR 的约束
+///
+/// vertexStride 和 vertexData 都必须是格式最小分量大小的倍数，vertexStride 不能超过 u32::MAX。
+fn validate_vertex_layout(
+    vertex_format: vk::Format,
+    vertex_stride: vk::DeviceSize,
+    position_offset: vk::DeviceSize,
+) -> Result<(), RtError> {
+    let (component_size, position_size) =
+        vertex_format_layout(vertex_format).ok_or(RtError::UnsupportedFormat(vertex_format))?;
+
+    if vertex_stride == 0
+        || vertex_stride > u32::MAX as vk::DeviceSize
+        || !vertex_stride.is_multiple_of(component_size)
+    {
+        return Err(RtError::InvalidConfiguration(format!(
+            "vertex stride {} must be a non-zero multiple of {} no larger than u32::MAX for {:?}",
+            vertex_stride, component_size, vertex_format
+        )));
+    }
+    if !position_offset.is_multiple_of(component_size) {
+        return Err(RtError::InvalidConfiguration(format!(
+            "position offset {} must be a multiple of {} for {:?}",
+            position_offset, component_size, vertex_format
+        )));
+    }
+    if position_offset + position_size > vertex_stride {
+        return Err(RtError::InvalidConfiguration(format!(
+            "{:?} position at offset {} does not fit in a {}-byte vertex",
+            vertex_format, position_offset, vertex_stride
+        )));
+    }
+    Ok(())
+}
+
+pub struct BottomLevelAS {
+    pub acceleration_structure: vk::AccelerationStructureKHR,
+    pub buffer: BufferResource,
+    pub device_address: u64,
+    /// 构建时使用的标志，refit 要求包含 ALLOW_UPDATE
+    pub flags: vk::BuildAccelerationStructureFlagsKHR,
+    /// 每个几何的图元数，refit 时拓扑必须保持一致
+    pub primitive_counts: Vec<u32>,
+    /// 构建时 get_acceleration_structure_build_sizes 返回的 acceleration_structure_size
+    pub size: vk::DeviceSize,
+    /// query_compacted_size 查询到的压缩后大小
+    pub compacted_size: Option<vk::DeviceSize>,
+}
+
+impl BottomLevelAS {
+    /// 静态场景默认使用的构建标志
+    pub const DEFAULT_BUILD_FLAGS: vk::BuildAccelerationStructureFlagsKHR =
+        vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE;
+
+    /// 以 DEFAULT_BUILD_FLAGS 构建单个网格的 BLAS，提交后等待完成
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        mesh: &MeshBuffers,
+        limits: &AccelerationStructureLimits,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        Self::new_multi(
+            device,
+            as_loader,
+            command_pool,
+            queue,
+            &[mesh.triangle_geometry()],
+            Self::DEFAULT_BUILD_FLAGS,
+            limits,
+            device_memory_properties,
+        )
+    }
+
+    /// 直接从主机端数组构建 BLAS，适合快速原型
+    ///
+    /// 内部创建 DEVICE_LOCAL 的顶点/索引缓冲并一起返回：BLAS 引用这些缓冲的设备地址，
+    /// 之后的 update（refit）仍会读取它们，因此 MeshBuffers 必须在 BLAS 销毁之后再销毁。
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_vertices(
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        positions: &[[f32; 3]],
+        indices: &[u32],
+        limits: &AccelerationStructureLimits,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<(Self, MeshBuffers), RtError> {
+        let mesh = MeshBuffers::new_device_local(
+            device,
+            command_pool,
+            queue,
+            device_memory_properties,
+            positions,
+            indices,
+        )?;
+
+        match Self::new(
+            device,
+            as_loader,
+            command_pool,
+            queue,
+            &mesh,
+            limits,
+            device_memory_properties,
+        ) {
+            Ok(blas) => Ok((blas, mesh)),
+            Err(err) => {
+                unsafe { mesh.destroy(device) };
+                Err(err)
+            }
+        }
+    }
+
+    /// 完全在 CPU 上构建单个三角形网格的 BLAS，不提交任何队列命令
+    ///
+    /// 需要以 DeviceConfig::acceleration_structure_host_commands 创建设备。几何直接引用 positions/indices
+    /// 的主机地址，scratch 为主机内存；加速结构本身放在 HOST_VISIBLE 内存中，构建完成后即可用于 TLAS。
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_on_host(
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        positions: &[[f32; 3]],
+        indices: &[u32],
+        build_flags: vk::BuildAccelerationStructureFlagsKHR,
+        limits: &AccelerationStructureLimits,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        validate_build_flags(build_flags)?;
+
+        let primitive_counts = [indices.len() as u32 / 3];
+        limits.check_blas(&primitive_counts)?;
+
+        let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR::default()
+            .vertex_format(TriangleGeometry::DEFAULT_VERTEX_FORMAT)
+            .vertex_data(vk::DeviceOrHostAddressConstKHR {
+                host_address: positions.as_ptr().cast(),
+            })
+            .vertex_stride(TriangleGeometry::DEFAULT_VERTEX_STRIDE)
+            .max_vertex((positions.len() as u32).saturating_sub(1))
+            .index_type(vk::IndexType::UINT32)
+            .index_data(vk::DeviceOrHostAddressConstKHR {
+                host_address: indices.as_ptr().cast(),
+            });
+        let geometries = [vk::AccelerationStructureGeometryKHR::default()
+            .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR { triangles })
+            .flags(vk::GeometryFlagsKHR::OPAQUE)];
+
+        let build_range_infos = [vk::AccelerationStructureBuildRangeInfoKHR::default()
+            .primitive_count(primitive_counts[0])];
+
+        let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .flags(build_flags)
+            .geometries(&geometries)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL);
+
+        let mut size_info = vk::AccelerationStructureBuildSizesInfoKHR::default();
+        unsafe {
+            as_loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::HOST,
+                &build_info,
+                &primitive_counts,
+                &mut size_info,
+            );
+        }
+
+        // 主机构建要求加速结构所在的内存是 host 可见的
+        let buffer = BufferResource::new(
+            size_info.acceleration_structure_size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            device_memory_properties,
+        );
+
+        let as_create_info = vk::AccelerationStructureCreateInfoKHR::default()
+            .ty(build_info.ty)
+            .size(size_info.acceleration_structure_size)
+            .buffer(buffer.buffer)
+            .offset(0);
+
+        let acceleration_structure =
+            match unsafe { as_loader.create_acceleration_structure(&as_create_info, None) } {
+                Ok(acceleration_structure) => acceleration_structure,
+                Err(err) => {
+                    unsafe { buffer.destroy(device) };
+                    return Err(err.into());
+                }
+            };
+
+        let mut scratch = vec![0u8; size_info.build_scratch_size as usize];
+        build_info = build_info
+            .dst_acceleration_structure(acceleration_structure)
+            .scratch_data(vk::DeviceOrHostAddressKHR {
+                host_address: scratch.as_mut_ptr().cast(),
+            });
+
+        if let Err(err) = unsafe {
+            as_loader.build_acceleration_structures(
+                vk::DeferredOperationKHR::null(),
+                &[build_info],
+                &[&build_range_infos],
+            )
+        } {
+            unsafe {
+                as_loader.destroy_acceleration_structure(acceleration_structure, None);
+                buffer.destroy(device);
+            }
+            return Err(err.into());
+        }
+
+        let device_address = unsafe {
+            as_loader.get_acceleration_structure_device_address(
+                &vk::AccelerationStructureDeviceAddressInfoKHR::default()
+                    .acceleration_structure(acceleration_structure),
+            )
+        };
+
+        Ok(Self {
+            acceleration_structure,
+            buffer,
+            device_address,
+            flags: build_flags,
+            primitive_counts: primitive_counts.to_vec(),
+            size: size_info.acceleration_structure_size,
+            compacted_size: None,
+        })
+    }
+
+    /// 把多个三角形几何打包进同一个 BLAS
+    ///
+    /// 每个几何对应一个 build range，着色器中的 gl_GeometryIndexEXT 即其在 geometries 中的下标。
+    /// 几何数或图元总数超过 limits 时返回 ExceedsAccelerationStructureLimit。
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_multi(
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        geometries: &[TriangleGeometry],
+        build_flags: vk::BuildAccelerationStructureFlagsKHR,
+        limits: &AccelerationStructureLimits,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        let vk_geometries: Vec<_> = geometries.iter().map(|g| g.geometry(device)).collect();
+        let primitive_counts: Vec<u32> = geometries.iter().map(|g| g.primitive_count()).collect();
+        limits.check_blas(&primitive_counts)?;
+
+        let command_buffer = command_pool.begin_one_time(device)?;
+
+        let (blas, scratch_buffer) = Self::record(
+            device,
+            as_loader,
+            command_buffer,
+            &vk_geometries,
+            &primitive_counts,
+            build_flags,
+            device_memory_properties,
+        )?;
+
+        command_pool.end_one_time(device, queue, command_buffer)?;
+
+        unsafe { scratch_buffer.destroy(device) };
+
+        Ok(blas)
+    }
+
+    /// 创建 BLAS 并把构建命令录制到 command_buffer 中
+    ///
+    /// 返回的 scratch buffer 在命令执行完成之前不能销毁。
+    /// flags 中 PREFER_FAST_TRACE 与 PREFER_FAST_BUILD 同时设置时返回 RtError::InvalidConfiguration。
+    pub fn record(
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_buffer: vk::CommandBuffer,
+        geometries: &[vk::AccelerationStructureGeometryKHR],
+        primitive_counts: &[u32],
+        flags: vk::BuildAccelerationStructureFlagsKHR,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<(Self, BufferResource), RtError> {
+        validate_build_flags(flags)?;
+
+        let build_range_infos: Vec<vk::AccelerationStructureBuildRangeInfoKHR> = primitive_counts
+            .iter()
+            .map(|&count| {
+                vk::AccelerationStructureBuildRangeInfoKHR::default().primitive_count(count)
+            })
+            .collect();
+
+        let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .flags(flags)
+            .geometries(geometries)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL);
+
+        let mut size_info = vk::AccelerationStructureBuildSizesInfoKHR::default();
+
+        unsafe {
+            as_loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &build_info,
+                primitive_counts,
+                &mut size_info,
+            );
+        }
+
+        let buffer = BufferResource::new(
+            size_info.acceleration_structure_size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            device_memory_properties,
+        );
+
+        let as_create_info = vk::AccelerationStructureCreateInfoKHR::default()
+            .ty(build_info.ty)
+            .size(size_info.acceleration_structure_size)
+            .buffer(buffer.buffer)
+            .offset(0);
+
+        let acceleration_structure =
+            match unsafe { as_loader.create_acceleration_structure(&as_create_info, None) } {
+                Ok(acceleration_structure) => acceleration_structure,
+                Err(err) => {
+                    unsafe { buffer.destroy(device) };
+                    return Err(err.into());
+                }
+            };
+
+        // 每次构建使用独立的 scratch buffer，保证多线程录制时互不干扰
+        let scratch_buffer = BufferResource::new(
+            size_info.build_scratch_size,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            device_memory_properties,
+        );
+
+        build_info = build_info
+            .dst_acceleration_structure(acceleration_structure)
+            .scratch_data(vk::DeviceOrHostAddressKHR {
+                device_address: scratch_buffer.device_address(device),
+            });
+
+        unsafe {
+            as_loader.cmd_build_acceleration_structures(
+                command_buffer,
+                &[build_info],
+                &[&build_range_infos],
+            );
+        }
+
+        let device_address = unsafe {
+            as_loader.get_acceleration_structure_device_address(
+                &vk::AccelerationStructureDeviceAddressInfoKHR::default()
+                    .acceleration_structure(acceleration_structure),
+            )
+        };
+
+        Ok((
+            Self {
+                acceleration_structure,
+                buffer,
+                device_address,
+                flags,
+                primitive_counts: primitive_counts.to_vec(),
+                size: size_info.acceleration_structure_size,
+                compacted_size: None,
+            },
+            scratch_buffer,
+        ))
+    }
+
+    /// 使用更新后的顶点数据原地 refit BLAS（BUILD_MODE_UPDATE）
+    ///
+    /// 原始构建必须带有 ALLOW_UPDATE 标志，且每个几何的索引数（拓扑）必须与构建时完全相同，
+    /// 只允许顶点位置变化，否则返回 RtError::InvalidConfiguration。
+    /// refit 复用原有的 BLAS 存储，device_address 不变。
+    /// 返回的 scratch buffer 在命令执行完成之前不能销毁；驱动报告 refit 不需要 scratch 时返回 None。
+    pub fn update(
+        &mut self,
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_buffer: vk::CommandBuffer,
+        updated_geometries: &[TriangleGeometry],
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Option<BufferResource>, RtError> {
+        let primitive_counts: Vec<u32> = updated_geometries
+            .iter()
+            .map(|g| g.primitive_count())
+            .collect();
+        validate_refit(self.flags, &self.primitive_counts, &primitive_counts)?;
+
+        let geometries: Vec<_> = updated_geometries
+            .iter()
+            .map(|g| g.geometry(device))
+            .collect();
+        let build_range_infos: Vec<vk::AccelerationStructureBuildRangeInfoKHR> = primitive_counts
+            .iter()
+            .map(|&count| {
+                vk::AccelerationStructureBuildRangeInfoKHR::default().primitive_count(count)
+            })
+            .collect();
+
+        let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .flags(self.flags)
+            .geometries(&geometries)
+            .mode(vk::BuildAccelerationStructureModeKHR::UPDATE)
+            .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
+            .src_acceleration_structure(self.acceleration_structure)
+            .dst_acceleration_structure(self.acceleration_structure);
+
+        let mut size_info = vk::AccelerationStructureBuildSizesInfoKHR::default();
+
+        unsafe {
+            as_loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &build_info,
+                &primitive_counts,
+                &mut size_info,
+            );
+        }
+
+        let scratch_buffer = create_scratch_buffer(
+            size_info.update_scratch_size,
+            device,
+            device_memory_properties,
+        );
+
+        build_info = build_info.scratch_data(vk::DeviceOrHostAddressKHR {
+            device_address: scratch_buffer
+                .as_ref()
+                .map_or(0, |scratch_buffer| scratch_buffer.device_address(device)),
+        });
+
+        unsafe {
+            as_loader.cmd_build_acceleration_structures(
+                command_buffer,
+                &[build_info],
+                &[&build_range_infos],
+            );
+        }
+
+        Ok(scratch_buffer)
+    }
+
+    /// 把 BLAS 原样复制到新分配的等大 buffer 中（COPY_MODE_CLONE），无需重新构建
+    ///
+    /// 复制命令录制到 command_buffer 中，执行完成之前不能使用返回的 BLAS。
+    pub fn clone_gpu(
+        &self,
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_buffer: vk::CommandBuffer,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, vk::Result> {
+        let buffer = BufferResource::new(
+            self.size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            device_memory_properties,
+        );
+
+        let as_create_info = vk::AccelerationStructureCreateInfoKHR::default()
+            .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
+            .size(self.size)
+            .buffer(buffer.buffer)
+            .offset(0);
+
+        let acceleration_structure =
+            match unsafe { as_loader.create_acceleration_structure(&as_create_info, None) } {
+                Ok(acceleration_structure) => acceleration_structure,
+                Err(err) => {
+                    unsafe { buffer.destroy(device) };
+                    return Err(err);
+                }
+            };
+
+        unsafe {
+            as_loader.cmd_copy_acceleration_structure(
+                command_buffer,
+                &vk::CopyAccelerationStructureInfoKHR::default()
+                    .src(self.acceleration_structure)
+                    .dst(acceleration_structure)
+                    .mode(vk::CopyAccelerationStructureModeKHR::CLONE),
+            );
+        }
+
+        let device_address = unsafe {
+            as_loader.get_acceleration_structure_device_address(
+                &vk::AccelerationStructureDeviceAddressInfoKHR::default()
+                    .acceleration_structure(acceleration_structure),
+            )
+        };
+
+        Ok(Self {
+            acceleration_structure,
+            buffer,
+            device_address,
+            flags: self.flags,
+            primitive_counts: self.primitive_counts.clone(),
+            size: self.size,
+            compacted_size: self.compacted_size,
+        })
+    }
+
+    /// 把 BLAS 序列化（COPY_MODE_SERIALIZE）后写入 path，供下次启动时跳过构建
+    pub fn serialize_to_file(
+        &self,
+        path: impl AsRef<Path>,
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<(), RtError> {
+        let query_pool_create_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::ACCELERATION_STRUCTURE_SERIALIZATION_SIZE_KHR)
+            .query_count(1);
+        let query_pool = unsafe { device.create_query_pool(&query_pool_create_info, None) }?;
+
+        let command_buffer = command_pool.begin_one_time(device)?;
+        unsafe {
+            device.cmd_reset_query_pool(command_buffer, query_pool, 0, 1);
+            as_loader.cmd_write_acceleration_structures_properties(
+                command_buffer,
+                &[self.acceleration_structure],
+                vk::QueryType::ACCELERATION_STRUCTURE_SERIALIZATION_SIZE_KHR,
+                query_pool,
+                0,
+            );
+        }
+        command_pool.end_one_time(device, queue, command_buffer)?;
+
+        let mut serialized_size = [0u64; 1];
+        let result = unsafe {
+            device.get_query_pool_results(
+                query_pool,
+                0,
+                &mut serialized_size,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+            )
+        };
+        unsafe { device.destroy_query_pool(query_pool, None) };
+        result?;
+        let serialized_size = serialized_size[0];
+
+        let host_buffer = BufferResource::new(
+            serialized_size,
+            vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            device_memory_properties,
+        );
+
+        let command_buffer = command_pool.begin_one_time(device)?;
+        unsafe {
+            as_loader.cmd_copy_acceleration_structure_to_memory(
+                command_buffer,
+                &vk::CopyAccelerationStructureToMemoryInfoKHR::default()
+                    .src(self.acceleration_structure)
+                    .dst(vk::DeviceOrHostAddressKHR {
+                        device_address: host_buffer.device_address(device),
+                    })
+                    .mode(vk::CopyAccelerationStructureModeKHR::SERIALIZE),
+            );
+        }
+        let result = command_pool.end_one_time(device, queue, command_buffer);
+
+        let data = result.map(|()| {
+            let mapped = host_buffer.map(0, serialized_size, device);
+            let data = unsafe {
+                std::slice::from_raw_parts(mapped as *const u8, serialized_size as usize)
+            }
+            .to_vec();
+            host_buffer.unmap(device);
+            data
+        });
+        unsafe { host_buffer.destroy(device) };
+
+        std::fs::write(path, data?)?;
+        Ok(())
+    }
+
+    /// 从 serialize_to_file 写入的文件恢复 BLAS（COPY_MODE_DESERIALIZE）
+    ///
+    /// 先用文件头中的驱动 UUID 检查兼容性，不兼容时返回
+    /// `RtError::IncompatibleAccelerationStructure`，调用方应重新构建。
+    /// 序列化数据不包含构建标志与图元数，恢复出的 BLAS 的 flags 为空，不能 refit。
+    pub fn deserialize_from_file(
+        path: impl AsRef<Path>,
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        // 头部：driverUUID、compatibilityUUID、序列化大小、反序列化大小、句柄数
+        const HEADER_SIZE: usize = 2 * vk::UUID_SIZE + 3 * 8;
+
+        let data = std::fs::read(path)?;
+        if data.len() < HEADER_SIZE {
+            return Err(RtError::IncompatibleAccelerationStructure);
+        }
+
+        let version_info = vk::AccelerationStructureVersionInfoKHR::default()
+            .version_data((&data[..2 * vk::UUID_SIZE]).try_into().unwrap());
+        let compatibility =
+            unsafe { as_loader.get_device_acceleration_structure_compatibility(&version_info) };
+        if compatibility != vk::AccelerationStructureCompatibilityKHR::COMPATIBLE {
+            return Err(RtError::IncompatibleAccelerationStructure);
+        }
+
+        let size_offset = 2 * vk::UUID_SIZE + 8;
+        let size = u64::from_ne_bytes(data[size_offset..size_offset + 8].try_into().unwrap());
+
+        let host_buffer = BufferResource::new(
+            data.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            device_memory_properties,
+        );
+        host_buffer.store_from_thread(&data, 0, device);
+
+        let buffer = BufferResource::new(
+            size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            device_memory_properties,
+        );
+
+        let as_create_info = vk::AccelerationStructureCreateInfoKHR::default()
+            .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
+            .size(size)
+            .buffer(buffer.buffer)
+            .offset(0);
+
+        let mut acceleration_structure = vk::AccelerationStructureKHR::null();
+        let result = (|| -> Result<(), RtError> {
+            acceleration_structure =
+                unsafe { as_loader.create_acceleration_structure(&as_create_info, None) }?;
+
+            let command_buffer = command_pool.begin_one_time(device)?;
+            unsafe {
+                as_loader.cmd_copy_memory_to_acceleration_structure(
+                    command_buffer,
+                    &vk::CopyMemoryToAccelerationStructureInfoKHR::default()
+                        .src(vk::DeviceOrHostAddressConstKHR {
+                            device_address: host_buffer.device_address(device),
+                        })
+                        .dst(acceleration_structure)
+                        .mode(vk::CopyAccelerationStructureModeKHR::DESERIALIZE),
+                );
+            }
+            command_pool.end_one_time(device, queue, command_buffer)?;
+            Ok(())
+        })();
+
+        unsafe { host_buffer.destroy(device) };
+        if let Err(err) = result {
+            unsafe {
+                // 销毁空句柄是合法的，创建失败时 acceleration_structure 仍为 null
+                as_loader.destroy_acceleration_structure(acceleration_structure, None);
+                buffer.destroy(device);
+            }
+            return Err(err);
+        }
+
+        let device_address = unsafe {
+            as_loader.get_acceleration_structure_device_address(
+                &vk::AccelerationStructureDeviceAddressInfoKHR::default()
+                    .acceleration_structure(acceleration_structure),
+            )
+        };
+
+        Ok(Self {
+            acceleration_structure,
+            buffer,
+            device_address,
+            flags: vk::BuildAccelerationStructureFlagsKHR::empty(),
+            primitive_counts: Vec::new(),
+            size,
+            compacted_size: None,
+        })
+    }
+
+    /// 底层 buffer 占用的显存大小（字节）
+    pub fn memory_size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    /// 查询压缩后的大小并记录到 compacted_size，构建时必须带有 ALLOW_COMPACTION
+    pub fn query_compacted_size(
+        &mut self,
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+    ) -> Result<vk::DeviceSize, RtError> {
+        assert!(
+            self.flags
+                .contains(vk::BuildAccelerationStructureFlagsKHR::ALLOW_COMPACTION),
+            "Compacted size query requires the build to use ALLOW_COMPACTION"
+        );
+
+        let query_pool_create_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::ACCELERATION_STRUCTURE_COMPACTED_SIZE_KHR)
+            .query_count(1);
+        let query_pool = unsafe { device.create_query_pool(&query_pool_create_info, None) }?;
+
+        let command_buffer = command_pool.begin_one_time(device)?;
+        unsafe {
+            device.cmd_reset_query_pool(command_buffer, query_pool, 0, 1);
+            as_loader.cmd_write_acceleration_structures_properties(
+                command_buffer,
+                &[self.acceleration_structure],
+                vk::QueryType::ACCELERATION_STRUCTURE_COMPACTED_SIZE_KHR,
+                query_pool,
+                0,
+            );
+        }
+        command_pool.end_one_time(device, queue, command_buffer)?;
+
+        let mut compacted_size = [0u64; 1];
+        let result = unsafe {
+            device.get_query_pool_results(
+                query_pool,
+                0,
+                &mut compacted_size,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+            )
+        };
+        unsafe { device.destroy_query_pool(query_pool, None) };
+        result?;
+
+        self.compacted_size = Some(compacted_size[0]);
+        Ok(compacted_size[0])
+    }
+
+    pub unsafe fn destroy(self, device: &Device, as_loader: &khr::acceleration_structure::Device) {
+        unsafe {
+            as_loader.destroy_acceleration_structure(self.acceleration_structure, None);
+            self.buffer.destroy(device);
+        }
+    }
+}
+
+/// 设备的加速结构规模上限（VkPhysicalDeviceAccelerationStructurePropertiesKHR）
+///
+/// 构建前检查可以得到带具体数值的错误，而不是在驱动中不透明地失败。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccelerationStructureLimits {
+    /// 单个 BLAS 中的几何数上限
+    pub max_geometry_count: u64,
+    /// 单个 TLAS 中的实例数上限
+    pub max_instance_count: u64,
+    /// 单个 BLAS 中所有几何的图元总数上限
+    pub max_primitive_count: u64,
+}
+
+impl AccelerationStructureLimits {
+    pub fn query(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let mut as_properties = vk::PhysicalDeviceAccelerationStructurePropertiesKHR::default();
+        let mut properties2 =
+            vk::PhysicalDeviceProperties2::default().push_next(&mut as_properties);
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+
+        Self {
+            max_geometry_count: as_properties.max_geometry_count,
+            max_instance_count: as_properties.max_instance_count,
+            max_primitive_count: as_properties.max_primitive_count,
+        }
+    }
+
+    fn check(limit: &'static str, requested: u64, max: u64) -> Result<(), RtError> {
+        if requested > max {
+            return Err(RtError::ExceedsAccelerationStructureLimit {
+                limit,
+                requested,
+                max,
+            });
+        }
+        Ok(())
+    }
+
+    /// 检查一个 BLAS 的几何数与图元总数
+    pub fn check_blas(&self, primitive_counts: &[u32]) -> Result<(), RtError> {
+        Self::check(
+            "maxGeometryCount",
+            primitive_counts.len() as u64,
+            self.max_geometry_count,
+        )?;
+        Self::check(
+            "maxPrimitiveCount",
+            primitive_counts.iter().map(|&count| count as u64).sum(),
+            self.max_primitive_count,
+        )
+    }
+
+    /// 检查一个 TLAS 的实例数
+    pub fn check_tlas(&self, instance_count: usize) -> Result<(), RtError> {
+        Self::check(
+            "maxInstanceCount",
+            instance_count as u64,
+            self.max_instance_count,
+        )
+    }
+}
+
+/// TopLevelAS::update 实际采用的更新方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlasUpdate {
+    /// 实例数不变且带有 ALLOW_UPDATE，原地 refit
+    Refit,
+    /// 实例数在容量之内，复用现有存储重新构建
+    Rebuild,
+    /// 实例数超过容量，重新分配更大的存储并从头构建，device_address 会改变
+    Reallocated,
+}
+
+pub struct TopLevelAS {
+    pub acceleration_structure: vk::AccelerationStructureKHR,
+    pub buffer: BufferResource,
+    /// host 可见的 vk::AccelerationStructureInstanceKHR 数组，容量为 instance_capacity
+    pub instance_buffer: BufferResource,
+    pub device_address: u64,
+    pub flags: vk::BuildAccelerationStructureFlagsKHR,
+    /// 存储按此实例数分配，超过时 update 会重新分配
+    pub instance_capacity: u32,
+    /// 最近一次构建使用的实例数
+    pub instance_count: u32,
+    pub size: vk::DeviceSize,
+}
+
+impl TopLevelAS {
+    /// 以 instances.len() 为容量创建并构建 TLAS，提交后等待完成，实例数超过 limits 时返回错误
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        instances: &[vk::AccelerationStructureInstanceKHR],
+        flags: vk::BuildAccelerationStructureFlagsKHR,
+        limits: &AccelerationStructureLimits,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        limits.check_tlas(instances.len())?;
+        let mut tlas = Self::allocate(
+            device,
+            as_loader,
+            instances.len() as u32,
+            flags,
+            device_memory_properties,
+        )?;
+        if let Err(err) = tlas.build(
+            device,
+            as_loader,
+            command_pool,
+            queue,
+            instances,
+            vk::BuildAccelerationStructureModeKHR::BUILD,
+            device_memory_properties,
+        ) {
+            unsafe { tlas.destroy(device, as_loader) };
+            return Err(err);
+        }
+        Ok(tlas)
+    }
+
+    /// 更新实例并重新构建，提交后等待完成
+    ///
+    /// - 实例数不变且构建带 ALLOW_UPDATE 时原地 refit
+    /// - 实例数不超过容量时复用存储重新构建
+    /// - 超过容量时按 max(实例数, 2 × 容量) 重新分配实例 buffer 与 TLAS 存储并从头构建，
+    ///   构建完成后才释放旧的分配。此时 acceleration_structure 与 device_address 都会改变，
+    ///   引用它们的描述符集需要重新写入
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        instances: &[vk::AccelerationStructureInstanceKHR],
+        limits: &AccelerationStructureLimits,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<TlasUpdate, RtError> {
+        limits.check_tlas(instances.len())?;
+        let instance_count = instances.len() as u32;
+
+        if instance_count > self.instance_capacity {
+            // 翻倍扩容但不超过设备允许的最大实例数
+            let capacity = instance_count.max(
+                self.instance_capacity
+                    .saturating_mul(2)
+                    .min(limits.max_instance_count.min(u32::MAX as u64) as u32),
+            );
+            let mut grown = Self::allocate(
+                device,
+                as_loader,
+                capacity,
+                self.flags,
+                device_memory_properties,
+            )?;
+            if let Err(err) = grown.build(
+                device,
+                as_loader,
+                command_pool,
+                queue,
+                instances,
+                vk::BuildAccelerationStructureModeKHR::BUILD,
+                device_memory_properties,
+            ) {
+                // 构建失败时保留原有的 TLAS 不变
+                unsafe { grown.destroy(device, as_loader) };
+                return Err(err);
+            }
+
+            // build 已等待队列空闲，旧的分配不再被使用
+            let old = std::mem::replace(self, grown);
+            unsafe { old.destroy(device, as_loader) };
+            return Ok(TlasUpdate::Reallocated);
+        }
+
+        let refit = instance_count == self.instance_count
+            && self
+                .flags
+                .contains(vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE);
+        let mode = if refit {
+            vk::BuildAccelerationStructureModeKHR::UPDATE
+        } else {
+            vk::BuildAccelerationStructureModeKHR::BUILD
+        };
+
+        self.build(
+            device,
+            as_loader,
+            command_pool,
+            queue,
+            instances,
+            mode,
+            device_memory_properties,
+        )?;
+
+        Ok(if refit {
+            TlasUpdate::Refit
+        } else {
+            TlasUpdate::Rebuild
+        })
+    }
+
+    /// 按 capacity 个实例分配实例 buffer 与 TLAS 存储，不录制构建
+    fn allocate(
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        capacity: u32,
+        flags: vk::BuildAccelerationStructureFlagsKHR,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        validate_build_flags(flags)?;
+        let capacity = capacity.max(1);
+
+        let instance_buffer = BufferResource::new(
+            capacity as vk::DeviceSize
+                * size_of::<vk::AccelerationStructureInstanceKHR>() as vk::DeviceSize,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            device_memory_properties,
+        );
+
+        let geometries = [Self::instances_geometry(
+            instance_buffer.device_address(device),
+        )];
+        let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .flags(flags)
+            .geometries(&geometries)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL);
+
+        let mut size_info = vk::AccelerationStructureBuildSizesInfoKHR::default();
+        unsafe {
+            as_loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &build_info,
+                &[capacity],
+                &mut size_info,
+            );
+        }
+
+        let buffer = BufferResource::new(
+            size_info.acceleration_structure_size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            device_memory_properties,
+        );
+
+        let as_create_info = vk::AccelerationStructureCreateInfoKHR::default()
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+            .size(size_info.acceleration_structure_size)
+            .buffer(buffer.buffer)
+            .offset(0);
+
+        let acceleration_structure =
+            match unsafe { as_loader.create_acceleration_structure(&as_create_info, None) } {
+                Ok(acceleration_structure) => acceleration_structure,
+                Err(err) => {
+                    unsafe {
+                        buffer.destroy(device);
+                        instance_buffer.destroy(device);
+                    }
+                    return Err(err.into());
+                }
+            };
+
+        let device_address = unsafe {
+            as_loader.get_acceleration_structure_device_address(
+                &vk::AccelerationStructureDeviceAddressInfoKHR::default()
+                    .acceleration_structure(acceleration_structure),
+            )
+        };
+
+        Ok(Self {
+            acceleration_structure,
+            buffer,
+            instance_buffer,
+            device_address,
+            flags,
+            instance_capacity: capacity,
+            instance_count: 0,
+            size: size_info.acceleration_structure_size,
+        })
+    }
+
+    /// 写入实例数据并以 mode 构建，提交后等待完成
+    #[allow(clippy::too_many_arguments)]
+    fn build(
+        &mut self,
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        instances: &[vk::AccelerationStructureInstanceKHR],
+        mode: vk::BuildAccelerationStructureModeKHR,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<(), RtError> {
+        let instance_count = instances.len() as u32;
+        assert!(
+            instance_count <= self.instance_capacity,
+            "{} instances exceed the TLAS capacity of {}",
+            instance_count,
+            self.instance_capacity
+        );
+
+        if !instances.is_empty() {
+            self.instance_buffer.store_from_thread(instances, 0, device);
+        }
+
+        let geometries = [Self::instances_geometry(
+            self.instance_buffer.device_address(device),
+        )];
+        let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .flags(self.flags)
+            .geometries(&geometries)
+            .mode(mode)
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+            .dst_acceleration_structure(self.acceleration_structure);
+        if mode == vk::BuildAccelerationStructureModeKHR::UPDATE {
+            build_info = build_info.src_acceleration_structure(self.acceleration_structure);
+        }
+
+        let mut size_info = vk::AccelerationStructureBuildSizesInfoKHR::default();
+        unsafe {
+            as_loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &build_info,
+                &[instance_count],
+                &mut size_info,
+            );
+        }
+
+        let scratch_size = if mode == vk::BuildAccelerationStructureModeKHR::UPDATE {
+            size_info.update_scratch_size
+        } else {
+            size_info.build_scratch_size
+        };
+        let scratch_buffer = create_scratch_buffer(scratch_size, device, device_memory_properties);
+
+        build_info = build_info.scratch_data(vk::DeviceOrHostAddressKHR {
+            device_address: scratch_buffer
+                .as_ref()
+                .map_or(0, |scratch_buffer| scratch_buffer.device_address(device)),
+        });
+        let build_range_infos = [
+            vk::AccelerationStructureBuildRangeInfoKHR::default().primitive_count(instance_count)
+        ];
+
+        let result = (|| -> Result<(), RtError> {
+            let command_buffer = command_pool.begin_one_time(device)?;
+            unsafe {
+                as_loader.cmd_build_acceleration_structures(
+                    command_buffer,
+                    &[build_info],
+                    &[&build_range_infos],
+                );
+            }
+            command_pool.end_one_time(device, queue, command_buffer)
+        })();
+
+        if let Some(scratch_buffer) = scratch_buffer {
+            unsafe { scratch_buffer.destroy(device) };
+        }
+        result?;
+
+        self.instance_count = instance_count;
+        Ok(())
+    }
+
+    pub(crate) fn instances_geometry(
+        instance_address: u64,
+    ) -> vk::AccelerationStructureGeometryKHR<'static> {
+        let instances = vk::AccelerationStructureGeometryInstancesDataKHR::default()
+            .array_of_pointers(false)
+            .data(vk::DeviceOrHostAddressConstKHR {
+                device_address: instance_address,
+            });
+
+        vk::AccelerationStructureGeometryKHR::default()
+            .geometry_type(vk::GeometryTypeKHR::INSTANCES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR { instances })
+    }
+
+    pub unsafe fn destroy(self, device: &Device, as_loader: &khr::acceleration_structure::Device) {
+        unsafe {
+            as_loader.destroy_acceleration_structure(self.acceleration_structure, None);
+            self.buffer.destroy(device);
+            self.instance_buffer.destroy(device);
+        }
+    }
+}
+
+/// 场景中所有加速结构的显存统计
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AccelerationStructureStats {
+    pub count: usize,
+    /// 所有加速结构 buffer 的总大小
+    pub total_size: vk::DeviceSize,
+    /// 已查询压缩大小的加速结构按压缩大小计，其余按原大小计
+    pub total_compacted_size: vk::DeviceSize,
+}
+
+impl AccelerationStructureStats {
+    pub fn from_blases(blases: &[BottomLevelAS]) -> Self {
+        blases.iter().fold(Self::default(), |mut stats, blas| {
+            stats.count += 1;
+            stats.total_size += blas.memory_size();
+            stats.total_compacted_size += blas.compacted_size.unwrap_or(blas.size);
+            stats
+        })
+    }
+}
+
+/// 检查构建标志组合是否合法
+///
+/// 动态几何可使用 PREFER_FAST_BUILD | ALLOW_UPDATE，静态几何使用 PREFER_FAST_TRACE，
+/// 两种偏好互斥；ALLOW_COMPACTION 与 LOW_MEMORY 可以与任意一种组合。
+pub fn validate_build_flags(flags: vk::BuildAccelerationStructureFlagsKHR) -> Result<(), RtError> {
+    if flags.contains(
+        vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+            | vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_BUILD,
+    ) {
+        return Err(RtError::InvalidConfiguration(format!(
+            "PREFER_FAST_TRACE and PREFER_FAST_BUILD are mutually exclusive: {:?}",
+            flags
+        )));
+    }
+    Ok(())
+}
+
+/// 检查 refit 的前提：原始构建带有 ALLOW_UPDATE，且每个几何的图元数与构建时一致
+fn validate_refit(
+    flags: vk::BuildAccelerationStructureFlagsKHR,
+    built_counts: &[u32],
+    updated_counts: &[u32],
+) -> Result<(), RtError> {
+    if !flags.contains(vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE) {
+        return Err(RtError::InvalidConfiguration(format!(
+            "BLAS refit requires the original build to use ALLOW_UPDATE: {:?}",
+            flags
+        )));
+    }
+    if built_counts != updated_counts {
+        return Err(RtError::InvalidConfiguration(format!(
+            "BLAS refit requires identical topology: built with {:?} primitives, updated with {:?}",
+            built_counts, updated_counts
+        )));
+    }
+    Ok(())
+}
+
+/// 分配构建用的 scratch buffer
+///
+/// 驱动可能对某些构建（常见于 refit）报告 0 字节的 scratch 需求，而 0 大小的 VkBuffer 不合法；
+/// 此时返回 None，构建时 scratch 地址传 0。
+fn create_scratch_buffer(
+    size: vk::DeviceSize,
+    device: &Device,
+    device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+) -> Option<BufferResource> {
+    (size > 0).then(|| {
+        BufferResource::new(
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            device_memory_properties,
+        )
+    })
+}
+
+/// 使用多个线程并行录制 BLAS 构建命令
+///
+/// 每个线程使用 command_pools_per_thread 中各自的命令池和独立的 scratch buffer，
+/// 录制完成后在调用线程上一次性提交并等待 fence。返回顺序与 meshes 一致。
+#[allow(clippy::too_many_arguments)]
+pub fn build_blas_batch(
+    meshes: &[MeshBuffers],
+    build_flags: vk::BuildAccelerationStructureFlagsKHR,
+    device: &Device,
+    as_loader: &khr::acceleration_structure::Device,
+    command_pools_per_thread: &[CommandPoolManager],
+    queue: vk::Queue,
+    limits: &AccelerationStructureLimits,
+    device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+) -> Result<Vec<BottomLevelAS>, RtError> {
+    if meshes.is_empty() {
+        return Ok(Vec::new());
+    }
+    for mesh in meshes {
+        limits.check_blas(&[mesh.primitive_count()])?;
+    }
+    assert!(
+        !command_pools_per_thread.is_empty(),
+        "build_blas_batch needs at least one command pool"
+    );
+
+    let chunk_size = meshes.len().div_ceil(command_pools_per_thread.len());
+
+    let recorded: Vec<Result<_, RtError>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = meshes
+            .chunks(chunk_size)
+            .zip(command_pools_per_thread)
+            .map(|(chunk, command_pool)| {
+                scope.spawn(move || {
+                    let command_buffer = command_pool.begin_one_time(device)?;
+
+                    let mut built = Vec::with_capacity(chunk.len());
+                    let result = (|| -> Result<(), RtError> {
+                        for mesh in chunk {
+                            built.push(BottomLevelAS::record(
+                                device,
+                                as_loader,
+                                command_buffer,
+                                &[mesh.geometry(device)],
+                                &[mesh.primitive_count()],
+                                build_flags,
+                                device_memory_properties,
+                            )?);
+                        }
+                        unsafe { device.end_command_buffer(command_buffer) }?;
+                        Ok(())
+                    })();
+
+                    match result {
+                        Ok(()) => Ok((command_pool.pool, command_buffer, built)),
+                        Err(err) => {
+                            unsafe {
+                                destroy_recorded_blases(
+                                    device,
+                                    as_loader,
+                                    command_pool.pool,
+                                    command_buffer,
+                                    built,
+                                )
+                            };
+                            Err(err)
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("BLAS build thread panicked"))
+            .collect()
+    });
+
+    // 任一线程失败时，其他线程已录制的 BLAS 与命令缓冲也要一并释放
+    let mut first_error = None;
+    let mut succeeded = Vec::with_capacity(recorded.len());
+    for result in recorded {
+        match result {
+            Ok(recorded) => succeeded.push(recorded),
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    if let Some(err) = first_error {
+        for (pool, command_buffer, built) in succeeded {
+            unsafe { destroy_recorded_blases(device, as_loader, pool, command_buffer, built) };
+        }
+        return Err(err);
+    }
+    let recorded = succeeded;
+
+    let command_buffers: Vec<vk::CommandBuffer> = recorded
+        .iter()
+        .map(|(_, command_buffer, _)| *command_buffer)
+        .collect();
+
+    let submitted = (|| -> Result<(), RtError> {
+        unsafe {
+            let fence = device.create_fence(&vk::FenceCreateInfo::default(), None)?;
+
+            let submit_infos = [vk::SubmitInfo::default().command_buffers(&command_buffers)];
+            let result = device
+                .queue_submit(queue, &submit_infos, fence)
+                .and_then(|()| device.wait_for_fences(&[fence], true, u64::MAX));
+            device.destroy_fence(fence, None);
+            result?;
+        }
+        Ok(())
+    })();
+
+    if let Err(err) = submitted {
+        for (pool, command_buffer, built) in recorded {
+            unsafe { destroy_recorded_blases(device, as_loader, pool, command_buffer, built) };
+        }
+        return Err(err);
+    }
+
+    let mut blases = Vec::with_capacity(meshes.len());
+    for (pool, command_buffer, built) in recorded {
+        unsafe { device.free_command_buffers(pool, &[command_buffer]) };
+        for (blas, scratch_buffer) in built {
+            unsafe { scratch_buffer.destroy(device) };
+            blases.push(blas);
+        }
+    }
+
+    Ok(blases)
+}
+
+/// 释放一个线程录制的命令缓冲以及其中已创建的 BLAS 和 scratch buffer，用于构建失败时的清理
+unsafe fn destroy_recorded_blases(
+    device: &Device,
+    as_loader: &khr::acceleration_structure::Device,
+    pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    built: Vec<(BottomLevelAS, BufferResource)>,
+) {
+    unsafe {
+        device.free_command_buffers(pool, &[command_buffer]);
+        for (blas, scratch_buffer) in built {
+            scratch_buffer.destroy(device);
+            blas.destroy(device, as_loader);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflicting_build_preferences_are_rejected() {
+        let flags = vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+            | vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_BUILD;
+        assert!(matches!(
+            validate_build_flags(flags),
+            Err(RtError::InvalidConfiguration(_))
+        ));
+    }
+
+    #[test]
+    fn single_build_preference_is_accepted() {
+        assert!(
+            validate_build_flags(
+                vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_BUILD
+                    | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE
+            )
+            .is_ok()
+        );
+        assert!(
+            validate_build_flags(
+                vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+                    | vk::BuildAccelerationStructureFlagsKHR::ALLOW_COMPACTION
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn build_blas_batch_cleans_up_after_a_failed_build() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("build_blas_batch_cleans_up_after_a_failed_build") else {
+            return;
+        };
+        let device = &context.device;
+        let pools = [context.command_pool(), context.command_pool()];
+        let meshes: Vec<MeshBuffers> = (0..3)
+            .map(|i| {
+                let x = i as f32;
+                MeshBuffers::new(
+                    device,
+                    context.device_memory_properties,
+                    &[[x, 0.0, 0.0], [x + 1.0, 0.0, 0.0], [x, 1.0, 0.0]],
+                    &[0, 1, 2],
+                )
+            })
+            .collect();
+
+        let failed = build_blas_batch(
+            &meshes,
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+                | vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_BUILD,
+            device,
+            &context.as_loader,
+            &pools,
+            context.queue,
+            &context.limits,
+            context.device_memory_properties,
+        );
+        assert!(matches!(failed, Err(RtError::InvalidConfiguration(_))));
+
+        let blases = build_blas_batch(
+            &meshes,
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+            device,
+            &context.as_loader,
+            &pools,
+            context.queue,
+            &context.limits,
+            context.device_memory_properties,
+        )
+        .expect("BLAS batch build failed");
+        assert_eq!(blases.len(), meshes.len());
+
+        unsafe {
+            for blas in blases {
+                blas.destroy(device, &context.as_loader);
+            }
+            for mesh in meshes {
+                mesh.destroy(device);
+            }
+            for pool in pools {
+                pool.destroy(device);
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_rejects_truncated_and_foreign_files() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("deserialize_rejects_truncated_and_foreign_files") else {
+            return;
+        };
+        let command_pool = context.command_pool();
+        let path = std::env::temp_dir().join("rt_deserialize_rejects_foreign.bin");
+
+        for contents in [vec![0u8; 8], vec![0u8; 2 * vk::UUID_SIZE + 3 * 8 + 64]] {
+            std::fs::write(&path, &contents).unwrap();
+            let result = BottomLevelAS::deserialize_from_file(
+                &path,
+                &context.device,
+                &context.as_loader,
+                &command_pool,
+                context.queue,
+                context.device_memory_properties,
+            );
+            assert!(matches!(
+                result,
+                Err(RtError::IncompatibleAccelerationStructure)
+            ));
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        unsafe { command_pool.destroy(&context.device) };
+    }
+
+    fn mocked_limits(max_instance_count: u64) -> AccelerationStructureLimits {
+        AccelerationStructureLimits {
+            max_geometry_count: 4,
+            max_instance_count,
+            max_primitive_count: 64,
+        }
+    }
+
+    #[test]
+    fn instance_count_above_the_limit_names_the_limit() {
+        let limits = mocked_limits(2);
+        assert!(limits.check_tlas(2).is_ok());
+        assert!(matches!(
+            limits.check_tlas(3),
+            Err(RtError::ExceedsAccelerationStructureLimit {
+                limit: "maxInstanceCount",
+                requested: 3,
+                max: 2,
+            })
+        ));
+        assert!(matches!(
+            limits.check_blas(&[1; 5]),
+            Err(RtError::ExceedsAccelerationStructureLimit {
+                limit: "maxGeometryCount",
+                requested: 5,
+                max: 4,
+            })
+        ));
+        assert!(matches!(
+            limits.check_blas(&[40, 40]),
+            Err(RtError::ExceedsAccelerationStructureLimit {
+                limit: "maxPrimitiveCount",
+                requested: 80,
+                max: 64,
+            })
+        ));
+    }
+
+    #[test]
+    fn tlas_with_more_instances_than_the_limit_is_rejected_before_building() {
+        use crate::test_support::test_context;
+
+        let Some(context) =
+            test_context("tlas_with_more_instances_than_the_limit_is_rejected_before_building")
+        else {
+            return;
+        };
+        let command_pool = context.command_pool();
+        let instance = vk::AccelerationStructureInstanceKHR {
+            transform: vk::TransformMatrixKHR {
+                matrix: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            },
+            instance_custom_index_and_mask: vk::Packed24_8::new(0, 0xFF),
+            instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(0, 0),
+            acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+                device_handle: 0,
+            },
+        };
+
+        let result = TopLevelAS::new(
+            &context.device,
+            &context.as_loader,
+            &command_pool,
+            context.queue,
+            &[instance; 3],
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+            &mocked_limits(2),
+            context.device_memory_properties,
+        );
+        assert!(matches!(
+            result,
+            Err(RtError::ExceedsAccelerationStructureLimit {
+                limit: "maxInstanceCount",
+                requested: 3,
+                max: 2,
+            })
+        ));
+
+        unsafe { command_pool.destroy(&context.device) };
+    }
+
+    #[test]
+    fn tlas_update_grows_then_refits() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("tlas_update_grows_then_refits") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let (blas, mesh) = BottomLevelAS::from_vertices(
+            device,
+            &context.as_loader,
+            &command_pool,
+            context.queue,
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            &[0, 1, 2],
+            &context.limits,
+            context.device_memory_properties,
+        )
+        .expect("BLAS build failed");
+        let instance = |x: f32| vk::AccelerationStructureInstanceKHR {
+            transform: vk::TransformMatrixKHR {
+                matrix: [1.0, 0.0, 0.0, x, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            },
+            instance_custom_index_and_mask: vk::Packed24_8::new(0, 0xFF),
+            instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(0, 0),
+            acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+                device_handle: blas.device_address,
+            },
+        };
+
+        let mut tlas = TopLevelAS::new(
+            device,
+            &context.as_loader,
+            &command_pool,
+            context.queue,
+            &[instance(0.0)],
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_BUILD
+                | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE,
+            &context.limits,
+            context.device_memory_properties,
+        )
+        .expect("TLAS build failed");
+
+        let grown = [instance(0.0), instance(2.0), instance(4.0)];
+        let update = |tlas: &mut TopLevelAS, instances: &[_]| {
+            tlas.update(
+                device,
+                &context.as_loader,
+                &command_pool,
+                context.queue,
+                instances,
+                &context.limits,
+                context.device_memory_properties,
+            )
+            .expect("TLAS update failed")
+        };
+        assert_eq!(update(&mut tlas, &grown), TlasUpdate::Reallocated);
+        assert!(tlas.instance_capacity >= 3);
+        assert_eq!(update(&mut tlas, &grown), TlasUpdate::Refit);
+        assert_eq!(update(&mut tlas, &grown[..2]), TlasUpdate::Rebuild);
+
+        unsafe {
+            tlas.destroy(device, &context.as_loader);
+            blas.destroy(device, &context.as_loader);
+            mesh.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+
+    #[test]
+    fn blas_refit_keeps_device_address() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("blas_refit_keeps_device_address") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let mesh = MeshBuffers::new(
+            device,
+            context.device_memory_properties,
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            &[0, 1, 2],
+        );
+        let flags = vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_BUILD
+            | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE;
+
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        let (mut blas, build_scratch) = BottomLevelAS::record(
+            device,
+            &context.as_loader,
+            command_buffer,
+            &[mesh.geometry(device)],
+            &[mesh.primitive_count()],
+            flags,
+            context.device_memory_properties,
+        )
+        .expect("BLAS record failed");
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+        let address = blas.device_address;
+
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        let update_scratch = blas
+            .update(
+                device,
+                &context.as_loader,
+                command_buffer,
+                &[mesh.triangle_geometry()],
+                context.device_memory_properties,
+            )
+            .expect("BLAS refit failed");
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+        assert_eq!(blas.device_address, address);
+
+        unsafe {
+            if let Some(update_scratch) = update_scratch {
+                update_scratch.destroy(device);
+            }
+            build_scratch.destroy(device);
+            blas.destroy(device, &context.as_loader);
+            mesh.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+
+    #[test]
+    fn interleaved_vertex_layout_is_accepted() {
+        assert!(validate_vertex_layout(vk::Format::R32G32B32_SFLOAT, 32, 0).is_ok());
+        assert!(validate_vertex_layout(vk::Format::R32G32B32_SFLOAT, 32, 20).is_ok());
+        assert!(validate_vertex_layout(vk::Format::R16G16B16A16_SFLOAT, 10, 2).is_ok());
+    }
+
+    #[test]
+    fn misaligned_or_overflowing_vertex_layout_is_rejected() {
+        for (stride, offset) in [(0, 0), (30, 0), (32, 2), (32, 24), (1 << 32, 0)] {
+            assert!(
+                matches!(
+                    validate_vertex_layout(vk::Format::R32G32B32_SFLOAT, stride, offset),
+                    Err(RtError::InvalidConfiguration(_))
+                ),
+                "stride {} offset {}",
+                stride,
+                offset
+            );
+        }
+        assert!(matches!(
+            validate_vertex_layout(vk::Format::R8G8B8A8_UNORM, 32, 0),
+            Err(RtError::UnsupportedFormat(vk::Format::R8G8B8A8_UNORM))
+        ));
+    }
+
+    #[test]
+    fn blas_builds_from_interleaved_vertices() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("blas_builds_from_interleaved_vertices") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        // 位置、法线和 UV 交错排列，每个顶点 32 字节
+        let vertices: [[f32; 8]; 3] = [
+            [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0],
+        ];
+        let usage = TriangleGeometry::REQUIRED_USAGE | vk::BufferUsageFlags::STORAGE_BUFFER;
+        let vertex_buffer = BufferResource::new_device_local(
+            &vertices,
+            usage,
+            device,
+            context.device_memory_properties,
+            &command_pool,
+            context.queue,
+        )
+        .unwrap();
+        let index_buffer = BufferResource::new_device_local(
+            &[0u32, 1, 2],
+            usage,
+            device,
+            context.device_memory_properties,
+            &command_pool,
+            context.queue,
+        )
+        .unwrap();
+
+        let geometry = TriangleGeometry::from_buffers(&vertex_buffer, &index_buffer, 3, 3)
+            .unwrap()
+            .with_vertex_layout(
+                vk::Format::R32G32B32_SFLOAT,
+                std::mem::size_of::<[f32; 8]>() as vk::DeviceSize,
+                0,
+            )
+            .unwrap();
+        let blas = BottomLevelAS::new_multi(
+            device,
+            &context.as_loader,
+            &command_pool,
+            context.queue,
+            &[geometry],
+            BottomLevelAS::DEFAULT_BUILD_FLAGS,
+            &context.limits,
+            context.device_memory_properties,
+        )
+        .expect("interleaved BLAS build failed");
+        assert_ne!(blas.device_address, 0);
+
+        unsafe {
+            blas.destroy(device, &context.as_loader);
+            vertex_buffer.destroy(device);
+            index_buffer.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+
+    #[test]
+    fn multi_geometry_blas_sizes_cover_every_geometry() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("multi_geometry_blas_sizes_cover_every_geometry") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let first = MeshBuffers::new(
+            device,
+            context.device_memory_properties,
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            &[0, 1, 2],
+        );
+        let second = MeshBuffers::new(
+            device,
+            context.device_memory_properties,
+            &[
+                [2.0, 0.0, 0.0],
+                [3.0, 0.0, 0.0],
+                [2.0, 1.0, 0.0],
+                [3.0, 1.0, 0.0],
+            ],
+            &[0, 1, 2, 2, 1, 3],
+        );
+
+        let build = |meshes: &[&MeshBuffers]| {
+            let geometries: Vec<_> = meshes.iter().map(|m| m.triangle_geometry()).collect();
+            BottomLevelAS::new_multi(
+                device,
+                &context.as_loader,
+                &command_pool,
+                context.queue,
+                &geometries,
+                BottomLevelAS::DEFAULT_BUILD_FLAGS,
+                &context.limits,
+                context.device_memory_properties,
+            )
+            .expect("BLAS build failed")
+        };
+        let single = build(&[&first]);
+        let combined = build(&[&first, &second]);
+
+        assert_eq!(combined.primitive_counts, vec![1, 2]);
+        assert!(combined.size >= single.size);
+
+        unsafe {
+            single.destroy(device, &context.as_loader);
+            combined.destroy(device, &context.as_loader);
+            first.destroy(device);
+            second.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+
+    #[test]
+    fn refit_requires_allow_update_and_identical_topology() {
+        let updatable = vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_BUILD
+            | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE;
+        assert!(validate_refit(updatable, &[1, 2], &[1, 2]).is_ok());
+        assert!(matches!(
+            validate_refit(BottomLevelAS::DEFAULT_BUILD_FLAGS, &[1], &[1]),
+            Err(RtError::InvalidConfiguration(_))
+        ));
+        assert!(matches!(
+            validate_refit(updatable, &[1, 2], &[1, 3]),
+            Err(RtError::InvalidConfiguration(_))
+        ));
+        assert!(matches!(
+            validate_refit(updatable, &[1, 2], &[1]),
+            Err(RtError::InvalidConfiguration(_))
+        ));
+    }
+
+    #[test]
+    fn packed_cube_index_data_follows_the_aligned_vertex_data() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("packed_cube_index_data_follows_the_aligned_vertex_data")
+        else {
+            return;
+        };
+        let device = &context.device;
+        let vertices: Vec<[f32; 3]> = (0..8)
+            .map(|i| [(i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32])
+            .collect();
+        let indices: [u32; 36] = [
+            0, 2, 1, 1, 2, 3, 4, 5, 6, 5, 7, 6, 0, 1, 4, 1, 5, 4, 2, 6, 3, 3, 6, 7, 0, 4, 2, 2, 4,
+            6, 1, 3, 5, 3, 7, 5,
+        ];
+        let mesh = PackedMesh::new(
+            device,
+            context.device_memory_properties,
+            &vertices,
+            &indices,
+        );
+
+        let aligned_vertex_size = std::mem::size_of_val(vertices.as_slice())
+            .next_multiple_of(PackedMesh::INDEX_ALIGNMENT as usize)
+            as vk::DeviceSize;
+        assert_eq!(mesh.index_offset, aligned_vertex_size);
+        assert_eq!(mesh.primitive_count(), 12);
+        let base = mesh.buffer.device_address(device);
+        assert_ne!(base, 0);
+        assert_eq!(mesh.vertex_address(device), base);
+        assert_eq!(mesh.index_address(device), base + aligned_vertex_size);
+
+        unsafe { mesh.destroy(device) };
+    }
+
+    #[test]
+    fn blas_memory_size_matches_the_backing_buffer() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("blas_memory_size_matches_the_backing_buffer") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let (blas, mesh) = BottomLevelAS::from_vertices(
+            device,
+            &context.as_loader,
+            &command_pool,
+            context.queue,
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            &[0, 1, 2],
+            &context.limits,
+            context.device_memory_properties,
+        )
+        .unwrap();
+
+        assert!(blas.memory_size() > 0);
+        assert_eq!(blas.memory_size(), blas.buffer.size);
+        assert_eq!(blas.compacted_size, None);
+
+        let blases = [blas];
+        let stats = AccelerationStructureStats::from_blases(&blases);
+        assert_eq!(stats.count, 1);
+        assert_eq!(stats.total_size, blases[0].buffer.size);
+        assert_eq!(stats.total_compacted_size, blases[0].buffer.size);
+
+        unsafe {
+            let [blas] = blases;
+            blas.destroy(device, &context.as_loader);
+            mesh.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+
+    #[test]
+    fn triangle_blas_from_host_arrays_has_a_device_address() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("triangle_blas_from_host_arrays_has_a_device_address")
+        else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let indices = [0, 1, 2];
+        let (blas, mesh) = BottomLevelAS::from_vertices(
+            device,
+            &context.as_loader,
+            &command_pool,
+            context.queue,
+            &positions,
+            &indices,
+            &context.limits,
+            context.device_memory_properties,
+        )
+        .unwrap();
+
+        assert_ne!(blas.device_address, 0);
+        assert_eq!(blas.primitive_counts, [1]);
+        assert_eq!((mesh.vertex_count, mesh.index_count), (3, 3));
+        for buffer in [&mesh.vertex_buffer, &mesh.index_buffer] {
+            assert!(
+                buffer
+                    .require_usage(TriangleGeometry::REQUIRED_USAGE)
+                    .is_ok()
+            );
+        }
+
+        // 几何 buffer 要比 BLAS 活得更久
+        unsafe {
+            blas.destroy(device, &context.as_loader);
+            mesh.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+
+    #[test]
+    fn triangle_blas_builds_entirely_on_the_host() {
+        use crate::test_support::TestContext;
+
+        let Some(context) =
+            TestContext::with_config(|config| config.acceleration_structure_host_commands = true)
+        else {
+            eprintln!(
+                "skipping triangle_blas_builds_entirely_on_the_host: no accelerationStructureHostCommands"
+            );
+            return;
+        };
+        let device = &context.device;
+        let blas = BottomLevelAS::new_on_host(
+            device,
+            &context.as_loader,
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            &[0, 1, 2],
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+            &context.limits,
+            context.device_memory_properties,
+        )
+        .unwrap();
+
+        assert_ne!(
+            blas.acceleration_structure,
+            vk::AccelerationStructureKHR::null()
+        );
+        assert_ne!(blas.device_address, 0);
+        assert_eq!(blas.primitive_counts, [1]);
+
+        unsafe { blas.destroy(device, &context.as_loader) };
+    }
+
+    #[test]
+    fn gpu_clone_has_its_own_device_address() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("gpu_clone_has_its_own_device_address") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let (blas, mesh) = BottomLevelAS::from_vertices(
+            device,
+            &context.as_loader,
+            &command_pool,
+            context.queue,
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            &[0, 1, 2],
+            &context.limits,
+            context.device_memory_properties,
+        )
+        .unwrap();
+
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        let clone = blas
+            .clone_gpu(
+                device,
+                &context.as_loader,
+                command_buffer,
+                context.device_memory_properties,
+            )
+            .unwrap();
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+
+        assert_ne!(clone.device_address, 0);
+        assert_ne!(clone.device_address, blas.device_address);
+        assert_ne!(clone.acceleration_structure, blas.acceleration_structure);
+        assert_eq!(clone.size, blas.size);
+        assert_eq!(clone.primitive_counts, blas.primitive_counts);
+
+        unsafe {
+            clone.destroy(device, &context.as_loader);
+            blas.destroy(device, &context.as_loader);
+            mesh.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+
+    #[test]
+    fn geometry_from_buffers_without_device_address_usage_is_rejected() {
+        use crate::test_support::test_context;
+
+        let Some(context) =
+            test_context("geometry_from_buffers_without_device_address_usage_is_rejected")
+        else {
+            return;
+        };
+        let device = &context.device;
+        let create = |usage| {
+            BufferResource::new(
+                256,
+                usage,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                device,
+                context.device_memory_properties,
+            )
+        };
+        let complete = create(TriangleGeometry::REQUIRED_USAGE);
+        let missing_address =
+            create(vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR);
+
+        match TriangleGeometry::from_buffers(&missing_address, &complete, 3, 3) {
+            Err(RtError::MissingBufferUsage {
+                buffer,
+                required,
+                actual,
+            }) => {
+                assert_eq!(buffer, missing_address.buffer);
+                assert_eq!(required, TriangleGeometry::REQUIRED_USAGE);
+                assert!(!actual.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS));
+            }
+            other => panic!("expected MissingBufferUsage, got {other:?}"),
+        }
+        assert!(matches!(
+            TriangleGeometry::from_buffers(&complete, &missing_address, 3, 3),
+            Err(RtError::MissingBufferUsage { buffer, .. }) if buffer == missing_address.buffer
+        ));
+        assert!(TriangleGeometry::from_buffers(&complete, &complete, 3, 3).is_ok());
+
+        unsafe {
+            complete.destroy(device);
+            missing_address.destroy(device);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use ash::vk;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+/// 一段已登记的设备地址区间
+#[derive(Clone, Debug)]
+struct AddressRange {
+    size: vk::DeviceSize,
+    buffer: vk::Buffer,
+    label: String,
+}
+
+/// 设备地址到 buffer 的映射，用于把 GPU 崩溃时的出错地址还原为具名 buffer 与偏移
+///
+/// 大量使用 buffer reference 时，出错地址本身几乎无法定位；
+/// 启用后 BufferResource::new 会登记每个带 SHADER_DEVICE_ADDRESS 用途的 buffer。
+#[derive(Default)]
+pub struct AddressRegistry {
+    ranges: BTreeMap<u64, AddressRange>,
+}
+
+impl AddressRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记 `[address, address + size)`，同一起始地址的旧记录会被覆盖
+    pub fn register(
+        &mut self,
+        address: u64,
+        size: vk::DeviceSize,
+        buffer: vk::Buffer,
+        label: impl Into<String>,
+    ) {
+        self.ranges.insert(
+            address,
+            AddressRange {
+                size,
+                buffer,
+                label: label.into(),
+            },
+        );
+    }
+
+    /// 移除 buffer 的记录，buffer 销毁时调用
+    pub fn unregister(&mut self, buffer: vk::Buffer) {
+        self.ranges.retain(|_, range| range.buffer != buffer);
+    }
+
+    /// 修改 buffer 的标签，返回是否找到了该 buffer
+    pub fn set_label(&mut self, buffer: vk::Buffer, label: impl Into<String>) -> bool {
+        match self
+            .ranges
+            .values_mut()
+            .find(|range| range.buffer == buffer)
+        {
+            Some(range) => {
+                range.label = label.into();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 查找包含 address 的 buffer，返回其标签与 address 在 buffer 内的偏移
+    pub fn lookup(&self, address: u64) -> Option<(&str, vk::DeviceSize)> {
+        let (&start, range) = self.ranges.range(..=address).next_back()?;
+        let offset = address - start;
+        (offset < range.size).then_some((range.label.as_str(), offset))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+static ADDRESS_REGISTRY: OnceLock<Mutex<AddressRegistry>> = OnceLock::new();
+
+/// 启用全局地址登记，仅在 debug 构建中生效，只有第一次调用生效；之后创建的 buffer 才会被登记
+pub fn enable_address_registry() {
+    if cfg!(debug_assertions) {
+        let _ = ADDRESS_REGISTRY.set(Mutex::new(AddressRegistry::new()));
+    }
+}
+
+/// 在全局登记表启用时对其执行 f
+pub fn with_address_registry<R>(f: impl FnOnce(&mut AddressRegistry) -> R) -> Option<R> {
+    let registry = ADDRESS_REGISTRY.get()?;
+    let mut registry = registry
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    Some(f(&mut registry))
+}
+
+/// 在全局登记表中解析出错地址，返回 buffer 标签与偏移
+pub fn lookup_device_address(address: u64) -> Option<(String, vk::DeviceSize)> {
+    with_address_registry(|registry| {
+        registry
+            .lookup(address)
+            .map(|(label, offset)| (label.to_owned(), offset))
+    })
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn lookup_resolves_addresses_inside_registered_ranges() {
+        let vertices = vk::Buffer::from_raw(1);
+        let indices = vk::Buffer::from_raw(2);
+        let mut registry = AddressRegistry::new();
+        registry.register(0x1000, 0x100, vertices, "vertices");
+        registry.register(0x2000, 0x40, indices, "indices");
+
+        assert_eq!(registry.lookup(0x1000), Some(("vertices", 0)));
+        assert_eq!(registry.lookup(0x10ff), Some(("vertices", 0xff)));
+        assert_eq!(registry.lookup(0x1100), None);
+        assert_eq!(registry.lookup(0x0fff), None);
+        assert_eq!(registry.lookup(0x2010), Some(("indices", 0x10)));
+    }
+
+    #[test]
+    fn set_label_and_unregister_follow_the_buffer() {
+        let buffer = vk::Buffer::from_raw(7);
+        let mut registry = AddressRegistry::new();
+        registry.register(0x4000, 0x10, buffer, "unnamed");
+
+        assert!(registry.set_label(buffer, "scene uniforms"));
+        assert!(!registry.set_label(vk::Buffer::from_raw(8), "missing"));
+        assert_eq!(registry.lookup(0x4008), Some(("scene uniforms", 8)));
+
+        registry.unregister(buffer);
+        assert!(registry.is_empty());
+        assert_eq!(registry.lookup(0x4008), None);
+    }
+}
@@ -0,0 +1,461 @@
+use ash::{Device, vk};
+use bytemuck::{Pod, Zeroable};
+
+use crate::buffer::{BufferResource, fill_buffer};
+use crate::error::RtError;
+use crate::image_utils::RenderTargetImage;
+use crate::pipeline::{create_compute_pipeline, create_shader_module};
+use crate::tonemap::{ACCUMULATION_FORMAT, ExposureBuffer};
+
+/// 自动曝光参数
+#[derive(Clone, Copy, Debug)]
+pub struct AutoExposureSettings {
+    /// 直方图覆盖的最小 log2 亮度
+    pub min_log_luminance: f32,
+    /// 直方图覆盖的最大 log2 亮度
+    pub max_log_luminance: f32,
+    /// 适应速度，越大越快收敛到目标曝光
+    pub adaptation_speed: f32,
+    /// 平均亮度映射到的中间灰
+    pub key_value: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        Self {
+            min_log_luminance: -10.0,
+            max_log_luminance: 4.0,
+            adaptation_speed: 1.5,
+            key_value: 0.18,
+        }
+    }
+}
+
+/// 两个自动曝光着色器共用的 push constant，布局与着色器中的 AutoExposureConstants 一致
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct AutoExposureConstants {
+    pub min_log_luminance: f32,
+    pub log_luminance_range: f32,
+    pub sample_scale: f32,
+    pub delta_time: f32,
+    pub adaptation_speed: f32,
+    pub key_value: f32,
+    pub pixel_count: u32,
+    pub padding: u32,
+}
+
+/// 基于亮度直方图的自动曝光（shaders/luminance_histogram.comp 与 shaders/auto_exposure_adapt.comp）
+///
+/// 每帧先统计累积图像的对数亮度直方图，再由直方图求平均亮度并平滑地更新 exposure，
+/// exposure 缓冲直接传给 TonemapPass::write_descriptor_set 使用。
+pub struct AutoExposure {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub histogram_pipeline: vk::Pipeline,
+    pub adapt_pipeline: vk::Pipeline,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    pub histogram: BufferResource,
+    pub exposure: ExposureBuffer,
+    pub settings: AutoExposureSettings,
+    extent: vk::Extent2D,
+}
+
+impl AutoExposure {
+    /// 直方图的 bin 数，与着色器中的 BIN_COUNT 一致
+    pub const BIN_COUNT: u32 = 256;
+
+    const HISTOGRAM_WORKGROUP_SIZE: u32 = 16;
+
+    /// accumulation 需为 R32G32B32A32_SFLOAT（否则返回 RtError::UnsupportedFormat）且在 update 时处于 GENERAL 布局
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        histogram_shader_code: &[u32],
+        adapt_shader_code: &[u32],
+        cache: vk::PipelineCache,
+        accumulation: &RenderTargetImage,
+        settings: AutoExposureSettings,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        if accumulation.format != ACCUMULATION_FORMAT {
+            return Err(RtError::UnsupportedFormat(accumulation.format));
+        }
+
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = [
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(binding, descriptor_type)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding as u32)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        })
+        .collect();
+
+        let descriptor_set_layout = unsafe {
+            device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+                None,
+            )
+        }?;
+
+        let set_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(std::mem::size_of::<AutoExposureConstants>() as u32)];
+        let pipeline_layout = unsafe {
+            device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&set_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )
+        }?;
+
+        let create_pipeline = |code: &[u32]| -> Result<vk::Pipeline, vk::Result> {
+            let shader_module = create_shader_module(device, code)?;
+            let pipeline =
+                create_compute_pipeline(device, pipeline_layout, shader_module, cache, &[]);
+            unsafe { device.destroy_shader_module(shader_module, None) };
+            pipeline
+        };
+        let histogram_pipeline = create_pipeline(histogram_shader_code)?;
+        let adapt_pipeline = create_pipeline(adapt_shader_code)?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(2),
+        ];
+        let descriptor_pool = unsafe {
+            device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )
+        }?;
+
+        let descriptor_set = unsafe {
+            device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&set_layouts),
+            )
+        }?[0];
+
+        let histogram = BufferResource::new(
+            Self::BIN_COUNT as vk::DeviceSize * std::mem::size_of::<u32>() as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            device_memory_properties,
+        );
+        let exposure = ExposureBuffer::new(device, 1.0, device_memory_properties);
+
+        let image_info = [vk::DescriptorImageInfo::default()
+            .image_view(accumulation.view)
+            .image_layout(vk::ImageLayout::GENERAL)];
+        let histogram_info = [vk::DescriptorBufferInfo::default()
+            .buffer(histogram.buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)];
+        let exposure_info = [exposure.descriptor_info()];
+
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&image_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(descriptor_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&histogram_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(descriptor_set)
+                .dst_binding(2)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&exposure_info),
+        ];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        Ok(Self {
+            descriptor_set_layout,
+            pipeline_layout,
+            histogram_pipeline,
+            adapt_pipeline,
+            descriptor_pool,
+            descriptor_set,
+            histogram,
+            exposure,
+            settings,
+            extent: accumulation.extent,
+        })
+    }
+
+    fn constants(&self, n_samples: u32, delta_time: f32) -> AutoExposureConstants {
+        AutoExposureConstants {
+            min_log_luminance: self.settings.min_log_luminance,
+            log_luminance_range: self.settings.max_log_luminance - self.settings.min_log_luminance,
+            sample_scale: 1.0 / n_samples.max(1) as f32,
+            delta_time,
+            adaptation_speed: self.settings.adaptation_speed,
+            key_value: self.settings.key_value,
+            pixel_count: self.extent.width * self.extent.height,
+            padding: 0,
+        }
+    }
+
+    /// 录制一帧的自动曝光更新：清空直方图、统计亮度、更新曝光
+    ///
+    /// 调用方需保证累积图像的写入已对计算着色器可见。录制结束时已插入屏障，
+    /// 之后的 TonemapPass::record 可以直接读取新的曝光值。
+    pub fn update(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        n_samples: u32,
+        delta_time: f32,
+    ) {
+        let constants = self.constants(n_samples, delta_time);
+        let barrier = |src_stage, src_access, dst_stage, dst_access| unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::default()
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access)],
+                &[],
+                &[],
+            );
+        };
+
+        // 上一帧的直方图/适应 pass 可能仍在读写直方图，清零前要等它们完成
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[vk::BufferMemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .buffer(self.histogram.buffer)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)],
+                &[],
+            );
+        }
+        fill_buffer(
+            device,
+            command_buffer,
+            &self.histogram,
+            0,
+            vk::WHOLE_SIZE,
+            0,
+        );
+        barrier(
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(&constants),
+            );
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.histogram_pipeline,
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                self.extent.width.div_ceil(Self::HISTOGRAM_WORKGROUP_SIZE),
+                self.extent.height.div_ceil(Self::HISTOGRAM_WORKGROUP_SIZE),
+                1,
+            );
+        }
+
+        barrier(
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.adapt_pipeline,
+            );
+            device.cmd_dispatch(command_buffer, 1, 1, 1);
+        }
+
+        barrier(
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::HOST,
+            vk::AccessFlags::UNIFORM_READ | vk::AccessFlags::HOST_READ,
+        );
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            self.exposure.destroy(device);
+            self.histogram.destroy(device);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_pipeline(self.adapt_pipeline, None);
+            device.destroy_pipeline(self.histogram_pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::{RenderTargetUsage, transition_image_to_general};
+    use crate::test_support::{TestContext, test_context};
+
+    fn read_shader(name: &str) -> Option<Vec<u32>> {
+        let path = format!("{}/shaders/{}.spv", env!("CARGO_MANIFEST_DIR"), name);
+        let Ok(spv) = std::fs::read(&path) else {
+            eprintln!("skipping: {} not compiled", path);
+            return None;
+        };
+        Some(ash::util::read_spv(&mut std::io::Cursor::new(spv)).unwrap())
+    }
+
+    /// 把累积图像清成常量 radiance 后跑一帧自动曝光，返回得到的曝光值
+    fn exposure_for_constant_input(
+        context: &TestContext,
+        histogram_code: &[u32],
+        adapt_code: &[u32],
+        radiance: f32,
+    ) -> f32 {
+        let device = &context.device;
+        let properties = context.device_memory_properties;
+        let command_pool = context.command_pool();
+        let mut accumulation = RenderTargetImage::new(
+            device,
+            32,
+            32,
+            ACCUMULATION_FORMAT,
+            RenderTargetUsage::Accumulation,
+            properties,
+        )
+        .unwrap();
+        transition_image_to_general(device, command_pool.pool, context.queue, &mut accumulation)
+            .unwrap();
+        let auto_exposure = AutoExposure::new(
+            device,
+            histogram_code,
+            adapt_code,
+            vk::PipelineCache::null(),
+            &accumulation,
+            AutoExposureSettings::default(),
+            properties,
+        )
+        .unwrap();
+
+        let range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1);
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        unsafe {
+            device.cmd_clear_color_image(
+                command_buffer,
+                accumulation.image,
+                vk::ImageLayout::GENERAL,
+                &vk::ClearColorValue {
+                    float32: [radiance, radiance, radiance, 1.0],
+                },
+                &[range],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                    .old_layout(vk::ImageLayout::GENERAL)
+                    .new_layout(vk::ImageLayout::GENERAL)
+                    .image(accumulation.image)
+                    .subresource_range(range)],
+            );
+        }
+        auto_exposure.update(device, command_buffer, 1, 1.0 / 60.0);
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+        let state = auto_exposure.exposure.read(device).unwrap();
+
+        unsafe {
+            auto_exposure.destroy(device);
+            accumulation.destroy(device);
+            command_pool.destroy(device);
+        }
+        state.exposure
+    }
+
+    #[test]
+    fn bright_input_gets_a_lower_exposure_than_dark_input() {
+        let Some(context) = test_context("bright_input_gets_a_lower_exposure_than_dark_input")
+        else {
+            return;
+        };
+        let (Some(histogram_code), Some(adapt_code)) = (
+            read_shader("luminance_histogram.comp"),
+            read_shader("auto_exposure_adapt.comp"),
+        ) else {
+            return;
+        };
+
+        let dark = exposure_for_constant_input(&context, &histogram_code, &adapt_code, 0.02);
+        let bright = exposure_for_constant_input(&context, &histogram_code, &adapt_code, 4.0);
+        assert!(dark.is_finite() && bright.is_finite());
+        assert!(
+            bright < dark,
+            "bright exposure {} should be below dark exposure {}",
+            bright,
+            dark
+        );
+    }
+}
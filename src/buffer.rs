@@ -1,20 +1,96 @@
 use ash::util::Align;
 use ash::{vk, Device};
+use std::sync::{Arc, Mutex};
+
+use crate::address_registry::with_address_registry;
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
 
 #[derive(Clone)]
 pub struct BufferResource {
     pub buffer: vk::Buffer,
     pub memory: vk::DeviceMemory,
     pub size: vk::DeviceSize,
+    pub usage: vk::BufferUsageFlags,
+    /// 实际分配的内存大小，非 HOST_COHERENT 的 host 可见内存会向上取整到 nonCoherentAtomSize 的倍数
+    pub allocation_size: vk::DeviceSize,
+    /// flush/invalidate 区间取整使用的 nonCoherentAtomSize
+    pub non_coherent_atom_size: vk::DeviceSize,
+    /// 所选内存类型是否带有 HOST_COHERENT，为 false 时写入后需要 flush、读取前需要 invalidate
+    pub host_coherent: bool,
+    /// 串行化 store_from_thread 对 memory 的映射，clone 出的句柄共享同一把锁
+    map_lock: Arc<Mutex<()>>,
+}
+
+/// Vulkan 规范允许的 nonCoherentAtomSize 上限
+///
+/// 实际值总是不超过它的 2 的幂，拿不到设备 limits 时按它取整对任何设备都满足 flush/invalidate 的对齐要求。
+pub const MAX_NON_COHERENT_ATOM_SIZE: vk::DeviceSize = 256;
+
+/// 查询设备的 nonCoherentAtomSize，传给 BufferResource::new_with_atom_size
+pub fn non_coherent_atom_size(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> vk::DeviceSize {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    properties.limits.non_coherent_atom_size
+}
+
+/// 计算实际分配的内存大小
+///
+/// 只有非 HOST_COHERENT 的 host 可见内存需要向上取整到 max(alignment, atom_size)，
+/// 使 flush/invalidate 的区间总能按 atom 取整而不越过分配末尾；其他内存直接使用 required_size。
+pub fn host_allocation_size(
+    required_size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    non_coherent_atom_size: vk::DeviceSize,
+    memory_flags: vk::MemoryPropertyFlags,
+) -> vk::DeviceSize {
+    let non_coherent = memory_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        && !memory_flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT);
+    if !non_coherent {
+        return required_size;
+    }
+    let allocation_size =
+        required_size.next_multiple_of(alignment.max(non_coherent_atom_size).max(1));
+    debug_assert!(
+        allocation_size.is_multiple_of(alignment.max(1)),
+        "allocation size {} does not honor the required alignment {}",
+        allocation_size,
+        alignment
+    );
+    allocation_size
 }
 
 impl BufferResource {
+    /// 以 MAX_NON_COHERENT_ATOM_SIZE 作为 nonCoherentAtomSize 创建 buffer
+    ///
+    /// 能拿到设备 limits 时应使用 new_with_atom_size 传入真实值，避免非 coherent 内存多分配。
     pub fn new(
         size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
         memory_properties: vk::MemoryPropertyFlags,
         device: &Device,
         device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Self {
+        Self::new_with_atom_size(
+            size,
+            usage,
+            memory_properties,
+            MAX_NON_COHERENT_ATOM_SIZE,
+            device,
+            device_memory_properties,
+        )
+    }
+
+    /// non_coherent_atom_size 来自 non_coherent_atom_size(instance, physical_device)
+    pub fn new_with_atom_size(
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        memory_properties: vk::MemoryPropertyFlags,
+        non_coherent_atom_size: vk::DeviceSize,
+        device: &Device,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
     ) -> Self {
         unsafe {
             let buffer_info = vk::BufferCreateInfo::default()
@@ -42,48 +118,231 @@ impl BufferResource {
                     allocate_info_default.push_next(&mut memory_allocate_flags_info);
             }
 
+            // 按实际选中的内存类型判断，请求 HOST_VISIBLE 也可能落在 coherent 的类型上
+            let memory_type_flags =
+                device_memory_properties.memory_types[memory_index as usize].property_flags;
+            let allocation_size = host_allocation_size(
+                memory_req.size,
+                memory_req.alignment,
+                non_coherent_atom_size,
+                memory_type_flags,
+            );
+
             let allocate_info = allocate_info_default
-                .allocation_size(memory_req.size)
+                .allocation_size(allocation_size)
                 .memory_type_index(memory_index);
 
             let memory = device.allocate_memory(&allocate_info, None).unwrap();
 
+            let host_coherent = memory_type_flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT);
+
             device.bind_buffer_memory(buffer, memory, 0).unwrap();
 
-            BufferResource {
+            let resource = BufferResource {
                 buffer,
                 memory,
                 size,
+                usage,
+                allocation_size,
+                non_coherent_atom_size,
+                host_coherent,
+                map_lock: Arc::default(),
+            };
+            if usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS) {
+                with_address_registry(|registry| {
+                    registry.register(
+                        resource.device_address(device),
+                        size,
+                        buffer,
+                        format!("{:?} {:?}", buffer, usage),
+                    )
+                });
             }
+            resource
         }
     }
 
+    /// 在地址登记表（enable_address_registry）中为该 buffer 设置便于识别的标签
+    pub fn set_debug_label(&self, label: impl Into<String>) {
+        with_address_registry(|registry| registry.set_label(self.buffer, label));
+    }
+
+    /// 通过 staging buffer 把 data 上传到新建的 DEVICE_LOCAL buffer
+    ///
+    /// usage 会自动加上 TRANSFER_DST，函数返回时拷贝已经完成
+    pub fn new_device_local<T: Copy>(
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+        device: &Device,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+    ) -> Result<Self, RtError> {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+
+        let mut staging_buffer = BufferResource::new(
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            device_memory_properties,
+        );
+        staging_buffer.store(data, device);
+
+        let buffer = BufferResource::new(
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            device_memory_properties,
+        );
+
+        let command_buffer = command_pool.begin_one_time(device)?;
+        copy_buffer(device, command_buffer, &staging_buffer, &buffer, size);
+        command_pool.end_one_time(device, queue, command_buffer)?;
+
+        unsafe { staging_buffer.destroy(device) };
+
+        Ok(buffer)
+    }
+
     pub fn store<T: Copy>(&mut self, data: &[T], device: &Device) {
+        self.store_from_thread(data, 0, device);
+    }
+
+    /// 只需 `&self` 的写入接口，可在工作线程中调用
+    ///
+    /// 仅映射 `[offset, offset + size)` 区间。`BufferResource` 只保存句柄，
+    /// 本身是 `Send + Sync` 的，不同的 `BufferResource` 可以在多个线程上并行写入。
+    /// Vulkan 要求对同一块 `vk::DeviceMemory` 的 map/unmap 外部同步，
+    /// 因此映射期间持有 buffer 自带的锁，同一个 buffer 的并发写入会被串行化。
+    /// 直接调用 map/unmap 的代码不经过这把锁，不能与本函数并发使用同一个 buffer。
+    pub fn store_from_thread<T: Copy>(&self, data: &[T], offset: vk::DeviceSize, device: &Device) {
+        let _mapping = self
+            .map_lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
         unsafe {
-            let size = (std::mem::size_of::<T>() * data.len()) as u64;
-            assert!(self.size >= size);
-            let mapped_ptr = self.map(size, device);
+            let size = std::mem::size_of_val(data) as u64;
+            assert!(self.size >= offset + size);
+            // 按 atom 对齐映射，保证之后 flush 的区间落在映射范围内
+            let (map_offset, map_size) = self.atom_aligned_range(offset, size);
+            let mapped_ptr = self
+                .map(map_offset, map_size, device)
+                .byte_add((offset - map_offset) as usize);
             let mut mapped_slice = Align::new(mapped_ptr, std::mem::align_of::<T>() as u64, size);
-            mapped_slice.copy_from_slice(&data);
+            mapped_slice.copy_from_slice(data);
+            self.flush(device, offset, size).unwrap();
             self.unmap(device);
         }
     }
 
-    fn map(&mut self, size: vk::DeviceSize, device: &Device) -> *mut std::ffi::c_void {
+    /// 映射 `[offset, offset + size)`，同一块内存同时只能有一个映射
+    pub fn map(
+        &self,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        device: &Device,
+    ) -> *mut std::ffi::c_void {
         unsafe {
             device
-                .map_memory(self.memory, 0, size, vk::MemoryMapFlags::empty())
+                .map_memory(self.memory, offset, size, vk::MemoryMapFlags::empty())
                 .unwrap()
         }
     }
 
-    fn unmap(&mut self, device: &Device) {
+    pub fn unmap(&self, device: &Device) {
         unsafe {
             device.unmap_memory(self.memory);
         }
     }
 
+    /// 把 host 写入刷新到设备可见，内存为 HOST_COHERENT 时不做任何事
+    ///
+    /// 区间会向外取整到 nonCoherentAtomSize，取整后的区间必须处于映射状态。
+    pub fn flush(
+        &self,
+        device: &Device,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Result<(), vk::Result> {
+        if self.host_coherent {
+            return Ok(());
+        }
+        let (offset, size) = self.atom_aligned_range(offset, size);
+        let range = vk::MappedMemoryRange::default()
+            .memory(self.memory)
+            .offset(offset)
+            .size(size);
+        unsafe { device.flush_mapped_memory_ranges(&[range]) }
+    }
+
+    /// 使设备写入对 host 可见，内存为 HOST_COHERENT 时不做任何事
+    ///
+    /// 区间会向外取整到 nonCoherentAtomSize，取整后的区间必须处于映射状态。
+    pub fn invalidate(
+        &self,
+        device: &Device,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Result<(), vk::Result> {
+        if self.host_coherent {
+            return Ok(());
+        }
+        let (offset, size) = self.atom_aligned_range(offset, size);
+        let range = vk::MappedMemoryRange::default()
+            .memory(self.memory)
+            .offset(offset)
+            .size(size);
+        unsafe { device.invalidate_mapped_memory_ranges(&[range]) }
+    }
+
+    /// 把区间向外取整到 non_coherent_atom_size，并限制在分配范围内
+    fn atom_aligned_range(
+        &self,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> (vk::DeviceSize, vk::DeviceSize) {
+        let atom = self.non_coherent_atom_size.max(1);
+        let begin = offset - offset % atom;
+        let end = (offset + size)
+            .next_multiple_of(atom)
+            .min(self.allocation_size);
+        (begin, end - begin)
+    }
+
+    /// 检查 buffer 创建时带有 required 中的全部用途
+    pub fn require_usage(&self, required: vk::BufferUsageFlags) -> Result<(), RtError> {
+        if self.usage.contains(required) {
+            Ok(())
+        } else {
+            Err(RtError::MissingBufferUsage {
+                buffer: self.buffer,
+                required,
+                actual: self.usage,
+            })
+        }
+    }
+
+    /// 获取设备地址，缺少 SHADER_DEVICE_ADDRESS 用途时返回错误而不是得到无效的 0 地址
+    pub fn checked_device_address(&self, device: &Device) -> Result<u64, RtError> {
+        self.require_usage(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)?;
+        Ok(unsafe { get_buffer_device_address(device, self.buffer) })
+    }
+
+    /// 获取 buffer 的设备地址，创建时必须带有 SHADER_DEVICE_ADDRESS 用途
+    pub fn device_address(&self, device: &Device) -> u64 {
+        debug_assert!(
+            self.usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS),
+            "buffer {:?} was created without SHADER_DEVICE_ADDRESS usage ({:?})",
+            self.buffer,
+            self.usage
+        );
+        unsafe { get_buffer_device_address(device, self.buffer) }
+    }
+
     pub unsafe fn destroy(self, device: &Device) {
+        with_address_registry(|registry| registry.unregister(self.buffer));
         unsafe {
             device.destroy_buffer(self.buffer, None);
             device.free_memory(self.memory, None);
@@ -91,6 +350,63 @@ impl BufferResource {
     }
 }
 
+/// 录制从 src 开头到 dst 开头拷贝 size 字节的命令
+///
+/// src 需要 TRANSFER_SRC 用途，dst 需要 TRANSFER_DST 用途
+pub fn copy_buffer(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    src: &BufferResource,
+    dst: &BufferResource,
+    size: vk::DeviceSize,
+) {
+    assert!(
+        size <= src.size && size <= dst.size,
+        "copy of {} bytes exceeds buffer bounds (src {}, dst {})",
+        size,
+        src.size,
+        dst.size
+    );
+    unsafe {
+        device.cmd_copy_buffer(
+            command_buffer,
+            src.buffer,
+            dst.buffer,
+            &[vk::BufferCopy::default().size(size)],
+        );
+    }
+}
+
+/// 录制用重复的 32 位值 data 填充 `[offset, offset + size)` 的命令
+///
+/// offset 与 size 必须是 4 的倍数，size 可为 vk::WHOLE_SIZE 表示填充到末尾
+pub fn fill_buffer(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    dst: &BufferResource,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    data: u32,
+) {
+    assert!(
+        offset.is_multiple_of(4) && (size == vk::WHOLE_SIZE || size.is_multiple_of(4)),
+        "fill offset {} and size {} must be multiples of 4",
+        offset,
+        size
+    );
+    assert!(
+        offset < dst.size && (size == vk::WHOLE_SIZE || offset + size <= dst.size),
+        "fill of [{}, {} + {}) exceeds buffer size {}",
+        offset,
+        offset,
+        size,
+        dst.size
+    );
+    unsafe {
+        device.cmd_fill_buffer(command_buffer, dst.buffer, offset, size, data);
+    }
+}
+
 pub fn get_memory_type_index(
     device_memory_properties: vk::PhysicalDeviceMemoryProperties,
     mut type_bits: u32,
@@ -117,4 +433,140 @@ pub unsafe fn get_buffer_device_address(device: &Device, buffer: vk::Buffer) ->
         let buffer_device_address_info = vk::BufferDeviceAddressInfo::default().buffer(buffer);
         device.get_buffer_device_address(&buffer_device_address_info)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acceleration_structure::BottomLevelAS;
+    use crate::test_support::test_context;
+
+    const HOST_NON_COHERENT: vk::MemoryPropertyFlags = vk::MemoryPropertyFlags::HOST_VISIBLE;
+    const HOST_COHERENT: vk::MemoryPropertyFlags = vk::MemoryPropertyFlags::from_raw(
+        vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw()
+            | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw(),
+    );
+
+    #[test]
+    fn one_byte_non_coherent_allocation_rounds_to_atom_size() {
+        assert_eq!(host_allocation_size(1, 4, 64, HOST_NON_COHERENT), 64);
+        assert_eq!(host_allocation_size(65, 4, 64, HOST_NON_COHERENT), 128);
+        // alignment 大于 atom 时取 alignment
+        assert_eq!(host_allocation_size(1, 256, 64, HOST_NON_COHERENT), 256);
+    }
+
+    #[test]
+    fn coherent_and_device_local_allocations_are_not_rounded() {
+        assert_eq!(host_allocation_size(4, 4, 64, HOST_COHERENT), 4);
+        assert_eq!(
+            host_allocation_size(100, 4, 64, vk::MemoryPropertyFlags::DEVICE_LOCAL),
+            100
+        );
+    }
+
+    #[test]
+    fn two_threads_store_and_build_blas() {
+        let Some(context) = test_context("two_threads_store_and_build_blas") else {
+            return;
+        };
+        let device = &context.device;
+
+        let shared = BufferResource::new(
+            2 * 1024 * size_of::<u32>() as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            context.device_memory_properties,
+        );
+        // vkQueueSubmit 要求队列外部同步
+        let queue = Mutex::new(context.queue);
+
+        std::thread::scope(|scope| {
+            for thread in 0..2u32 {
+                let (context, shared, queue) = (&context, &shared, &queue);
+                scope.spawn(move || {
+                    let device = &context.device;
+                    let command_pool = context.command_pool();
+
+                    // 两个线程交替写同一个 buffer 的不同区间，映射由 map_lock 串行化
+                    let values = vec![thread; 1024];
+                    for _ in 0..64 {
+                        shared.store_from_thread(
+                            &values,
+                            thread as vk::DeviceSize * 1024 * size_of::<u32>() as vk::DeviceSize,
+                            device,
+                        );
+                    }
+
+                    let offset = thread as f32;
+                    let positions = [
+                        [offset, 0.0, 0.0],
+                        [offset + 1.0, 0.0, 0.0],
+                        [offset, 1.0, 0.0],
+                    ];
+                    let queue = queue.lock().unwrap();
+                    let (blas, mesh) = BottomLevelAS::from_vertices(
+                        device,
+                        &context.as_loader,
+                        &command_pool,
+                        *queue,
+                        &positions,
+                        &[0, 1, 2],
+                        &context.limits,
+                        context.device_memory_properties,
+                    )
+                    .expect("BLAS build failed");
+                    drop(queue);
+                    assert_ne!(blas.device_address, 0);
+
+                    unsafe {
+                        blas.destroy(device, &context.as_loader);
+                        mesh.destroy(device);
+                        command_pool.destroy(device);
+                    }
+                });
+            }
+        });
+
+        let mapped = shared.map(0, vk::WHOLE_SIZE, device) as *const u32;
+        let contents = unsafe { std::slice::from_raw_parts(mapped, 2 * 1024) };
+        assert!(contents[..1024].iter().all(|&value| value == 0));
+        assert!(contents[1024..].iter().all(|&value| value == 1));
+        shared.unmap(device);
+        unsafe { shared.destroy(device) };
+    }
+
+    #[test]
+    fn filled_buffer_reads_back_the_repeated_pattern() {
+        let Some(context) = test_context("filled_buffer_reads_back_the_repeated_pattern") else {
+            return;
+        };
+        let device = &context.device;
+        let buffer = BufferResource::new(
+            64 * size_of::<u32>() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            HOST_COHERENT,
+            device,
+            context.device_memory_properties,
+        );
+        buffer.store_from_thread(&[0u32; 64], 0, device);
+
+        let command_pool = context.command_pool();
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        fill_buffer(device, command_buffer, &buffer, 16, 32 * 4, 0xDEADBEEF);
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+
+        let mapped = buffer.map(0, vk::WHOLE_SIZE, device) as *const u32;
+        let contents = unsafe { std::slice::from_raw_parts(mapped, 64) };
+        assert!(contents[..4].iter().all(|&value| value == 0));
+        assert!(contents[4..36].iter().all(|&value| value == 0xDEADBEEF));
+        assert!(contents[36..].iter().all(|&value| value == 0));
+        buffer.unmap(device);
+        unsafe {
+            buffer.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
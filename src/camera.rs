@@ -0,0 +1,249 @@
+use crate::jitter::lens_sample;
+
+/// 第一人称相机，yaw/pitch 为弧度，yaw = 0 时朝向 -Z，Y 轴向上
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    /// 垂直视场角（度）
+    pub vertical_fov: f32,
+    /// 薄透镜光圈半径（世界单位），0 表示针孔相机，没有景深
+    pub aperture_radius: f32,
+    /// 对焦距离：沿视线方向到清晰平面的距离（世界单位）
+    pub focus_distance: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0, 3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            vertical_fov: 60.0,
+            aperture_radius: 0.0,
+            focus_distance: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    /// 启用景深，focus_distance 会被限制为正数
+    pub fn with_dof(mut self, aperture_radius: f32, focus_distance: f32) -> Self {
+        self.aperture_radius = aperture_radius.max(0.0);
+        self.focus_distance = focus_distance.max(f32::EPSILON);
+        self
+    }
+
+    /// 第 index 次采样的镜头偏移（right/up 分量），针孔相机时恒为 [0, 0]
+    pub fn lens_sample(&self, index: u32) -> [f32; 2] {
+        lens_sample(index, self.aperture_radius)
+    }
+
+    /// 薄透镜扰动：把针孔光线方向 pinhole_direction 转换为从镜头上 lens_offset 处出发的光线
+    ///
+    /// raygen 着色器应做同样的变换：先求针孔光线与对焦平面（沿 forward 距离 focus_distance）的交点，
+    /// 再把原点移到 position + right * u + up * v，方向指向该交点。对焦平面上的点在所有镜头采样下不动，
+    /// 其余位置随光圈大小变模糊。返回 (origin, direction)，direction 已归一化。
+    pub fn thin_lens_ray(
+        &self,
+        pinhole_direction: [f32; 3],
+        lens_offset: [f32; 2],
+    ) -> ([f32; 3], [f32; 3]) {
+        let forward = self.forward();
+        let right = self.right();
+        let up = cross(right, forward);
+
+        let along_forward = dot(pinhole_direction, forward).max(f32::EPSILON);
+        let t = self.focus_distance / along_forward;
+        let mut origin = self.position;
+        let mut direction = [0.0; 3];
+        for i in 0..3 {
+            let focus_point = self.position[i] + pinhole_direction[i] * t;
+            origin[i] += right[i] * lens_offset[0] + up[i] * lens_offset[1];
+            direction[i] = focus_point - origin[i];
+        }
+        (origin, normalize(direction))
+    }
+
+    /// 视线方向（单位向量）
+    pub fn forward(&self) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        [sin_yaw * cos_pitch, sin_pitch, -cos_yaw * cos_pitch]
+    }
+
+    /// 水平面内的右方向（单位向量）
+    pub fn right(&self) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        [cos_yaw, 0.0, sin_yaw]
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    if length > 0.0 {
+        [v[0] / length, v[1] / length, v[2] / length]
+    } else {
+        v
+    }
+}
+
+/// 一帧内的输入状态，由窗口层（GLFW）填写，相机模块本身不依赖窗口库
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CameraInput {
+    pub forward: bool,
+    pub backward: bool,
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    /// 本帧鼠标移动量（像素），只在按住视角键时填写
+    pub look_delta: [f32; 2],
+}
+
+/// WASD 移动 + 鼠标视角的相机控制器
+#[derive(Clone, Copy, Debug)]
+pub struct CameraController {
+    /// 移动速度（单位/秒）
+    pub move_speed: f32,
+    /// 视角灵敏度（弧度/像素）
+    pub look_sensitivity: f32,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            move_speed: 2.0,
+            look_sensitivity: 0.003,
+        }
+    }
+}
+
+impl CameraController {
+    /// 按 delta_time（秒）更新相机，相机发生变化时返回 true，调用方据此重置累积
+    pub fn update(&self, camera: &mut Camera, input: &CameraInput, delta_time: f32) -> bool {
+        let before = *camera;
+
+        camera.yaw += input.look_delta[0] * self.look_sensitivity;
+        camera.pitch = (camera.pitch - input.look_delta[1] * self.look_sensitivity)
+            .clamp(-89.0_f32.to_radians(), 89.0_f32.to_radians());
+
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        let forward_amount = axis(input.forward, input.backward);
+        let right_amount = axis(input.right, input.left);
+        let up_amount = axis(input.up, input.down);
+
+        let forward = camera.forward();
+        let right = camera.right();
+        let distance = self.move_speed * delta_time;
+        for i in 0..3 {
+            camera.position[i] +=
+                (forward[i] * forward_amount + right[i] * right_amount) * distance;
+        }
+        camera.position[1] += up_amount * distance;
+
+        *camera != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for i in 0..3 {
+            assert!(
+                (actual[i] - expected[i]).abs() < 1e-5,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn controller_moves_the_camera_frame_by_frame() {
+        let controller = CameraController::default();
+        let mut camera = Camera::default();
+        let forward = CameraInput {
+            forward: true,
+            ..Default::default()
+        };
+
+        // 速度 2.0，每帧 0.5 秒，朝 -Z 每帧前进 1
+        for frame in 1..=3 {
+            assert!(controller.update(&mut camera, &forward, 0.5));
+            assert_close(camera.position, [0.0, 0.0, 3.0 - frame as f32]);
+        }
+
+        let strafe = CameraInput {
+            right: true,
+            up: true,
+            ..Default::default()
+        };
+        assert!(controller.update(&mut camera, &strafe, 0.25));
+        assert_close(camera.position, [0.5, 0.5, 0.0]);
+
+        // 相反方向的按键互相抵消，相机不动时不需要重置累积
+        let idle = CameraInput {
+            forward: true,
+            backward: true,
+            ..Default::default()
+        };
+        assert!(!controller.update(&mut camera, &idle, 0.5));
+        assert!(!controller.update(&mut camera, &CameraInput::default(), 0.5));
+        assert_close(camera.position, [0.5, 0.5, 0.0]);
+
+        let look = CameraInput {
+            look_delta: [100.0, 0.0],
+            ..Default::default()
+        };
+        assert!(controller.update(&mut camera, &look, 0.5));
+        assert!((camera.yaw - 0.3).abs() < 1e-6);
+        assert_close(camera.position, [0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn pinhole_camera_lens_sample_is_origin() {
+        let camera = Camera::default();
+        assert_eq!(camera.lens_sample(5), [0.0, 0.0]);
+
+        let (origin, direction) = camera.thin_lens_ray(camera.forward(), camera.lens_sample(5));
+        assert_eq!(origin, camera.position);
+        assert!(dot(direction, camera.forward()) > 1.0 - 1e-6);
+    }
+
+    #[test]
+    fn thin_lens_rays_converge_on_the_focus_plane() {
+        let camera = Camera::default().with_dof(0.5, 4.0);
+        let forward = camera.forward();
+        let focus_point = [
+            camera.position[0] + forward[0] * 4.0,
+            camera.position[1] + forward[1] * 4.0,
+            camera.position[2] + forward[2] * 4.0,
+        ];
+
+        for index in 1..8 {
+            let (origin, direction) = camera.thin_lens_ray(forward, camera.lens_sample(index));
+            let to_focus = normalize([
+                focus_point[0] - origin[0],
+                focus_point[1] - origin[1],
+                focus_point[2] - origin[2],
+            ]);
+            assert!(dot(direction, to_focus) > 1.0 - 1e-5, "sample {}", index);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! sRGB 传递函数，供 PNG 导出与自定义导出路径使用
+
+/// 线性值编码为 sRGB（IEC 61966-2-1 分段曲线）
+///
+/// 暗部使用线性段，比 `x.powf(1.0 / 2.2)` 的近似更准确；负值按 0 处理，
+/// 超过 1 的值按同一曲线延伸，由调用方负责截断。
+pub fn linear_to_srgb(x: f32) -> f32 {
+    let x = x.max(0.0);
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// sRGB 编码值解码为线性值，linear_to_srgb 的逆变换
+pub fn srgb_to_linear(x: f32) -> f32 {
+    let x = x.max(0.0);
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn srgb_curve_matches_reference_values() {
+        assert_close(linear_to_srgb(0.0), 0.0);
+        assert_close(linear_to_srgb(0.002), 0.02584);
+        assert_close(linear_to_srgb(0.18), 0.46135);
+        assert_close(linear_to_srgb(0.5), 0.73536);
+        assert_close(linear_to_srgb(1.0), 1.0);
+        assert_close(linear_to_srgb(-1.0), 0.0);
+
+        assert_close(srgb_to_linear(0.02), 0.001548);
+        assert_close(srgb_to_linear(0.5), 0.21404);
+        assert_close(srgb_to_linear(1.0), 1.0);
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        for i in 0..=100 {
+            let x = i as f32 / 100.0;
+            assert_close(srgb_to_linear(linear_to_srgb(x)), x);
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use ash::prelude::VkResult;
+use ash::{Device, ext, vk};
+
+use crate::error::RtError;
+use crate::vulkan_base::query_device_fault;
+
+/// 单个队列族的命令池，多线程录制时每个线程持有一个
+pub struct CommandPoolManager {
+    pub pool: vk::CommandPool,
+    pub queue_family_index: u32,
+    /// 设置后，提交时遇到设备丢失会打印 VK_EXT_device_fault 的诊断信息
+    pub fault_loader: Option<ext::device_fault::Device>,
+}
+
+impl CommandPoolManager {
+    pub fn new(device: &Device, queue_family_index: u32) -> Result<Self, vk::Result> {
+        let pool_create_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(queue_family_index);
+
+        let pool = unsafe { device.create_command_pool(&pool_create_info, None) }?;
+
+        Ok(Self {
+            pool,
+            queue_family_index,
+            fault_loader: None,
+        })
+    }
+
+    /// 设备以 DeviceConfig::device_fault 创建时，提交遇到设备丢失后查询并打印故障信息
+    pub fn with_device_fault(mut self, fault_loader: ext::device_fault::Device) -> Self {
+        self.fault_loader = Some(fault_loader);
+        self
+    }
+
+    /// 分配一个一次性提交的主命令缓冲并开始录制
+    pub fn begin_one_time(&self, device: &Device) -> Result<vk::CommandBuffer, vk::Result> {
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_buffer_count(1)
+            .command_pool(self.pool)
+            .level(vk::CommandBufferLevel::PRIMARY);
+
+        let command_buffer = unsafe { device.allocate_command_buffers(&allocate_info) }?[0];
+
+        unsafe {
+            device.begin_command_buffer(
+                command_buffer,
+                &vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            )
+        }?;
+
+        Ok(command_buffer)
+    }
+
+    /// 结束录制、提交并等待队列空闲，然后释放命令缓冲
+    ///
+    /// 提交失败时同样释放命令缓冲；设备丢失返回 RtError::DeviceLost。
+    pub fn end_one_time(
+        &self,
+        device: &Device,
+        queue: vk::Queue,
+        command_buffer: vk::CommandBuffer,
+    ) -> Result<(), RtError> {
+        submit_one_time(
+            device,
+            self.pool,
+            queue,
+            command_buffer,
+            self.fault_loader.as_ref(),
+        )
+    }
+
+    /// 分配并录制一个二级命令缓冲，供工作线程并行录制后由主命令缓冲执行
+    ///
+    /// 光线追踪/计算不在 render pass 内执行，inheritance_info 保持 render_pass 与 framebuffer 为空即可，
+    /// 此时也不能设置 RENDER_PASS_CONTINUE 标志。
+    pub fn record_secondary(
+        &self,
+        device: &Device,
+        inheritance_info: &vk::CommandBufferInheritanceInfo,
+        record: impl FnOnce(vk::CommandBuffer),
+    ) -> Result<vk::CommandBuffer, vk::Result> {
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_buffer_count(1)
+            .command_pool(self.pool)
+            .level(vk::CommandBufferLevel::SECONDARY);
+
+        let command_buffer = unsafe { device.allocate_command_buffers(&allocate_info) }?[0];
+
+        unsafe {
+            device.begin_command_buffer(
+                command_buffer,
+                &vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+                    .inheritance_info(inheritance_info),
+            )
+        }?;
+
+        record(command_buffer);
+
+        unsafe { device.end_command_buffer(command_buffer) }?;
+
+        Ok(command_buffer)
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            device.destroy_command_pool(self.pool, None);
+        }
+    }
+}
+
+/// 结束录制 command_buffer、提交并等待队列空闲，无论成功与否都从 command_pool 释放它
+///
+/// 不经过 CommandPoolManager 的 helper 共用这一提交路径；fault_loader 的作用见 map_submit_error。
+pub fn submit_one_time(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    command_buffer: vk::CommandBuffer,
+    fault_loader: Option<&ext::device_fault::Device>,
+) -> Result<(), RtError> {
+    let command_buffers = [command_buffer];
+    submit_then_free(
+        || unsafe {
+            device.end_command_buffer(command_buffer)?;
+            let submit_infos = [vk::SubmitInfo::default().command_buffers(&command_buffers)];
+            device.queue_submit(queue, &submit_infos, vk::Fence::null())?;
+            device.queue_wait_idle(queue)
+        },
+        || unsafe { device.free_command_buffers(command_pool, &command_buffers) },
+        fault_loader,
+    )
+}
+
+/// 执行 submit 后调用 free 释放命令缓冲，再把提交结果转换为 RtError
+fn submit_then_free(
+    submit: impl FnOnce() -> VkResult<()>,
+    free: impl FnOnce(),
+    fault_loader: Option<&ext::device_fault::Device>,
+) -> Result<(), RtError> {
+    let result = submit();
+    free();
+    result.map_err(|err| map_submit_error(err, fault_loader))
+}
+
+/// 把提交返回的错误转换为 RtError，ERROR_DEVICE_LOST 对应 RtError::DeviceLost
+///
+/// 设备丢失且提供了 fault_loader（设备以 DeviceConfig::device_fault 创建）时，先打印 query_device_fault 的诊断信息。
+pub fn map_submit_error(
+    result: vk::Result,
+    fault_loader: Option<&ext::device_fault::Device>,
+) -> RtError {
+    if result == vk::Result::ERROR_DEVICE_LOST {
+        if let Some(report) = fault_loader.and_then(query_device_fault) {
+            eprintln!("[Device fault] {}", report);
+        }
+    }
+    RtError::from(result)
+}
+
+/// 在主命令缓冲中执行已录制好的二级命令缓冲
+pub fn execute_secondaries(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    secondary_command_buffers: &[vk::CommandBuffer],
+) {
+    unsafe {
+        device.cmd_execute_commands(command_buffer, secondary_command_buffers);
+    }
+}
+
+/// 开始录制时 begin、离开作用域时自动 end 的命令缓冲录制守卫
+///
+/// 录制过程中通过 `?` 提前返回也不会留下未结束的命令缓冲；
+/// 正常结束时调用 `finish` 取回命令缓冲并拿到 end_command_buffer 的错误。
+pub struct CommandRecorder<'a> {
+    device: &'a Device,
+    command_buffer: vk::CommandBuffer,
+    ended: bool,
+}
+
+impl<'a> CommandRecorder<'a> {
+    pub fn begin(
+        device: &'a Device,
+        command_buffer: vk::CommandBuffer,
+        begin_info: &vk::CommandBufferBeginInfo,
+    ) -> Result<Self, vk::Result> {
+        unsafe { device.begin_command_buffer(command_buffer, begin_info) }?;
+
+        Ok(Self {
+            device,
+            command_buffer,
+            ended: false,
+        })
+    }
+
+    /// 正在录制的命令缓冲
+    pub fn command_buffer(&self) -> vk::CommandBuffer {
+        self.command_buffer
+    }
+
+    /// 结束录制并返回命令缓冲
+    pub fn finish(mut self) -> Result<vk::CommandBuffer, vk::Result> {
+        self.ended = true;
+        unsafe { self.device.end_command_buffer(self.command_buffer) }?;
+        Ok(self.command_buffer)
+    }
+}
+
+impl Drop for CommandRecorder<'_> {
+    fn drop(&mut self) {
+        if !self.ended {
+            let _ = unsafe { self.device.end_command_buffer(self.command_buffer) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{BufferResource, fill_buffer};
+    use crate::test_support::test_context;
+
+    #[test]
+    fn lost_device_on_submit_frees_the_command_buffer_and_maps_the_error() {
+        let mut freed = false;
+        let result = submit_then_free(|| Err(vk::Result::ERROR_DEVICE_LOST), || freed = true, None);
+        assert!(freed);
+        assert!(matches!(result, Err(RtError::DeviceLost)));
+
+        let result = submit_then_free(|| Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY), || {}, None);
+        assert!(matches!(
+            result,
+            Err(RtError::Vulkan(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY))
+        ));
+        assert!(submit_then_free(|| Ok(()), || {}, None).is_ok());
+    }
+
+    #[test]
+    fn secondaries_recorded_on_two_threads_execute_in_a_primary() {
+        let Some(context) =
+            test_context("secondaries_recorded_on_two_threads_execute_in_a_primary")
+        else {
+            return;
+        };
+        let device = &context.device;
+
+        const HALF: vk::DeviceSize = 256;
+        let target = BufferResource::new(
+            2 * HALF,
+            vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            context.device_memory_properties,
+        );
+
+        // 命令池不能跨线程共享，每个工作线程使用自己的池
+        let worker_pools = [context.command_pool(), context.command_pool()];
+        let inheritance_info = vk::CommandBufferInheritanceInfo::default();
+        let secondaries: Vec<vk::CommandBuffer> = std::thread::scope(|scope| {
+            let workers: Vec<_> = worker_pools
+                .iter()
+                .enumerate()
+                .map(|(index, pool)| {
+                    let target = &target;
+                    let inheritance_info = &inheritance_info;
+                    scope.spawn(move || {
+                        pool.record_secondary(device, inheritance_info, |command_buffer| {
+                            fill_buffer(
+                                device,
+                                command_buffer,
+                                target,
+                                index as vk::DeviceSize * HALF,
+                                HALF,
+                                index as u32 + 1,
+                            );
+                        })
+                        .unwrap()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        let command_pool = context.command_pool();
+        let primary = command_pool.begin_one_time(device).unwrap();
+        execute_secondaries(device, primary, &secondaries);
+        command_pool
+            .end_one_time(device, context.queue, primary)
+            .unwrap();
+
+        let values: Vec<u32> = context.read_buffer(&target);
+        let (first, second) = values.split_at(HALF as usize / 4);
+        assert!(first.iter().all(|&value| value == 1));
+        assert!(second.iter().all(|&value| value == 2));
+
+        unsafe {
+            target.destroy(device);
+            command_pool.destroy(device);
+            for pool in worker_pools {
+                pool.destroy(device);
+            }
+        }
+    }
+
+    #[test]
+    fn recorder_ends_the_buffer_when_recording_returns_early() {
+        let Some(context) = test_context("recorder_ends_the_buffer_when_recording_returns_early")
+        else {
+            return;
+        };
+        let device = &context.device;
+        let target = BufferResource::new(
+            256,
+            vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            context.device_memory_properties,
+        );
+        let command_pool = context.command_pool();
+        let command_buffer = unsafe {
+            device.allocate_command_buffers(
+                &vk::CommandBufferAllocateInfo::default()
+                    .command_pool(command_pool.pool)
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(1),
+            )
+        }
+        .unwrap()[0];
+
+        let record = || -> Result<vk::CommandBuffer, RtError> {
+            let recorder = CommandRecorder::begin(
+                device,
+                command_buffer,
+                &vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            )?;
+            fill_buffer(device, recorder.command_buffer(), &target, 0, 256, 7);
+            Err(RtError::InvalidConfiguration("early return".into()))?;
+            Ok(recorder.finish()?)
+        };
+        assert!(matches!(record(), Err(RtError::InvalidConfiguration(_))));
+
+        // Drop 已经结束录制，命令缓冲处于可执行状态，直接提交即可
+        let command_buffers = [command_buffer];
+        unsafe {
+            device
+                .queue_submit(
+                    context.queue,
+                    &[vk::SubmitInfo::default().command_buffers(&command_buffers)],
+                    vk::Fence::null(),
+                )
+                .unwrap();
+            device.queue_wait_idle(context.queue).unwrap();
+        }
+        let values: Vec<u32> = context.read_buffer(&target);
+        assert!(values.iter().all(|&value| value == 7));
+
+        unsafe {
+            target.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
@@ -0,0 +1,478 @@
+use ash::{Device, vk};
+
+use crate::buffer::get_memory_type_index;
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+use crate::pipeline::{create_compute_pipeline, create_shader_module};
+
+/// 六个面的立方体贴图（HDR，R32G32B32A32_SFLOAT）
+pub struct CubemapTexture {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    /// 采样用的 CUBE 视图
+    pub view: vk::ImageView,
+    /// 计算着色器写入用的 2D_ARRAY 视图
+    pub storage_view: vk::ImageView,
+    pub face_size: u32,
+    pub layout: vk::ImageLayout,
+}
+
+impl CubemapTexture {
+    pub const FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+
+    pub fn new(
+        device: &Device,
+        face_size: u32,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, vk::Result> {
+        let image_create_info = vk::ImageCreateInfo::default()
+            .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(Self::FORMAT)
+            .extent(
+                vk::Extent3D::default()
+                    .width(face_size)
+                    .height(face_size)
+                    .depth(1),
+            )
+            .mip_levels(1)
+            .array_layers(6)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(
+                vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST,
+            )
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let image = unsafe { device.create_image(&image_create_info, None) }?;
+
+        let mem_reqs = unsafe { device.get_image_memory_requirements(image) };
+        let mem_alloc_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(mem_reqs.size)
+            .memory_type_index(get_memory_type_index(
+                device_memory_properties,
+                mem_reqs.memory_type_bits,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ));
+
+        let memory = unsafe { device.allocate_memory(&mem_alloc_info, None) }?;
+        unsafe { device.bind_image_memory(image, memory, 0) }?;
+
+        let subresource_range = Self::subresource_range();
+
+        let view = unsafe {
+            device.create_image_view(
+                &vk::ImageViewCreateInfo::default()
+                    .image(image)
+                    .view_type(vk::ImageViewType::CUBE)
+                    .format(Self::FORMAT)
+                    .subresource_range(subresource_range),
+                None,
+            )
+        }?;
+
+        let storage_view = unsafe {
+            device.create_image_view(
+                &vk::ImageViewCreateInfo::default()
+                    .image(image)
+                    .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                    .format(Self::FORMAT)
+                    .subresource_range(subresource_range),
+                None,
+            )
+        }?;
+
+        Ok(Self {
+            image,
+            memory,
+            view,
+            storage_view,
+            face_size,
+            layout: vk::ImageLayout::UNDEFINED,
+        })
+    }
+
+    fn subresource_range() -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(6)
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            device.destroy_image_view(self.storage_view, None);
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+/// 用计算着色器把 equirectangular 环境贴图转换为立方体贴图
+///
+/// - equirect_view 需处于 SHADER_READ_ONLY_OPTIMAL 布局
+/// - shader_code 为 shaders/equirect_to_cubemap.comp 编译得到的 SPIR-V
+/// - cache 用于创建计算管线，不使用时传 vk::PipelineCache::null()
+///
+/// 返回的立方体贴图处于 SHADER_READ_ONLY_OPTIMAL 布局，面的朝向遵循 Vulkan 约定（+X, -X, +Y, -Y, +Z, -Z）
+#[allow(clippy::too_many_arguments)]
+pub fn equirect_to_cubemap(
+    device: &Device,
+    command_pool: &CommandPoolManager,
+    queue: vk::Queue,
+    equirect_view: vk::ImageView,
+    equirect_sampler: vk::Sampler,
+    face_size: u32,
+    device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    shader_code: &[u32],
+    cache: vk::PipelineCache,
+) -> Result<CubemapTexture, RtError> {
+    let mut cubemap = CubemapTexture::new(device, face_size, device_memory_properties)?;
+
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+    ];
+
+    let descriptor_set_layout = unsafe {
+        device.create_descriptor_set_layout(
+            &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+            None,
+        )
+    }?;
+
+    let set_layouts = [descriptor_set_layout];
+    let pipeline_layout = unsafe {
+        device.create_pipeline_layout(
+            &vk::PipelineLayoutCreateInfo::default().set_layouts(&set_layouts),
+            None,
+        )
+    }?;
+
+    let pool_sizes = [
+        vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1),
+        vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1),
+    ];
+
+    let descriptor_pool = unsafe {
+        device.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::default()
+                .max_sets(1)
+                .pool_sizes(&pool_sizes),
+            None,
+        )
+    }?;
+
+    let descriptor_set = unsafe {
+        device.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(&set_layouts),
+        )
+    }?[0];
+
+    let equirect_info = [vk::DescriptorImageInfo::default()
+        .image_view(equirect_view)
+        .sampler(equirect_sampler)
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+    let cubemap_info = [vk::DescriptorImageInfo::default()
+        .image_view(cubemap.storage_view)
+        .image_layout(vk::ImageLayout::GENERAL)];
+
+    let writes = [
+        vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&equirect_info),
+        vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(&cubemap_info),
+    ];
+    unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+    let shader_module = create_shader_module(device, shader_code)?;
+    let pipeline = create_compute_pipeline(device, pipeline_layout, shader_module, cache, &[])?;
+
+    let command_buffer = command_pool.begin_one_time(device)?;
+
+    let to_general = vk::ImageMemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::empty())
+        .dst_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .new_layout(vk::ImageLayout::GENERAL)
+        .image(cubemap.image)
+        .subresource_range(CubemapTexture::subresource_range());
+
+    let to_read_only = vk::ImageMemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)
+        .old_layout(vk::ImageLayout::GENERAL)
+        .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image(cubemap.image)
+        .subresource_range(CubemapTexture::subresource_range());
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[to_general],
+        );
+
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline_layout,
+            0,
+            &[descriptor_set],
+            &[],
+        );
+        device.cmd_dispatch(
+            command_buffer,
+            face_size.div_ceil(8),
+            face_size.div_ceil(8),
+            6,
+        );
+
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[to_read_only],
+        );
+    }
+
+    command_pool.end_one_time(device, queue, command_buffer)?;
+    cubemap.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+    unsafe {
+        device.destroy_pipeline(pipeline, None);
+        device.destroy_shader_module(shader_module, None);
+        device.destroy_descriptor_pool(descriptor_pool, None);
+        device.destroy_pipeline_layout(pipeline_layout, None);
+        device.destroy_descriptor_set_layout(descriptor_set_layout, None);
+    }
+
+    Ok(cubemap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferResource;
+    use crate::image_utils::{RenderTargetImage, RenderTargetUsage};
+    use crate::test_support::test_context;
+
+    fn color_range(layer_count: u32) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(layer_count)
+    }
+
+    #[test]
+    fn constant_equirect_map_gives_six_constant_faces() {
+        let Some(context) = test_context("constant_equirect_map_gives_six_constant_faces") else {
+            return;
+        };
+        let spv_path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/shaders/equirect_to_cubemap.comp.spv"
+        );
+        let Ok(spv) = std::fs::read(spv_path) else {
+            eprintln!(
+                "skipping constant_equirect_map_gives_six_constant_faces: {} not compiled",
+                spv_path
+            );
+            return;
+        };
+        let shader_code = ash::util::read_spv(&mut std::io::Cursor::new(spv)).unwrap();
+        let device = &context.device;
+        let properties = context.device_memory_properties;
+        let command_pool = context.command_pool();
+        const COLOR: [f32; 4] = [0.25, 0.5, 2.0, 1.0];
+        const FACE_SIZE: u32 = 8;
+
+        // 用 clear 填充的常量 equirect 贴图
+        let equirect = RenderTargetImage::new(
+            device,
+            16,
+            8,
+            CubemapTexture::FORMAT,
+            RenderTargetUsage::Custom(
+                vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            ),
+            properties,
+        )
+        .unwrap();
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::default()
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .old_layout(vk::ImageLayout::UNDEFINED)
+                    .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .image(equirect.image)
+                    .subresource_range(color_range(1))],
+            );
+            device.cmd_clear_color_image(
+                command_buffer,
+                equirect.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &vk::ClearColorValue { float32: COLOR },
+                &[color_range(1)],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                    .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .image(equirect.image)
+                    .subresource_range(color_range(1))],
+            );
+        }
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+        let sampler = unsafe {
+            device.create_sampler(
+                &vk::SamplerCreateInfo::default()
+                    .mag_filter(vk::Filter::LINEAR)
+                    .min_filter(vk::Filter::LINEAR)
+                    .address_mode_u(vk::SamplerAddressMode::REPEAT)
+                    .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                None,
+            )
+        }
+        .unwrap();
+
+        let cubemap = equirect_to_cubemap(
+            device,
+            &command_pool,
+            context.queue,
+            equirect.view,
+            sampler,
+            FACE_SIZE,
+            properties,
+            &shader_code,
+            vk::PipelineCache::null(),
+        )
+        .unwrap();
+        assert_eq!(cubemap.layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        // 六个面依次拷贝到回读 buffer
+        let face_texels = (FACE_SIZE * FACE_SIZE) as usize;
+        let readback = BufferResource::new(
+            (6 * face_texels * size_of::<[f32; 4]>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            properties,
+        );
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::SHADER_READ)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                    .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .image(cubemap.image)
+                    .subresource_range(color_range(6))],
+            );
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                cubemap.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback.buffer,
+                &[vk::BufferImageCopy::default()
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::default()
+                            .aspect_mask(vk::ImageAspectFlags::COLOR)
+                            .layer_count(6),
+                    )
+                    .image_extent(vk::Extent3D {
+                        width: FACE_SIZE,
+                        height: FACE_SIZE,
+                        depth: 1,
+                    })],
+            );
+        }
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+
+        let texels: Vec<[f32; 4]> = unsafe {
+            let ptr = readback.map(0, readback.size, device) as *const [f32; 4];
+            std::slice::from_raw_parts(ptr, 6 * face_texels).to_vec()
+        };
+        readback.unmap(device);
+        for (face, texels) in texels.chunks_exact(face_texels).enumerate() {
+            for texel in texels {
+                for (channel, expected) in texel.iter().zip(COLOR) {
+                    assert!(
+                        (channel - expected).abs() < 1e-5,
+                        "face {} has {:?}",
+                        face,
+                        texel
+                    );
+                }
+            }
+        }
+
+        unsafe {
+            readback.destroy(device);
+            cubemap.destroy(device);
+            device.destroy_sampler(sampler, None);
+            equirect.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
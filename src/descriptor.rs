@@ -0,0 +1,82 @@
+use ash::{Device, vk};
+
+/// 把 view 以 GENERAL 布局写入 descriptor_set 的 STORAGE_IMAGE 绑定，只更新这一个绑定
+pub fn update_storage_image(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    binding: u32,
+    view: vk::ImageView,
+) {
+    let image_info = [vk::DescriptorImageInfo::default()
+        .image_view(view)
+        .image_layout(vk::ImageLayout::GENERAL)];
+    let write = vk::WriteDescriptorSet::default()
+        .dst_set(descriptor_set)
+        .dst_binding(binding)
+        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+        .image_info(&image_info);
+
+    unsafe { device.update_descriptor_sets(&[write], &[]) };
+}
+
+/// 绑定了渲染目标的一组描述符集（例如每个 frame in flight 一个）
+///
+/// 渲染目标在窗口尺寸变化时会被重建，旧的 image view 随之销毁，
+/// 必须用 rebind_render_target 把新的 view 写回，否则之后的帧会访问已销毁的图像。
+pub struct DescriptorSets {
+    pub sets: Vec<vk::DescriptorSet>,
+    /// 渲染目标在描述符集中的 STORAGE_IMAGE 绑定号
+    pub render_target_binding: u32,
+}
+
+impl DescriptorSets {
+    pub fn new(sets: Vec<vk::DescriptorSet>, render_target_binding: u32) -> Self {
+        Self {
+            sets,
+            render_target_binding,
+        }
+    }
+
+    /// 把所有描述符集的渲染目标绑定更新为 new_view
+    ///
+    /// 调用前需确保 GPU 已不再使用这些描述符集（未使用 UPDATE_AFTER_BIND 时更新正在使用的集合是非法的）。
+    pub fn rebind_render_target(&self, device: &Device, new_view: vk::ImageView) {
+        for &set in &self.sets {
+            update_storage_image(device, set, self.render_target_binding, new_view);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockDescriptorWrite, mock_device, with_mock};
+    use ash::vk::Handle;
+
+    #[test]
+    fn rebinding_writes_only_the_render_target_binding_of_every_set() {
+        let device = mock_device(None);
+        let sets = vec![
+            vk::DescriptorSet::from_raw(0x10),
+            vk::DescriptorSet::from_raw(0x20),
+        ];
+        let descriptor_sets = DescriptorSets::new(sets.clone(), 3);
+        let old_view = vk::ImageView::from_raw(0x100);
+        let new_view = vk::ImageView::from_raw(0x200);
+
+        descriptor_sets.rebind_render_target(&device, old_view);
+        with_mock(|mock| mock.descriptor_writes.clear());
+        descriptor_sets.rebind_render_target(&device, new_view);
+
+        let expected: Vec<MockDescriptorWrite> = sets
+            .iter()
+            .map(|&set| MockDescriptorWrite {
+                set,
+                binding: 3,
+                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                image_views: vec![(new_view, vk::ImageLayout::GENERAL)],
+            })
+            .collect();
+        with_mock(|mock| assert_eq!(mock.descriptor_writes, expected));
+    }
+}
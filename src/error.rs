@@ -0,0 +1,132 @@
+use ash::vk;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum RtError {
+    Vulkan(vk::Result),
+    /// 提交或等待时返回 ERROR_DEVICE_LOST，调用方需要重建逻辑设备
+    DeviceLost,
+    /// 光线追踪必需但设备不支持的特性
+    MissingRequiredFeature(&'static str),
+    /// 队列族的 timestampValidBits 为 0，不支持时间戳查询
+    TimestampsUnsupported {
+        queue_family: u32,
+    },
+    /// 请求的渲染目标尺寸超过设备的 maxImageDimension2D
+    RenderTargetTooLarge {
+        width: u32,
+        height: u32,
+        max_width: u32,
+        max_height: u32,
+    },
+    /// 序列化的加速结构与当前驱动不兼容，调用方需要重新构建
+    IncompatibleAccelerationStructure,
+    /// buffer 缺少所需的用途标志，例如用作 BLAS 输入却没有 SHADER_DEVICE_ADDRESS
+    MissingBufferUsage {
+        buffer: vk::Buffer,
+        required: vk::BufferUsageFlags,
+        actual: vk::BufferUsageFlags,
+    },
+    /// 加速结构的几何数、图元数或实例数超过设备限制
+    ExceedsAccelerationStructureLimit {
+        limit: &'static str,
+        requested: u64,
+        max: u64,
+    },
+    /// 纹理文件格式错误或包含不支持的特性
+    InvalidTexture(String),
+    /// 操作不支持该图像格式，例如回读非 8 位 RGBA/BGRA 的 swapchain 图像
+    UnsupportedFormat(vk::Format),
+    /// 读回的像素坐标不在图像范围内，例如鼠标拾取时光标位于窗口之外
+    PixelOutOfBounds {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    /// 调用方传入的配置或参数组合无效，例如同时启用 GPU 辅助验证与 debug printf
+    InvalidConfiguration(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for RtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RtError::Vulkan(result) => write!(f, "Vulkan error: {}", result),
+            RtError::DeviceLost => write!(f, "Vulkan device lost"),
+            RtError::MissingRequiredFeature(feature) => {
+                write!(f, "Required device feature not supported: {}", feature)
+            }
+            RtError::TimestampsUnsupported { queue_family } => write!(
+                f,
+                "Queue family {} does not support timestamps (timestampValidBits is 0)",
+                queue_family
+            ),
+            RtError::RenderTargetTooLarge {
+                width,
+                height,
+                max_width,
+                max_height,
+            } => write!(
+                f,
+                "Render target {}x{} exceeds the device limit of {}x{}",
+                width, height, max_width, max_height
+            ),
+            RtError::IncompatibleAccelerationStructure => write!(
+                f,
+                "Serialized acceleration structure is incompatible with this device"
+            ),
+            RtError::MissingBufferUsage {
+                buffer,
+                required,
+                actual,
+            } => write!(
+                f,
+                "Buffer {:?} is missing usage {:?} (created with {:?})",
+                buffer,
+                *required & !*actual,
+                actual
+            ),
+            RtError::ExceedsAccelerationStructureLimit {
+                limit,
+                requested,
+                max,
+            } => write!(
+                f,
+                "Acceleration structure exceeds {}: requested {}, device maximum is {}",
+                limit, requested, max
+            ),
+            RtError::InvalidTexture(reason) => write!(f, "Invalid texture: {}", reason),
+            RtError::UnsupportedFormat(format) => write!(f, "Unsupported format: {:?}", format),
+            RtError::PixelOutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                f,
+                "Pixel ({}, {}) is outside the {}x{} image",
+                x, y, width, height
+            ),
+            RtError::InvalidConfiguration(reason) => write!(f, "Invalid configuration: {}", reason),
+            RtError::Io(error) => write!(f, "I/O error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RtError {}
+
+impl From<vk::Result> for RtError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_DEVICE_LOST => RtError::DeviceLost,
+            _ => RtError::Vulkan(result),
+        }
+    }
+}
+
+impl From<std::io::Error> for RtError {
+    fn from(error: std::io::Error) -> Self {
+        RtError::Io(error)
+    }
+}
@@ -0,0 +1,111 @@
+use ash::{Device, vk};
+use std::collections::HashSet;
+
+/// 复用 fence 的池，避免异步提交时反复创建与销毁
+///
+/// acquire 借出的 fence 总是处于未触发状态，可以直接传给 vkQueueSubmit；
+/// release 时重置后放回空闲列表。
+#[derive(Default)]
+pub struct FencePool {
+    free: Vec<vk::Fence>,
+    in_flight: HashSet<vk::Fence>,
+}
+
+impl FencePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取出一个未触发的 fence，没有空闲的时新建
+    pub fn acquire(&mut self, device: &Device) -> Result<vk::Fence, vk::Result> {
+        let fence = match self.free.pop() {
+            Some(fence) => fence,
+            None => unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None) }?,
+        };
+
+        self.in_flight.insert(fence);
+        Ok(fence)
+    }
+
+    /// 重置并归还之前 acquire 得到的 fence
+    ///
+    /// 调用方需确保使用它的提交已经完成（fence 已触发或从未提交），否则重置是非法的。
+    pub fn release(&mut self, device: &Device, fence: vk::Fence) -> Result<(), vk::Result> {
+        assert!(
+            self.in_flight.remove(&fence),
+            "fence {:?} was not acquired from this pool",
+            fence
+        );
+        if let Err(err) = unsafe { device.reset_fences(&[fence]) } {
+            // 重置失败的 fence 状态未知，不再放回池中
+            unsafe { device.destroy_fence(fence, None) };
+            return Err(err);
+        }
+        self.free.push(fence);
+        Ok(())
+    }
+
+    /// 等待 fence 触发后归还
+    pub fn wait_and_release(
+        &mut self,
+        device: &Device,
+        fence: vk::Fence,
+        timeout: u64,
+    ) -> Result<(), vk::Result> {
+        unsafe { device.wait_for_fences(&[fence], true, timeout) }?;
+        self.release(device, fence)
+    }
+
+    /// 当前被借出、可能仍在使用中的 fence
+    pub fn in_flight(&self) -> impl Iterator<Item = vk::Fence> + '_ {
+        self.in_flight.iter().copied()
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn in_flight_count(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// 销毁池中所有 fence，包括仍被借出的；调用前需确保 GPU 已不再使用它们
+    pub unsafe fn destroy_all(self, device: &Device) {
+        if !self.in_flight.is_empty() {
+            println!(
+                "[Warning] FencePool destroyed with {} fences still in flight",
+                self.in_flight.len()
+            );
+        }
+        for fence in self.free.into_iter().chain(self.in_flight) {
+            unsafe { device.destroy_fence(fence, None) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_context;
+
+    #[test]
+    fn released_fences_are_reset_and_reused() {
+        let Some(context) = test_context("released_fences_are_reset_and_reused") else {
+            return;
+        };
+        let device = &context.device;
+        let mut pool = FencePool::new();
+
+        let fence = pool.acquire(device).unwrap();
+        assert_eq!(pool.in_flight_count(), 1);
+        unsafe { device.queue_submit(context.queue, &[], fence) }.unwrap();
+        pool.wait_and_release(device, fence, u64::MAX).unwrap();
+        assert_eq!((pool.free_count(), pool.in_flight_count()), (1, 0));
+
+        let reused = pool.acquire(device).unwrap();
+        assert_eq!(reused, fence);
+        assert!(!unsafe { device.get_fence_status(reused) }.unwrap());
+
+        unsafe { pool.destroy_all(device) };
+    }
+}
@@ -0,0 +1,205 @@
+use ash::{Device, vk};
+
+/// 多帧并行（frames in flight）所需的每帧同步对象
+///
+/// 每帧持有一个 fence（创建时即为 signaled）以及获取 swapchain 图像的信号量；
+/// 渲染完成信号量按 swapchain 图像划分，由 Swapchain::render_finished 持有。
+/// 提交使用 in_flight_fence 的命令后调用 mark_submitted，退出前用 drain 只等待仍在执行的帧。
+pub struct FrameSync {
+    pub in_flight_fences: Vec<vk::Fence>,
+    pub image_available: Vec<vk::Semaphore>,
+    /// 对应 fence 是否有尚未确认完成的提交
+    submitted: Vec<bool>,
+    frame: usize,
+}
+
+impl FrameSync {
+    /// 任一对象创建失败时销毁已创建的对象并返回错误
+    pub fn new(device: &Device, frames_in_flight: usize) -> Result<Self, vk::Result> {
+        assert!(
+            frames_in_flight > 0,
+            "FrameSync needs at least one frame in flight"
+        );
+
+        let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
+        let semaphore_info = vk::SemaphoreCreateInfo::default();
+
+        let mut sync = Self {
+            in_flight_fences: Vec::with_capacity(frames_in_flight),
+            image_available: Vec::with_capacity(frames_in_flight),
+            submitted: vec![false; frames_in_flight],
+            frame: 0,
+        };
+        let result = (|| {
+            for _ in 0..frames_in_flight {
+                unsafe {
+                    sync.in_flight_fences
+                        .push(device.create_fence(&fence_info, None)?);
+                    sync.image_available
+                        .push(device.create_semaphore(&semaphore_info, None)?);
+                }
+            }
+            Ok(())
+        })();
+        if let Err(error) = result {
+            unsafe { sync.destroy(device) };
+            return Err(error);
+        }
+
+        Ok(sync)
+    }
+
+    /// 当前帧的下标
+    pub fn frame_index(&self) -> usize {
+        self.frame
+    }
+
+    pub fn in_flight_fence(&self) -> vk::Fence {
+        self.in_flight_fences[self.frame]
+    }
+
+    /// 等待当前帧上一次的提交完成并重置 fence，之后才能复用该帧的资源
+    pub fn wait_and_reset(&mut self, device: &Device) -> Result<(), vk::Result> {
+        let fence = [self.in_flight_fence()];
+        unsafe {
+            device.wait_for_fences(&fence, true, u64::MAX)?;
+            device.reset_fences(&fence)?;
+        }
+        self.submitted[self.frame] = false;
+        Ok(())
+    }
+
+    /// 以当前帧的 in_flight_fence 提交 submit_info 并记录该帧已提交
+    ///
+    /// 调用前需已对当前帧调用 wait_and_reset。
+    pub fn submit(
+        &mut self,
+        device: &Device,
+        queue: vk::Queue,
+        submit_info: vk::SubmitInfo<'_>,
+    ) -> Result<(), vk::Result> {
+        unsafe { device.queue_submit(queue, &[submit_info], self.in_flight_fence()) }?;
+        self.mark_submitted();
+        Ok(())
+    }
+
+    /// 记录当前帧已提交了带 in_flight_fence 的命令
+    pub fn mark_submitted(&mut self) {
+        self.submitted[self.frame] = true;
+    }
+
+    /// 前进到下一帧
+    pub fn advance(&mut self) {
+        self.frame = (self.frame + 1) % self.in_flight_fences.len();
+    }
+
+    /// 有未确认完成的提交的 fence
+    pub fn outstanding_fences(&self) -> Vec<vk::Fence> {
+        self.in_flight_fences
+            .iter()
+            .zip(&self.submitted)
+            .filter(|(_, submitted)| **submitted)
+            .map(|(fence, _)| *fence)
+            .collect()
+    }
+
+    /// 只等待仍在执行的帧的 fence，用于退出前的最小同步
+    ///
+    /// 这比 device_wait_idle 更精确：不会掩盖其它地方遗漏的同步，多队列时也更快。
+    /// 返回错误（例如 DEVICE_LOST）时调用方可退回到 device_wait_idle。
+    pub fn drain(&mut self, device: &Device) -> Result<(), vk::Result> {
+        let fences = self.outstanding_fences();
+        if !fences.is_empty() {
+            unsafe { device.wait_for_fences(&fences, true, u64::MAX) }?;
+        }
+        self.submitted.fill(false);
+        Ok(())
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            for fence in self.in_flight_fences {
+                device.destroy_fence(fence, None);
+            }
+            for semaphore in self.image_available {
+                device.destroy_semaphore(semaphore, None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{mock_device, with_mock};
+
+    #[test]
+    fn drain_waits_on_exactly_the_submitted_fences() {
+        let device = mock_device(None);
+        let mut sync = FrameSync::new(&device, 3).unwrap();
+        let queue = vk::Queue::null();
+
+        // 第 0、2 帧提交，第 1 帧只等待不提交
+        for frame in 0..3 {
+            sync.wait_and_reset(&device).unwrap();
+            if frame != 1 {
+                sync.submit(&device, queue, vk::SubmitInfo::default())
+                    .unwrap();
+            }
+            sync.advance();
+        }
+        let expected = vec![sync.in_flight_fences[0], sync.in_flight_fences[2]];
+        assert_eq!(sync.outstanding_fences(), expected);
+        with_mock(|mock| {
+            let fences: Vec<_> = mock.submits.iter().map(|submit| submit.fence).collect();
+            assert_eq!(fences, expected);
+            mock.waited.clear();
+        });
+        sync.drain(&device).unwrap();
+        with_mock(|mock| assert_eq!(mock.waited, [expected]));
+
+        // 已确认完成后再次 drain 不再等待任何 fence
+        assert!(sync.outstanding_fences().is_empty());
+        sync.drain(&device).unwrap();
+        with_mock(|mock| assert_eq!(mock.waited.len(), 1));
+
+        unsafe { sync.destroy(&device) };
+        with_mock(|mock| assert!(mock.live.is_empty()));
+    }
+
+    #[test]
+    fn failed_creation_destroys_the_objects_created_so_far() {
+        // 第三帧的 fence 创建失败
+        let device = mock_device(Some(4));
+        assert_eq!(
+            FrameSync::new(&device, 3).err(),
+            Some(vk::Result::ERROR_OUT_OF_HOST_MEMORY)
+        );
+        with_mock(|mock| {
+            assert_eq!(mock.next_handle, 4);
+            assert!(mock.live.is_empty());
+        });
+    }
+
+    #[test]
+    fn drain_returns_once_real_fences_signal() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("drain_returns_once_real_fences_signal") else {
+            return;
+        };
+        let device = &context.device;
+        let mut sync = FrameSync::new(device, 2).unwrap();
+        sync.wait_and_reset(device).unwrap();
+        sync.submit(device, context.queue, vk::SubmitInfo::default())
+            .unwrap();
+        let fence = sync.in_flight_fence();
+        sync.advance();
+
+        sync.drain(device).unwrap();
+        assert!(unsafe { device.get_fence_status(fence) }.unwrap());
+        assert!(sync.outstanding_fences().is_empty());
+
+        unsafe { sync.destroy(device) };
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// 基于 CPU 时间的帧率统计，对最近 window 帧的帧时间取滑动平均
+#[derive(Clone, Debug)]
+pub struct FrameTimer {
+    last_frame: Option<Instant>,
+    frame_times: VecDeque<Duration>,
+    window: usize,
+}
+
+impl FrameTimer {
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "FrameTimer window must be at least one frame");
+        Self {
+            last_frame: None,
+            frame_times: VecDeque::with_capacity(window),
+            window,
+        }
+    }
+
+    /// 在每帧开始（或结束）时调用一次，记录与上一次调用之间的间隔
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            self.record(now - last_frame);
+        }
+    }
+
+    /// 直接记录一帧的耗时
+    pub fn record(&mut self, frame_time: Duration) {
+        if self.frame_times.len() == self.window {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+    }
+
+    /// 最近 window 帧的平均帧时间（毫秒），尚无记录时为 0
+    pub fn frame_time_ms(&self) -> f64 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        let total: Duration = self.frame_times.iter().sum();
+        total.as_secs_f64() * 1000.0 / self.frame_times.len() as f64
+    }
+
+    /// 由平均帧时间换算的帧率，尚无记录时为 0
+    pub fn fps(&self) -> f64 {
+        let frame_time_ms = self.frame_time_ms();
+        if frame_time_ms > 0.0 {
+            1000.0 / frame_time_ms
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_timer_reports_zero() {
+        let mut timer = FrameTimer::new(4);
+        assert_eq!(timer.frame_time_ms(), 0.0);
+        assert_eq!(timer.fps(), 0.0);
+
+        // 第一次 tick 只记录起点
+        timer.tick();
+        assert_eq!(timer.frame_time_ms(), 0.0);
+    }
+
+    #[test]
+    fn average_covers_only_the_last_window_frames() {
+        let mut timer = FrameTimer::new(2);
+        timer.record(Duration::from_millis(100));
+        timer.record(Duration::from_millis(10));
+        timer.record(Duration::from_millis(30));
+
+        assert!((timer.frame_time_ms() - 20.0).abs() < 1e-9);
+        assert!((timer.fps() - 50.0).abs() < 1e-9);
+    }
+}
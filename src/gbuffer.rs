@@ -0,0 +1,326 @@
+use ash::{Device, vk};
+
+use crate::error::RtError;
+use crate::image_utils::{
+    RenderTargetImage, RenderTargetUsage, read_texel, transition_image_to_general,
+};
+
+/// 光线追踪附带输出的辅助图像，全部处于 GENERAL 布局，按 descriptor_bindings 的顺序绑定
+///
+/// - object_id（R32_UINT）：closest-hit 写入 `gl_InstanceCustomIndexEXT + 1`，0 表示未命中
+/// - depth（R32_SFLOAT）：closest-hit 写入 `gl_HitTEXT`，即沿单位长度主光线方向到命中点的距离，
+///   未命中为 `f32::INFINITY`。与光栅化深度合成时需用 `distance_to_view_z` 转成视空间 Z
+pub struct GBuffer {
+    pub object_id: RenderTargetImage,
+    pub depth: RenderTargetImage,
+}
+
+impl GBuffer {
+    pub const OBJECT_ID_FORMAT: vk::Format = vk::Format::R32_UINT;
+    pub const DEPTH_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+    /// 未命中像素的深度
+    pub const DEPTH_MISS: f32 = f32::INFINITY;
+    /// 占用的绑定数：object_id、depth
+    pub const IMAGE_COUNT: u32 = 2;
+    /// 着色器写入、读回拷贝，并在每次 trace 前用 cmd_clear_color_image 清空
+    pub const USAGE: RenderTargetUsage = RenderTargetUsage::Custom(vk::ImageUsageFlags::from_raw(
+        vk::ImageUsageFlags::STORAGE.as_raw()
+            | vk::ImageUsageFlags::TRANSFER_SRC.as_raw()
+            | vk::ImageUsageFlags::TRANSFER_DST.as_raw(),
+    ));
+
+    /// 创建 G-buffer 图像并转换到 GENERAL 布局
+    pub fn new(
+        device: &Device,
+        command_pool: vk::CommandPool,
+        graphics_queue: vk::Queue,
+        width: u32,
+        height: u32,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        let mut object_id = RenderTargetImage::new(
+            device,
+            width,
+            height,
+            Self::OBJECT_ID_FORMAT,
+            Self::USAGE,
+            device_memory_properties,
+        )?;
+        transition_image_to_general(device, command_pool, graphics_queue, &mut object_id)?;
+
+        let mut depth = RenderTargetImage::new(
+            device,
+            width,
+            height,
+            Self::DEPTH_FORMAT,
+            Self::USAGE,
+            device_memory_properties,
+        )?;
+        transition_image_to_general(device, command_pool, graphics_queue, &mut depth)?;
+
+        Ok(Self { object_id, depth })
+    }
+
+    /// 从 first_binding 开始依次排列的存储图像绑定，raygen 清空、closest-hit 写入
+    pub fn descriptor_bindings(first_binding: u32) -> Vec<vk::DescriptorSetLayoutBinding<'static>> {
+        (0..Self::IMAGE_COUNT)
+            .map(|i| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(first_binding + i)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .descriptor_count(1)
+                    .stage_flags(
+                        vk::ShaderStageFlags::RAYGEN_KHR | vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                    )
+            })
+            .collect()
+    }
+
+    /// 把 G-buffer 图像写入 descriptor_set，绑定号与 descriptor_bindings 一致
+    pub fn write_descriptor_set(
+        &self,
+        device: &Device,
+        descriptor_set: vk::DescriptorSet,
+        first_binding: u32,
+    ) {
+        let image_infos = [&self.object_id, &self.depth].map(|image| {
+            vk::DescriptorImageInfo::default()
+                .image_view(image.view)
+                .image_layout(vk::ImageLayout::GENERAL)
+        });
+
+        let writes: Vec<vk::WriteDescriptorSet> = image_infos
+            .iter()
+            .enumerate()
+            .map(|(i, image_info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(first_binding + i as u32)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(std::slice::from_ref(image_info))
+            })
+            .collect();
+
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    /// 录制清空命令：object_id 清为 0、depth 清为 DEPTH_MISS，在每次 trace rays 之前调用
+    pub fn record_clear(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        let range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1);
+
+        unsafe {
+            device.cmd_clear_color_image(
+                command_buffer,
+                self.object_id.image,
+                vk::ImageLayout::GENERAL,
+                &vk::ClearColorValue { uint32: [0; 4] },
+                &[range],
+            );
+            device.cmd_clear_color_image(
+                command_buffer,
+                self.depth.image,
+                vk::ImageLayout::GENERAL,
+                &vk::ClearColorValue {
+                    float32: [Self::DEPTH_MISS; 4],
+                },
+                &[range],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_WRITE)],
+                &[],
+                &[],
+            );
+        }
+    }
+
+    /// 读回 (x, y) 处命中的实例索引，背景（未命中）返回 None
+    pub fn read_object_id(
+        &self,
+        device: &Device,
+        command_pool: vk::CommandPool,
+        graphics_queue: vk::Queue,
+        x: u32,
+        y: u32,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Option<u32>, RtError> {
+        let raw: u32 = read_texel(
+            device,
+            command_pool,
+            graphics_queue,
+            &self.object_id,
+            x,
+            y,
+            device_memory_properties,
+        )?;
+        Ok(decode_object_id(raw))
+    }
+
+    /// 读回 (x, y) 处的命中距离，未命中为 DEPTH_MISS
+    pub fn read_depth(
+        &self,
+        device: &Device,
+        command_pool: vk::CommandPool,
+        graphics_queue: vk::Queue,
+        x: u32,
+        y: u32,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<f32, RtError> {
+        read_texel(
+            device,
+            command_pool,
+            graphics_queue,
+            &self.depth,
+            x,
+            y,
+            device_memory_properties,
+        )
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            self.object_id.destroy(device);
+            self.depth.destroy(device);
+        }
+    }
+}
+
+/// object_id 图像中存的是 `实例索引 + 1`，0 为背景
+pub fn decode_object_id(raw: u32) -> Option<u32> {
+    raw.checked_sub(1)
+}
+
+/// 把沿主光线方向的命中距离转换为视空间深度（沿相机前方向的距离）
+///
+/// ray_direction 与 camera_forward 都需要是单位向量；未命中的 INFINITY 保持不变。
+pub fn distance_to_view_z(distance: f32, ray_direction: [f32; 3], camera_forward: [f32; 3]) -> f32 {
+    let cos_theta = ray_direction
+        .iter()
+        .zip(camera_forward)
+        .map(|(a, b)| a * b)
+        .sum::<f32>();
+    distance * cos_theta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_id_zero_is_background() {
+        assert_eq!(decode_object_id(0), None);
+        assert_eq!(decode_object_id(1), Some(0));
+        // 着色器写入 gl_InstanceCustomIndexEXT + 1
+        assert_eq!(decode_object_id(3 + 1), Some(3));
+        assert_eq!(decode_object_id(u32::MAX), Some(u32::MAX - 1));
+    }
+
+    #[test]
+    fn view_z_scales_distance_by_the_ray_angle() {
+        let forward = [0.0, 0.0, -1.0];
+        assert_eq!(distance_to_view_z(2.0, forward, forward), 2.0);
+        let diagonal = [0.6, 0.0, -0.8];
+        assert!((distance_to_view_z(5.0, diagonal, forward) - 4.0).abs() < 1e-6);
+        assert_eq!(
+            distance_to_view_z(GBuffer::DEPTH_MISS, diagonal, forward),
+            f32::INFINITY
+        );
+    }
+
+    #[test]
+    fn written_depths_read_back_and_misses_are_infinite() {
+        use crate::buffer::BufferResource;
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("written_depths_read_back_and_misses_are_infinite") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let gbuffer = GBuffer::new(
+            device,
+            command_pool.pool,
+            context.queue,
+            4,
+            4,
+            context.device_memory_properties,
+        )
+        .unwrap();
+
+        // 模拟 closest-hit：清空后只在 (1, 2) 写入一个已知深度
+        let staging = BufferResource::new(
+            size_of::<f32>() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            context.device_memory_properties,
+        );
+        staging.store_from_thread(&[2.5f32], 0, device);
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        gbuffer.record_clear(device, command_buffer);
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)],
+                &[],
+                &[],
+            );
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging.buffer,
+                gbuffer.depth.image,
+                vk::ImageLayout::GENERAL,
+                &[vk::BufferImageCopy::default()
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::default()
+                            .aspect_mask(vk::ImageAspectFlags::COLOR)
+                            .layer_count(1),
+                    )
+                    .image_offset(vk::Offset3D { x: 1, y: 2, z: 0 })
+                    .image_extent(vk::Extent3D {
+                        width: 1,
+                        height: 1,
+                        depth: 1,
+                    })],
+            );
+        }
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+
+        let read = |x, y| {
+            gbuffer
+                .read_depth(
+                    device,
+                    command_pool.pool,
+                    context.queue,
+                    x,
+                    y,
+                    context.device_memory_properties,
+                )
+                .unwrap()
+        };
+        assert_eq!(read(1, 2), 2.5);
+        assert_eq!(read(0, 0), GBuffer::DEPTH_MISS);
+        assert_eq!(read(3, 3), GBuffer::DEPTH_MISS);
+
+        unsafe {
+            staging.destroy(device);
+            gbuffer.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
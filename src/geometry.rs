@@ -0,0 +1,138 @@
+use ash::{Device, vk};
+use bytemuck::{Pod, Zeroable};
+
+use crate::acceleration_structure::MeshBuffers;
+use crate::buffer::BufferResource;
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+
+/// 单个几何的顶点/索引缓冲设备地址，供着色器通过 buffer_reference 读取顶点属性
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct GeometryAddress {
+    pub vertex_address: u64,
+    pub index_address: u64,
+    pub material_index: u32,
+    pub _padding: u32,
+}
+
+impl GeometryAddress {
+    pub fn from_mesh(device: &Device, mesh: &MeshBuffers, material_index: u32) -> Self {
+        Self {
+            vertex_address: mesh.vertex_buffer.device_address(device),
+            index_address: mesh.index_buffer.device_address(device),
+            material_index,
+            _padding: 0,
+        }
+    }
+}
+
+/// 所有几何的 GeometryAddress 组成的 STORAGE_BUFFER
+///
+/// 着色器中以 gl_InstanceCustomIndexEXT + gl_GeometryIndexEXT 索引
+pub struct GeometryAddresses {
+    pub buffer: BufferResource,
+    pub addresses: Vec<GeometryAddress>,
+}
+
+impl GeometryAddresses {
+    /// meshes 中每一项为 (网格, 材质下标)
+    pub fn from_meshes(
+        meshes: &[(&MeshBuffers, u32)],
+        device: &Device,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+    ) -> Result<Self, RtError> {
+        let addresses: Vec<GeometryAddress> = meshes
+            .iter()
+            .map(|&(mesh, material_index)| GeometryAddress::from_mesh(device, mesh, material_index))
+            .collect();
+
+        let buffer = BufferResource::new_device_local(
+            &addresses,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+            device,
+            device_memory_properties,
+            command_pool,
+            queue,
+        )?;
+
+        Ok(Self { buffer, addresses })
+    }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer.buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            self.buffer.destroy(device);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::get_buffer_device_address;
+    use crate::test_support::test_context;
+
+    #[test]
+    fn stored_addresses_match_the_mesh_buffers() {
+        let Some(context) = test_context("stored_addresses_match_the_mesh_buffers") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+
+        let triangle = MeshBuffers::new(
+            device,
+            context.device_memory_properties,
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            &[0, 1, 2],
+        );
+        let quad = MeshBuffers::new(
+            device,
+            context.device_memory_properties,
+            &[
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+            &[0, 1, 2, 0, 2, 3],
+        );
+        let geometry = GeometryAddresses::from_meshes(
+            &[(&triangle, 0), (&quad, 5)],
+            device,
+            context.device_memory_properties,
+            &command_pool,
+            context.queue,
+        )
+        .unwrap();
+
+        let read_back: Vec<GeometryAddress> = context.read_buffer(&geometry.buffer);
+        assert_eq!(read_back.len(), 2);
+        for (stored, (mesh, material_index)) in read_back.iter().zip([(&triangle, 0), (&quad, 5)]) {
+            let expected_vertex =
+                unsafe { get_buffer_device_address(device, mesh.vertex_buffer.buffer) };
+            let expected_index =
+                unsafe { get_buffer_device_address(device, mesh.index_buffer.buffer) };
+            assert_ne!(expected_vertex, 0);
+            assert_eq!(stored.vertex_address, expected_vertex);
+            assert_eq!(stored.index_address, expected_index);
+            assert_eq!(stored.material_index, material_index);
+        }
+
+        unsafe {
+            geometry.destroy(device);
+            triangle.destroy(device);
+            quad.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
@@ -1,14 +1,166 @@
-use ash::{vk, Device};
+use ash::{vk, Device, Instance};
 use bytemuck;
 use std::fs::File;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 
-use crate::buffer::get_memory_type_index;
+use crate::buffer::{BufferResource, get_memory_type_index};
+use crate::color::linear_to_srgb;
+use crate::command::submit_one_time;
+use crate::error::RtError;
+use crate::tonemap::TonemapOperator;
+
+/// 设备支持的最大二维图像尺寸（maxImageDimension2D）
+pub fn max_render_target_size(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> (u32, u32) {
+    let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
+    (limits.max_image_dimension2_d, limits.max_image_dimension2_d)
+}
+
+/// 颜色与深度帧缓冲都支持的最高采样数
+///
+/// 混合 MSAA pass 中颜色与深度附件的采样数必须一致，只能从两者的交集中选取。
+pub fn max_usable_sample_count(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> vk::SampleCountFlags {
+    let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
+    max_common_sample_count(&limits)
+}
+
+/// 从 limits 中取 framebufferColorSampleCounts 与 framebufferDepthSampleCounts 交集的最高位
+pub fn max_common_sample_count(limits: &vk::PhysicalDeviceLimits) -> vk::SampleCountFlags {
+    let counts = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+    highest_sample_count(counts)
+}
+
+/// 把请求的采样数限制到颜色与深度都支持、且不超过请求值的最高采样数
+pub fn clamp_sample_count(
+    requested: vk::SampleCountFlags,
+    limits: &vk::PhysicalDeviceLimits,
+) -> vk::SampleCountFlags {
+    let counts = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+    // 不超过 requested 的所有采样数位
+    let at_most_requested =
+        vk::SampleCountFlags::from_raw((requested.as_raw() << 1).wrapping_sub(1));
+    highest_sample_count(counts & at_most_requested)
+}
+
+fn highest_sample_count(counts: vk::SampleCountFlags) -> vk::SampleCountFlags {
+    match counts.as_raw() {
+        0 => vk::SampleCountFlags::TYPE_1,
+        raw => vk::SampleCountFlags::from_raw(1 << (31 - raw.leading_zeros())),
+    }
+}
+
+/// 检查渲染目标尺寸是否在设备限制内，超出时返回带有具体尺寸的错误
+pub fn check_render_target_size(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    width: u32,
+    height: u32,
+) -> Result<(), RtError> {
+    let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
+    check_render_target_size_against(&limits, width, height)
+}
+
+/// 按给定的 maxImageDimension2D 检查渲染目标尺寸
+pub fn check_render_target_size_against(
+    limits: &vk::PhysicalDeviceLimits,
+    width: u32,
+    height: u32,
+) -> Result<(), RtError> {
+    let (max_width, max_height) = (limits.max_image_dimension2_d, limits.max_image_dimension2_d);
+    if width > max_width || height > max_height {
+        return Err(RtError::RenderTargetTooLarge {
+            width,
+            height,
+            max_width,
+            max_height,
+        });
+    }
+    Ok(())
+}
+
+/// choose_render_target_format 的默认候选格式，按优先级排列
+pub const DEFAULT_RENDER_TARGET_FORMATS: [vk::Format; 2] = [
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::R16G16B16A16_SFLOAT,
+];
+
+/// 返回第一个在 OPTIMAL tiling 下支持 STORAGE_IMAGE 的候选格式
+///
+/// candidates 为空时使用 DEFAULT_RENDER_TARGET_FORMATS。
+/// save_image_to_png 按 R32G32B32A32_SFLOAT 读取；save_render_target_to_png 两种默认格式都支持，
+/// 而 TonemapPass 与 AutoExposure 的着色器只接受 R32G32B32A32_SFLOAT 的累积图像。
+pub fn choose_render_target_format(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    candidates: &[vk::Format],
+) -> Option<vk::Format> {
+    let candidates = if candidates.is_empty() {
+        &DEFAULT_RENDER_TARGET_FORMATS[..]
+    } else {
+        candidates
+    };
+
+    candidates.iter().copied().find(|&format| {
+        let properties =
+            unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+        properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::STORAGE_IMAGE)
+    })
+}
+
+/// 渲染目标的用途预设，只请求实际需要的用途以提高格式兼容性
+///
+/// 部分硬件上浮点格式不能作为 COLOR_ATTACHMENT，纯光线追踪/计算输出应使用 RayTracingOutput。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderTargetUsage {
+    /// STORAGE | TRANSFER_SRC：纯光追/计算输出，着色器写入后拷贝或 blit 出去，
+    /// 不要求 COLOR_ATTACHMENT，浮点格式兼容性最好
+    RayTracingOutput,
+    /// 在 RayTracingOutput 基础上加 TRANSFER_DST，渐进累积失效时可用 record_clear_accumulation 清空
+    Accumulation,
+    /// COLOR_ATTACHMENT | STORAGE | TRANSFER_SRC | TRANSFER_DST，可与光栅化混用
+    Rasterization,
+    Custom(vk::ImageUsageFlags),
+}
+
+impl RenderTargetUsage {
+    pub fn flags(self) -> vk::ImageUsageFlags {
+        match self {
+            RenderTargetUsage::RayTracingOutput => {
+                vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC
+            }
+            RenderTargetUsage::Accumulation => {
+                vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST
+            }
+            RenderTargetUsage::Rasterization => {
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_DST
+                    | vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+            }
+            RenderTargetUsage::Custom(flags) => flags,
+        }
+    }
+}
 
 pub struct RenderTargetImage {
     pub image: vk::Image,
     pub memory: vk::DeviceMemory,
     pub view: vk::ImageView,
+    /// 当前记录的图像布局，由各个 helper 在提交布局转换后更新
+    pub layout: vk::ImageLayout,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub usage: vk::ImageUsageFlags,
 }
 
 impl RenderTargetImage {
@@ -17,8 +169,10 @@ impl RenderTargetImage {
         width: u32,
         height: u32,
         format: vk::Format,
+        usage: RenderTargetUsage,
         device_memory_properties: vk::PhysicalDeviceMemoryProperties,
     ) -> Result<Self, vk::Result> {
+        let usage = usage.flags();
         let image_create_info = vk::ImageCreateInfo::default()
             .image_type(vk::ImageType::TYPE_2D)
             .format(format)
@@ -27,12 +181,7 @@ impl RenderTargetImage {
             .array_layers(1)
             .samples(vk::SampleCountFlags::TYPE_1)
             .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(
-                vk::ImageUsageFlags::COLOR_ATTACHMENT
-                    | vk::ImageUsageFlags::TRANSFER_DST
-                    | vk::ImageUsageFlags::STORAGE
-                    | vk::ImageUsageFlags::TRANSFER_SRC,
-            )
+            .usage(usage)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
         let image = unsafe { device.create_image(&image_create_info, None) }?;
@@ -63,7 +212,48 @@ impl RenderTargetImage {
 
         let view = unsafe { device.create_image_view(&image_view_create_info, None) }?;
 
-        Ok(Self { image, memory, view })
+        Ok(Self {
+            image,
+            memory,
+            view,
+            layout: vk::ImageLayout::UNDEFINED,
+            format,
+            extent: vk::Extent2D { width, height },
+            usage,
+        })
+    }
+
+    /// 以相同的格式与用途按新尺寸重建图像并转换到 GENERAL 布局，随后销毁旧的 image 与 view
+    ///
+    /// 调用前需确保 GPU 已不再使用旧图像；引用旧 view 的描述符集需要用
+    /// DescriptorSets::rebind_render_target 重新写入。新图像创建失败时保留旧图像不变。
+    pub fn resize(
+        &mut self,
+        device: &Device,
+        command_pool: vk::CommandPool,
+        graphics_queue: vk::Queue,
+        width: u32,
+        height: u32,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<(), RtError> {
+        let mut resized = Self::new(
+            device,
+            width,
+            height,
+            self.format,
+            RenderTargetUsage::Custom(self.usage),
+            device_memory_properties,
+        )?;
+        if let Err(err) =
+            transition_image_to_general(device, command_pool, graphics_queue, &mut resized)
+        {
+            unsafe { resized.destroy(device) };
+            return Err(err);
+        }
+
+        let old = std::mem::replace(self, resized);
+        unsafe { old.destroy(device) };
+        Ok(())
     }
 
     pub unsafe fn destroy(self, device: &Device) {
@@ -75,12 +265,28 @@ impl RenderTargetImage {
     }
 }
 
+/// debug 模式下检查图像记录的布局是否与操作期望的布局一致
+pub(crate) fn debug_assert_layout(
+    image: &RenderTargetImage,
+    expected: vk::ImageLayout,
+    operation: &str,
+) {
+    debug_assert!(
+        image.layout == expected,
+        "{}: image {:?} is in layout {:?}, expected {:?}",
+        operation,
+        image.image,
+        image.layout,
+        expected
+    );
+}
+
 pub fn transition_image_to_general(
     device: &Device,
     command_pool: vk::CommandPool,
     graphics_queue: vk::Queue,
-    image: vk::Image,
-) -> Result<(), vk::Result> {
+    target: &mut RenderTargetImage,
+) -> Result<(), RtError> {
     let command_buffer = {
         let allocate_info = vk::CommandBufferAllocateInfo::default()
             .command_buffer_count(1)
@@ -103,7 +309,7 @@ pub fn transition_image_to_general(
         .dst_access_mask(vk::AccessFlags::empty())
         .old_layout(vk::ImageLayout::UNDEFINED)
         .new_layout(vk::ImageLayout::GENERAL)
-        .image(image)
+        .image(target.image)
         .subresource_range(
             vk::ImageSubresourceRange::default()
                 .aspect_mask(vk::ImageAspectFlags::COLOR)
@@ -123,25 +329,87 @@ pub fn transition_image_to_general(
             &[],
             &[image_barrier],
         );
-
-        device.end_command_buffer(command_buffer)?;
     }
 
-    let command_buffers = [command_buffer];
-    let submit_infos = [vk::SubmitInfo::default().command_buffers(&command_buffers)];
+    submit_one_time(device, command_pool, graphics_queue, command_buffer, None)?;
 
-    unsafe {
-        device
-            .queue_submit(graphics_queue, &submit_infos, vk::Fence::null())
-            .expect("Failed to execute queue submit.");
-
-        device.queue_wait_idle(graphics_queue)?;
-        device.free_command_buffers(command_pool, &[command_buffer]);
-    }
+    target.layout = vk::ImageLayout::GENERAL;
 
     Ok(())
 }
 
+/// 录制清空 GENERAL 布局累积图像的命令，之后的光追 dispatch 从全 0 开始累加
+///
+/// 图像需带 TRANSFER_DST 用途且处于 GENERAL 布局；清空前等待之前所有对它的读写，清空后对光追与计算着色器可见。
+pub fn record_clear_accumulation(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    target: &RenderTargetImage,
+) {
+    debug_assert_layout(
+        target,
+        vk::ImageLayout::GENERAL,
+        "record_clear_accumulation",
+    );
+    debug_assert!(
+        target.usage.contains(vk::ImageUsageFlags::TRANSFER_DST),
+        "record_clear_accumulation: image {:?} was created without TRANSFER_DST (usage {:?})",
+        target.image,
+        target.usage
+    );
+
+    let range = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .level_count(1)
+        .layer_count(1);
+    let shader_access = vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE;
+    let shader_stages =
+        vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR | vk::PipelineStageFlags::COMPUTE_SHADER;
+
+    let before = vk::ImageMemoryBarrier::default()
+        .src_access_mask(shader_access | vk::AccessFlags::TRANSFER_READ)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .old_layout(vk::ImageLayout::GENERAL)
+        .new_layout(vk::ImageLayout::GENERAL)
+        .image(target.image)
+        .subresource_range(range);
+    let after = vk::ImageMemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(shader_access | vk::AccessFlags::TRANSFER_READ)
+        .old_layout(vk::ImageLayout::GENERAL)
+        .new_layout(vk::ImageLayout::GENERAL)
+        .image(target.image)
+        .subresource_range(range);
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            shader_stages | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[before],
+        );
+        device.cmd_clear_color_image(
+            command_buffer,
+            target.image,
+            vk::ImageLayout::GENERAL,
+            &vk::ClearColorValue::default(),
+            &[range],
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            shader_stages | vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[after],
+        );
+    }
+}
+
 pub fn create_host_visible_image(
     device: &Device,
     width: u32,
@@ -182,11 +450,61 @@ pub fn copy_image_to_host(
     device: &Device,
     command_pool: vk::CommandPool,
     graphics_queue: vk::Queue,
-    src_image: vk::Image,
+    src_image: &RenderTargetImage,
     dst_image: vk::Image,
     width: u32,
     height: u32,
-) -> Result<(), vk::Result> {
+) -> Result<(), RtError> {
+    debug_assert_layout(src_image, vk::ImageLayout::GENERAL, "copy_image_to_host");
+
+    let subresource = vk::ImageSubresourceLayers::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .layer_count(1);
+    let copy_region = vk::ImageCopy::default()
+        .src_subresource(subresource)
+        .dst_subresource(subresource)
+        .extent(vk::Extent3D::default().width(width).height(height).depth(1));
+
+    copy_image_region_to_host(
+        device,
+        command_pool,
+        graphics_queue,
+        src_image.image,
+        vk::ImageLayout::GENERAL,
+        dst_image,
+        copy_region,
+    )
+}
+
+/// 第 level 级 mip 的尺寸，每个维度最小为 1
+pub fn mip_level_extent(extent: vk::Extent3D, level: u32) -> vk::Extent3D {
+    vk::Extent3D {
+        width: (extent.width >> level).max(1),
+        height: (extent.height >> level).max(1),
+        depth: (extent.depth >> level).max(1),
+    }
+}
+
+/// 按 region 指定的 mip 级别、数组层与偏移把 src_image 的一部分拷贝到 host 可见图像
+///
+/// 可用于读回某一级 mip 或立方体贴图的某个面。src_image 必须处于 src_layout，
+/// dst_image 拷贝后转换为 GENERAL；region.extent 需要与所选 mip 级别的尺寸相匹配。
+pub fn copy_image_region_to_host(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    graphics_queue: vk::Queue,
+    src_image: vk::Image,
+    src_layout: vk::ImageLayout,
+    dst_image: vk::Image,
+    region: vk::ImageCopy,
+) -> Result<(), RtError> {
+    let dst_range = vk::ImageSubresourceRange::default()
+        .aspect_mask(region.dst_subresource.aspect_mask)
+        .base_mip_level(region.dst_subresource.mip_level)
+        .level_count(1)
+        .base_array_layer(region.dst_subresource.base_array_layer)
+        .layer_count(region.dst_subresource.layer_count);
+
     let copy_cmd = {
         let allocate_info = vk::CommandBufferAllocateInfo::default()
             .command_pool(command_pool)
@@ -205,14 +523,7 @@ pub fn copy_image_to_host(
         .old_layout(vk::ImageLayout::UNDEFINED)
         .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
         .image(dst_image)
-        .subresource_range(
-            vk::ImageSubresourceRange::default()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .base_mip_level(0)
-                .level_count(1)
-                .base_array_layer(0)
-                .layer_count(1),
-        );
+        .subresource_range(dst_range);
 
     unsafe {
         device.cmd_pipeline_barrier(
@@ -226,27 +537,14 @@ pub fn copy_image_to_host(
         );
     }
 
-    let copy_region = vk::ImageCopy::default()
-        .src_subresource(
-            vk::ImageSubresourceLayers::default()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .layer_count(1),
-        )
-        .dst_subresource(
-            vk::ImageSubresourceLayers::default()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .layer_count(1),
-        )
-        .extent(vk::Extent3D::default().width(width).height(height).depth(1));
-
     unsafe {
         device.cmd_copy_image(
             copy_cmd,
             src_image,
-            vk::ImageLayout::GENERAL,
+            src_layout,
             dst_image,
             vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            &[copy_region],
+            &[region],
         );
     }
 
@@ -256,14 +554,7 @@ pub fn copy_image_to_host(
         .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
         .new_layout(vk::ImageLayout::GENERAL)
         .image(dst_image)
-        .subresource_range(
-            vk::ImageSubresourceRange::default()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .base_mip_level(0)
-                .level_count(1)
-                .base_array_layer(0)
-                .layer_count(1),
-        );
+        .subresource_range(dst_range);
 
     unsafe {
         device.cmd_pipeline_barrier(
@@ -275,24 +566,228 @@ pub fn copy_image_to_host(
             &[],
             &[image_barrier],
         );
+    }
+
+    submit_one_time(device, command_pool, graphics_queue, copy_cmd, None)
+}
+
+/// 读回 GENERAL 布局的 R32G32B32A32_SFLOAT 存储图像在 (x, y) 处的像素，用于 GPU 拾取
+///
+/// 只拷贝 1x1 区域到一个小的 host 可见 buffer，函数返回时拷贝已完成。
+/// raygen 着色器把实例 ID 写入辅助图像后，即可用鼠标坐标查询点中的物体。
+pub fn read_pixel(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    graphics_queue: vk::Queue,
+    image: &RenderTargetImage,
+    x: u32,
+    y: u32,
+    device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+) -> Result<[f32; 4], RtError> {
+    read_texel(
+        device,
+        command_pool,
+        graphics_queue,
+        image,
+        x,
+        y,
+        device_memory_properties,
+    )
+}
+
+/// (x, y) 不在 extent 范围内时返回 RtError::PixelOutOfBounds
+pub fn check_pixel_in_bounds(x: u32, y: u32, extent: vk::Extent2D) -> Result<(), RtError> {
+    if x >= extent.width || y >= extent.height {
+        return Err(RtError::PixelOutOfBounds {
+            x,
+            y,
+            width: extent.width,
+            height: extent.height,
+        });
+    }
+    Ok(())
+}
+
+/// 单个像素占用的字节数，只覆盖渲染目标、G-buffer 与色调映射输出使用的格式
+pub fn texel_size(format: vk::Format) -> Option<usize> {
+    match format {
+        vk::Format::R32G32B32A32_SFLOAT => Some(16),
+        vk::Format::R16G16B16A16_SFLOAT => Some(8),
+        vk::Format::R32_UINT | vk::Format::R32_SFLOAT | vk::Format::R8G8B8A8_UNORM => Some(4),
+        _ => None,
+    }
+}
+
+/// 读回 GENERAL 布局存储图像在 (x, y) 处的像素
+///
+/// T 的大小与图像格式的像素大小不一致时返回 RtError::UnsupportedFormat，
+/// (x, y) 超出图像范围时返回 RtError::PixelOutOfBounds。
+pub fn read_texel<T: bytemuck::Pod>(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    graphics_queue: vk::Queue,
+    image: &RenderTargetImage,
+    x: u32,
+    y: u32,
+    device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+) -> Result<T, RtError> {
+    debug_assert_layout(image, vk::ImageLayout::GENERAL, "read_texel");
+    if texel_size(image.format) != Some(size_of::<T>()) {
+        return Err(RtError::UnsupportedFormat(image.format));
+    }
+    check_pixel_in_bounds(x, y, image.extent)?;
+
+    let pixel_size = size_of::<T>() as vk::DeviceSize;
+    let readback_buffer = BufferResource::new(
+        pixel_size,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_VISIBLE,
+        device,
+        device_memory_properties,
+    );
+
+    let result = (|| -> Result<T, RtError> {
+        let copy_cmd = {
+            let allocate_info = vk::CommandBufferAllocateInfo::default()
+                .command_pool(command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1);
+
+            unsafe { device.allocate_command_buffers(&allocate_info) }?[0]
+        };
 
-        device.end_command_buffer(copy_cmd)?;
+        let cmd_begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe { device.begin_command_buffer(copy_cmd, &cmd_begin_info) }?;
 
-        device
-            .queue_submit(
-                graphics_queue,
-                &[vk::SubmitInfo::default().command_buffers(&[copy_cmd])],
-                vk::Fence::null(),
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1),
             )
-            .expect("Failed to execute queue submit.");
+            .image_offset(vk::Offset3D {
+                x: x as i32,
+                y: y as i32,
+                z: 0,
+            })
+            .image_extent(vk::Extent3D {
+                width: 1,
+                height: 1,
+                depth: 1,
+            });
+
+        unsafe {
+            // 等待之前的着色器写入，再把拷贝结果对 host 可见
+            device.cmd_pipeline_barrier(
+                copy_cmd,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ)],
+                &[],
+                &[],
+            );
+            device.cmd_copy_image_to_buffer(
+                copy_cmd,
+                image.image,
+                vk::ImageLayout::GENERAL,
+                readback_buffer.buffer,
+                &[region],
+            );
+            device.cmd_pipeline_barrier(
+                copy_cmd,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::HOST_READ)],
+                &[],
+                &[],
+            );
+        }
+        submit_one_time(device, command_pool, graphics_queue, copy_cmd, None)?;
+
+        let data = readback_buffer.map(0, readback_buffer.allocation_size, device);
+        readback_buffer.invalidate(device, 0, pixel_size)?;
+        let pixel = bytemuck::pod_read_unaligned(unsafe {
+            std::slice::from_raw_parts(data as *const u8, size_of::<T>())
+        });
+        readback_buffer.unmap(device);
+        Ok(pixel)
+    })();
+
+    unsafe { readback_buffer.destroy(device) };
+    result
+}
 
-        device.queue_wait_idle(graphics_queue)?;
-        device.free_command_buffers(command_pool, &[copy_cmd]);
+/// PNG 导出的可选后处理参数
+#[derive(Clone, Copy, Debug)]
+pub struct ExportOptions {
+    /// 平均后每个通道辐射度的上限，用于压制 firefly；None 保持无偏输出
+    pub firefly_clamp: Option<f32>,
+    /// 是否上下翻转行顺序；raygen 着色器按自底向上写入时保持 true
+    pub flip_vertical: bool,
+    /// 输出目标本身按 sRGB 编码时设为 true，此时跳过手动 sRGB 编码，按线性值量化
+    ///
+    /// 线性浮点数据写入 `_SRGB` 格式时硬件会在存储时做 gamma 编码，再手动编码一次会使画面偏亮。
+    pub output_is_srgb: bool,
+    /// sRGB 编码前应用的色调映射算子，与 GPU 端 TonemapPass 使用同一套实现
+    pub tonemap: TonemapOperator,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            firefly_clamp: None,
+            flip_vertical: true,
+            output_is_srgb: false,
+            tonemap: TonemapOperator::Clamp,
+        }
     }
+}
 
-    Ok(())
+/// `_SRGB` 结尾的格式在写入时由硬件做 sRGB 编码
+pub fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+/// 把累积的辐射度转换为 8 位 sRGB 编码值
+///
+/// NaN、Inf 与负值会被当作 0，避免 powf 与 `as u8` 产生未定义的噪点；
+/// 目标已按 sRGB 编码时跳过手动编码，按线性值量化
+fn encode_channel(value: f32, scale: f32, options: &ExportOptions) -> u8 {
+    let value = if value.is_finite() {
+        value.max(0.0)
+    } else {
+        0.0
+    };
+    let mut radiance = value * scale;
+    if let Some(max) = options.firefly_clamp {
+        radiance = radiance.min(max);
+    }
+    radiance = options.tonemap.apply(radiance);
+    let encoded = if options.output_is_srgb {
+        radiance
+    } else {
+        linear_to_srgb(radiance)
+    };
+    (256.0 * encoded.clamp(0.0, 0.999)) as u8
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn save_image_to_png(
     device: &Device,
     dst_device_memory: vk::DeviceMemory,
@@ -300,8 +795,240 @@ pub fn save_image_to_png(
     width: u32,
     height: u32,
     n_samples: u32,
-    filename: &str,
+    filename: impl AsRef<Path>,
+    options: &ExportOptions,
 ) {
+    save_host_image_to_png(
+        device,
+        dst_device_memory,
+        dst_image,
+        vk::Format::R32G32B32A32_SFLOAT,
+        width,
+        height,
+        n_samples,
+        filename,
+        options,
+    )
+    .expect("Failed to read back the R32G32B32A32_SFLOAT image");
+}
+
+/// 与 save_image_to_png 相同，但按 format 解码 host 可见图像
+#[allow(clippy::too_many_arguments)]
+pub fn save_host_image_to_png(
+    device: &Device,
+    dst_device_memory: vk::DeviceMemory,
+    dst_image: vk::Image,
+    format: vk::Format,
+    width: u32,
+    height: u32,
+    n_samples: u32,
+    filename: impl AsRef<Path>,
+    options: &ExportOptions,
+) -> Result<(), RtError> {
+    let rows = read_image_rgba8_rows(
+        device,
+        dst_device_memory,
+        dst_image,
+        format,
+        width,
+        height,
+        n_samples,
+        options,
+    )?;
+    write_png_rows(filename, width, height, &rows);
+    Ok(())
+}
+
+/// 渐进累积使用的渲染目标及其已累积的采样数
+///
+/// 导出时直接读取 accumulated_samples 作为除数，避免调用方单独维护的 n_samples 与实际累积状态不一致。
+pub struct AccumulationBuffer {
+    pub image: RenderTargetImage,
+    samples: u32,
+}
+
+impl AccumulationBuffer {
+    pub fn new(image: RenderTargetImage) -> Self {
+        Self { image, samples: 0 }
+    }
+
+    /// 已累积到图像中的采样数
+    pub fn accumulated_samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// 导出时使用的除数：已累积的采样数，尚未累积任何采样时按 1 处理
+    pub fn export_samples(&self) -> u32 {
+        self.samples.max(1)
+    }
+
+    /// 记录一次向图像累加了 samples 个采样的 dispatch
+    pub fn add_samples(&mut self, samples: u32) {
+        self.samples += samples;
+    }
+
+    /// 清空累积计数，下一次 dispatch 应覆盖而不是累加图像
+    pub fn reset(&mut self) {
+        self.samples = 0;
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe { self.image.destroy(device) };
+    }
+}
+
+/// 把 GENERAL 布局的渲染目标拷贝到临时的 host 可见图像，除以 n_samples 后保存为 PNG
+///
+/// 适合不经过 AccumulationBuffer 的独立使用；渐进渲染的结果应使用 save_accumulation_to_png。
+#[allow(clippy::too_many_arguments)]
+pub fn save_render_target_to_png(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    graphics_queue: vk::Queue,
+    render_target: &RenderTargetImage,
+    n_samples: u32,
+    filename: impl AsRef<Path>,
+    options: &ExportOptions,
+    device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+) -> Result<(), RtError> {
+    let format = render_target.format;
+    readback_texel_size(format).ok_or(RtError::UnsupportedFormat(format))?;
+
+    let vk::Extent2D { width, height } = render_target.extent;
+    let (dst_image, dst_memory) =
+        create_host_visible_image(device, width, height, format, device_memory_properties)?;
+
+    let result = copy_image_to_host(
+        device,
+        command_pool,
+        graphics_queue,
+        render_target,
+        dst_image,
+        width,
+        height,
+    )
+    .and_then(|()| {
+        save_host_image_to_png(
+            device, dst_memory, dst_image, format, width, height, n_samples, filename, options,
+        )
+    });
+
+    unsafe {
+        device.destroy_image(dst_image, None);
+        device.free_memory(dst_memory, None);
+    }
+
+    result
+}
+
+/// 以 accumulation.export_samples() 为除数导出累积结果
+#[allow(clippy::too_many_arguments)]
+pub fn save_accumulation_to_png(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    graphics_queue: vk::Queue,
+    accumulation: &AccumulationBuffer,
+    filename: impl AsRef<Path>,
+    options: &ExportOptions,
+    device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+) -> Result<(), RtError> {
+    save_render_target_to_png(
+        device,
+        command_pool,
+        graphics_queue,
+        &accumulation.image,
+        accumulation.export_samples(),
+        filename,
+        options,
+        device_memory_properties,
+    )
+}
+
+/// 把 IEEE 754 半精度浮点数的位模式转换为 f32
+fn half_to_f32(bits: u16) -> f32 {
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    let magnitude = match exponent {
+        // 非规格化数：mantissa × 2^-24
+        0 => mantissa as f32 / 16_777_216.0,
+        0x1f if mantissa == 0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => f32::from_bits(((exponent + 112) << 23) | (mantissa << 13)),
+    };
+    if bits & 0x8000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// read_image_rgba8_rows 支持的格式每个像素的字节数
+fn readback_texel_size(format: vk::Format) -> Option<usize> {
+    match format {
+        vk::Format::R32G32B32A32_SFLOAT => Some(16),
+        vk::Format::R16G16B16A16_SFLOAT => Some(8),
+        _ => None,
+    }
+}
+
+/// 解码一个像素的 RGB 通道，texel 的长度为 readback_texel_size(format)
+fn decode_rgb(texel: &[u8], format: vk::Format) -> [f32; 3] {
+    if format == vk::Format::R16G16B16A16_SFLOAT {
+        std::array::from_fn(|i| half_to_f32(u16::from_ne_bytes([texel[2 * i], texel[2 * i + 1]])))
+    } else {
+        std::array::from_fn(|i| f32::from_ne_bytes(texel[4 * i..4 * i + 4].try_into().unwrap()))
+    }
+}
+
+/// 累积图像除以 n_samples 得到平均辐射度时使用的缩放系数
+///
+/// n_samples 通常来自 SceneUniform::total_samples，即 dispatch 次数乘以 samples_per_dispatch
+fn sample_scale(n_samples: u32) -> f32 {
+    1.0 / n_samples as f32
+}
+
+/// 把一行 format 格式的累积像素乘以 scale 后编码为 RGBA8，含 NaN/Inf 或负值的像素计入 bad_pixels
+fn encode_row(
+    row: &[u8],
+    format: vk::Format,
+    scale: f32,
+    options: &ExportOptions,
+    bad_pixels: &mut usize,
+) -> Vec<u8> {
+    let texel_size = readback_texel_size(format).expect("unsupported readback format");
+    row.chunks_exact(texel_size)
+        .flat_map(|texel| {
+            let pixel = decode_rgb(texel, format);
+            if pixel.iter().any(|&c| !c.is_finite() || c < 0.0) {
+                *bad_pixels += 1;
+            }
+            [
+                encode_channel(pixel[0], scale, options),
+                encode_channel(pixel[1], scale, options),
+                encode_channel(pixel[2], scale, options),
+                255u8,
+            ]
+        })
+        .collect()
+}
+
+/// 读取 host 可见的线性图像，按 options 转换为逐行的 RGBA8 数据
+///
+/// 支持 R32G32B32A32_SFLOAT 与 R16G16B16A16_SFLOAT（choose_render_target_format 的两个默认候选），
+/// 其他格式返回 RtError::UnsupportedFormat。
+#[allow(clippy::too_many_arguments)]
+pub fn read_image_rgba8_rows(
+    device: &Device,
+    dst_device_memory: vk::DeviceMemory,
+    dst_image: vk::Image,
+    format: vk::Format,
+    width: u32,
+    height: u32,
+    n_samples: u32,
+    options: &ExportOptions,
+) -> Result<Vec<Vec<u8>>, RtError> {
+    let texel_size = readback_texel_size(format).ok_or(RtError::UnsupportedFormat(format))?;
+
     let subresource_layout = {
         let subresource = vk::ImageSubresource::default()
             .aspect_mask(vk::ImageAspectFlags::COLOR);
@@ -309,18 +1036,56 @@ pub fn save_image_to_png(
     };
 
     let data: *const u8 = unsafe {
-        device
-            .map_memory(
-                dst_device_memory,
-                0,
-                vk::WHOLE_SIZE,
-                vk::MemoryMapFlags::empty(),
-            )
-            .unwrap() as _
-    };
+        device.map_memory(
+            dst_device_memory,
+            0,
+            vk::WHOLE_SIZE,
+            vk::MemoryMapFlags::empty(),
+        )
+    }? as _;
 
     let mut data = unsafe { data.offset(subresource_layout.offset as isize) };
 
+    let scale = sample_scale(n_samples);
+
+    let mut bad_pixels = 0usize;
+    let mut rows = Vec::new();
+    for _ in 0..height {
+        let row = unsafe { std::slice::from_raw_parts(data, texel_size * width as usize) };
+        rows.push(encode_row(row, format, scale, options, &mut bad_pixels));
+        data = unsafe { data.offset(subresource_layout.row_pitch as isize) };
+    }
+
+    unsafe {
+        device.unmap_memory(dst_device_memory);
+    }
+
+    if bad_pixels > 0 {
+        println!(
+            "[Warning] {} pixels contained NaN/Inf or negative radiance and were written as 0",
+            bad_pixels
+        );
+    }
+
+    Ok(orient_rows(rows, options.flip_vertical))
+}
+
+/// 按 flip_vertical 调整行序：true 时按自下而上的帧缓冲约定翻转，false 时保持图像内存中的行序
+fn orient_rows(mut rows: Vec<Vec<u8>>, flip_vertical: bool) -> Vec<Vec<u8>> {
+    if flip_vertical {
+        rows.reverse();
+    }
+    rows
+}
+
+/// 把逐行的 RGBA8 数据写成 PNG，每行长度必须为 4 * width
+pub fn write_png_rows(filename: impl AsRef<Path>, width: u32, height: u32, rows: &[Vec<u8>]) {
+    assert_eq!(
+        rows.len(),
+        height as usize,
+        "PNG row count must match height"
+    );
+
     let mut png_encoder = png::Encoder::new(File::create(filename).unwrap(), width, height);
     png_encoder.set_depth(png::BitDepth::Eight);
     png_encoder.set_color(png::ColorType::Rgba);
@@ -331,35 +1096,907 @@ pub fn save_image_to_png(
         .into_stream_writer_with_size((4 * width) as usize)
         .unwrap();
 
-    let scale = 1.0 / n_samples as f32;
-    let gamma = 1.0 / 2.2_f32;
+    for row in rows {
+        png_writer.write_all(row).unwrap();
+    }
 
-    let mut rows = Vec::new();
-    for _ in 0..height {
-        let row = unsafe { std::slice::from_raw_parts(data, 4 * 4 * width as usize) };
-        let row_f32: &[f32] = bytemuck::cast_slice(row);
-        let row_rgba8: Vec<u8> = row_f32
-            .chunks(4)
-            .flat_map(|pixel| {
-                [
-                    (256.0 * (pixel[0] * scale).powf(gamma).clamp(0.0, 0.999)) as u8,
-                    (256.0 * (pixel[1] * scale).powf(gamma).clamp(0.0, 0.999)) as u8,
-                    (256.0 * (pixel[2] * scale).powf(gamma).clamp(0.0, 0.999)) as u8,
-                    255u8,
-                ]
-            })
+    png_writer.finish().unwrap();
+}
+
+/// 按文件名模板（如 `frame_{:04}.png`）输出帧序列，用于无头模式渲染动画
+pub struct FrameWriter {
+    pub directory: PathBuf,
+    prefix: String,
+    suffix: String,
+    pad: usize,
+    /// 每帧导出时使用的后处理参数
+    pub options: ExportOptions,
+}
+
+impl FrameWriter {
+    /// 解析文件名模板并预先创建输出目录
+    /// - 模板必须包含一个 `{}` 或 `{:0N}` 占位符
+    pub fn new(directory: impl AsRef<Path>, template: &str) -> std::io::Result<Self> {
+        let invalid = |msg: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{}: {:?}", msg, template),
+            )
+        };
+
+        let start = template
+            .find('{')
+            .ok_or_else(|| invalid("Frame template has no placeholder"))?;
+        let end = start
+            + template[start..]
+                .find('}')
+                .ok_or_else(|| invalid("Frame template has an unclosed placeholder"))?;
+
+        let spec = &template[start + 1..end];
+        let pad = match spec {
+            "" | ":" => 0,
+            _ => spec
+                .strip_prefix(":0")
+                .and_then(|width| width.parse::<usize>().ok())
+                .ok_or_else(|| invalid("Unsupported frame placeholder"))?,
+        };
+
+        let suffix = &template[end + 1..];
+        if suffix.contains('{') || suffix.contains('}') {
+            return Err(invalid("Frame template has more than one placeholder"));
+        }
+
+        let directory = directory.as_ref().to_path_buf();
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            directory,
+            prefix: template[..start].to_string(),
+            suffix: suffix.to_string(),
+            pad,
+            options: ExportOptions::default(),
+        })
+    }
+
+    /// 第 index 帧的输出路径
+    pub fn frame_path(&self, index: u32) -> PathBuf {
+        self.directory.join(format!(
+            "{}{:0pad$}{}",
+            self.prefix,
+            index,
+            self.suffix,
+            pad = self.pad
+        ))
+    }
+
+    /// 将已拷贝到 host 可见图像中的帧按 format 解码并保存为 PNG，返回写入的路径
+    ///
+    /// format 不是可读回的浮点格式时返回 RtError::UnsupportedFormat。
+    #[allow(clippy::too_many_arguments)]
+    pub fn save_frame(
+        &self,
+        device: &Device,
+        dst_device_memory: vk::DeviceMemory,
+        dst_image: vk::Image,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        n_samples: u32,
+        index: u32,
+    ) -> Result<PathBuf, RtError> {
+        let rows = read_image_rgba8_rows(
+            device,
+            dst_device_memory,
+            dst_image,
+            format,
+            width,
+            height,
+            n_samples,
+            &self.options,
+        )?;
+        Ok(self.write_frame_rows(index, width, height, &rows))
+    }
+
+    /// 把已编码的 RGBA8 行写为第 index 帧，返回写入的路径
+    pub fn write_frame_rows(
+        &self,
+        index: u32,
+        width: u32,
+        height: u32,
+        rows: &[Vec<u8>],
+    ) -> PathBuf {
+        let path = self.frame_path(index);
+        write_png_rows(&path, width, height, rows);
+        path
+    }
+}
+
+/// 窗口模式的截图请求：request 只记录路径，下一帧调用 capture_pending 时才拷贝并保存
+#[derive(Default)]
+pub struct ScreenshotCapture {
+    pending: Option<PathBuf>,
+    /// 保存时使用的后处理参数
+    pub options: ExportOptions,
+}
+
+impl ScreenshotCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 请求在下一帧保存截图，重复请求时以最后一次的路径为准
+    pub fn request_screenshot(&mut self, path: impl Into<PathBuf>) {
+        self.pending = Some(path.into());
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// 有待处理的请求时把 render_target 拷贝到临时的 host 可见图像并保存为 PNG，返回写入的路径
+    ///
+    /// render_target 必须处于 GENERAL 布局。拷贝单独提交并只等待这一次拷贝完成，
+    /// 渲染目标本身的布局不变，不影响之后的呈现。
+    #[allow(clippy::too_many_arguments)]
+    pub fn capture_pending(
+        &mut self,
+        device: &Device,
+        command_pool: vk::CommandPool,
+        graphics_queue: vk::Queue,
+        render_target: &RenderTargetImage,
+        n_samples: u32,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Option<PathBuf>, RtError> {
+        let Some(path) = self.pending.take() else {
+            return Ok(None);
+        };
+
+        save_render_target_to_png(
+            device,
+            command_pool,
+            graphics_queue,
+            render_target,
+            n_samples,
+            &path,
+            &self.options,
+            device_memory_properties,
+        )
+        .map(|()| Some(path))
+    }
+
+    /// 与 capture_pending 相同，但采样数取自 accumulation 当前的累积状态
+    pub fn capture_pending_accumulation(
+        &mut self,
+        device: &Device,
+        command_pool: vk::CommandPool,
+        graphics_queue: vk::Queue,
+        accumulation: &AccumulationBuffer,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Option<PathBuf>, RtError> {
+        self.capture_pending(
+            device,
+            command_pool,
+            graphics_queue,
+            &accumulation.image,
+            accumulation.export_samples(),
+            device_memory_properties,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_to_f32_decodes_reference_values() {
+        assert_eq!(half_to_f32(0x0000), 0.0);
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x0001), 2.0_f32.powi(-24));
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+        assert!(half_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn decode_rgb_handles_both_readback_formats() {
+        let texel32: Vec<u8> = [0.25_f32, 0.5, 2.0, 1.0]
+            .iter()
+            .flat_map(|c| c.to_ne_bytes())
             .collect();
-        rows.push(row_rgba8);
-        data = unsafe { data.offset(subresource_layout.row_pitch as isize) };
+        let format32 = vk::Format::R32G32B32A32_SFLOAT;
+        assert_eq!(readback_texel_size(format32), Some(texel32.len()));
+        assert_eq!(decode_rgb(&texel32, format32), [0.25, 0.5, 2.0]);
+
+        let texel16: Vec<u8> = [0x3400_u16, 0x3800, 0x4000, 0x3c00]
+            .iter()
+            .flat_map(|c| c.to_ne_bytes())
+            .collect();
+        let format16 = vk::Format::R16G16B16A16_SFLOAT;
+        assert_eq!(readback_texel_size(format16), Some(texel16.len()));
+        assert_eq!(decode_rgb(&texel16, format16), [0.25, 0.5, 2.0]);
+
+        assert_eq!(readback_texel_size(vk::Format::R8G8B8A8_UNORM), None);
     }
 
-    for row in rows.iter().rev() {
-        png_writer.write_all(row).unwrap();
+    #[test]
+    fn non_finite_and_negative_radiance_encode_as_black() {
+        let options = ExportOptions::default();
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, -1.0] {
+            assert_eq!(encode_channel(value, 1.0, &options), 0);
+        }
+        assert_eq!(encode_channel(1.0, 1.0, &options), 255);
+        assert_eq!(encode_channel(0.5, 2.0, &options), 255);
+
+        let row: Vec<u8> = [
+            [f32::NAN, -1.0, f32::INFINITY, 1.0],
+            [-0.5, f32::NEG_INFINITY, f32::NAN, 1.0],
+            [1.0, 1.0, 1.0, 1.0],
+        ]
+        .iter()
+        .flatten()
+        .flat_map(|c| c.to_ne_bytes())
+        .collect();
+        let mut bad_pixels = 0;
+        let encoded = encode_row(
+            &row,
+            vk::Format::R32G32B32A32_SFLOAT,
+            1.0,
+            &options,
+            &mut bad_pixels,
+        );
+        assert_eq!(bad_pixels, 2);
+        assert_eq!(encoded, [0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255]);
     }
 
-    png_writer.finish().unwrap();
+    #[test]
+    fn firefly_clamp_bounds_outliers_and_keeps_normal_pixels() {
+        // Reinhard 让超过 1 的辐射度仍可区分，才能观察到上限的效果
+        let reinhard = ExportOptions {
+            tonemap: TonemapOperator::Reinhard,
+            ..ExportOptions::default()
+        };
+        let clamped = ExportOptions {
+            firefly_clamp: Some(10.0),
+            ..reinhard
+        };
 
-    unsafe {
-        device.unmap_memory(dst_device_memory);
+        assert_eq!(
+            encode_channel(1e6, 1.0, &clamped),
+            encode_channel(10.0, 1.0, &reinhard)
+        );
+        assert!(encode_channel(1e6, 1.0, &clamped) < encode_channel(1e6, 1.0, &reinhard));
+
+        for value in [0.0, 0.18, 0.5, 1.0, 4.0, 10.0] {
+            assert_eq!(
+                encode_channel(value, 1.0, &clamped),
+                encode_channel(value, 1.0, &reinhard)
+            );
+        }
+    }
+
+    #[test]
+    fn srgb_output_skips_manual_encoding() {
+        let linear = ExportOptions {
+            output_is_srgb: true,
+            ..Default::default()
+        };
+        assert_eq!(encode_channel(0.5, 1.0, &linear), 128);
+        assert_eq!(encode_channel(0.5, 1.0, &ExportOptions::default()), 188);
+    }
+
+    #[test]
+    fn flip_vertical_controls_the_row_order() {
+        let gradient: Vec<Vec<u8>> = (0..4u8).map(|y| vec![y * 64, 0, 0, 255]).collect();
+
+        let top_down = orient_rows(gradient.clone(), false);
+        assert_eq!(top_down[0], gradient[0]);
+        assert_eq!(top_down, gradient);
+
+        let bottom_up = orient_rows(gradient.clone(), true);
+        assert_eq!(bottom_up[0], gradient[3]);
+        assert_eq!(bottom_up.into_iter().rev().collect::<Vec<_>>(), gradient);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn export_divisor_counts_every_sample_of_every_dispatch() {
+        use crate::jitter::JitterSequence;
+        use crate::scene::SceneUniform;
+
+        let mut scene = SceneUniform {
+            samples_per_dispatch: 4,
+            ..Default::default()
+        };
+        let mut jitter = JitterSequence::new();
+        for _ in 0..3 {
+            scene.next_frame(&mut jitter);
+        }
+        assert_eq!(scene.total_samples(), 12);
+        assert_eq!(sample_scale(scene.total_samples()), 1.0 / 12.0);
+
+        // 12 个采样各贡献 0.5，平均后应与直接编码 0.5 一致
+        let accumulated: Vec<u8> = [6.0f32, 6.0, 6.0, 12.0]
+            .iter()
+            .flat_map(|c| c.to_ne_bytes())
+            .collect();
+        let options = ExportOptions::default();
+        let mut bad_pixels = 0;
+        let row = encode_row(
+            &accumulated,
+            vk::Format::R32G32B32A32_SFLOAT,
+            sample_scale(scene.total_samples()),
+            &options,
+            &mut bad_pixels,
+        );
+        let expected = encode_channel(0.5, 1.0, &options);
+        assert_eq!(row, [expected, expected, expected, 255]);
+        assert_eq!(bad_pixels, 0);
+    }
+
+    #[test]
+    fn pixel_outside_the_image_is_an_error() {
+        let extent = vk::Extent2D {
+            width: 4,
+            height: 2,
+        };
+        assert!(check_pixel_in_bounds(0, 0, extent).is_ok());
+        assert!(check_pixel_in_bounds(3, 1, extent).is_ok());
+        assert!(matches!(
+            check_pixel_in_bounds(4, 0, extent),
+            Err(RtError::PixelOutOfBounds { x: 4, y: 0, .. })
+        ));
+        assert!(matches!(
+            check_pixel_in_bounds(0, 2, extent),
+            Err(RtError::PixelOutOfBounds {
+                width: 4,
+                height: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn oversized_render_target_reports_the_device_limit() {
+        let limits = vk::PhysicalDeviceLimits {
+            max_image_dimension2_d: 16384,
+            ..Default::default()
+        };
+        assert!(check_render_target_size_against(&limits, 16384, 16384).is_ok());
+
+        let error = check_render_target_size_against(&limits, 80000, 80000).unwrap_err();
+        assert!(matches!(
+            error,
+            RtError::RenderTargetTooLarge {
+                width: 80000,
+                height: 80000,
+                max_width: 16384,
+                max_height: 16384,
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Render target 80000x80000 exceeds the device limit of 16384x16384"
+        );
+    }
+
+    #[test]
+    fn mip_level_two_copies_at_its_own_size() {
+        use crate::test_support::test_context;
+
+        let extent = vk::Extent3D {
+            width: 16,
+            height: 8,
+            depth: 1,
+        };
+        let mip2 = mip_level_extent(extent, 2);
+        assert_eq!((mip2.width, mip2.height, mip2.depth), (4, 2, 1));
+        assert_eq!(mip_level_extent(extent, 4).width, 1);
+        assert_eq!(mip_level_extent(extent, 4).height, 1);
+
+        let Some(context) = test_context("mip_level_two_copies_at_its_own_size") else {
+            return;
+        };
+        let device = &context.device;
+        let format = vk::Format::R8G8B8A8_UNORM;
+        let src_image = unsafe {
+            device.create_image(
+                &vk::ImageCreateInfo::default()
+                    .image_type(vk::ImageType::TYPE_2D)
+                    .format(format)
+                    .extent(extent)
+                    .mip_levels(3)
+                    .array_layers(1)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .tiling(vk::ImageTiling::OPTIMAL)
+                    .usage(vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST),
+                None,
+            )
+        }
+        .unwrap();
+        let requirements = unsafe { device.get_image_memory_requirements(src_image) };
+        let src_memory = unsafe {
+            device.allocate_memory(
+                &vk::MemoryAllocateInfo::default()
+                    .allocation_size(requirements.size)
+                    .memory_type_index(get_memory_type_index(
+                        context.device_memory_properties,
+                        requirements.memory_type_bits,
+                        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    )),
+                None,
+            )
+        }
+        .unwrap();
+        unsafe { device.bind_image_memory(src_image, src_memory, 0) }.unwrap();
+
+        // 只把 mip 2 清成已知颜色，然后整个图像转换为 TRANSFER_SRC_OPTIMAL
+        let command_pool = context.command_pool();
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        let all_levels = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(3)
+            .layer_count(1);
+        let level_two = all_levels.base_mip_level(2).level_count(1);
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::default()
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .old_layout(vk::ImageLayout::UNDEFINED)
+                    .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .image(src_image)
+                    .subresource_range(all_levels)],
+            );
+            device.cmd_clear_color_image(
+                command_buffer,
+                src_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &vk::ClearColorValue {
+                    float32: [1.0, 0.0, 0.0, 1.0],
+                },
+                &[level_two],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                    .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .image(src_image)
+                    .subresource_range(all_levels)],
+            );
+        }
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+
+        let (dst_image, dst_memory) = create_host_visible_image(
+            device,
+            mip2.width,
+            mip2.height,
+            format,
+            context.device_memory_properties,
+        )
+        .unwrap();
+        let color = vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .layer_count(1);
+        copy_image_region_to_host(
+            device,
+            command_pool.pool,
+            context.queue,
+            src_image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            dst_image,
+            vk::ImageCopy::default()
+                .src_subresource(color.mip_level(2))
+                .dst_subresource(color)
+                .extent(mip2),
+        )
+        .unwrap();
+
+        let layout = unsafe {
+            device.get_image_subresource_layout(
+                dst_image,
+                vk::ImageSubresource::default().aspect_mask(vk::ImageAspectFlags::COLOR),
+            )
+        };
+        assert!(layout.row_pitch >= 4 * mip2.width as vk::DeviceSize);
+        assert!(layout.size >= layout.row_pitch * mip2.height as vk::DeviceSize);
+        unsafe {
+            let data = device
+                .map_memory(dst_memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+                .unwrap() as *const u8;
+            for y in 0..mip2.height as usize {
+                let row = std::slice::from_raw_parts(
+                    data.add(layout.offset as usize + y * layout.row_pitch as usize),
+                    4 * mip2.width as usize,
+                );
+                assert!(row.chunks_exact(4).all(|texel| texel == [255, 0, 0, 255]));
+            }
+            device.unmap_memory(dst_memory);
+
+            device.destroy_image(dst_image, None);
+            device.free_memory(dst_memory, None);
+            device.destroy_image(src_image, None);
+            device.free_memory(src_memory, None);
+            command_pool.destroy(device);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "record_clear_accumulation")]
+    fn layout_mismatch_names_the_operation() {
+        let image = RenderTargetImage {
+            image: vk::Image::null(),
+            memory: vk::DeviceMemory::null(),
+            view: vk::ImageView::null(),
+            layout: vk::ImageLayout::UNDEFINED,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            extent: vk::Extent2D {
+                width: 1,
+                height: 1,
+            },
+            usage: vk::ImageUsageFlags::STORAGE,
+        };
+        debug_assert_layout(
+            &image,
+            vk::ImageLayout::GENERAL,
+            "record_clear_accumulation",
+        );
+    }
+
+    #[test]
+    fn ray_tracing_output_requests_only_storage_and_transfer_src() {
+        assert_eq!(
+            RenderTargetUsage::RayTracingOutput.flags(),
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC
+        );
+        assert_eq!(
+            RenderTargetUsage::Accumulation.flags(),
+            RenderTargetUsage::RayTracingOutput.flags() | vk::ImageUsageFlags::TRANSFER_DST
+        );
+        assert!(
+            RenderTargetUsage::Rasterization
+                .flags()
+                .contains(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        );
+    }
+
+    #[test]
+    fn storage_only_float_target_does_not_need_color_attachment_support() {
+        use crate::test_support::test_context;
+
+        let Some(context) =
+            test_context("storage_only_float_target_does_not_need_color_attachment_support")
+        else {
+            return;
+        };
+        let format = vk::Format::R32G32B32A32_SFLOAT;
+        let features = unsafe {
+            context
+                .instance
+                .get_physical_device_format_properties(context.physical_device, format)
+        }
+        .optimal_tiling_features;
+        if !features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE) {
+            eprintln!("skipping: {:?} is not a storage image format here", format);
+            return;
+        }
+        if features.contains(vk::FormatFeatureFlags::COLOR_ATTACHMENT) {
+            eprintln!(
+                "note: {:?} is also a color attachment format here, checking the storage-only usage anyway",
+                format
+            );
+        }
+
+        let device = &context.device;
+        let image = RenderTargetImage::new(
+            device,
+            16,
+            16,
+            format,
+            RenderTargetUsage::RayTracingOutput,
+            context.device_memory_properties,
+        )
+        .unwrap();
+        assert_eq!(image.format, format);
+        assert!(!image.usage.contains(vk::ImageUsageFlags::COLOR_ATTACHMENT));
+
+        unsafe { image.destroy(device) };
+    }
+
+    #[test]
+    fn resized_render_target_matches_the_swapchain_extent() {
+        use crate::test_support::test_context;
+        use crate::windowed::choose_extent;
+
+        let Some(context) = test_context("resized_render_target_matches_the_swapchain_extent")
+        else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let mut target = RenderTargetImage::new(
+            device,
+            64,
+            48,
+            vk::Format::R32G32B32A32_SFLOAT,
+            RenderTargetUsage::Accumulation,
+            context.device_memory_properties,
+        )
+        .unwrap();
+        transition_image_to_general(device, command_pool.pool, context.queue, &mut target).unwrap();
+
+        // 模拟窗口被拖到 surface 上限之外：swapchain 取限制后的尺寸，渲染目标跟随它
+        let capabilities = vk::SurfaceCapabilitiesKHR {
+            current_extent: vk::Extent2D {
+                width: u32::MAX,
+                height: u32::MAX,
+            },
+            min_image_extent: vk::Extent2D {
+                width: 1,
+                height: 1,
+            },
+            max_image_extent: vk::Extent2D {
+                width: 120,
+                height: 90,
+            },
+            ..Default::default()
+        };
+        let extent = choose_extent(&capabilities, 160, 72);
+        target
+            .resize(
+                device,
+                command_pool.pool,
+                context.queue,
+                extent.width,
+                extent.height,
+                context.device_memory_properties,
+            )
+            .unwrap();
+
+        assert_eq!(target.extent, extent);
+        assert_eq!((extent.width, extent.height), (120, 72));
+        assert_eq!(target.format, vk::Format::R32G32B32A32_SFLOAT);
+        assert_eq!(target.usage, RenderTargetUsage::Accumulation.flags());
+        assert_eq!(target.layout, vk::ImageLayout::GENERAL);
+
+        unsafe {
+            target.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+
+    fn null_render_target() -> RenderTargetImage {
+        RenderTargetImage {
+            image: vk::Image::null(),
+            memory: vk::DeviceMemory::null(),
+            view: vk::ImageView::null(),
+            layout: vk::ImageLayout::GENERAL,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            extent: vk::Extent2D {
+                width: 1,
+                height: 1,
+            },
+            usage: RenderTargetUsage::Accumulation.flags(),
+        }
+    }
+
+    #[test]
+    fn accumulation_export_divides_by_the_accumulated_samples() {
+        let mut accumulation = AccumulationBuffer::new(null_render_target());
+        assert_eq!(accumulation.export_samples(), 1);
+
+        accumulation.add_samples(3);
+        accumulation.add_samples(2);
+        assert_eq!(accumulation.accumulated_samples(), 5);
+        assert_eq!(accumulation.export_samples(), 5);
+
+        accumulation.reset();
+        assert_eq!(accumulation.export_samples(), 1);
+    }
+
+    #[test]
+    fn saved_accumulation_png_uses_the_buffer_sample_count() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("saved_accumulation_png_uses_the_buffer_sample_count")
+        else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let mut image = RenderTargetImage::new(
+            device,
+            4,
+            4,
+            vk::Format::R32G32B32A32_SFLOAT,
+            RenderTargetUsage::Accumulation,
+            context.device_memory_properties,
+        )
+        .unwrap();
+        transition_image_to_general(device, command_pool.pool, context.queue, &mut image).unwrap();
+
+        // 4 个采样累加到 3.2，平均值 0.8；若错误地按 1 个采样导出会被截断成 255
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        unsafe {
+            device.cmd_clear_color_image(
+                command_buffer,
+                image.image,
+                vk::ImageLayout::GENERAL,
+                &vk::ClearColorValue {
+                    float32: [3.2, 3.2, 3.2, 4.0],
+                },
+                &[vk::ImageSubresourceRange::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .level_count(1)
+                    .layer_count(1)],
+            );
+        }
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+        let mut accumulation = AccumulationBuffer::new(image);
+        accumulation.add_samples(4);
+
+        let path =
+            std::env::temp_dir().join("rt_saved_accumulation_png_uses_the_buffer_sample_count.png");
+        let options = ExportOptions {
+            output_is_srgb: true,
+            ..ExportOptions::default()
+        };
+        save_accumulation_to_png(
+            device,
+            command_pool.pool,
+            context.queue,
+            &accumulation,
+            &path,
+            &options,
+            context.device_memory_properties,
+        )
+        .unwrap();
+
+        let decoder =
+            png::Decoder::new(std::io::BufReader::new(std::fs::File::open(&path).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut pixels).unwrap();
+        let expected = encode_channel(3.2, 1.0 / 4.0, &options);
+        assert_eq!(expected, 204);
+        assert_eq!(&pixels[..3], [expected; 3]);
+
+        std::fs::remove_file(&path).ok();
+        unsafe {
+            accumulation.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn copying_from_an_undefined_image_panics() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("copying_from_an_undefined_image_panics") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let image = RenderTargetImage::new(
+            device,
+            4,
+            4,
+            vk::Format::R32G32B32A32_SFLOAT,
+            RenderTargetUsage::RayTracingOutput,
+            context.device_memory_properties,
+        )
+        .unwrap();
+        assert_eq!(image.layout, vk::ImageLayout::UNDEFINED);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            copy_image_to_host(
+                device,
+                command_pool.pool,
+                context.queue,
+                &image,
+                vk::Image::null(),
+                4,
+                4,
+            )
+        }));
+        assert!(result.is_err(), "copy from UNDEFINED image did not panic");
+
+        unsafe {
+            image.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+
+    #[test]
+    fn frame_template_parsing() {
+        let directory = std::env::temp_dir().join("rt_frame_template_parsing");
+        let writer = FrameWriter::new(&directory, "frame_{:04}.png").unwrap();
+        assert_eq!(writer.frame_path(7), directory.join("frame_0007.png"));
+        let writer = FrameWriter::new(&directory, "{}.png").unwrap();
+        assert_eq!(writer.frame_path(12), directory.join("12.png"));
+
+        for template in [
+            "frame.png",
+            "frame_{:04.png",
+            "frame_{:x}.png",
+            "frame_{}_{}.png",
+        ] {
+            let error = FrameWriter::new(&directory, template).err();
+            assert_eq!(
+                error.map(|error| error.kind()),
+                Some(std::io::ErrorKind::InvalidInput),
+                "{}",
+                template
+            );
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn frame_writer_writes_a_numbered_sequence() {
+        let directory = std::env::temp_dir().join("rt_frame_writer_sequence");
+        let _ = std::fs::remove_dir_all(&directory);
+        let writer = FrameWriter::new(&directory, "frame_{:04}.png").unwrap();
+        let rows = vec![[255u8, 0, 0, 255].repeat(2); 2];
+
+        for index in 0..3 {
+            writer.write_frame_rows(index, 2, 2, &rows);
+        }
+
+        for name in ["frame_0000.png", "frame_0001.png", "frame_0002.png"] {
+            assert!(directory.join(name).is_file(), "{} is missing", name);
+        }
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 3);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn sample_counts_come_from_the_color_depth_intersection() {
+        let limits = vk::PhysicalDeviceLimits {
+            framebuffer_color_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_4
+                | vk::SampleCountFlags::TYPE_8,
+            framebuffer_depth_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_2
+                | vk::SampleCountFlags::TYPE_4,
+            ..Default::default()
+        };
+        assert_eq!(
+            max_common_sample_count(&limits),
+            vk::SampleCountFlags::TYPE_4
+        );
+        assert_eq!(
+            clamp_sample_count(vk::SampleCountFlags::TYPE_8, &limits),
+            vk::SampleCountFlags::TYPE_4
+        );
+        // 交集中没有 2x，退回到更低的 1x
+        assert_eq!(
+            clamp_sample_count(vk::SampleCountFlags::TYPE_2, &limits),
+            vk::SampleCountFlags::TYPE_1
+        );
+        assert_eq!(
+            max_common_sample_count(&vk::PhysicalDeviceLimits::default()),
+            vk::SampleCountFlags::TYPE_1
+        );
+    }
+}
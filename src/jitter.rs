@@ -0,0 +1,115 @@
+/// 以 base 为底的 radical inverse，得到 Halton 序列的第 index 项
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0_f32;
+    let mut fraction = 1.0_f32 / base as f32;
+    while index > 0 {
+        result += (index % base) as f32 * fraction;
+        index /= base;
+        fraction /= base as f32;
+    }
+    result
+}
+
+/// 二维 Halton(2, 3) 序列的第 index 项，取值范围 [0, 1)
+///
+/// 序号 0 对应原点，通常从 1 开始取样
+pub fn halton_2d(index: u32) -> (f32, f32) {
+    (halton(index, 2), halton(index, 3))
+}
+
+/// 单位正方形到单位圆盘的同心映射（Shirley-Chiu），保持面积均匀且畸变小
+pub fn concentric_disk(u: f32, v: f32) -> [f32; 2] {
+    let a = 2.0 * u - 1.0;
+    let b = 2.0 * v - 1.0;
+    if a == 0.0 && b == 0.0 {
+        return [0.0, 0.0];
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, std::f32::consts::FRAC_PI_4 * (b / a))
+    } else {
+        (
+            b,
+            std::f32::consts::FRAC_PI_2 - std::f32::consts::FRAC_PI_4 * (a / b),
+        )
+    };
+    let (sin, cos) = theta.sin_cos();
+    [r * cos, r * sin]
+}
+
+/// 第 index 次采样在半径为 aperture_radius 的镜头圆盘上的位置（相机空间 right/up 方向）
+///
+/// 使用 Halton(5, 7)，与子像素抖动的 Halton(2, 3) 不相关；aperture_radius 为 0 时恒为原点
+pub fn lens_sample(index: u32, aperture_radius: f32) -> [f32; 2] {
+    if aperture_radius <= 0.0 {
+        return [0.0, 0.0];
+    }
+    let [x, y] = concentric_disk(halton(index, 5), halton(index, 7));
+    [x * aperture_radius, y * aperture_radius]
+}
+
+/// 逐帧推进的子像素抖动序列，用于累积式抗锯齿
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JitterSequence {
+    pub index: u32,
+}
+
+impl JitterSequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 推进序列并返回以像素中心为原点的偏移，范围 [-0.5, 0.5)
+    pub fn next_jitter(&mut self) -> [f32; 2] {
+        self.index = self.index.wrapping_add(1);
+        let (x, y) = halton_2d(self.index);
+        [x - 0.5, y - 0.5]
+    }
+
+    pub fn reset(&mut self) {
+        self.index = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_sixteen_halton_samples_hit_every_quadrant() {
+        let mut quadrants = [0u32; 4];
+        for index in 1..=16 {
+            let (x, y) = halton_2d(index);
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+            quadrants[(x >= 0.5) as usize + 2 * (y >= 0.5) as usize] += 1;
+        }
+        assert!(quadrants.iter().all(|&count| count > 0), "{:?}", quadrants);
+    }
+
+    #[test]
+    fn zero_aperture_lens_sample_is_origin() {
+        for index in 0..16 {
+            assert_eq!(lens_sample(index, 0.0), [0.0, 0.0]);
+            assert_eq!(lens_sample(index, -1.0), [0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn lens_samples_stay_inside_the_aperture() {
+        for index in 1..256 {
+            let [x, y] = lens_sample(index, 0.25);
+            assert!((x * x + y * y).sqrt() <= 0.25 + 1e-6, "sample {}", index);
+        }
+    }
+
+    #[test]
+    fn jitter_stays_within_the_pixel_and_resets() {
+        let mut jitter = JitterSequence::new();
+        let first = jitter.next_jitter();
+        for _ in 0..64 {
+            let [x, y] = jitter.next_jitter();
+            assert!((-0.5..0.5).contains(&x) && (-0.5..0.5).contains(&y));
+        }
+        jitter.reset();
+        assert_eq!(jitter.next_jitter(), first);
+    }
+}
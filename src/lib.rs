@@ -2,8 +2,82 @@ pub mod vulkan_base;
 pub mod windowed;
 pub mod image_utils;
 pub mod buffer;
+pub mod command;
+pub mod acceleration_structure;
+pub mod trace;
+pub mod profiler;
+pub mod pipeline;
+pub mod jitter;
+pub mod scene;
+pub mod error;
+pub mod material;
+pub mod geometry;
+pub mod progressive;
+pub mod ring_buffer;
+#[cfg(feature = "shader-watcher")]
+pub mod shader_watcher;
+pub mod cubemap;
+pub mod wavefront;
+#[cfg(feature = "reflection")]
+pub mod reflection;
+pub mod frame_timer;
+pub mod camera;
+pub mod stereo;
+pub mod resource_tracker;
+pub mod texture;
+pub mod gbuffer;
+pub mod scene_graph;
+pub mod render_target_pool;
+pub mod frame_sync;
+pub mod tonemap;
+pub mod auto_exposure;
+pub mod fence_pool;
+pub mod semaphore_pool;
+pub mod descriptor;
+pub mod address_registry;
+pub mod text_overlay;
+pub mod color;
+pub mod motion_blur;
+#[cfg(test)]
+mod test_support;
 
 pub use vulkan_base::*;
 pub use windowed::*;
 pub use image_utils::*;
-pub use buffer::*;
\ No newline at end of file
+pub use buffer::*;
+pub use command::*;
+pub use acceleration_structure::*;
+pub use trace::*;
+pub use profiler::*;
+pub use pipeline::*;
+pub use jitter::*;
+pub use scene::*;
+pub use error::*;
+pub use material::*;
+pub use geometry::*;
+pub use progressive::*;
+pub use ring_buffer::*;
+#[cfg(feature = "shader-watcher")]
+pub use shader_watcher::*;
+pub use cubemap::*;
+pub use wavefront::*;
+#[cfg(feature = "reflection")]
+pub use reflection::*;
+pub use frame_timer::*;
+pub use camera::*;
+pub use stereo::*;
+pub use resource_tracker::*;
+pub use texture::*;
+pub use gbuffer::*;
+pub use scene_graph::*;
+pub use render_target_pool::*;
+pub use frame_sync::*;
+pub use tonemap::*;
+pub use auto_exposure::*;
+pub use fence_pool::*;
+pub use semaphore_pool::*;
+pub use descriptor::*;
+pub use address_registry::*;
+pub use text_overlay::*;
+pub use color::*;
+pub use motion_blur::*;
\ No newline at end of file
@@ -1,6 +1,8 @@
 use vulkan_raytracing::*;
-use ash::khr;
+use ash::{ext, khr, vk};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+use std::cell::Cell;
+use std::rc::Rc;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // ========== 渲染配置 ==========
@@ -10,9 +12,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // ========== GLFW 初始化 ==========
     let mut glfw = glfw::init(glfw::fail_on_errors)?;
-    let window = if !HEADLESS_MODE {
+    // 帧缓冲尺寸变化时由回调置位，主循环据此重建 swapchain 与渲染目标
+    let resized = Rc::new(Cell::new(false));
+    let mut window = if !HEADLESS_MODE {
         glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::NoApi));
-        glfw.window_hint(glfw::WindowHint::Resizable(false));
+        glfw.window_hint(glfw::WindowHint::Resizable(true));
         let (mut win, _events) = glfw
             .create_window(
                 WIDTH,
@@ -28,6 +32,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         });
 
+        let resized = Rc::clone(&resized);
+        win.set_framebuffer_size_callback(move |_window, _width, _height| {
+            resized.set(true);
+        });
+
         Some(win)
     } else {
         None
@@ -37,14 +46,27 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let validation = ValidationLayerConfig::new();
     let entry = unsafe { ash::Entry::load() }?;
     assert!(validation.check_support(&entry)?, "Validation layer not supported");
+    // 每帧重复的验证警告只打印前 5 次，之后每抑制 100 条打印一次汇总
+    enable_message_dedup(5, 100)?;
 
     // ========== Vulkan Instance 创建 ==========
-    let instance_extensions = get_instance_extensions(HEADLESS_MODE);
+    let mut instance_extensions =
+        get_available_instance_extensions(&entry, &get_instance_extensions(HEADLESS_MODE));
+    // VK_EXT_validation_features 由验证层提供，不在 loader 的扩展列表中，因此不参与过滤
+    let validation_features = validation.enabled_features()?;
+    if !validation_features.is_empty() {
+        instance_extensions.push(ext::validation_features::NAME.as_ptr());
+    }
+    instance_extensions.extend(get_optional_instance_extensions(&entry, HEADLESS_MODE));
+    // 缺少 debug_utils 时仍启用验证层，只是不挂调试信使
+    let enable_debug_messenger = validation.enabled && has_debug_utils(&instance_extensions);
     let instance = create_instance(
         &entry,
+        &ApplicationInfo::default(),
         &validation.as_ptrs(),
         &instance_extensions,
-        validation.enabled,
+        enable_debug_messenger,
+        &validation_features,
     )?;
 
     println!("Vulkan Instance created successfully");
@@ -77,8 +99,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // ========== 物理设备和队列族选择 ==========
     let (physical_device, queue_indices) = pick_physical_device_and_queue_family_indices(
-        &instance,
-        surface_loader.as_ref(),
+        &InstanceQueries::new(&instance, surface_loader.as_ref()),
         surface,
         &[
             khr::acceleration_structure::NAME,
@@ -92,12 +113,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let graphics_queue_index = queue_indices.graphics_family.unwrap();
 
     // 打印物理设备信息
-    let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
-    let device_name = unsafe {
-        std::ffi::CStr::from_ptr(device_properties.device_name.as_ptr())
-            .to_string_lossy()
-    };
-    println!("Selected physical device: {}", device_name);
+    print_device_report(&instance, physical_device);
     println!("Graphics queue family index: {}", graphics_queue_index);
     if let Some(compute_index) = queue_indices.compute_family {
         println!("Compute queue family index: {}", compute_index);
@@ -107,7 +123,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // ========== 逻辑设备创建 ==========
-    let device = create_device(&instance, physical_device, &queue_indices, HEADLESS_MODE)?;
+    let device_config = DeviceConfig::new(HEADLESS_MODE);
+    let device = create_device(&instance, physical_device, &queue_indices, &device_config)?;
 
     println!("Logical device created successfully");
 
@@ -115,16 +132,47 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let graphics_queue = unsafe { device.get_device_queue(graphics_queue_index, 0) };
     println!("Graphics queue obtained: {:?}", graphics_queue);
 
+    let device_memory_properties =
+        unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+    // 登记设备子对象，销毁设备前检查是否都已销毁
+    let mut resource_tracker = ResourceTracker::new();
+
+    // ========== 命令池与渲染目标 ==========
+    let command_pool = CommandPoolManager::new(&device, graphics_queue_index)?;
+    resource_tracker.track(command_pool.pool, "command pool");
+
+    check_render_target_size(&instance, physical_device, WIDTH, HEIGHT)?;
+    let render_target_format = choose_render_target_format(&instance, physical_device, &[])
+        .ok_or("No storage image format available for the render target")?;
+    let mut render_target = RenderTargetImage::new(
+        &device,
+        WIDTH,
+        HEIGHT,
+        render_target_format,
+        RenderTargetUsage::Accumulation,
+        device_memory_properties,
+    )?;
+    resource_tracker.track(render_target.image, "render target");
+    transition_image_to_general(&device, command_pool.pool, graphics_queue, &mut render_target)?;
+
+    println!(
+        "Render target created: {}x{} ({:?})",
+        WIDTH, HEIGHT, render_target_format
+    );
+
     // ========== Swapchain 创建 ==========
-    let swapchain = if !HEADLESS_MODE && surface.is_some() {
+    let mut swapchain = if !HEADLESS_MODE && surface.is_some() {
         let sc = Swapchain::new(
             &instance,
             &device,
             physical_device,
+            &queue_indices,
             surface.unwrap(),
             surface_loader.as_ref().unwrap(),
             WIDTH,
             HEIGHT,
+            None,
         )?;
         println!(
             "Swapchain created: format={:?}, extent={}x{}, images={}",
@@ -133,12 +181,78 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             sc.extent.height,
             sc.images.len()
         );
+        resource_tracker.track(sc.swapchain, "swapchain");
         Some(sc)
     } else {
         None
     };
 
+    // ========== 帧同步 ==========
+    // 每帧一个命令缓冲，以该帧的 in_flight_fence 提交，复用前由 wait_and_reset 等待上一次提交完成
+    const FRAMES_IN_FLIGHT: usize = 2;
+    let mut frame_sync = FrameSync::new(&device, FRAMES_IN_FLIGHT)?;
+    let frame_command_buffers = unsafe {
+        device.allocate_command_buffers(
+            &vk::CommandBufferAllocateInfo::default()
+                .command_pool(command_pool.pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(FRAMES_IN_FLIGHT as u32),
+        )
+    }?;
+
+    // ========== 描述符集 ==========
+    // 光追管线的 set 0：渲染目标以 STORAGE_IMAGE 绑定，每个 frame in flight 一个描述符集
+    const RENDER_TARGET_BINDING: u32 = 0;
+    let descriptor_set_layout = unsafe {
+        device.create_descriptor_set_layout(
+            &vk::DescriptorSetLayoutCreateInfo::default().bindings(&[
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(RENDER_TARGET_BINDING)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR),
+            ]),
+            None,
+        )
+    }?;
+    resource_tracker.track(descriptor_set_layout, "descriptor set layout");
+    let descriptor_pool = unsafe {
+        device.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::default()
+                .max_sets(FRAMES_IN_FLIGHT as u32)
+                .pool_sizes(&[vk::DescriptorPoolSize {
+                    ty: vk::DescriptorType::STORAGE_IMAGE,
+                    descriptor_count: FRAMES_IN_FLIGHT as u32,
+                }]),
+            None,
+        )
+    }?;
+    resource_tracker.track(descriptor_pool, "descriptor pool");
+    let descriptor_sets = DescriptorSets::new(
+        unsafe {
+            device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&[descriptor_set_layout; FRAMES_IN_FLIGHT]),
+            )
+        }?,
+        RENDER_TARGET_BINDING,
+    );
+    descriptor_sets.rebind_render_target(&device, render_target.view);
+
     // ========== 主循环 ==========
+    let mut frame_timer = FrameTimer::new(60);
+    let mut frame_count = 0u64;
+    let mut camera = Camera::default();
+    let camera_controller = CameraController::default();
+    // 渐进累积状态：相机移动或渲染目标重建后置 dirty，下一帧清空累积图像并从 1 个采样重新开始
+    let mut scene = SceneUniform::default();
+    let mut jitter = JitterSequence::new();
+    let mut accumulation_dirty = false;
+    let mut last_frame_time = glfw.get_time();
+    let mut last_cursor_pos: Option<(f64, f64)> = None;
+    let mut screenshot = ScreenshotCapture::new();
+    let mut screenshot_key_down = false;
     while !HEADLESS_MODE {
         glfw.poll_events();
         if let Some(win) = window.as_ref() {
@@ -147,22 +261,174 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
+        // 窗口尺寸变化：等待 GPU 空闲后按新的帧缓冲尺寸重建 swapchain 与渲染目标
+        if resized.get() {
+            let (width, height) = window.as_ref().unwrap().get_framebuffer_size();
+            // 最小化时帧缓冲尺寸为 0，等恢复后再重建
+            if width > 0 && height > 0 {
+                resized.set(false);
+                let (width, height) = (width as u32, height as u32);
+                frame_sync.drain(&device)?;
+
+                if let Some(sc) = swapchain.as_mut() {
+                    resource_tracker.untrack(sc.swapchain);
+                    sc.recreate(
+                        &instance,
+                        &device,
+                        physical_device,
+                        &queue_indices,
+                        surface.unwrap(),
+                        surface_loader.as_ref().unwrap(),
+                        width,
+                        height,
+                    )?;
+                    resource_tracker.track(sc.swapchain, "swapchain");
+                }
+
+                // swapchain 会把帧缓冲尺寸限制在 surface 允许的范围内，渲染目标与它保持一致
+                let (width, height) = swapchain
+                    .as_ref()
+                    .map_or((width, height), |sc| (sc.extent.width, sc.extent.height));
+                check_render_target_size(&instance, physical_device, width, height)?;
+                resource_tracker.untrack(render_target.image);
+                render_target.resize(
+                    &device,
+                    command_pool.pool,
+                    graphics_queue,
+                    width,
+                    height,
+                    device_memory_properties,
+                )?;
+                resource_tracker.track(render_target.image, "render target");
+                // 旧的 image view 已销毁，把新的 view 写回所有描述符集（drain 之后它们都不在使用中）
+                descriptor_sets.rebind_render_target(&device, render_target.view);
+
+                accumulation_dirty = true;
+                println!("Resized to {}x{}", width, height);
+            }
+        }
+
+        frame_timer.tick();
+        frame_count += 1;
+
+        let now = glfw.get_time();
+        let delta_time = (now - last_frame_time) as f32;
+        last_frame_time = now;
+
+        // WASD 移动，Space/LeftShift 升降，按住右键转动视角
+        if let Some(win) = window.as_ref() {
+            let pressed = |key| win.get_key(key) == glfw::Action::Press;
+            let mut input = CameraInput {
+                forward: pressed(glfw::Key::W),
+                backward: pressed(glfw::Key::S),
+                left: pressed(glfw::Key::A),
+                right: pressed(glfw::Key::D),
+                up: pressed(glfw::Key::Space),
+                down: pressed(glfw::Key::LeftShift),
+                look_delta: [0.0, 0.0],
+            };
+
+            let cursor_pos = win.get_cursor_pos();
+            if win.get_mouse_button(glfw::MouseButton::Button2) == glfw::Action::Press {
+                if let Some((last_x, last_y)) = last_cursor_pos {
+                    input.look_delta =
+                        [(cursor_pos.0 - last_x) as f32, (cursor_pos.1 - last_y) as f32];
+                }
+                last_cursor_pos = Some(cursor_pos);
+            } else {
+                last_cursor_pos = None;
+            }
+
+            if camera_controller.update(&mut camera, &input, delta_time) {
+                accumulation_dirty = true;
+            }
+
+            // F12 按下时（边沿触发）请求截图
+            let f12_down = pressed(glfw::Key::F12);
+            if f12_down && !screenshot_key_down {
+                screenshot.request_screenshot(format!("screenshot_{:06}.png", frame_count));
+            }
+            screenshot_key_down = f12_down;
+        }
+
+        frame_sync.wait_and_reset(&device)?;
+        let recorder = CommandRecorder::begin(
+            &device,
+            frame_command_buffers[frame_sync.frame_index()],
+            &vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+        )?;
+        if advance_accumulation(&mut scene, &mut jitter, &mut accumulation_dirty) {
+            record_clear_accumulation(&device, recorder.command_buffer(), &render_target);
+        }
+        let command_buffers = [recorder.finish()?];
+        frame_sync.submit(
+            &device,
+            graphics_queue,
+            vk::SubmitInfo::default().command_buffers(&command_buffers),
+        )?;
+        frame_sync.advance();
+
+        if let Some(path) = screenshot.capture_pending(
+            &device,
+            command_pool.pool,
+            graphics_queue,
+            &render_target,
+            scene.total_samples(),
+            device_memory_properties,
+        )? {
+            println!("Screenshot saved to {}", path.display());
+        }
+        if frame_count.is_multiple_of(30) {
+            if let Some(win) = window.as_mut() {
+                win.set_title(&format!(
+                    "Vulkan Raytracing - {:.1} FPS ({:.2} ms)",
+                    frame_timer.fps(),
+                    frame_timer.frame_time_ms()
+                ));
+            }
+        }
+
         std::thread::sleep(std::time::Duration::from_millis(16));
     }
 
     // ========== 资源清理 ==========
+    // 销毁顺序与创建顺序相反，先销毁依赖 device 的对象：
+    // 管线 → SBT/缓冲 → 描述符池 → 加速结构 → 图像 → 命令池 → Swapchain → 逻辑设备 → Surface → Instance
     println!("Cleaning up resources...");
 
+    // 只等待仍在执行的帧；失败时退回到等待整个设备空闲
+    if let Err(error) = frame_sync.drain(&device) {
+        println!("[Warning] Fence drain failed ({}), waiting for device idle", error);
+        unsafe { device.device_wait_idle() }?;
+    }
+
     unsafe {
-        device.device_wait_idle()?;
+        // 销毁帧同步对象
+        frame_sync.destroy(&device);
+
+        // 销毁描述符池（同时释放其中的描述符集）与布局
+        resource_tracker.untrack(descriptor_pool);
+        device.destroy_descriptor_pool(descriptor_pool, None);
+        resource_tracker.untrack(descriptor_set_layout);
+        device.destroy_descriptor_set_layout(descriptor_set_layout, None);
+
+        // 销毁渲染目标
+        resource_tracker.untrack(render_target.image);
+        render_target.destroy(&device);
+
+        // 销毁命令池
+        resource_tracker.untrack(command_pool.pool);
+        command_pool.destroy(&device);
 
         // 销毁 Swapchain
         if let Some(sc) = swapchain {
+            resource_tracker.untrack(sc.swapchain);
             sc.destroy(&device);
         }
 
         // 销毁逻辑设备
-        device.destroy_device(None);
+        safe_destroy_device(&device, &resource_tracker);
 
         // 销毁 Surface
         if let Some(s) = surface {
@@ -0,0 +1,137 @@
+use ash::{Device, vk};
+use bytemuck::{Pod, Zeroable};
+
+use crate::buffer::BufferResource;
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+
+/// 与着色器中 Material 结构一一对应（scalar 布局，48 字节）
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct Material {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// 纹理下标，-1 表示不使用纹理
+    pub base_color_texture: i32,
+    pub normal_texture: i32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            emissive: [0.0, 0.0, 0.0, 0.0],
+            metallic: 0.0,
+            roughness: 1.0,
+            base_color_texture: -1,
+            normal_texture: -1,
+        }
+    }
+}
+
+/// 场景中所有材质组成的 STORAGE_BUFFER
+///
+/// closest-hit 着色器通过 gl_InstanceCustomIndexEXT 索引
+pub struct MaterialBuffer {
+    pub buffer: BufferResource,
+    pub count: u32,
+}
+
+impl MaterialBuffer {
+    pub fn from_materials(
+        materials: &[Material],
+        device: &Device,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+    ) -> Result<Self, RtError> {
+        let buffer = BufferResource::new_device_local(
+            materials,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+            device,
+            device_memory_properties,
+            command_pool,
+            queue,
+        )?;
+
+        Ok(Self {
+            buffer,
+            count: materials.len() as u32,
+        })
+    }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer.buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            self.buffer.destroy(device);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_context;
+
+    #[test]
+    fn material_matches_the_shader_layout() {
+        assert_eq!(size_of::<Material>(), 48);
+        assert_eq!(std::mem::offset_of!(Material, emissive), 16);
+        assert_eq!(std::mem::offset_of!(Material, metallic), 32);
+        assert_eq!(std::mem::offset_of!(Material, normal_texture), 44);
+    }
+
+    #[test]
+    fn uploaded_materials_read_back_unchanged() {
+        let Some(context) = test_context("uploaded_materials_read_back_unchanged") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+
+        let materials = [
+            Material::default(),
+            Material {
+                base_color: [0.8, 0.1, 0.1, 1.0],
+                metallic: 1.0,
+                roughness: 0.2,
+                base_color_texture: 3,
+                ..Default::default()
+            },
+            Material {
+                emissive: [4.0, 4.0, 4.0, 0.0],
+                normal_texture: 7,
+                ..Default::default()
+            },
+        ];
+        let buffer = MaterialBuffer::from_materials(
+            &materials,
+            device,
+            context.device_memory_properties,
+            &command_pool,
+            context.queue,
+        )
+        .unwrap();
+        assert_eq!(buffer.count, 3);
+        assert_eq!(buffer.buffer.size, 3 * 48);
+
+        let read_back: Vec<Material> = context.read_buffer(&buffer.buffer);
+        assert_eq!(
+            bytemuck::cast_slice::<Material, u8>(&read_back),
+            bytemuck::cast_slice::<Material, u8>(&materials)
+        );
+
+        unsafe {
+            buffer.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
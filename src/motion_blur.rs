@@ -0,0 +1,327 @@
+use ash::{Device, khr, vk};
+
+use crate::acceleration_structure::{
+    AccelerationStructureLimits, TopLevelAS, validate_build_flags,
+};
+use crate::buffer::BufferResource;
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+
+/// 运动实例数组的步长，规范要求每个 vk::AccelerationStructureMotionInstanceNV 占 160 字节
+pub const MOTION_INSTANCE_STRIDE: vk::DeviceSize = 160;
+
+/// 带起止变换的 TLAS 实例，光线时间 t ∈ [0, 1] 时的变换由两者线性插值得到
+#[derive(Clone, Copy)]
+pub struct MotionInstance {
+    /// 快门打开（time = 0）时的变换
+    pub transform_t0: vk::TransformMatrixKHR,
+    /// 快门关闭（time = 1）时的变换
+    pub transform_t1: vk::TransformMatrixKHR,
+    /// gl_InstanceCustomIndexEXT，只使用低 24 位
+    pub custom_index: u32,
+    pub mask: u8,
+    /// 命中组在 SBT 中的偏移，只使用低 24 位
+    pub sbt_record_offset: u32,
+    pub flags: vk::GeometryInstanceFlagsKHR,
+    /// 引用的 BLAS 的 device address，BLAS 本身不需要带 MOTION_NV 构建
+    pub blas_address: u64,
+}
+
+impl MotionInstance {
+    /// 与 SceneGraph 生成的静态实例使用相同的默认值：mask 0xFF，关闭背面剔除
+    pub fn new(
+        blas_address: u64,
+        custom_index: u32,
+        transform_t0: vk::TransformMatrixKHR,
+        transform_t1: vk::TransformMatrixKHR,
+    ) -> Self {
+        Self {
+            transform_t0,
+            transform_t1,
+            custom_index,
+            mask: 0xFF,
+            sbt_record_offset: 0,
+            flags: vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE,
+            blas_address,
+        }
+    }
+
+    /// 打包为矩阵运动实例
+    pub fn to_vk(&self) -> vk::AccelerationStructureMotionInstanceNV {
+        vk::AccelerationStructureMotionInstanceNV {
+            ty: vk::AccelerationStructureMotionInstanceTypeNV::MATRIX_MOTION,
+            flags: vk::AccelerationStructureMotionInstanceFlagsNV::empty(),
+            data: vk::AccelerationStructureMotionInstanceDataNV {
+                matrix_motion_instance: vk::AccelerationStructureMatrixMotionInstanceNV {
+                    transform_t0: self.transform_t0,
+                    transform_t1: self.transform_t1,
+                    instance_custom_index_and_mask: vk::Packed24_8::new(
+                        self.custom_index,
+                        self.mask,
+                    ),
+                    instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+                        self.sbt_record_offset,
+                        self.flags.as_raw() as u8,
+                    ),
+                    acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+                        device_handle: self.blas_address,
+                    },
+                },
+            },
+        }
+    }
+}
+
+/// 按 MOTION_INSTANCE_STRIDE 把实例打包为字节数组，供上传到实例 buffer
+///
+/// ash 中 vk::AccelerationStructureMotionInstanceNV 只有 152 字节，不能直接作为数组上传，
+/// 每个实例后面补零到 160 字节。
+pub fn pack_motion_instances(instances: &[MotionInstance]) -> Vec<u8> {
+    let stride = MOTION_INSTANCE_STRIDE as usize;
+    let mut bytes = vec![0u8; instances.len() * stride];
+    for (instance, slot) in instances.iter().zip(bytes.chunks_exact_mut(stride)) {
+        let packed = instance.to_vk();
+        let size = size_of::<vk::AccelerationStructureMotionInstanceNV>();
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                &packed as *const vk::AccelerationStructureMotionInstanceNV as *const u8,
+                slot.as_mut_ptr(),
+                size,
+            );
+        }
+    }
+    bytes
+}
+
+/// 在 flags 上加上运动 TLAS 必需的 MOTION_NV
+pub fn motion_build_flags(
+    flags: vk::BuildAccelerationStructureFlagsKHR,
+) -> vk::BuildAccelerationStructureFlagsKHR {
+    flags | vk::BuildAccelerationStructureFlagsKHR::MOTION_NV
+}
+
+/// 基于 VK_NV_ray_tracing_motion_blur 的运动模糊 TLAS，需要以 DeviceConfig::ray_tracing_motion_blur 创建设备
+///
+/// raygen 需以 RAY_TRACING_ALLOW_MOTION_NV 创建管线，并用 traceRayMotionNV 传入光线时间。
+/// cmd_trace_rays 本身没有时间参数，时间通过 SceneUniform::shutter_time 逐帧传给着色器，
+/// 在 [0, 1) 内随累积采样变化，累积足够帧后得到运动模糊。
+pub struct MotionTopLevelAS {
+    pub acceleration_structure: vk::AccelerationStructureKHR,
+    pub buffer: BufferResource,
+    /// host 可见的 vk::AccelerationStructureMotionInstanceNV 数组，步长 MOTION_INSTANCE_STRIDE
+    pub instance_buffer: BufferResource,
+    pub device_address: u64,
+    /// 构建使用的标志，总是包含 MOTION_NV
+    pub flags: vk::BuildAccelerationStructureFlagsKHR,
+    pub instance_count: u32,
+    pub size: vk::DeviceSize,
+}
+
+impl MotionTopLevelAS {
+    /// 创建并构建运动 TLAS，提交后等待完成，flags 会自动加上 MOTION_NV
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        instances: &[MotionInstance],
+        flags: vk::BuildAccelerationStructureFlagsKHR,
+        limits: &AccelerationStructureLimits,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        limits.check_tlas(instances.len())?;
+        let flags = motion_build_flags(flags);
+        validate_build_flags(flags)?;
+        let instance_count = instances.len() as u32;
+
+        let motion_instances = pack_motion_instances(instances);
+
+        let instance_buffer = BufferResource::new(
+            instance_count.max(1) as vk::DeviceSize * MOTION_INSTANCE_STRIDE,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            device_memory_properties,
+        );
+        if !motion_instances.is_empty() {
+            instance_buffer.store_from_thread(&motion_instances, 0, device);
+        }
+
+        let geometries = [TopLevelAS::instances_geometry(
+            instance_buffer.device_address(device),
+        )];
+        let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .flags(flags)
+            .geometries(&geometries)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL);
+
+        let mut size_info = vk::AccelerationStructureBuildSizesInfoKHR::default();
+        unsafe {
+            as_loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &build_info,
+                &[instance_count],
+                &mut size_info,
+            );
+        }
+
+        let buffer = BufferResource::new(
+            size_info.acceleration_structure_size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+            device_memory_properties,
+        );
+
+        let mut motion_info =
+            vk::AccelerationStructureMotionInfoNV::default().max_instances(instance_count);
+        let as_create_info = vk::AccelerationStructureCreateInfoKHR::default()
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+            .create_flags(vk::AccelerationStructureCreateFlagsKHR::MOTION_NV)
+            .size(size_info.acceleration_structure_size)
+            .buffer(buffer.buffer)
+            .offset(0)
+            .push_next(&mut motion_info);
+
+        let mut acceleration_structure = vk::AccelerationStructureKHR::null();
+        let result = (|| -> Result<(), RtError> {
+            acceleration_structure =
+                unsafe { as_loader.create_acceleration_structure(&as_create_info, None) }?;
+
+            let scratch_buffer = BufferResource::new(
+                size_info.build_scratch_size,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                device,
+                device_memory_properties,
+            );
+
+            build_info = build_info
+                .dst_acceleration_structure(acceleration_structure)
+                .scratch_data(vk::DeviceOrHostAddressKHR {
+                    device_address: scratch_buffer.device_address(device),
+                });
+            let build_range_infos = [vk::AccelerationStructureBuildRangeInfoKHR::default()
+                .primitive_count(instance_count)];
+
+            let submitted = (|| -> Result<(), RtError> {
+                let command_buffer = command_pool.begin_one_time(device)?;
+                unsafe {
+                    as_loader.cmd_build_acceleration_structures(
+                        command_buffer,
+                        &[build_info],
+                        &[&build_range_infos],
+                    );
+                }
+                command_pool.end_one_time(device, queue, command_buffer)
+            })();
+
+            unsafe { scratch_buffer.destroy(device) };
+            submitted
+        })();
+
+        if let Err(err) = result {
+            unsafe {
+                // 创建失败时 acceleration_structure 仍为 null，销毁空句柄是合法的
+                as_loader.destroy_acceleration_structure(acceleration_structure, None);
+                buffer.destroy(device);
+                instance_buffer.destroy(device);
+            }
+            return Err(err);
+        }
+
+        let device_address = unsafe {
+            as_loader.get_acceleration_structure_device_address(
+                &vk::AccelerationStructureDeviceAddressInfoKHR::default()
+                    .acceleration_structure(acceleration_structure),
+            )
+        };
+
+        Ok(Self {
+            acceleration_structure,
+            buffer,
+            instance_buffer,
+            device_address,
+            flags,
+            instance_count,
+            size: size_info.acceleration_structure_size,
+        })
+    }
+
+    pub unsafe fn destroy(self, device: &Device, as_loader: &khr::acceleration_structure::Device) {
+        unsafe {
+            as_loader.destroy_acceleration_structure(self.acceleration_structure, None);
+            self.buffer.destroy(device);
+            self.instance_buffer.destroy(device);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(x: f32) -> vk::TransformMatrixKHR {
+        vk::TransformMatrixKHR {
+            matrix: [1.0, 0.0, 0.0, x, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        }
+    }
+
+    #[test]
+    fn motion_build_flags_adds_motion_and_keeps_the_rest() {
+        let flags = motion_build_flags(
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+                | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE,
+        );
+        assert_eq!(
+            flags,
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+                | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE
+                | vk::BuildAccelerationStructureFlagsKHR::MOTION_NV
+        );
+        assert_eq!(motion_build_flags(flags), flags);
+        assert!(validate_build_flags(flags).is_ok());
+    }
+
+    #[test]
+    fn motion_instance_packs_both_transforms() {
+        let packed =
+            MotionInstance::new(0xABCD_0000, 7, translation(1.0), translation(3.0)).to_vk();
+        assert_eq!(
+            packed.ty,
+            vk::AccelerationStructureMotionInstanceTypeNV::MATRIX_MOTION
+        );
+        let matrix = unsafe { packed.data.matrix_motion_instance };
+        assert_eq!(matrix.transform_t0.matrix[3], 1.0);
+        assert_eq!(matrix.transform_t1.matrix[3], 3.0);
+        assert_eq!(matrix.instance_custom_index_and_mask.low_24(), 7);
+        assert_eq!(matrix.instance_custom_index_and_mask.high_8(), 0xFF);
+        assert_eq!(
+            unsafe { matrix.acceleration_structure_reference.device_handle },
+            0xABCD_0000
+        );
+    }
+
+    #[test]
+    fn packed_motion_instances_use_the_spec_stride() {
+        let instances = [
+            MotionInstance::new(1, 0, translation(0.0), translation(1.0)),
+            MotionInstance::new(2, 1, translation(2.0), translation(5.0)),
+        ];
+        let bytes = pack_motion_instances(&instances);
+        assert_eq!(bytes.len(), 2 * MOTION_INSTANCE_STRIDE as usize);
+
+        // 第二个实例的 transform_t1 位于 160 字节之后：type + flags (8) + transform_t0 (48)
+        let t1_x = 160 + 8 + 48 + 3 * 4;
+        assert_eq!(
+            f32::from_ne_bytes(bytes[t1_x..t1_x + 4].try_into().unwrap()),
+            5.0
+        );
+        let size = size_of::<vk::AccelerationStructureMotionInstanceNV>();
+        assert!(bytes[size..160].iter().all(|&byte| byte == 0));
+    }
+}
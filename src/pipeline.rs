@@ -0,0 +1,236 @@
+use ash::{Device, vk};
+use std::path::Path;
+
+/// 可持久化到磁盘的 vk::PipelineCache
+///
+/// 创建光线追踪管线或计算管线时传入 `cache` 句柄，程序退出前调用 `save`，
+/// 下次启动时 `load_or_create` 即可复用已编译的管线数据。
+pub struct PipelineCache {
+    pub cache: vk::PipelineCache,
+}
+
+impl PipelineCache {
+    /// 从 path 读取缓存数据创建管线缓存；文件不存在或数据损坏时创建空缓存
+    pub fn load_or_create(device: &Device, path: impl AsRef<Path>) -> Result<Self, vk::Result> {
+        let initial_data = std::fs::read(path)
+            .ok()
+            .filter(|data| Self::has_valid_header(data));
+
+        if let Some(data) = initial_data {
+            let create_info = vk::PipelineCacheCreateInfo::default().initial_data(&data);
+            if let Ok(cache) = unsafe { device.create_pipeline_cache(&create_info, None) } {
+                return Ok(Self { cache });
+            }
+        }
+
+        let cache =
+            unsafe { device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None) }?;
+
+        Ok(Self { cache })
+    }
+
+    /// 检查 VkPipelineCacheHeaderVersionOne 的头部长度与版本字段
+    fn has_valid_header(data: &[u8]) -> bool {
+        const HEADER_SIZE: usize = 16 + 4 * 4;
+
+        if data.len() < HEADER_SIZE {
+            return false;
+        }
+
+        let header_size = u32::from_ne_bytes(data[0..4].try_into().unwrap());
+        let header_version = i32::from_ne_bytes(data[4..8].try_into().unwrap());
+
+        header_size as usize == HEADER_SIZE
+            && header_version == vk::PipelineCacheHeaderVersion::ONE.as_raw()
+    }
+
+    /// 读取缓存数据
+    pub fn data(&self, device: &Device) -> Result<Vec<u8>, vk::Result> {
+        unsafe { device.get_pipeline_cache_data(self.cache) }
+    }
+
+    /// 将缓存数据写入 path
+    pub fn save(
+        &self,
+        device: &Device,
+        path: impl AsRef<Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let data = self.data(device)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            device.destroy_pipeline_cache(self.cache, None);
+        }
+    }
+}
+
+/// 从 SPIR-V 字码创建着色器模块
+pub fn create_shader_module(device: &Device, code: &[u32]) -> Result<vk::ShaderModule, vk::Result> {
+    let create_info = vk::ShaderModuleCreateInfo::default().code(code);
+    unsafe { device.create_shader_module(&create_info, None) }
+}
+
+/// 特化常量的值，按 SPIR-V 中对应常量的类型选择
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpecValue {
+    U32(u32),
+    I32(i32),
+    F32(f32),
+    /// SPIR-V 的布尔特化常量占 4 字节（VkBool32）
+    Bool(bool),
+}
+
+impl SpecValue {
+    fn to_bytes(self) -> [u8; 4] {
+        match self {
+            SpecValue::U32(value) => value.to_ne_bytes(),
+            SpecValue::I32(value) => value.to_ne_bytes(),
+            SpecValue::F32(value) => value.to_ne_bytes(),
+            SpecValue::Bool(value) => (value as vk::Bool32).to_ne_bytes(),
+        }
+    }
+}
+
+/// 由 `(constant_id, value)` 列表组装的特化常量数据，需要在创建管线期间保持存活
+#[derive(Clone, Debug, Default)]
+pub struct SpecializationData {
+    pub map_entries: Vec<vk::SpecializationMapEntry>,
+    pub data: Vec<u8>,
+}
+
+impl SpecializationData {
+    pub fn new(specialization: &[(u32, SpecValue)]) -> Self {
+        let mut map_entries = Vec::with_capacity(specialization.len());
+        let mut data = Vec::with_capacity(specialization.len() * 4);
+        for &(constant_id, value) in specialization {
+            map_entries.push(
+                vk::SpecializationMapEntry::default()
+                    .constant_id(constant_id)
+                    .offset(data.len() as u32)
+                    .size(4),
+            );
+            data.extend_from_slice(&value.to_bytes());
+        }
+        Self { map_entries, data }
+    }
+
+    pub fn info(&self) -> vk::SpecializationInfo<'_> {
+        vk::SpecializationInfo::default()
+            .map_entries(&self.map_entries)
+            .data(&self.data)
+    }
+}
+
+/// 以 main 为入口创建计算管线，cache 可为 vk::PipelineCache::null()
+///
+/// specialization 为空时不设置特化信息
+pub fn create_compute_pipeline(
+    device: &Device,
+    pipeline_layout: vk::PipelineLayout,
+    shader_module: vk::ShaderModule,
+    cache: vk::PipelineCache,
+    specialization: &[(u32, SpecValue)],
+) -> Result<vk::Pipeline, vk::Result> {
+    let specialization_data = SpecializationData::new(specialization);
+    let specialization_info = specialization_data.info();
+
+    let create_info = vk::ComputePipelineCreateInfo::default()
+        .stage(compute_stage(
+            shader_module,
+            (!specialization.is_empty()).then_some(&specialization_info),
+        ))
+        .layout(pipeline_layout);
+
+    unsafe { device.create_compute_pipelines(cache, &[create_info], None) }
+        .map(|pipelines| pipelines[0])
+        .map_err(|(_, result)| result)
+}
+
+/// 计算管线以 main 为入口的着色器阶段，specialization_info 为 None 时不设置特化信息
+fn compute_stage<'a>(
+    shader_module: vk::ShaderModule,
+    specialization_info: Option<&'a vk::SpecializationInfo<'a>>,
+) -> vk::PipelineShaderStageCreateInfo<'a> {
+    let stage = vk::PipelineShaderStageCreateInfo::default()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader_module)
+        .name(c"main");
+    match specialization_info {
+        Some(info) => stage.specialization_info(info),
+        None => stage,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(header_size: u32, header_version: i32) -> Vec<u8> {
+        let mut data = vec![0u8; 32];
+        data[0..4].copy_from_slice(&header_size.to_ne_bytes());
+        data[4..8].copy_from_slice(&header_version.to_ne_bytes());
+        data
+    }
+
+    #[test]
+    fn cache_header_is_validated() {
+        let version_one = vk::PipelineCacheHeaderVersion::ONE.as_raw();
+        assert!(PipelineCache::has_valid_header(&header(32, version_one)));
+        assert!(!PipelineCache::has_valid_header(
+            &header(32, version_one)[..31]
+        ));
+        assert!(!PipelineCache::has_valid_header(&header(16, version_one)));
+        assert!(!PipelineCache::has_valid_header(&header(32, 2)));
+        assert!(!PipelineCache::has_valid_header(&[]));
+    }
+
+    #[test]
+    fn specialization_constant_is_wired_into_the_stage() {
+        let data = SpecializationData::new(&[(3, SpecValue::U32(8))]);
+        assert_eq!(data.data, 8u32.to_ne_bytes());
+        let info = data.info();
+        let stage = compute_stage(vk::ShaderModule::null(), Some(&info));
+
+        let wired = unsafe { &*stage.p_specialization_info };
+        assert_eq!(wired.map_entry_count, 1);
+        let entry = unsafe { &*wired.p_map_entries };
+        assert_eq!((entry.constant_id, entry.offset, entry.size), (3, 0, 4));
+        let bytes =
+            unsafe { std::slice::from_raw_parts(wired.p_data as *const u8, wired.data_size) };
+        assert_eq!(u32::from_ne_bytes(bytes.try_into().unwrap()), 8);
+
+        let plain = compute_stage(vk::ShaderModule::null(), None);
+        assert!(plain.p_specialization_info.is_null());
+    }
+
+    #[test]
+    fn cache_survives_save_and_reload() {
+        use crate::test_support::{RaygenFixture, test_context};
+
+        let Some(context) = test_context("cache_survives_save_and_reload") else {
+            return;
+        };
+        let device = &context.device;
+        let path = std::env::temp_dir().join("rt_pipeline_cache_roundtrip.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let cache = PipelineCache::load_or_create(device, &path).unwrap();
+        let fixture = RaygenFixture::with_cache(&context, 1, 1, cache.cache);
+        cache.save(device, &path).unwrap();
+        unsafe {
+            fixture.destroy(device);
+            cache.destroy(device);
+        }
+
+        let saved = std::fs::read(&path).unwrap();
+        assert!(PipelineCache::has_valid_header(&saved));
+        let reloaded = PipelineCache::load_or_create(device, &path).unwrap();
+        assert!(!reloaded.data(device).unwrap().is_empty());
+
+        unsafe { reloaded.destroy(device) };
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+use ash::{Device, khr, vk};
+
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+use crate::trace::TraceDispatch;
+use crate::vulkan_base::PhysicalDeviceQueries;
+
+/// 某个队列族上的时间戳换算参数
+///
+/// timestampValidBits 小于 64 时时间戳只有低位有效（专用 transfer 队列族甚至可能为 0），
+/// 读回的值需要先按有效位掩码再求差，否则得到的是无意义的耗时。
+#[derive(Clone, Copy, Debug)]
+pub struct TimestampProfiler {
+    /// 每个 tick 的纳秒数（PhysicalDeviceLimits::timestamp_period）
+    pub timestamp_period: f32,
+    pub valid_bits: u32,
+}
+
+impl TimestampProfiler {
+    /// 查询 queue_family_index 的时间戳支持，timestampValidBits 为 0 时返回 TimestampsUnsupported
+    pub fn new(
+        instance: &impl PhysicalDeviceQueries,
+        physical_device: vk::PhysicalDevice,
+        queue_family_index: u32,
+    ) -> Result<Self, RtError> {
+        let valid_bits = instance
+            .queue_family_properties(physical_device)
+            .get(queue_family_index as usize)
+            .map_or(0, |properties| properties.timestamp_valid_bits);
+        let timestamp_period = instance
+            .device_properties(physical_device)
+            .limits
+            .timestamp_period;
+        Self::from_valid_bits(queue_family_index, valid_bits, timestamp_period)
+    }
+
+    pub fn from_valid_bits(
+        queue_family_index: u32,
+        valid_bits: u32,
+        timestamp_period: f32,
+    ) -> Result<Self, RtError> {
+        if valid_bits == 0 {
+            return Err(RtError::TimestampsUnsupported {
+                queue_family: queue_family_index,
+            });
+        }
+        Ok(Self {
+            timestamp_period,
+            valid_bits: valid_bits.min(64),
+        })
+    }
+
+    /// 有效位的掩码
+    pub fn mask(&self) -> u64 {
+        if self.valid_bits >= 64 {
+            u64::MAX
+        } else {
+            (1u64 << self.valid_bits) - 1
+        }
+    }
+
+    /// 两个时间戳之间的 tick 数，按有效位掩码后求差，计数器回绕时仍然正确
+    pub fn delta_ticks(&self, start: u64, end: u64) -> u64 {
+        (end & self.mask()).wrapping_sub(start & self.mask()) & self.mask()
+    }
+
+    /// 两个时间戳之间的纳秒数
+    pub fn delta_ns(&self, start: u64, end: u64) -> f64 {
+        self.delta_ticks(start, end) as f64 * self.timestamp_period as f64
+    }
+}
+
+/// 测量 trace rays 吞吐量，返回平均每秒百万条主光线（Mrays/s）
+///
+/// 先执行一次不计时的预热，之后每次 dispatch 都用一对 timestamp query 包裹，
+/// 单独提交并 queue_wait_idle，最后以 GPU 时间累加计算吞吐量。
+/// profiler 需以 queue 所在的队列族创建。`benches/trace_throughput.rs` 用它输出各分辨率下的结果。
+pub fn measure_trace_throughput(
+    device: &Device,
+    rt_loader: &khr::ray_tracing_pipeline::Device,
+    command_pool: &CommandPoolManager,
+    queue: vk::Queue,
+    dispatch: &TraceDispatch,
+    profiler: &TimestampProfiler,
+    iterations: u32,
+) -> Result<f64, RtError> {
+    assert!(
+        iterations > 0,
+        "measure_trace_throughput needs at least one iteration"
+    );
+
+    let query_pool_create_info = vk::QueryPoolCreateInfo::default()
+        .query_type(vk::QueryType::TIMESTAMP)
+        .query_count(2);
+    let query_pool = unsafe { device.create_query_pool(&query_pool_create_info, None) }?;
+
+    let measured = (|| -> Result<f64, RtError> {
+        // 预热，避免首次 dispatch 的管线/缓存开销计入结果
+        let command_buffer = command_pool.begin_one_time(device)?;
+        dispatch.record(device, rt_loader, command_buffer);
+        command_pool.end_one_time(device, queue, command_buffer)?;
+
+        let mut total_ns = 0.0_f64;
+        for _ in 0..iterations {
+            let command_buffer = command_pool.begin_one_time(device)?;
+
+            unsafe {
+                device.cmd_reset_query_pool(command_buffer, query_pool, 0, 2);
+                device.cmd_write_timestamp(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    query_pool,
+                    0,
+                );
+            }
+
+            dispatch.record(device, rt_loader, command_buffer);
+
+            unsafe {
+                device.cmd_write_timestamp(
+                    command_buffer,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    query_pool,
+                    1,
+                );
+            }
+
+            command_pool.end_one_time(device, queue, command_buffer)?;
+
+            let mut timestamps = [0u64; 2];
+            unsafe {
+                device.get_query_pool_results(
+                    query_pool,
+                    0,
+                    &mut timestamps,
+                    vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+                )
+            }?;
+
+            total_ns += profiler.delta_ns(timestamps[0], timestamps[1]);
+        }
+        Ok(total_ns)
+    })();
+
+    unsafe { device.destroy_query_pool(query_pool, None) };
+    let total_ns = measured?;
+
+    let rays = dispatch.width as f64 * dispatch.height as f64 * iterations as f64;
+    Ok(rays / (total_ns * 1e-9) / 1e6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{RaygenFixture, test_context};
+    use crate::vulkan_base::InstanceQueries;
+
+    #[test]
+    fn zero_valid_bits_means_unsupported() {
+        assert!(matches!(
+            TimestampProfiler::from_valid_bits(3, 0, 1.0),
+            Err(RtError::TimestampsUnsupported { queue_family: 3 })
+        ));
+    }
+
+    #[test]
+    fn delta_ticks_masks_and_handles_wraparound() {
+        let profiler = TimestampProfiler::from_valid_bits(0, 36, 1.0).unwrap();
+        let mask = (1u64 << 36) - 1;
+        assert_eq!(profiler.mask(), mask);
+        // 高于有效位的垃圾位被忽略
+        assert_eq!(
+            profiler.delta_ticks(0xFF00_0000_0000_0010, 0x0000_0000_0000_0030),
+            0x20
+        );
+        // 计数器在有效位范围内回绕
+        assert_eq!(profiler.delta_ticks(mask - 4, 5), 10);
+
+        let full = TimestampProfiler::from_valid_bits(0, 64, 2.0).unwrap();
+        assert_eq!(full.mask(), u64::MAX);
+        assert_eq!(full.delta_ticks(u64::MAX, 1), 2);
+        assert_eq!(full.delta_ns(10, 15), 10.0);
+    }
+
+    #[test]
+    fn trace_throughput_is_positive_and_finite() {
+        let Some(context) = test_context("trace_throughput_is_positive_and_finite") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+        let fixture = RaygenFixture::new(&context, 256, 256);
+        let profiler = TimestampProfiler::new(
+            &InstanceQueries::new(&context.instance, None),
+            context.physical_device,
+            context.queue_family_index,
+        )
+        .expect("queue family has no timestamp support");
+
+        let mrays = measure_trace_throughput(
+            device,
+            &fixture.rt_loader,
+            &command_pool,
+            context.queue,
+            &fixture.dispatch,
+            &profiler,
+            3,
+        )
+        .expect("throughput measurement failed");
+        assert!(mrays.is_finite() && mrays > 0.0, "{} Mrays/s", mrays);
+
+        unsafe {
+            fixture.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
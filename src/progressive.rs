@@ -0,0 +1,146 @@
+use ash::{Device, khr, vk};
+
+use crate::buffer::BufferResource;
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+use crate::image_utils::{RenderTargetImage, record_clear_accumulation};
+use crate::jitter::JitterSequence;
+use crate::scene::SceneUniform;
+use crate::trace::TraceDispatch;
+
+/// 渐进式离线渲染：反复 dispatch 并累积到同一个累积图像，直到达到目标采样数
+///
+/// scene_buffer 是绑定在 dispatch 描述符集中的 SceneUniform uniform buffer（host 可见）。
+/// 累积失效时 render_pass 先用 record_clear_accumulation 清空累积图像，raygen 着色器总是累加。
+pub struct ProgressiveRenderer {
+    pub dispatch: TraceDispatch,
+    pub scene: SceneUniform,
+    pub jitter: JitterSequence,
+    pub scene_buffer: BufferResource,
+    /// 相机或场景发生变化，下一次 dispatch 前需要清空累积
+    dirty: bool,
+}
+
+impl ProgressiveRenderer {
+    pub fn new(dispatch: TraceDispatch, scene: SceneUniform, scene_buffer: BufferResource) -> Self {
+        Self {
+            dispatch,
+            scene,
+            jitter: JitterSequence::new(),
+            scene_buffer,
+            dirty: false,
+        }
+    }
+
+    /// 标记累积失效，相机移动（CameraController::update 返回 true）或编辑场景后调用
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// 当前累积的总采样数
+    pub fn samples(&self) -> u32 {
+        self.scene.total_samples()
+    }
+
+    /// 执行一次 dispatch 并等待完成，返回累积的总采样数
+    ///
+    /// 第一次调用或被 mark_dirty 标记时先把采样数清零并清空 accumulation（GENERAL 布局，需 TRANSFER_DST 用途）。
+    pub fn render_pass(
+        &mut self,
+        device: &Device,
+        rt_loader: &khr::ray_tracing_pipeline::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        accumulation: &RenderTargetImage,
+    ) -> Result<u32, RtError> {
+        let cleared = advance_accumulation(&mut self.scene, &mut self.jitter, &mut self.dirty);
+        self.scene_buffer
+            .store_from_thread(&[self.scene], 0, device);
+
+        let command_buffer = command_pool.begin_one_time(device)?;
+        if cleared {
+            record_clear_accumulation(device, command_buffer, accumulation);
+        }
+        self.dispatch.record(device, rt_loader, command_buffer);
+        command_pool.end_one_time(device, queue, command_buffer)?;
+
+        Ok(self.samples())
+    }
+
+    /// 持续渲染直到累积采样数达到 target_samples，每次 dispatch 完成后以当前采样数回调 on_progress
+    ///
+    /// scene.samples_per_dispatch 为 0 时返回 RtError::InvalidConfiguration。
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_progressive(
+        &mut self,
+        device: &Device,
+        rt_loader: &khr::ray_tracing_pipeline::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        accumulation: &RenderTargetImage,
+        target_samples: u32,
+        mut on_progress: impl FnMut(u32),
+    ) -> Result<(), RtError> {
+        // 每次 dispatch 不增加采样数时循环永远不会结束
+        if self.scene.samples_per_dispatch == 0 {
+            return Err(RtError::InvalidConfiguration(
+                "samples_per_dispatch must be at least 1".to_string(),
+            ));
+        }
+        while self.samples() < target_samples {
+            let samples = self.render_pass(device, rt_loader, command_pool, queue, accumulation)?;
+            on_progress(samples);
+        }
+        Ok(())
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            self.scene_buffer.destroy(device);
+        }
+    }
+}
+
+/// 推进一帧的累积状态，返回本帧之前是否需要清空累积图像
+///
+/// dirty 或尚未累积任何帧时清零采样数并清除 dirty，随后进入下一帧。
+pub fn advance_accumulation(
+    scene: &mut SceneUniform,
+    jitter: &mut JitterSequence,
+    dirty: &mut bool,
+) -> bool {
+    let clear = *dirty || scene.frame_index == 0;
+    if clear {
+        scene.reset(jitter);
+        *dirty = false;
+    }
+    scene.next_frame(jitter);
+    clear
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_frame_resets_and_clean_frame_accumulates() {
+        let mut scene = SceneUniform::default();
+        let mut jitter = JitterSequence::new();
+        let mut dirty = false;
+
+        assert!(advance_accumulation(&mut scene, &mut jitter, &mut dirty));
+        assert_eq!(scene.total_samples(), 1);
+        assert!(!advance_accumulation(&mut scene, &mut jitter, &mut dirty));
+        assert!(!advance_accumulation(&mut scene, &mut jitter, &mut dirty));
+        assert_eq!(scene.total_samples(), 3);
+
+        dirty = true;
+        assert!(advance_accumulation(&mut scene, &mut jitter, &mut dirty));
+        assert!(!dirty);
+        assert_eq!(scene.total_samples(), 1);
+    }
+}
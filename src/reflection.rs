@@ -0,0 +1,272 @@
+use ash::vk;
+use rspirv::dr::{Instruction, Module, Operand};
+use rspirv::spirv::{Decoration, Dim, ExecutionModel, Op, StorageClass};
+use std::collections::HashMap;
+
+/// 从 SPIR-V 反射出的一个描述符绑定
+#[derive(Clone, Copy, Debug)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: vk::DescriptorSetLayoutBinding<'static>,
+}
+
+/// 反射着色器中所有带 DescriptorSet/Binding 装饰的资源
+///
+/// stage_flags 取自模块中全部入口点的执行模型。运行时数组（bindless）的 descriptor_count 记为 1，
+/// 需要调用方按实际数量修改。结果按 (set, binding) 排序。
+pub fn reflect_descriptor_layout(
+    spirv: &[u32],
+) -> Result<Vec<ReflectedBinding>, Box<dyn std::error::Error>> {
+    let module = rspirv::dr::load_words(spirv)?;
+
+    let stage_flags = module
+        .entry_points
+        .iter()
+        .filter_map(|entry_point| match entry_point.operands.first() {
+            Some(Operand::ExecutionModel(model)) => Some(stage_flags(*model)),
+            _ => None,
+        })
+        .fold(vk::ShaderStageFlags::empty(), |acc, stage| acc | stage);
+
+    let mut sets = HashMap::new();
+    let mut bindings = HashMap::new();
+    let mut buffer_blocks = Vec::new();
+    for annotation in &module.annotations {
+        if annotation.class.opcode != Op::Decorate {
+            continue;
+        }
+        let (Some(Operand::IdRef(target)), Some(Operand::Decoration(decoration))) =
+            (annotation.operands.first(), annotation.operands.get(1))
+        else {
+            continue;
+        };
+        match (decoration, annotation.operands.get(2)) {
+            (Decoration::DescriptorSet, Some(Operand::LiteralInt32(set))) => {
+                sets.insert(*target, *set);
+            }
+            (Decoration::Binding, Some(Operand::LiteralInt32(binding))) => {
+                bindings.insert(*target, *binding);
+            }
+            (Decoration::BufferBlock, _) => buffer_blocks.push(*target),
+            _ => {}
+        }
+    }
+
+    let types: HashMap<u32, &Instruction> = module
+        .types_global_values
+        .iter()
+        .filter_map(|instruction| instruction.result_id.map(|id| (id, instruction)))
+        .collect();
+
+    let mut reflected = Vec::new();
+    for variable in &module.types_global_values {
+        if variable.class.opcode != Op::Variable {
+            continue;
+        }
+        let Some(id) = variable.result_id else {
+            continue;
+        };
+        let (Some(&set), Some(&binding)) = (sets.get(&id), bindings.get(&id)) else {
+            continue;
+        };
+
+        let pointer = variable
+            .result_type
+            .and_then(|ty| types.get(&ty))
+            .ok_or_else(|| format!("Descriptor variable %{} has no pointer type", id))?;
+        let (storage_class, pointee) = match (pointer.operands.first(), pointer.operands.get(1)) {
+            (Some(Operand::StorageClass(storage_class)), Some(Operand::IdRef(pointee))) => {
+                (*storage_class, *pointee)
+            }
+            _ => return Err(format!("Descriptor variable %{} has a malformed pointer", id).into()),
+        };
+
+        let (element, descriptor_count) = unwrap_array(&module, &types, pointee);
+        let descriptor_type = descriptor_type(&types, &buffer_blocks, storage_class, element)
+            .ok_or_else(|| {
+                format!(
+                    "Unsupported descriptor type at set {} binding {}",
+                    set, binding
+                )
+            })?;
+
+        reflected.push(ReflectedBinding {
+            set,
+            binding: vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(descriptor_count)
+                .stage_flags(stage_flags),
+        });
+    }
+
+    reflected.sort_by_key(|reflected| (reflected.set, reflected.binding.binding));
+    Ok(reflected)
+}
+
+/// 取出某个 set 的全部绑定，用于创建 vk::DescriptorSetLayout
+pub fn bindings_for_set(
+    reflected: &[ReflectedBinding],
+    set: u32,
+) -> Vec<vk::DescriptorSetLayoutBinding<'static>> {
+    reflected
+        .iter()
+        .filter(|reflected| reflected.set == set)
+        .map(|reflected| reflected.binding)
+        .collect()
+}
+
+fn stage_flags(model: ExecutionModel) -> vk::ShaderStageFlags {
+    match model {
+        ExecutionModel::Vertex => vk::ShaderStageFlags::VERTEX,
+        ExecutionModel::Fragment => vk::ShaderStageFlags::FRAGMENT,
+        ExecutionModel::GLCompute => vk::ShaderStageFlags::COMPUTE,
+        ExecutionModel::RayGenerationNV => vk::ShaderStageFlags::RAYGEN_KHR,
+        ExecutionModel::IntersectionNV => vk::ShaderStageFlags::INTERSECTION_KHR,
+        ExecutionModel::AnyHitNV => vk::ShaderStageFlags::ANY_HIT_KHR,
+        ExecutionModel::ClosestHitNV => vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+        ExecutionModel::MissNV => vk::ShaderStageFlags::MISS_KHR,
+        ExecutionModel::CallableNV => vk::ShaderStageFlags::CALLABLE_KHR,
+        _ => vk::ShaderStageFlags::empty(),
+    }
+}
+
+/// 去掉数组类型，返回元素类型与描述符数量
+fn unwrap_array(module: &Module, types: &HashMap<u32, &Instruction>, ty: u32) -> (u32, u32) {
+    let Some(instruction) = types.get(&ty) else {
+        return (ty, 1);
+    };
+    match (instruction.class.opcode, instruction.operands.as_slice()) {
+        (Op::TypeArray, [Operand::IdRef(element), Operand::IdRef(length), ..]) => {
+            let count = module
+                .types_global_values
+                .iter()
+                .find(|constant| constant.result_id == Some(*length))
+                .and_then(|constant| match constant.operands.first() {
+                    Some(Operand::LiteralInt32(count)) => Some(*count),
+                    _ => None,
+                })
+                .unwrap_or(1);
+            (*element, count)
+        }
+        (Op::TypeRuntimeArray, [Operand::IdRef(element), ..]) => (*element, 1),
+        _ => (ty, 1),
+    }
+}
+
+fn descriptor_type(
+    types: &HashMap<u32, &Instruction>,
+    buffer_blocks: &[u32],
+    storage_class: StorageClass,
+    ty: u32,
+) -> Option<vk::DescriptorType> {
+    let instruction = types.get(&ty)?;
+    match instruction.class.opcode {
+        Op::TypeAccelerationStructureNV => Some(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR),
+        Op::TypeSampler => Some(vk::DescriptorType::SAMPLER),
+        Op::TypeSampledImage => Some(vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        Op::TypeImage => {
+            let dim = match instruction.operands.get(1) {
+                Some(Operand::Dim(dim)) => *dim,
+                _ => return None,
+            };
+            let storage = matches!(instruction.operands.get(5), Some(Operand::LiteralInt32(2)));
+            Some(match (dim, storage) {
+                (Dim::DimSubpassData, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (Dim::DimBuffer, true) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (Dim::DimBuffer, false) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (_, true) => vk::DescriptorType::STORAGE_IMAGE,
+                (_, false) => vk::DescriptorType::SAMPLED_IMAGE,
+            })
+        }
+        Op::TypeStruct => match storage_class {
+            StorageClass::StorageBuffer => Some(vk::DescriptorType::STORAGE_BUFFER),
+            StorageClass::Uniform if buffer_blocks.contains(&ty) => {
+                Some(vk::DescriptorType::STORAGE_BUFFER)
+            }
+            StorageClass::Uniform => Some(vk::DescriptorType::UNIFORM_BUFFER),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rspirv::binary::Assemble;
+    use rspirv::spirv::{AddressingModel, Capability, FunctionControl, ImageFormat, MemoryModel};
+
+    /// 与 raygen 着色器相同的资源声明：set 0 binding 0 为 TLAS，binding 1 为输出的 rgba32f 存储图像
+    fn raygen_spirv() -> Vec<u32> {
+        let mut builder = rspirv::dr::Builder::new();
+        builder.set_version(1, 4);
+        builder.capability(Capability::Shader);
+        builder.capability(Capability::RayTracingKHR);
+        builder.extension("SPV_KHR_ray_tracing");
+        builder.memory_model(AddressingModel::Logical, MemoryModel::GLSL450);
+
+        let float = builder.type_float(32);
+        let tlas_type = builder.type_acceleration_structure_khr();
+        let image_type =
+            builder.type_image(float, Dim::Dim2D, 0, 0, 0, 2, ImageFormat::Rgba32f, None);
+        let tlas_pointer = builder.type_pointer(None, StorageClass::UniformConstant, tlas_type);
+        let image_pointer = builder.type_pointer(None, StorageClass::UniformConstant, image_type);
+        let tlas = builder.variable(tlas_pointer, None, StorageClass::UniformConstant, None);
+        let image = builder.variable(image_pointer, None, StorageClass::UniformConstant, None);
+        for (variable, binding) in [(tlas, 0), (image, 1)] {
+            builder.decorate(
+                variable,
+                Decoration::DescriptorSet,
+                [Operand::LiteralInt32(0)],
+            );
+            builder.decorate(
+                variable,
+                Decoration::Binding,
+                [Operand::LiteralInt32(binding)],
+            );
+        }
+
+        let void = builder.type_void();
+        let function_type = builder.type_function(void, []);
+        let main = builder
+            .begin_function(void, None, FunctionControl::NONE, function_type)
+            .unwrap();
+        builder.begin_block(None).unwrap();
+        builder.ret().unwrap();
+        builder.end_function().unwrap();
+        builder.entry_point(
+            ExecutionModel::RayGenerationKHR,
+            main,
+            "main",
+            [tlas, image],
+        );
+
+        builder.module().assemble()
+    }
+
+    #[test]
+    fn raygen_reports_the_tlas_at_set_0_binding_0() {
+        let reflected = reflect_descriptor_layout(&raygen_spirv()).unwrap();
+        assert_eq!(reflected.len(), 2);
+
+        let tlas = &reflected[0];
+        assert_eq!((tlas.set, tlas.binding.binding), (0, 0));
+        assert_eq!(
+            tlas.binding.descriptor_type,
+            vk::DescriptorType::ACCELERATION_STRUCTURE_KHR
+        );
+        assert_eq!(tlas.binding.descriptor_count, 1);
+        assert_eq!(tlas.binding.stage_flags, vk::ShaderStageFlags::RAYGEN_KHR);
+
+        let image = &reflected[1];
+        assert_eq!((image.set, image.binding.binding), (0, 1));
+        assert_eq!(
+            image.binding.descriptor_type,
+            vk::DescriptorType::STORAGE_IMAGE
+        );
+
+        assert_eq!(bindings_for_set(&reflected, 0).len(), 2);
+        assert!(bindings_for_set(&reflected, 1).is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use ash::{Device, vk};
+use std::collections::HashSet;
+
+use crate::image_utils::{RenderTargetImage, RenderTargetUsage};
+
+/// 回收复用同尺寸、同格式渲染目标的池，供 bloom、时域累积等需要 ping-pong 的多 pass 后处理使用
+///
+/// acquire 把图像的所有权交给调用方，用完后通过 release 归还；归还的图像保留原有布局，
+/// 新创建的图像处于 UNDEFINED，调用方按 `layout` 字段决定是否需要转换。
+#[derive(Default)]
+pub struct RenderTargetPool {
+    free: Vec<RenderTargetImage>,
+    in_use: HashSet<vk::Image>,
+}
+
+impl RenderTargetPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取出一个匹配 width/height/format/usage 的空闲图像，没有时新建
+    pub fn acquire(
+        &mut self,
+        device: &Device,
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        usage: RenderTargetUsage,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<RenderTargetImage, vk::Result> {
+        let extent = vk::Extent2D { width, height };
+        let usage_flags = usage.flags();
+        let image = match self.free.iter().position(|image| {
+            image.extent == extent && image.format == format && image.usage == usage_flags
+        }) {
+            Some(index) => self.free.swap_remove(index),
+            None => RenderTargetImage::new(
+                device,
+                width,
+                height,
+                format,
+                usage,
+                device_memory_properties,
+            )?,
+        };
+
+        self.in_use.insert(image.image);
+        Ok(image)
+    }
+
+    /// 归还之前 acquire 得到的图像，调用方需确保 GPU 已不再使用它
+    pub fn release(&mut self, image: RenderTargetImage) {
+        assert!(
+            self.in_use.remove(&image.image),
+            "image {:?} was not acquired from this pool",
+            image.image
+        );
+        self.free.push(image);
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn in_use_count(&self) -> usize {
+        self.in_use.len()
+    }
+
+    /// 销毁所有空闲图像；仍被借出的图像由调用方负责归还或销毁
+    pub unsafe fn destroy(self, device: &Device) {
+        if !self.in_use.is_empty() {
+            println!(
+                "[Warning] RenderTargetPool destroyed with {} images still acquired",
+                self.in_use.len()
+            );
+        }
+        for image in self.free {
+            unsafe { image.destroy(device) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_context;
+
+    #[test]
+    fn acquire_reuses_only_matching_images() {
+        let Some(context) = test_context("acquire_reuses_only_matching_images") else {
+            return;
+        };
+        let device = &context.device;
+        let format = vk::Format::R32G32B32A32_SFLOAT;
+        let usage = RenderTargetUsage::RayTracingOutput;
+        let mut pool = RenderTargetPool::new();
+
+        let image = pool
+            .acquire(
+                device,
+                8,
+                8,
+                format,
+                usage,
+                context.device_memory_properties,
+            )
+            .unwrap();
+        let handle = image.image;
+        pool.release(image);
+        assert_eq!((pool.free_count(), pool.in_use_count()), (1, 0));
+
+        // 尺寸不同时新建，匹配的空闲图像保留在池中
+        let other = pool
+            .acquire(
+                device,
+                16,
+                8,
+                format,
+                usage,
+                context.device_memory_properties,
+            )
+            .unwrap();
+        assert_ne!(other.image, handle);
+        assert_eq!(pool.free_count(), 1);
+
+        let reused = pool
+            .acquire(
+                device,
+                8,
+                8,
+                format,
+                usage,
+                context.device_memory_properties,
+            )
+            .unwrap();
+        assert_eq!(reused.image, handle);
+
+        pool.release(other);
+        pool.release(reused);
+        unsafe { pool.destroy(device) };
+    }
+}
@@ -0,0 +1,93 @@
+use ash::Device;
+use ash::vk::{self, Handle};
+use std::collections::HashMap;
+
+/// 记录尚未销毁的设备子对象，用于在销毁逻辑设备前检查销毁顺序
+///
+/// 只做登记，不拥有句柄：创建后调用 `track`，销毁后调用 `untrack`。
+#[derive(Default)]
+pub struct ResourceTracker {
+    live: HashMap<(vk::ObjectType, u64), String>,
+}
+
+impl ResourceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记一个已创建的对象，label 用于报告
+    pub fn track<H: Handle>(&mut self, handle: H, label: impl Into<String>) {
+        self.live.insert((H::TYPE, handle.as_raw()), label.into());
+    }
+
+    /// 注销一个已销毁的对象
+    pub fn untrack<H: Handle>(&mut self, handle: H) {
+        self.live.remove(&(H::TYPE, handle.as_raw()));
+    }
+
+    /// 仍未销毁的对象，格式为 `ObjectType 0x句柄 (label)`，按类型与句柄排序
+    pub fn outstanding(&self) -> Vec<String> {
+        let mut entries: Vec<_> = self.live.iter().collect();
+        entries.sort_by_key(|((object_type, raw), _)| (object_type.as_raw(), *raw));
+        entries
+            .into_iter()
+            .map(|((object_type, raw), label)| format!("{:?} 0x{:x} ({})", object_type, raw, label))
+            .collect()
+    }
+}
+
+/// 销毁逻辑设备；debug 构建下若仍有登记的子对象未销毁则 panic 并列出它们
+///
+/// 在子对象之前销毁设备是未定义行为，通常表现为退出时难以定位的崩溃。
+pub unsafe fn safe_destroy_device(device: &Device, tracker: &ResourceTracker) {
+    #[cfg(debug_assertions)]
+    {
+        let outstanding = tracker.outstanding();
+        if !outstanding.is_empty() {
+            panic!(
+                "Destroying the device with {} live child object(s):\n  {}",
+                outstanding.len(),
+                outstanding.join("\n  ")
+            );
+        }
+    }
+    #[cfg(not(debug_assertions))]
+    let _ = tracker;
+
+    unsafe {
+        device.destroy_device(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 没有任何函数指针的 Device：调用任意 Vulkan 函数都会 panic，只用于在调用驱动之前就结束的路径
+    fn unloaded_device() -> Device {
+        unsafe { Device::load_with(|_| std::ptr::null(), vk::Device::null()) }
+    }
+
+    #[test]
+    fn outstanding_lists_live_objects_in_order() {
+        let mut tracker = ResourceTracker::new();
+        tracker.track(vk::Buffer::from_raw(0x20), "vertex buffer");
+        tracker.track(vk::Buffer::from_raw(0x10), "index buffer");
+        tracker.track(vk::Image::from_raw(0x30), "render target");
+        tracker.untrack(vk::Image::from_raw(0x30));
+
+        assert_eq!(
+            tracker.outstanding(),
+            ["BUFFER 0x10 (index buffer)", "BUFFER 0x20 (vertex buffer)"]
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "BUFFER 0x42 (scratch buffer)")]
+    fn destroying_the_device_with_a_live_buffer_panics() {
+        let mut tracker = ResourceTracker::new();
+        tracker.track(vk::Buffer::from_raw(0x42), "scratch buffer");
+        unsafe { safe_destroy_device(&unloaded_device(), &tracker) };
+    }
+}
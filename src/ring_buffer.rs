@@ -0,0 +1,239 @@
+use ash::{Device, vk};
+
+use crate::buffer::BufferResource;
+
+/// RingBuffer 中分配到的一段子区间
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RingAlloc {
+    pub buffer: vk::Buffer,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+}
+
+impl RingAlloc {
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer)
+            .offset(self.offset)
+            .range(self.size)
+    }
+}
+
+/// RingBuffer 的偏移计算，不持有任何 Vulkan 资源
+///
+/// 总大小为 segment_size × frames_in_flight，第 frame 段占据 [frame × segment_size, (frame + 1) × segment_size)。
+#[derive(Clone, Copy, Debug)]
+pub struct RingAllocator {
+    segment_size: vk::DeviceSize,
+    frames_in_flight: u32,
+    frame: u32,
+    head: vk::DeviceSize,
+}
+
+impl RingAllocator {
+    pub fn new(segment_size: vk::DeviceSize, frames_in_flight: u32) -> Self {
+        assert!(
+            frames_in_flight > 0,
+            "RingBuffer needs at least one frame in flight"
+        );
+        Self {
+            segment_size,
+            frames_in_flight,
+            frame: 0,
+            head: 0,
+        }
+    }
+
+    /// 所有段的总字节数
+    pub fn total_size(&self) -> vk::DeviceSize {
+        self.segment_size * self.frames_in_flight as vk::DeviceSize
+    }
+
+    /// 在当前帧的段内分配 size 字节，返回整块缓冲内的绝对偏移；段内空间不足时返回 None
+    ///
+    /// 对齐作用于绝对偏移，segment_size 不是 alignment 的倍数时也能得到正确对齐的结果。
+    /// alignment 必须是非零的 2 的幂。
+    pub fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        assert!(
+            alignment.is_power_of_two(),
+            "RingBuffer alignment must be a non-zero power of two, got {}",
+            alignment
+        );
+        let base = self.segment_base();
+        let offset = (base + self.head).checked_add(alignment - 1)? & !(alignment - 1);
+        let end = offset.checked_add(size)?;
+        if end > base + self.segment_size {
+            return None;
+        }
+        self.head = end - base;
+        Some(offset)
+    }
+
+    /// 切换到下一帧的段并清空其分配
+    pub fn next_frame(&mut self) {
+        self.frame = (self.frame + 1) % self.frames_in_flight;
+        self.head = 0;
+    }
+
+    fn segment_base(&self) -> vk::DeviceSize {
+        self.frame as vk::DeviceSize * self.segment_size
+    }
+}
+
+/// 每帧临时数据（uniform、实例数据等）的环形分配器
+///
+/// 整块 host 可见内存被持久映射，并平均切分成 frames_in_flight 段，每帧只在自己的段内线性分配。
+/// 调用 next_frame 前需确保 frames_in_flight 帧之前提交的命令已经完成（等待对应 fence），
+/// 这样 GPU 永远不会读到被回收复用的区域。
+pub struct RingBuffer {
+    pub buffer: BufferResource,
+    mapped_ptr: *mut u8,
+    allocator: RingAllocator,
+}
+
+impl RingBuffer {
+    pub fn new(
+        segment_size: vk::DeviceSize,
+        frames_in_flight: u32,
+        usage: vk::BufferUsageFlags,
+        device: &Device,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, vk::Result> {
+        let allocator = RingAllocator::new(segment_size, frames_in_flight);
+
+        let buffer = BufferResource::new(
+            allocator.total_size(),
+            usage,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            device_memory_properties,
+        );
+
+        let mapped_ptr = unsafe {
+            device.map_memory(
+                buffer.memory,
+                0,
+                vk::WHOLE_SIZE,
+                vk::MemoryMapFlags::empty(),
+            )
+        }? as *mut u8;
+
+        Ok(Self {
+            buffer,
+            mapped_ptr,
+            allocator,
+        })
+    }
+
+    /// 在当前帧的段内分配 size 字节，alignment 必须是非零的 2 的幂；段内空间不足时返回 None
+    pub fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<RingAlloc> {
+        let offset = self.allocator.allocate(size, alignment)?;
+        Some(RingAlloc {
+            buffer: self.buffer.buffer,
+            offset,
+            size,
+        })
+    }
+
+    /// 把 data 写入 alloc 对应的区间，alloc 必须来自本缓冲且 data 不超过 alloc.size
+    pub fn write<T: Copy>(&mut self, alloc: &RingAlloc, data: &[T]) {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        assert_eq!(
+            alloc.buffer, self.buffer.buffer,
+            "RingAlloc belongs to a different buffer"
+        );
+        assert!(
+            size <= alloc.size,
+            "{} bytes exceed the allocation of {} bytes",
+            size,
+            alloc.size
+        );
+        assert!(
+            alloc
+                .offset
+                .checked_add(size)
+                .is_some_and(|end| end <= self.allocator.total_size()),
+            "RingAlloc at offset {} is outside the mapped range",
+            alloc.offset
+        );
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr() as *const u8,
+                self.mapped_ptr.add(alloc.offset as usize),
+                size as usize,
+            );
+        }
+    }
+
+    /// 切换到下一帧的段并清空其分配
+    pub fn next_frame(&mut self) {
+        self.allocator.next_frame();
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            device.unmap_memory(self.buffer.memory);
+            self.buffer.destroy(device);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: (u64, u64), b: (u64, u64)) -> bool {
+        a.0 < b.0 + b.1 && b.0 < a.0 + a.1
+    }
+
+    #[test]
+    fn two_frames_in_flight_never_overlap() {
+        let mut ring = RingAllocator::new(100, 2);
+        let sizes = [24, 32, 8];
+
+        let mut frames = Vec::new();
+        for _ in 0..2 {
+            let allocs: Vec<(u64, u64)> = sizes
+                .iter()
+                .map(|&size| (ring.allocate(size, 16).unwrap(), size))
+                .collect();
+            frames.push(allocs);
+            ring.next_frame();
+        }
+
+        let all: Vec<(u64, u64)> = frames.concat();
+        for (i, &a) in all.iter().enumerate() {
+            assert_eq!(a.0 % 16, 0, "offset {} is not aligned", a.0);
+            assert!(a.0 + a.1 <= ring.total_size());
+            for &b in &all[i + 1..] {
+                assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+
+        // 第三帧复用第一帧的段
+        assert_eq!(ring.allocate(8, 16), Some(frames[0][0].0));
+    }
+
+    #[test]
+    fn alignment_applies_to_absolute_offset() {
+        // 段大小 100 不是 64 的倍数，第二段从 100 开始
+        let mut ring = RingAllocator::new(100, 2);
+        ring.next_frame();
+        assert_eq!(ring.allocate(4, 64), Some(128));
+        assert_eq!(ring.allocate(64, 64), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn zero_alignment_is_rejected() {
+        RingAllocator::new(100, 2).allocate(4, 0);
+    }
+}
@@ -0,0 +1,86 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::camera::Camera;
+use crate::jitter::{JitterSequence, halton, lens_sample};
+
+/// 每帧上传给 raygen 着色器的场景参数（std140/scalar 布局兼容）
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct SceneUniform {
+    /// 子像素抖动偏移，单位为像素，范围 [-0.5, 0.5)
+    pub pixel_jitter: [f32; 2],
+    /// 已提交的 dispatch 次数
+    pub frame_index: u32,
+    /// raygen 着色器在一次 dispatch 内循环采样的次数
+    pub samples_per_dispatch: u32,
+    /// 本帧的镜头采样偏移（相机 right/up 方向，世界单位），针孔相机时为 [0, 0]
+    pub lens_offset: [f32; 2],
+    /// 薄透镜光圈半径，0 表示不做景深
+    pub aperture_radius: f32,
+    /// 对焦距离，raygen 按 Camera::thin_lens_ray 的方式扰动光线
+    pub focus_distance: f32,
+    /// 本帧光线的快门时间 [0, 1)，运动模糊时作为 traceRayMotionNV 的 time 参数
+    pub shutter_time: f32,
+}
+
+impl Default for SceneUniform {
+    fn default() -> Self {
+        Self {
+            pixel_jitter: [0.0, 0.0],
+            frame_index: 0,
+            samples_per_dispatch: 1,
+            lens_offset: [0.0, 0.0],
+            aperture_radius: 0.0,
+            focus_distance: 1.0,
+            shutter_time: 0.0,
+        }
+    }
+}
+
+impl SceneUniform {
+    /// 累积缓冲中的总采样数，即导出 PNG 时的 n_samples
+    ///
+    /// 超过 u32::MAX 时饱和，而不是在 debug 构建中溢出 panic
+    pub fn total_samples(&self) -> u32 {
+        self.frame_index.saturating_mul(self.samples_per_dispatch)
+    }
+
+    /// 同步相机的景深参数，参数变化后调用方应重置累积
+    pub fn set_depth_of_field(&mut self, camera: &Camera) {
+        self.aperture_radius = camera.aperture_radius;
+        self.focus_distance = camera.focus_distance;
+    }
+
+    /// 清空累积：下一次 next_frame 后 frame_index 回到 1，raygen 着色器将覆盖而不是累加累积图像
+    pub fn reset(&mut self, jitter: &mut JitterSequence) {
+        self.frame_index = 0;
+        self.pixel_jitter = [0.0, 0.0];
+        self.lens_offset = [0.0, 0.0];
+        self.shutter_time = 0.0;
+        jitter.reset();
+    }
+
+    /// 进入下一帧：推进帧序号并写入新的子像素抖动
+    pub fn next_frame(&mut self, jitter: &mut JitterSequence) {
+        self.frame_index = self.frame_index.wrapping_add(1);
+        self.pixel_jitter = jitter.next_jitter();
+        self.lens_offset = lens_sample(jitter.index, self.aperture_radius);
+        // Halton(11) 与抖动、镜头采样使用的底数互不相关
+        self.shutter_time = halton(jitter.index, 11);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn total_samples_saturates() {
+        let scene = SceneUniform {
+            frame_index: u32::MAX / 2,
+            samples_per_dispatch: 4,
+            ..Default::default()
+        };
+        assert_eq!(scene.total_samples(), u32::MAX);
+    }
+}
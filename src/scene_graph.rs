@@ -0,0 +1,330 @@
+use ash::{Device, khr, vk};
+
+use crate::acceleration_structure::{
+    AccelerationStructureLimits, BottomLevelAS, TlasUpdate, TopLevelAS,
+};
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+
+/// 行优先的 3x4 仿射变换，与 VkTransformMatrixKHR 布局相同（省略的第四行为 0 0 0 1）
+pub type Transform = [[f32; 4]; 3];
+
+pub const IDENTITY_TRANSFORM: Transform = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+];
+
+/// 两个仿射变换相乘：先应用 b 再应用 a
+pub fn multiply_transforms(a: &Transform, b: &Transform) -> Transform {
+    let mut result = [[0.0; 4]; 3];
+    for (row, result_row) in result.iter_mut().enumerate() {
+        for (col, value) in result_row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[row][k] * b[k][col]).sum::<f32>();
+        }
+        result_row[3] += a[row][3];
+    }
+    result
+}
+
+/// 场景中的一个节点，mesh_index 为 BLAS 在 build_tlas_if_dirty 的 blases 中的下标
+#[derive(Clone, Copy, Debug)]
+pub struct SceneNode {
+    pub mesh_index: usize,
+    pub local_transform: Transform,
+    /// 父节点下标，必须小于本节点下标
+    pub parent: Option<usize>,
+}
+
+/// 带变换层级的场景：节点移动只重写 TLAS 的实例数据，BLAS 保持不变
+///
+/// 每个节点对应一个 TLAS 实例，gl_InstanceCustomIndexEXT 为节点下标。
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<SceneNode>,
+    world_transforms: Vec<Transform>,
+    dirty: bool,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加节点并返回其下标
+    pub fn add_node(
+        &mut self,
+        mesh_index: usize,
+        local_transform: Transform,
+        parent: Option<usize>,
+    ) -> usize {
+        let index = self.nodes.len();
+        if let Some(parent) = parent {
+            assert!(
+                parent < index,
+                "parent {} must be added before node {}",
+                parent,
+                index
+            );
+        }
+        self.nodes.push(SceneNode {
+            mesh_index,
+            local_transform,
+            parent,
+        });
+        self.dirty = true;
+        index
+    }
+
+    pub fn nodes(&self) -> &[SceneNode] {
+        &self.nodes
+    }
+
+    /// 修改节点的局部变换，子节点的世界变换随之改变
+    pub fn set_transform(&mut self, node: usize, local_transform: Transform) {
+        self.nodes[node].local_transform = local_transform;
+        self.dirty = true;
+    }
+
+    /// 自上次构建 TLAS 以来是否有节点被添加或移动
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// 按节点顺序重新计算世界变换（父节点总在子节点之前）
+    pub fn update_world_transforms(&mut self) -> &[Transform] {
+        self.world_transforms.clear();
+        for node in &self.nodes {
+            let world = match node.parent {
+                Some(parent) => {
+                    multiply_transforms(&self.world_transforms[parent], &node.local_transform)
+                }
+                None => node.local_transform,
+            };
+            self.world_transforms.push(world);
+        }
+        &self.world_transforms
+    }
+
+    /// 由当前世界变换生成 TLAS 实例，需要先调用 update_world_transforms
+    pub fn instances(&self, blases: &[BottomLevelAS]) -> Vec<vk::AccelerationStructureInstanceKHR> {
+        self.nodes
+            .iter()
+            .zip(&self.world_transforms)
+            .enumerate()
+            .map(
+                |(index, (node, world))| vk::AccelerationStructureInstanceKHR {
+                    transform: vk::TransformMatrixKHR {
+                        matrix: bytemuck::cast(*world),
+                    },
+                    instance_custom_index_and_mask: vk::Packed24_8::new(index as u32, 0xFF),
+                    instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+                        0,
+                        vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as u8,
+                    ),
+                    acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+                        device_handle: blases[node.mesh_index].device_address,
+                    },
+                },
+            )
+            .collect()
+    }
+
+    /// 场景有变化（或 tlas 为空）时重新计算世界变换并更新 TLAS 实例，否则什么都不做
+    ///
+    /// 只重写实例 buffer 并重建/refit TLAS，blases 不会被修改。返回 None 表示无需更新。
+    #[allow(clippy::too_many_arguments)]
+    pub fn build_tlas_if_dirty(
+        &mut self,
+        device: &Device,
+        as_loader: &khr::acceleration_structure::Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        tlas: &mut Option<TopLevelAS>,
+        blases: &[BottomLevelAS],
+        limits: &AccelerationStructureLimits,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Option<TlasUpdate>, RtError> {
+        if !self.dirty && tlas.is_some() {
+            return Ok(None);
+        }
+
+        self.update_world_transforms();
+        let instances = self.instances(blases);
+
+        let update = match tlas {
+            Some(tlas) => tlas.update(
+                device,
+                as_loader,
+                command_pool,
+                queue,
+                &instances,
+                limits,
+                device_memory_properties,
+            )?,
+            None => {
+                *tlas = Some(TopLevelAS::new(
+                    device,
+                    as_loader,
+                    command_pool,
+                    queue,
+                    &instances,
+                    vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+                        | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE,
+                    limits,
+                    device_memory_properties,
+                )?);
+                TlasUpdate::Reallocated
+            }
+        };
+
+        self.dirty = false;
+        Ok(Some(update))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(x: f32, y: f32, z: f32) -> Transform {
+        [[1.0, 0.0, 0.0, x], [0.0, 1.0, 0.0, y], [0.0, 0.0, 1.0, z]]
+    }
+
+    fn scale(s: f32) -> Transform {
+        [[s, 0.0, 0.0, 0.0], [0.0, s, 0.0, 0.0], [0.0, 0.0, s, 0.0]]
+    }
+
+    #[test]
+    fn multiply_applies_right_operand_first() {
+        assert_eq!(
+            multiply_transforms(&IDENTITY_TRANSFORM, &translation(1.0, 2.0, 3.0)),
+            translation(1.0, 2.0, 3.0)
+        );
+        // 先平移再缩放：平移量也被缩放
+        assert_eq!(
+            multiply_transforms(&scale(2.0), &translation(1.0, 2.0, 3.0)),
+            [
+                [2.0, 0.0, 0.0, 2.0],
+                [0.0, 2.0, 0.0, 4.0],
+                [0.0, 0.0, 2.0, 6.0]
+            ]
+        );
+        // 先缩放再平移：平移量不变
+        assert_eq!(
+            multiply_transforms(&translation(1.0, 2.0, 3.0), &scale(2.0)),
+            [
+                [2.0, 0.0, 0.0, 1.0],
+                [0.0, 2.0, 0.0, 2.0],
+                [0.0, 0.0, 2.0, 3.0]
+            ]
+        );
+    }
+
+    #[test]
+    fn moving_a_parent_moves_its_children() {
+        let mut scene = Scene::new();
+        let root = scene.add_node(0, translation(1.0, 0.0, 0.0), None);
+        let child = scene.add_node(1, translation(0.0, 2.0, 0.0), Some(root));
+        assert!(scene.is_dirty());
+
+        assert_eq!(
+            scene.update_world_transforms()[child],
+            translation(1.0, 2.0, 0.0)
+        );
+
+        scene.set_transform(root, translation(5.0, 0.0, 0.0));
+        assert!(scene.is_dirty());
+        assert_eq!(
+            scene.update_world_transforms()[child],
+            translation(5.0, 2.0, 0.0)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "must be added before")]
+    fn parent_must_precede_child() {
+        let mut scene = Scene::new();
+        scene.add_node(0, IDENTITY_TRANSFORM, Some(0));
+    }
+
+    #[test]
+    fn moving_a_node_updates_only_the_tlas_instances() {
+        use crate::test_support::test_context;
+
+        let Some(context) = test_context("moving_a_node_updates_only_the_tlas_instances") else {
+            return;
+        };
+        let device = &context.device;
+        let as_loader = &context.as_loader;
+        let command_pool = context.command_pool();
+        let (blas, mesh) = BottomLevelAS::from_vertices(
+            device,
+            as_loader,
+            &command_pool,
+            context.queue,
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            &[0, 1, 2],
+            &context.limits,
+            context.device_memory_properties,
+        )
+        .unwrap();
+        let blases = [blas];
+        let (blas_handle, blas_address) =
+            (blases[0].acceleration_structure, blases[0].device_address);
+
+        let mut scene = Scene::new();
+        let root = scene.add_node(0, IDENTITY_TRANSFORM, None);
+        let child = scene.add_node(0, translation(0.0, 2.0, 0.0), Some(root));
+        let mut tlas = None;
+        let build = |scene: &mut Scene, tlas: &mut Option<TopLevelAS>| {
+            scene
+                .build_tlas_if_dirty(
+                    device,
+                    as_loader,
+                    &command_pool,
+                    context.queue,
+                    tlas,
+                    &blases,
+                    &context.limits,
+                    context.device_memory_properties,
+                )
+                .unwrap()
+        };
+        assert!(matches!(
+            build(&mut scene, &mut tlas),
+            Some(TlasUpdate::Reallocated)
+        ));
+        assert!(build(&mut scene, &mut tlas).is_none());
+
+        scene.set_transform(root, translation(5.0, 0.0, 0.0));
+        assert!(matches!(
+            build(&mut scene, &mut tlas),
+            Some(TlasUpdate::Refit)
+        ));
+
+        // 实例 buffer 中的变换已更新，BLAS 保持不变
+        let tlas = tlas.unwrap();
+        let instance_buffer = &tlas.instance_buffer;
+        let mapped = instance_buffer.map(0, vk::WHOLE_SIZE, device)
+            as *const vk::AccelerationStructureInstanceKHR;
+        let instance = unsafe { std::ptr::read_unaligned(mapped.add(child)) };
+        instance_buffer.unmap(device);
+        let expected: [f32; 12] = bytemuck::cast(translation(5.0, 2.0, 0.0));
+        assert_eq!(instance.transform.matrix, expected);
+        assert_eq!(
+            unsafe { instance.acceleration_structure_reference.device_handle },
+            blas_address
+        );
+        assert_eq!(blases[0].acceleration_structure, blas_handle);
+        assert_eq!(blases[0].device_address, blas_address);
+
+        unsafe {
+            tlas.destroy(device, as_loader);
+            let [blas] = blases;
+            blas.destroy(device, as_loader);
+            mesh.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
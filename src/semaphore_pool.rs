@@ -0,0 +1,87 @@
+use ash::{Device, vk};
+use std::collections::HashSet;
+
+/// 复用二值信号量的池，用于每帧的 acquire/render-finished 握手与跨队列（计算→图形）同步
+///
+/// 二值信号量被 signal 后，必须等到对应的 wait 操作已经执行（例如等待该次提交的 fence 触发）
+/// 才能 release 并再次使用，否则下一次 signal 时信号量可能仍处于已触发状态，这是非法的。
+/// 二值信号量没有 reset 操作，wait 执行完成后它自然回到未触发状态。
+#[derive(Default)]
+pub struct SemaphorePool {
+    free: Vec<vk::Semaphore>,
+    in_use: HashSet<vk::Semaphore>,
+}
+
+impl SemaphorePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取出一个未触发的二值信号量，没有空闲的时新建
+    pub fn acquire(&mut self, device: &Device) -> Result<vk::Semaphore, vk::Result> {
+        let semaphore = match self.free.pop() {
+            Some(semaphore) => semaphore,
+            None => unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None) }?,
+        };
+
+        self.in_use.insert(semaphore);
+        Ok(semaphore)
+    }
+
+    /// 归还之前 acquire 得到的信号量，调用方需确保等待它的操作已经执行完成
+    pub fn release(&mut self, semaphore: vk::Semaphore) {
+        assert!(
+            self.in_use.remove(&semaphore),
+            "semaphore {:?} was not acquired from this pool",
+            semaphore
+        );
+        self.free.push(semaphore);
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn in_use_count(&self) -> usize {
+        self.in_use.len()
+    }
+
+    /// 销毁池中所有信号量，包括仍被借出的；调用前需确保 GPU 已不再使用它们
+    pub unsafe fn destroy_all(self, device: &Device) {
+        if !self.in_use.is_empty() {
+            println!(
+                "[Warning] SemaphorePool destroyed with {} semaphores still in use",
+                self.in_use.len()
+            );
+        }
+        for semaphore in self.free.into_iter().chain(self.in_use) {
+            unsafe { device.destroy_semaphore(semaphore, None) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_context;
+
+    #[test]
+    fn released_semaphores_are_reused() {
+        let Some(context) = test_context("released_semaphores_are_reused") else {
+            return;
+        };
+        let device = &context.device;
+        let mut pool = SemaphorePool::new();
+
+        let first = pool.acquire(device).unwrap();
+        let second = pool.acquire(device).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(pool.in_use_count(), 2);
+
+        pool.release(first);
+        assert_eq!((pool.free_count(), pool.in_use_count()), (1, 1));
+        assert_eq!(pool.acquire(device).unwrap(), first);
+
+        unsafe { pool.destroy_all(device) };
+    }
+}
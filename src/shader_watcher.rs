@@ -0,0 +1,104 @@
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// 监视目录中 .spv 文件的变化，去抖后以变化的文件路径回调
+///
+/// 回调在后台线程中执行，可在其中通知渲染线程重新加载着色器并重建管线。
+/// ShaderWatcher 被 drop 时停止监视，后台线程随之退出。
+pub struct ShaderWatcher {
+    watcher: Option<notify::RecommendedWatcher>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ShaderWatcher {
+    pub fn new(
+        directory: impl AsRef<Path>,
+        debounce: Duration,
+        mut on_change: impl FnMut(&Path) + Send + 'static,
+    ) -> notify::Result<Self> {
+        let (sender, receiver) = mpsc::channel::<PathBuf>();
+
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    return;
+                }
+                for path in event.paths {
+                    if path.extension().is_some_and(|ext| ext == "spv") {
+                        let _ = sender.send(path);
+                    }
+                }
+            })?;
+        watcher.watch(directory.as_ref(), RecursiveMode::NonRecursive)?;
+
+        let thread = std::thread::spawn(move || {
+            // 阻塞等待第一个事件，然后持续收集直到 debounce 时间内没有新事件
+            while let Ok(path) = receiver.recv() {
+                let mut changed = BTreeSet::from([path]);
+                loop {
+                    match receiver.recv_timeout(debounce) {
+                        Ok(path) => {
+                            changed.insert(path);
+                        }
+                        Err(mpsc::RecvTimeoutError::Timeout) => break,
+                        Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                for path in &changed {
+                    on_change(path);
+                }
+            }
+        });
+
+        Ok(Self {
+            watcher: Some(watcher),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for ShaderWatcher {
+    fn drop(&mut self) {
+        // 先销毁 watcher 关闭发送端，后台线程才会退出
+        self.watcher.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn touching_a_watched_shader_triggers_the_callback() {
+        let directory = std::env::temp_dir().join("rt_shader_watcher");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let watcher = ShaderWatcher::new(&directory, Duration::from_millis(50), move |path| {
+            let _ = sender.send(path.to_path_buf());
+        })
+        .unwrap();
+
+        std::fs::write(directory.join("notes.txt"), b"ignored").unwrap();
+        std::fs::write(directory.join("raygen.spv"), [0x03, 0x02, 0x23, 0x07]).unwrap();
+
+        let changed = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(changed.file_name().unwrap(), "raygen.spv");
+        // 去抖窗口内的多次写入只回调一次，.txt 文件不会触发回调
+        assert!(receiver.recv_timeout(Duration::from_millis(300)).is_err());
+
+        drop(watcher);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,208 @@
+use ash::{Device, khr, vk};
+use bytemuck::{Pod, Zeroable};
+use std::path::Path;
+
+use crate::camera::Camera;
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+use crate::image_utils::{ExportOptions, read_image_rgba8_rows, write_png_rows};
+use crate::trace::TraceDispatch;
+
+/// 每只眼睛通过 push constant 传给 raygen 着色器的相机参数
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct EyeConstants {
+    pub position: [f32; 3],
+    /// 0 为左眼，1 为右眼
+    pub eye_index: u32,
+    pub forward: [f32; 3],
+    /// 垂直视场角（度）
+    pub vertical_fov: f32,
+    pub right: [f32; 3],
+    pub _pad: u32,
+}
+
+impl EyeConstants {
+    pub fn new(camera: &Camera, eye_index: u32) -> Self {
+        Self {
+            position: camera.position,
+            eye_index,
+            forward: camera.forward(),
+            vertical_fov: camera.vertical_fov,
+            right: camera.right(),
+            _pad: 0,
+        }
+    }
+
+    /// 创建管线布局时需要声明的 push constant 范围
+    pub fn push_constant_range() -> vk::PushConstantRange {
+        vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR)
+            .offset(0)
+            .size(size_of::<Self>() as u32)
+    }
+}
+
+/// 以中心相机为基准、沿右方向左右各偏移半个瞳距的双眼相机
+#[derive(Clone, Copy, Debug)]
+pub struct StereoCamera {
+    pub center: Camera,
+    /// 瞳距（场景单位），人眼约 0.064
+    pub eye_separation: f32,
+}
+
+impl StereoCamera {
+    /// 返回 (左眼, 右眼) 相机
+    pub fn eyes(&self) -> (Camera, Camera) {
+        let right = self.center.right();
+        let half = self.eye_separation * 0.5;
+        let mut left_camera = self.center;
+        let mut right_camera = self.center;
+        for (axis, offset) in right.iter().enumerate() {
+            left_camera.position[axis] -= offset * half;
+            right_camera.position[axis] += offset * half;
+        }
+        (left_camera, right_camera)
+    }
+}
+
+/// 分别为左右眼录制一次 trace rays 并等待完成
+///
+/// eye_dispatches 的两个描述符集各自绑定一个渲染目标，管线布局需包含
+/// `EyeConstants::push_constant_range()`。
+#[allow(clippy::too_many_arguments)]
+pub fn render_stereo(
+    device: &Device,
+    rt_loader: &khr::ray_tracing_pipeline::Device,
+    command_pool: &CommandPoolManager,
+    queue: vk::Queue,
+    eye_dispatches: [&TraceDispatch; 2],
+    left_camera: &Camera,
+    right_camera: &Camera,
+) -> Result<(), RtError> {
+    let command_buffer = command_pool.begin_one_time(device)?;
+
+    for (eye_index, (dispatch, camera)) in eye_dispatches
+        .into_iter()
+        .zip([left_camera, right_camera])
+        .enumerate()
+    {
+        let constants = EyeConstants::new(camera, eye_index as u32);
+        unsafe {
+            device.cmd_push_constants(
+                command_buffer,
+                dispatch.pipeline_layout,
+                vk::ShaderStageFlags::RAYGEN_KHR,
+                0,
+                bytemuck::bytes_of(&constants),
+            );
+        }
+        dispatch.record(device, rt_loader, command_buffer);
+    }
+
+    command_pool.end_one_time(device, queue, command_buffer)
+}
+
+/// 把左右两张等大的 RGBA8 图像逐行拼接为宽 2 * eye_width 的并排图像
+pub fn side_by_side_rows(left: &[Vec<u8>], right: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    assert_eq!(
+        left.len(),
+        right.len(),
+        "Eye images must have the same height"
+    );
+    left.iter()
+        .zip(right)
+        .map(|(left_row, right_row)| [left_row.as_slice(), right_row.as_slice()].concat())
+        .collect()
+}
+
+/// 导出左右眼并排的 PNG，两张图像需已拷贝到 host 可见的线性图像
+///
+/// eye_images 与 eye_memories 的顺序为 (左眼, 右眼)，输出宽度为 2 * eye_width；
+/// 两张图像均为 format，支持的格式见 read_image_rgba8_rows。
+#[allow(clippy::too_many_arguments)]
+pub fn save_stereo_png(
+    device: &Device,
+    eye_memories: [vk::DeviceMemory; 2],
+    eye_images: [vk::Image; 2],
+    format: vk::Format,
+    eye_width: u32,
+    height: u32,
+    n_samples: u32,
+    filename: impl AsRef<Path>,
+    options: &ExportOptions,
+) -> Result<(), RtError> {
+    let [left, right] = [0, 1].map(|eye| {
+        read_image_rgba8_rows(
+            device,
+            eye_memories[eye],
+            eye_images[eye],
+            format,
+            eye_width,
+            height,
+            n_samples,
+            options,
+        )
+    });
+    let (left, right) = (left?, right?);
+
+    write_png_rows(
+        filename,
+        2 * eye_width,
+        height,
+        &side_by_side_rows(&left, &right),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_eye(width: usize, height: usize, color: [u8; 4]) -> Vec<Vec<u8>> {
+        vec![color.repeat(width); height]
+    }
+
+    #[test]
+    fn stereo_png_is_twice_the_eye_width() {
+        let (left_camera, right_camera) = StereoCamera {
+            center: Camera::default(),
+            eye_separation: 0.064,
+        }
+        .eyes();
+        assert!((right_camera.position[0] - left_camera.position[0] - 0.064).abs() < 1e-6);
+        assert_ne!(
+            EyeConstants::new(&left_camera, 0).position,
+            EyeConstants::new(&right_camera, 1).position
+        );
+
+        let (eye_width, height) = (3, 2);
+        let left = solid_eye(eye_width, height, [255, 0, 0, 255]);
+        let right = solid_eye(eye_width, height, [0, 255, 0, 255]);
+        let path = std::env::temp_dir().join("rt_stereo_side_by_side.png");
+        write_png_rows(
+            &path,
+            2 * eye_width as u32,
+            height as u32,
+            &side_by_side_rows(&left, &right),
+        );
+
+        let decoder =
+            png::Decoder::new(std::io::BufReader::new(std::fs::File::open(&path).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(
+            (info.width, info.height),
+            (2 * eye_width as u32, height as u32)
+        );
+        let first_row = &pixels[..info.line_size];
+        assert_eq!(&first_row[..4], [255, 0, 0, 255]);
+        assert_eq!(
+            &first_row[4 * eye_width..4 * eye_width + 4],
+            [0, 255, 0, 255]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
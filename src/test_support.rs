@@ -0,0 +1,582 @@
+//! 需要真实 Vulkan 设备的测试共用的无头上下文
+//!
+//! 没有 Vulkan loader 或没有支持光线追踪的设备时 TestContext::new 返回 None，
+//! 调用方应直接跳过测试，使纯逻辑测试在任何环境下都能运行。
+//! 只检查调用参数的测试可以改用 mock_device，它不需要 loader 也不需要 GPU。
+
+use ash::vk::Handle;
+use ash::{Device, Entry, Instance, khr, vk};
+use std::cell::RefCell;
+use std::ffi::{CStr, c_char, c_void};
+
+use crate::acceleration_structure::AccelerationStructureLimits;
+use crate::buffer::{BufferResource, copy_buffer};
+use crate::command::CommandPoolManager;
+use crate::pipeline::create_shader_module;
+use crate::trace::TraceDispatch;
+use crate::vulkan_base::{
+    ApplicationInfo, DeviceConfig, InstanceQueries, create_device, create_instance,
+    pick_physical_device_and_queue_family_indices,
+};
+
+pub struct TestContext {
+    /// 持有 Vulkan loader，必须比 instance 活得更久
+    _entry: Entry,
+    pub instance: Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: Device,
+    pub queue_family_index: u32,
+    pub queue: vk::Queue,
+    pub device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pub as_loader: khr::acceleration_structure::Device,
+    pub limits: AccelerationStructureLimits,
+}
+
+impl TestContext {
+    pub fn new() -> Option<Self> {
+        Self::with_config(|_| {})
+    }
+
+    /// configure 在默认的无头 DeviceConfig 上开启额外功能，设备不支持时返回 None
+    pub fn with_config(configure: impl FnOnce(&mut DeviceConfig)) -> Option<Self> {
+        let entry = unsafe { Entry::load() }.ok()?;
+        let instance =
+            create_instance(&entry, &ApplicationInfo::default(), &[], &[], false, &[]).ok()?;
+
+        let picked = pick_physical_device_and_queue_family_indices(
+            &InstanceQueries::new(&instance, None),
+            None,
+            &[
+                khr::acceleration_structure::NAME,
+                khr::deferred_host_operations::NAME,
+                khr::ray_tracing_pipeline::NAME,
+            ],
+            true,
+        );
+        let Ok(Some((physical_device, queue_indices))) = picked else {
+            unsafe { instance.destroy_instance(None) };
+            return None;
+        };
+        let Some(queue_family_index) = queue_indices.graphics_family else {
+            unsafe { instance.destroy_instance(None) };
+            return None;
+        };
+
+        let mut config = DeviceConfig::new(true);
+        configure(&mut config);
+        let Ok(device) = create_device(&instance, physical_device, &queue_indices, &config) else {
+            unsafe { instance.destroy_instance(None) };
+            return None;
+        };
+
+        let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
+        let device_memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let as_loader = khr::acceleration_structure::Device::new(&instance, &device);
+        let limits = AccelerationStructureLimits::query(&instance, physical_device);
+
+        Some(Self {
+            _entry: entry,
+            instance,
+            physical_device,
+            device,
+            queue_family_index,
+            queue,
+            device_memory_properties,
+            as_loader,
+            limits,
+        })
+    }
+
+    pub fn command_pool(&self) -> CommandPoolManager {
+        CommandPoolManager::new(&self.device, self.queue_family_index)
+            .expect("Failed to create command pool")
+    }
+
+    /// 把 buffer 的全部内容拷贝到 host 可见的回读 buffer 并按 T 解释，buffer 需要 TRANSFER_SRC 用途
+    pub fn read_buffer<T: bytemuck::Pod>(&self, buffer: &BufferResource) -> Vec<T> {
+        let device = &self.device;
+        let readback = BufferResource::new(
+            buffer.size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            self.device_memory_properties,
+        );
+        let command_pool = self.command_pool();
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        copy_buffer(device, command_buffer, buffer, &readback, buffer.size);
+        command_pool
+            .end_one_time(device, self.queue, command_buffer)
+            .unwrap();
+
+        let mut values = vec![T::zeroed(); buffer.size as usize / size_of::<T>()];
+        unsafe {
+            let ptr = readback.map(0, buffer.size, device);
+            let bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut values);
+            bytes.copy_from_slice(std::slice::from_raw_parts(ptr as *const u8, bytes.len()));
+        }
+        readback.unmap(device);
+        unsafe {
+            readback.destroy(device);
+            command_pool.destroy(device);
+        }
+        values
+    }
+}
+
+impl Drop for TestContext {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device_wait_idle().ok();
+            self.device.destroy_device(None);
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+
+/// 获取测试上下文，没有可用设备时打印原因并返回 None
+pub fn test_context(test_name: &str) -> Option<TestContext> {
+    let context = TestContext::new();
+    if context.is_none() {
+        eprintln!(
+            "skipping {}: no ray tracing capable Vulkan device",
+            test_name
+        );
+    }
+    context
+}
+
+/// 手工汇编的空 raygen 着色器（SPIR-V 1.4），不依赖外部编译好的 .spv 文件
+fn empty_raygen_spirv() -> Vec<u32> {
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+    fn string(text: &str) -> Vec<u32> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize(bytes.len() / 4 * 4 + 4, 0);
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+    const CAPABILITY_RAY_TRACING: u32 = 4479;
+    const EXECUTION_MODEL_RAYGEN: u32 = 5313;
+    let (main, void, function_type, label) = (1, 2, 3, 4);
+
+    let mut words = vec![0x0723_0203, 0x0001_0400, 0, 5, 0];
+    words.extend(instruction(17, &[CAPABILITY_RAY_TRACING]));
+    words.extend(instruction(10, &string("SPV_KHR_ray_tracing")));
+    words.extend(instruction(14, &[0, 1]));
+    words.extend(instruction(
+        15,
+        &[[EXECUTION_MODEL_RAYGEN, main].as_slice(), &string("main")].concat(),
+    ));
+    words.extend(instruction(19, &[void]));
+    words.extend(instruction(33, &[function_type, void]));
+    words.extend(instruction(54, &[void, main, 0, function_type]));
+    words.extend(instruction(248, &[label]));
+    words.extend(instruction(253, &[]));
+    words.extend(instruction(56, &[]));
+    words
+}
+
+/// 只有一个空 raygen 着色器的光追管线及其 SBT，用于测试 dispatch 相关的 helper
+pub struct RaygenFixture {
+    pub rt_loader: khr::ray_tracing_pipeline::Device,
+    pub dispatch: TraceDispatch,
+    set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    sbt: BufferResource,
+}
+
+impl RaygenFixture {
+    pub fn new(context: &TestContext, width: u32, height: u32) -> Self {
+        Self::with_cache(context, width, height, vk::PipelineCache::null())
+    }
+
+    /// 与 new 相同，但通过 cache 创建管线
+    pub fn with_cache(
+        context: &TestContext,
+        width: u32,
+        height: u32,
+        cache: vk::PipelineCache,
+    ) -> Self {
+        let device = &context.device;
+        let rt_loader = khr::ray_tracing_pipeline::Device::new(&context.instance, device);
+
+        let mut rt_properties = vk::PhysicalDeviceRayTracingPipelinePropertiesKHR::default();
+        let mut properties2 =
+            vk::PhysicalDeviceProperties2::default().push_next(&mut rt_properties);
+        unsafe {
+            context
+                .instance
+                .get_physical_device_properties2(context.physical_device, &mut properties2)
+        };
+
+        unsafe {
+            let set_layout = device
+                .create_descriptor_set_layout(&vk::DescriptorSetLayoutCreateInfo::default(), None)
+                .unwrap();
+            let set_layouts = [set_layout];
+            let pipeline_layout = device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::default().set_layouts(&set_layouts),
+                    None,
+                )
+                .unwrap();
+            let pool_sizes = [vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: 1,
+            }];
+            let descriptor_pool = device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::default()
+                        .max_sets(1)
+                        .pool_sizes(&pool_sizes),
+                    None,
+                )
+                .unwrap();
+            let descriptor_set = device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::default()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .unwrap()[0];
+
+            let module = create_shader_module(device, &empty_raygen_spirv()).unwrap();
+            let stages = [vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::RAYGEN_KHR)
+                .module(module)
+                .name(c"main")];
+            let groups = [vk::RayTracingShaderGroupCreateInfoKHR::default()
+                .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
+                .general_shader(0)
+                .closest_hit_shader(vk::SHADER_UNUSED_KHR)
+                .any_hit_shader(vk::SHADER_UNUSED_KHR)
+                .intersection_shader(vk::SHADER_UNUSED_KHR)];
+            let pipeline = rt_loader
+                .create_ray_tracing_pipelines(
+                    vk::DeferredOperationKHR::null(),
+                    cache,
+                    &[vk::RayTracingPipelineCreateInfoKHR::default()
+                        .stages(&stages)
+                        .groups(&groups)
+                        .max_pipeline_ray_recursion_depth(1)
+                        .layout(pipeline_layout)],
+                    None,
+                )
+                .map_err(|(_, err)| err)
+                .unwrap()[0];
+            device.destroy_shader_module(module, None);
+
+            // raygen 区域的起始地址需要按 shaderGroupBaseAlignment 对齐，多分配一个对齐量后手动偏移
+            let handle_size = rt_properties.shader_group_handle_size as vk::DeviceSize;
+            let base_alignment = rt_properties.shader_group_base_alignment as vk::DeviceSize;
+            let handles = rt_loader
+                .get_ray_tracing_shader_group_handles(pipeline, 0, 1, handle_size as usize)
+                .unwrap();
+            let sbt = BufferResource::new(
+                2 * base_alignment.max(handle_size),
+                vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR
+                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                device,
+                context.device_memory_properties,
+            );
+            let address = sbt.device_address(device);
+            let aligned_address = address.next_multiple_of(base_alignment);
+            sbt.store_from_thread(&handles, aligned_address - address, device);
+
+            let stride = handle_size
+                .next_multiple_of(rt_properties.shader_group_handle_alignment as vk::DeviceSize);
+            let dispatch = TraceDispatch {
+                pipeline,
+                pipeline_layout,
+                descriptor_set,
+                raygen_region: vk::StridedDeviceAddressRegionKHR {
+                    device_address: aligned_address,
+                    stride,
+                    size: stride,
+                },
+                miss_region: vk::StridedDeviceAddressRegionKHR::default(),
+                hit_region: vk::StridedDeviceAddressRegionKHR::default(),
+                callable_region: vk::StridedDeviceAddressRegionKHR::default(),
+                width,
+                height,
+            };
+
+            Self {
+                rt_loader,
+                dispatch,
+                set_layout,
+                descriptor_pool,
+                sbt,
+            }
+        }
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            device.destroy_pipeline(self.dispatch.pipeline, None);
+            device.destroy_pipeline_layout(self.dispatch.pipeline_layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+            self.sbt.destroy(device);
+        }
+    }
+}
+
+/// mock_device 记录的一次 vkQueueSubmit
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockSubmit {
+    pub wait_semaphores: Vec<vk::Semaphore>,
+    pub wait_stages: Vec<vk::PipelineStageFlags>,
+    pub signal_semaphores: Vec<vk::Semaphore>,
+    pub fence: vk::Fence,
+}
+
+/// mock_swapchain_loader 记录的一次 vkQueuePresentKHR
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockPresent {
+    pub wait_semaphores: Vec<vk::Semaphore>,
+    pub image_indices: Vec<u32>,
+}
+
+/// mock_device 记录的一次描述符写入
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockDescriptorWrite {
+    pub set: vk::DescriptorSet,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub image_views: Vec<(vk::ImageView, vk::ImageLayout)>,
+}
+
+/// 假设备的调用记录；remaining_creates 为 Some(0) 时下一次创建返回 OUT_OF_HOST_MEMORY
+#[derive(Default)]
+pub struct MockDevice {
+    pub next_handle: u64,
+    pub remaining_creates: Option<usize>,
+    /// 已创建且尚未销毁的 fence 与信号量
+    pub live: Vec<u64>,
+    pub waited: Vec<Vec<vk::Fence>>,
+    pub submits: Vec<MockSubmit>,
+    pub presents: Vec<MockPresent>,
+    pub descriptor_writes: Vec<MockDescriptorWrite>,
+}
+
+thread_local! {
+    static MOCK: RefCell<MockDevice> = RefCell::new(MockDevice::default());
+}
+
+/// 访问当前测试线程的假设备调用记录
+pub fn with_mock<R>(f: impl FnOnce(&mut MockDevice) -> R) -> R {
+    MOCK.with_borrow_mut(f)
+}
+
+fn create_mock_object() -> Result<u64, vk::Result> {
+    with_mock(|mock| {
+        if let Some(remaining) = mock.remaining_creates.as_mut() {
+            if *remaining == 0 {
+                return Err(vk::Result::ERROR_OUT_OF_HOST_MEMORY);
+            }
+            *remaining -= 1;
+        }
+        mock.next_handle += 1;
+        mock.live.push(mock.next_handle);
+        Ok(mock.next_handle)
+    })
+}
+
+fn destroy_mock_object(handle: u64) {
+    with_mock(|mock| mock.live.retain(|&live| live != handle));
+}
+
+/// 把 count 个元素的数组指针转成 Vec，count 为 0 时指针可以为空
+unsafe fn mock_slice<T: Clone>(data: *const T, count: u32) -> Vec<T> {
+    if count == 0 {
+        Vec::new()
+    } else {
+        unsafe { std::slice::from_raw_parts(data, count as usize) }.to_vec()
+    }
+}
+
+unsafe extern "system" fn mock_create_fence(
+    _device: vk::Device,
+    _info: *const vk::FenceCreateInfo<'_>,
+    _allocator: *const vk::AllocationCallbacks<'_>,
+    fence: *mut vk::Fence,
+) -> vk::Result {
+    match create_mock_object() {
+        Ok(handle) => {
+            unsafe { *fence = vk::Fence::from_raw(handle) };
+            vk::Result::SUCCESS
+        }
+        Err(error) => error,
+    }
+}
+
+unsafe extern "system" fn mock_create_semaphore(
+    _device: vk::Device,
+    _info: *const vk::SemaphoreCreateInfo<'_>,
+    _allocator: *const vk::AllocationCallbacks<'_>,
+    semaphore: *mut vk::Semaphore,
+) -> vk::Result {
+    match create_mock_object() {
+        Ok(handle) => {
+            unsafe { *semaphore = vk::Semaphore::from_raw(handle) };
+            vk::Result::SUCCESS
+        }
+        Err(error) => error,
+    }
+}
+
+unsafe extern "system" fn mock_destroy_fence(
+    _device: vk::Device,
+    fence: vk::Fence,
+    _allocator: *const vk::AllocationCallbacks<'_>,
+) {
+    destroy_mock_object(fence.as_raw());
+}
+
+unsafe extern "system" fn mock_destroy_semaphore(
+    _device: vk::Device,
+    semaphore: vk::Semaphore,
+    _allocator: *const vk::AllocationCallbacks<'_>,
+) {
+    destroy_mock_object(semaphore.as_raw());
+}
+
+unsafe extern "system" fn mock_wait_for_fences(
+    _device: vk::Device,
+    fence_count: u32,
+    fences: *const vk::Fence,
+    _wait_all: vk::Bool32,
+    _timeout: u64,
+) -> vk::Result {
+    let fences = unsafe { mock_slice(fences, fence_count) };
+    with_mock(|mock| mock.waited.push(fences));
+    vk::Result::SUCCESS
+}
+
+unsafe extern "system" fn mock_reset_fences(
+    _device: vk::Device,
+    _fence_count: u32,
+    _fences: *const vk::Fence,
+) -> vk::Result {
+    vk::Result::SUCCESS
+}
+
+unsafe extern "system" fn mock_queue_submit(
+    _queue: vk::Queue,
+    submit_count: u32,
+    submits: *const vk::SubmitInfo<'_>,
+    fence: vk::Fence,
+) -> vk::Result {
+    for submit in unsafe { mock_slice(submits, submit_count) } {
+        let recorded = unsafe {
+            MockSubmit {
+                wait_semaphores: mock_slice(submit.p_wait_semaphores, submit.wait_semaphore_count),
+                wait_stages: mock_slice(submit.p_wait_dst_stage_mask, submit.wait_semaphore_count),
+                signal_semaphores: mock_slice(
+                    submit.p_signal_semaphores,
+                    submit.signal_semaphore_count,
+                ),
+                fence,
+            }
+        };
+        with_mock(|mock| mock.submits.push(recorded));
+    }
+    vk::Result::SUCCESS
+}
+
+unsafe extern "system" fn mock_queue_present(
+    _queue: vk::Queue,
+    present_info: *const vk::PresentInfoKHR<'_>,
+) -> vk::Result {
+    let recorded = unsafe {
+        let info = &*present_info;
+        MockPresent {
+            wait_semaphores: mock_slice(info.p_wait_semaphores, info.wait_semaphore_count),
+            image_indices: mock_slice(info.p_image_indices, info.swapchain_count),
+        }
+    };
+    with_mock(|mock| mock.presents.push(recorded));
+    vk::Result::SUCCESS
+}
+
+unsafe extern "system" fn mock_update_descriptor_sets(
+    _device: vk::Device,
+    write_count: u32,
+    writes: *const vk::WriteDescriptorSet<'_>,
+    _copy_count: u32,
+    _copies: *const vk::CopyDescriptorSet<'_>,
+) {
+    for write in unsafe { mock_slice(writes, write_count) } {
+        let image_infos = unsafe { mock_slice(write.p_image_info, write.descriptor_count) };
+        let recorded = MockDescriptorWrite {
+            set: write.dst_set,
+            binding: write.dst_binding,
+            descriptor_type: write.descriptor_type,
+            image_views: image_infos
+                .iter()
+                .map(|info| (info.image_view, info.image_layout))
+                .collect(),
+        };
+        with_mock(|mock| mock.descriptor_writes.push(recorded));
+    }
+}
+
+/// 只实现同步对象创建/销毁、fence 等待、提交与描述符更新的假设备，每个测试线程有独立的调用记录
+pub fn mock_device(remaining_creates: Option<usize>) -> Device {
+    MOCK.set(MockDevice {
+        remaining_creates,
+        ..MockDevice::default()
+    });
+    let load = |name: &CStr| -> *const c_void {
+        match name.to_bytes() {
+            b"vkCreateFence" => mock_create_fence as *const c_void,
+            b"vkCreateSemaphore" => mock_create_semaphore as *const c_void,
+            b"vkDestroyFence" => mock_destroy_fence as *const c_void,
+            b"vkDestroySemaphore" => mock_destroy_semaphore as *const c_void,
+            b"vkWaitForFences" => mock_wait_for_fences as *const c_void,
+            b"vkResetFences" => mock_reset_fences as *const c_void,
+            b"vkQueueSubmit" => mock_queue_submit as *const c_void,
+            b"vkUpdateDescriptorSets" => mock_update_descriptor_sets as *const c_void,
+            _ => std::ptr::null(),
+        }
+    };
+    unsafe { Device::load_with(load, vk::Device::from_raw(1)) }
+}
+
+unsafe extern "system" fn mock_get_device_proc_addr(
+    _device: vk::Device,
+    name: *const c_char,
+) -> vk::PFN_vkVoidFunction {
+    if unsafe { CStr::from_ptr(name) } == c"vkQueuePresentKHR" {
+        Some(unsafe {
+            std::mem::transmute::<vk::PFN_vkQueuePresentKHR, unsafe extern "system" fn()>(
+                mock_queue_present,
+            )
+        })
+    } else {
+        None
+    }
+}
+
+/// 与 mock_device 配套的 swapchain 扩展函数表，只实现 vkQueuePresentKHR
+pub fn mock_swapchain_loader(device: &Device) -> khr::swapchain::Device {
+    let load = |name: &CStr| -> *const c_void {
+        if name == c"vkGetDeviceProcAddr" {
+            mock_get_device_proc_addr as *const c_void
+        } else {
+            std::ptr::null()
+        }
+    };
+    let instance = unsafe { Instance::load_with(load, vk::Instance::from_raw(1)) };
+    khr::swapchain::Device::new(&instance, device)
+}
@@ -0,0 +1,465 @@
+use ash::{Device, vk};
+use bytemuck::{Pod, Zeroable};
+
+use crate::buffer::BufferResource;
+use crate::command::CommandPoolManager;
+use crate::descriptor::update_storage_image;
+use crate::error::RtError;
+use crate::image_utils::RenderTargetImage;
+use crate::pipeline::{create_compute_pipeline, create_shader_module};
+
+/// 字形宽度（像素）
+pub const GLYPH_WIDTH: u32 = 5;
+/// 字形高度（像素）
+pub const GLYPH_HEIGHT: u32 = 7;
+/// 相邻字符的水平间距，字形宽度加 1 像素
+pub const GLYPH_ADVANCE: u32 = 6;
+
+/// 5x7 位图字体，每行低 5 位为像素，最高位在最左边；小写字母按大写绘制
+#[rustfmt::skip]
+const FONT: &[(char, [u8; 7])] = &[
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('%', [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+];
+
+/// 字符在字体中的字形序号，不支持的字符映射为 '?'
+pub fn glyph_index(c: char) -> u32 {
+    let c = c.to_ascii_uppercase();
+    FONT.iter().position(|&(glyph, _)| glyph == c).unwrap_or(1) as u32
+}
+
+/// 上传到 GPU 的字体图集：按字形序号依次排列，每个字形 GLYPH_HEIGHT 个 u32
+pub fn font_atlas() -> Vec<u32> {
+    FONT.iter()
+        .flat_map(|(_, rows)| rows.iter().map(|&row| row as u32))
+        .collect()
+}
+
+/// 以 scale 倍绘制 text 时覆盖的像素尺寸
+pub fn text_extent(text: &str, scale: u32) -> vk::Extent2D {
+    let chars = text.chars().count() as u32;
+    vk::Extent2D {
+        width: (chars * GLYPH_ADVANCE).saturating_sub(1) * scale,
+        height: GLYPH_HEIGHT * scale,
+    }
+}
+
+/// text_overlay.comp 的 push constant，布局与着色器中的 TextConstants 一致
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct TextConstants {
+    pub origin: [i32; 2],
+    pub first_char: u32,
+    pub char_count: u32,
+    pub scale: u32,
+    pub padding: [u32; 3],
+    pub color: [f32; 4],
+}
+
+/// 在输出图像上绘制统计文字的计算 pass（shaders/text_overlay.comp）
+///
+/// 没有光栅化管线，因此直接把位图字形写入 R8G8B8A8_UNORM 的输出图像，
+/// 通常是 TonemapPass 的输出，在 present_render_target 之前录制。
+/// 每次 draw_text 把字符写入常驻映射的文本缓冲的下一段，同一帧内可以多次调用，
+/// 同一帧的多次 dispatch 之间插入屏障，重叠的文字按调用顺序覆盖。
+/// begin_frame 把写入位置归零，调用前需确保上一帧的命令已经执行完成。
+pub struct TextOverlay {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    pub font_buffer: BufferResource,
+    pub text_buffer: BufferResource,
+    /// 字形放大倍数
+    pub scale: u32,
+    pub color: [f32; 4],
+    /// text_buffer 在创建时映射，destroy 时解除映射
+    text_mapped: *mut u32,
+    cursor: u32,
+    /// 本帧已录制的 dispatch 数
+    dispatch_count: u32,
+}
+
+impl TextOverlay {
+    /// 每帧最多可绘制的字符数
+    pub const MAX_CHARS: u32 = 1024;
+
+    const WORKGROUP_SIZE: u32 = 8;
+
+    /// output 需为 STORAGE 用途的 R8G8B8A8_UNORM 图像，draw_text 时处于 GENERAL 布局
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        shader_code: &[u32],
+        cache: vk::PipelineCache,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        output: &RenderTargetImage,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = [
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(binding, descriptor_type)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding as u32)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        })
+        .collect();
+
+        let descriptor_set_layout = unsafe {
+            device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+                None,
+            )
+        }?;
+
+        let set_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(std::mem::size_of::<TextConstants>() as u32)];
+        let pipeline_layout = unsafe {
+            device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&set_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )
+        }?;
+
+        let shader_module = create_shader_module(device, shader_code)?;
+        let pipeline = create_compute_pipeline(device, pipeline_layout, shader_module, cache, &[]);
+        unsafe { device.destroy_shader_module(shader_module, None) };
+        let pipeline = pipeline?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(2),
+        ];
+        let descriptor_pool = unsafe {
+            device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )
+        }?;
+
+        let descriptor_set = unsafe {
+            device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&set_layouts),
+            )
+        }?[0];
+
+        let font_buffer = BufferResource::new_device_local(
+            &font_atlas(),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            device,
+            device_memory_properties,
+            command_pool,
+            queue,
+        )?;
+        let text_buffer = BufferResource::new(
+            Self::MAX_CHARS as vk::DeviceSize * std::mem::size_of::<u32>() as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            device_memory_properties,
+        );
+        let text_mapped = unsafe {
+            device.map_memory(
+                text_buffer.memory,
+                0,
+                vk::WHOLE_SIZE,
+                vk::MemoryMapFlags::empty(),
+            )
+        }? as *mut u32;
+
+        let buffer_infos = [&font_buffer, &text_buffer].map(|buffer| {
+            [vk::DescriptorBufferInfo::default()
+                .buffer(buffer.buffer)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)]
+        });
+        let writes: Vec<vk::WriteDescriptorSet> = buffer_infos
+            .iter()
+            .enumerate()
+            .map(|(index, info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(index as u32 + 1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(info)
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+        update_storage_image(device, descriptor_set, 0, output.view);
+
+        Ok(Self {
+            descriptor_set_layout,
+            pipeline_layout,
+            pipeline,
+            descriptor_pool,
+            descriptor_set,
+            font_buffer,
+            text_buffer,
+            scale: 2,
+            color: [1.0, 1.0, 1.0, 1.0],
+            text_mapped,
+            cursor: 0,
+            dispatch_count: 0,
+        })
+    }
+
+    /// 输出图像重建（例如窗口尺寸变化）后重新绑定
+    pub fn rebind_output(&self, device: &Device, output: &RenderTargetImage) {
+        update_storage_image(device, self.descriptor_set, 0, output.view);
+    }
+
+    /// 开始新的一帧，复用文本缓冲
+    pub fn begin_frame(&mut self) {
+        self.cursor = 0;
+        self.dispatch_count = 0;
+    }
+
+    /// 录制在 (x, y)（左上角，像素）处绘制 text 的 dispatch，返回实际绘制的字符数
+    ///
+    /// 本帧剩余容量不足 MAX_CHARS 时多余的字符被截断。
+    pub fn draw_text(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        text: &str,
+        x: i32,
+        y: i32,
+    ) -> usize {
+        let remaining = (Self::MAX_CHARS - self.cursor) as usize;
+        let glyphs: Vec<u32> = text.chars().take(remaining).map(glyph_index).collect();
+        if glyphs.is_empty() {
+            return 0;
+        }
+
+        // 文本缓冲为 HOST_COHERENT，写入在提交时自动对设备可见
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                glyphs.as_ptr(),
+                self.text_mapped.add(self.cursor as usize),
+                glyphs.len(),
+            );
+        }
+
+        let char_count = glyphs.len() as u32;
+        let scale = self.scale.max(1);
+        let constants = TextConstants {
+            origin: [x, y],
+            first_char: self.cursor,
+            char_count,
+            scale,
+            padding: [0; 3],
+            color: self.color,
+        };
+        self.cursor += char_count;
+
+        unsafe {
+            // 同一帧的前一次 dispatch 写入同一张图像，重叠区域需要按顺序写入
+            if self.dispatch_count > 0 {
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[vk::MemoryBarrier::default()
+                        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                        .dst_access_mask(vk::AccessFlags::SHADER_WRITE)],
+                    &[],
+                    &[],
+                );
+            }
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(&constants),
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                (char_count * GLYPH_ADVANCE * scale).div_ceil(Self::WORKGROUP_SIZE),
+                (GLYPH_HEIGHT * scale).div_ceil(Self::WORKGROUP_SIZE),
+                1,
+            );
+        }
+        self.dispatch_count += 1;
+
+        glyphs.len()
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            device.unmap_memory(self.text_buffer.memory);
+            self.text_buffer.destroy(device);
+            self.font_buffer.destroy(device);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::{
+        RenderTargetUsage, read_texel, record_clear_accumulation, transition_image_to_general,
+    };
+    use crate::test_support::test_context;
+
+    #[test]
+    fn drawing_42_writes_glyph_pixels() {
+        let Some(context) = test_context("drawing_42_writes_glyph_pixels") else {
+            return;
+        };
+        let spv_path = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/text_overlay.comp.spv");
+        let Ok(spv) = std::fs::read(spv_path) else {
+            eprintln!(
+                "skipping drawing_42_writes_glyph_pixels: {} not compiled",
+                spv_path
+            );
+            return;
+        };
+        let shader_code = ash::util::read_spv(&mut std::io::Cursor::new(spv)).unwrap();
+        let device = &context.device;
+        let properties = context.device_memory_properties;
+        let command_pool = context.command_pool();
+
+        let mut output = RenderTargetImage::new(
+            device,
+            32,
+            16,
+            vk::Format::R8G8B8A8_UNORM,
+            RenderTargetUsage::Accumulation,
+            properties,
+        )
+        .unwrap();
+        transition_image_to_general(device, command_pool.pool, context.queue, &mut output).unwrap();
+        let mut overlay = TextOverlay::new(
+            device,
+            &shader_code,
+            vk::PipelineCache::null(),
+            &command_pool,
+            context.queue,
+            &output,
+            properties,
+        )
+        .unwrap();
+        overlay.scale = 1;
+
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        record_clear_accumulation(device, command_buffer, &output);
+        overlay.begin_frame();
+        assert_eq!(overlay.draw_text(device, command_buffer, "42", 4, 4), 2);
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+
+        let texel = |x, y| -> u32 {
+            read_texel(
+                device,
+                command_pool.pool,
+                context.queue,
+                &output,
+                x,
+                y,
+                properties,
+            )
+            .unwrap()
+        };
+        // '4' 第 0 行只有第 3 列亮；'2' 第 6 行整行亮，起始于 x + GLYPH_ADVANCE
+        assert_eq!(texel(4 + 3, 4), u32::MAX);
+        assert_eq!(texel(4, 4), 0);
+        assert_eq!(texel(4 + GLYPH_ADVANCE, 4 + 6), u32::MAX);
+        assert_eq!(texel(4 + GLYPH_ADVANCE + 4, 4 + 6), u32::MAX);
+        assert_eq!(texel(0, 0), 0);
+        assert_eq!(texel(31, 15), 0);
+
+        unsafe {
+            overlay.destroy(device);
+            output.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use ash::{Device, Instance, vk};
+use std::path::Path;
+
+use crate::buffer::{BufferResource, get_memory_type_index};
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+/// identifier + 9 个 u32 头部字段 + 4 个 u32 与 2 个 u64 的索引
+const KTX2_LEVEL_INDEX_OFFSET: usize = 12 + 9 * 4 + 4 * 4 + 2 * 8;
+
+/// 从 KTX2 文件解析出的 2D 压缩纹理，levels[0] 为最大的 mip
+#[derive(Clone, Debug)]
+pub struct Ktx2Image {
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+/// BC1 ~ BC7 的块压缩格式
+pub fn is_bc_format(format: vk::Format) -> bool {
+    (vk::Format::BC1_RGB_UNORM_BLOCK.as_raw()..=vk::Format::BC7_SRGB_BLOCK.as_raw())
+        .contains(&format.as_raw())
+}
+
+/// 解析只含 BCn 数据的 KTX2 文件（单层、单面、无超压缩）
+pub fn parse_ktx2(bytes: &[u8]) -> Result<Ktx2Image, RtError> {
+    let invalid = |reason: &str| RtError::InvalidTexture(reason.to_string());
+    let read_u32 = |offset: usize| -> Result<u32, RtError> {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| invalid("KTX2 file is truncated"))
+    };
+    let read_u64 = |offset: usize| -> Result<u64, RtError> {
+        bytes
+            .get(offset..offset + 8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| invalid("KTX2 file is truncated"))
+    };
+
+    if bytes.get(..12) != Some(&KTX2_IDENTIFIER[..]) {
+        return Err(invalid("missing KTX2 identifier"));
+    }
+
+    let format = vk::Format::from_raw(read_u32(12)? as i32);
+    let width = read_u32(20)?;
+    let height = read_u32(24)?;
+    let depth = read_u32(28)?;
+    let layer_count = read_u32(32)?;
+    let face_count = read_u32(36)?;
+    // levelCount 为 0 表示只存了一层、希望运行时生成 mip，这里按一层处理
+    let level_count = read_u32(40)?.max(1);
+    let supercompression = read_u32(44)?;
+
+    if !is_bc_format(format) {
+        return Err(invalid("only BCn compressed KTX2 files are supported"));
+    }
+    if depth > 1 || layer_count > 1 || face_count != 1 {
+        return Err(invalid("only single-layer 2D KTX2 textures are supported"));
+    }
+    if supercompression != 0 {
+        return Err(invalid("supercompressed KTX2 files are not supported"));
+    }
+
+    let levels = (0..level_count as usize)
+        .map(|level| {
+            let entry = KTX2_LEVEL_INDEX_OFFSET + level * 3 * 8;
+            let out_of_bounds = || invalid("KTX2 level data is out of bounds");
+            let offset = usize::try_from(read_u64(entry)?).map_err(|_| out_of_bounds())?;
+            let length = usize::try_from(read_u64(entry + 8)?).map_err(|_| out_of_bounds())?;
+            let end = offset.checked_add(length).ok_or_else(out_of_bounds)?;
+            bytes
+                .get(offset..end)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(out_of_bounds)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Ktx2Image {
+        format,
+        width,
+        height,
+        levels,
+    })
+}
+
+/// 采样用的 2D 纹理，处于 SHADER_READ_ONLY_OPTIMAL 布局
+pub struct Texture {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub mip_levels: u32,
+    pub layout: vk::ImageLayout,
+}
+
+impl Texture {
+    /// 加载 KTX2 中预压缩的 BCn mip 数据，按块直接拷贝到图像，不做运行时解压或转码
+    ///
+    /// 设备需要启用 textureCompressionBC（DeviceConfig::texture_compression_bc），
+    /// 格式不支持采样时返回 MissingRequiredFeature。
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_ktx2(
+        path: impl AsRef<Path>,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &Device,
+        command_pool: &CommandPoolManager,
+        queue: vk::Queue,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self, RtError> {
+        let ktx2 = parse_ktx2(&std::fs::read(path)?)?;
+
+        let format_properties =
+            unsafe { instance.get_physical_device_format_properties(physical_device, ktx2.format) };
+        if !format_properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+        {
+            return Err(RtError::MissingRequiredFeature("textureCompressionBC"));
+        }
+
+        let mip_levels = ktx2.levels.len() as u32;
+        let image_create_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(ktx2.format)
+            .extent(
+                vk::Extent3D::default()
+                    .width(ktx2.width)
+                    .height(ktx2.height)
+                    .depth(1),
+            )
+            .mip_levels(mip_levels)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let image = unsafe { device.create_image(&image_create_info, None) }?;
+
+        let mem_reqs = unsafe { device.get_image_memory_requirements(image) };
+        let mem_alloc_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(mem_reqs.size)
+            .memory_type_index(get_memory_type_index(
+                device_memory_properties,
+                mem_reqs.memory_type_bits,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ));
+
+        // 之后任一步失败都要释放已创建的图像与内存；释放空的 VkDeviceMemory 句柄是合法的
+        let mut memory = vk::DeviceMemory::null();
+        let view = (|| -> Result<vk::ImageView, RtError> {
+            memory = unsafe { device.allocate_memory(&mem_alloc_info, None) }?;
+            unsafe { device.bind_image_memory(image, memory, 0) }?;
+
+            // 每层按 16 字节（BC 块大小）对齐放进 staging buffer
+            let mut level_offsets = Vec::with_capacity(ktx2.levels.len());
+            let mut staging_size = 0;
+            for level in &ktx2.levels {
+                level_offsets.push(staging_size);
+                staging_size = (staging_size + level.len() as vk::DeviceSize).next_multiple_of(16);
+            }
+
+            let staging_buffer = BufferResource::new(
+                staging_size.max(16),
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                vk::MemoryPropertyFlags::HOST_VISIBLE,
+                device,
+                device_memory_properties,
+            );
+            for (level, &offset) in ktx2.levels.iter().zip(&level_offsets) {
+                staging_buffer.store_from_thread(level, offset, device);
+            }
+
+            let subresource_range = vk::ImageSubresourceRange::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .level_count(mip_levels)
+                .layer_count(1);
+
+            let regions: Vec<vk::BufferImageCopy> = level_offsets
+                .iter()
+                .enumerate()
+                .map(|(level, &offset)| {
+                    vk::BufferImageCopy::default()
+                        .buffer_offset(offset)
+                        .image_subresource(
+                            vk::ImageSubresourceLayers::default()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .mip_level(level as u32)
+                                .layer_count(1),
+                        )
+                        .image_extent(vk::Extent3D {
+                            width: (ktx2.width >> level).max(1),
+                            height: (ktx2.height >> level).max(1),
+                            depth: 1,
+                        })
+                })
+                .collect();
+
+            let uploaded = (|| -> Result<(), RtError> {
+                let command_buffer = command_pool.begin_one_time(device)?;
+                unsafe {
+                    device.cmd_pipeline_barrier(
+                        command_buffer,
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[],
+                        &[vk::ImageMemoryBarrier::default()
+                            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                            .old_layout(vk::ImageLayout::UNDEFINED)
+                            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                            .image(image)
+                            .subresource_range(subresource_range)],
+                    );
+                    device.cmd_copy_buffer_to_image(
+                        command_buffer,
+                        staging_buffer.buffer,
+                        image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &regions,
+                    );
+                    device.cmd_pipeline_barrier(
+                        command_buffer,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::PipelineStageFlags::ALL_COMMANDS,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[],
+                        &[vk::ImageMemoryBarrier::default()
+                            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                            .image(image)
+                            .subresource_range(subresource_range)],
+                    );
+                }
+                command_pool.end_one_time(device, queue, command_buffer)
+            })();
+
+            unsafe { staging_buffer.destroy(device) };
+            uploaded?;
+
+            Ok(unsafe {
+                device.create_image_view(
+                    &vk::ImageViewCreateInfo::default()
+                        .image(image)
+                        .view_type(vk::ImageViewType::TYPE_2D)
+                        .format(ktx2.format)
+                        .subresource_range(subresource_range),
+                    None,
+                )
+            }?)
+        })();
+        let view = match view {
+            Ok(view) => view,
+            Err(err) => {
+                unsafe {
+                    device.destroy_image(image, None);
+                    device.free_memory(memory, None);
+                }
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            image,
+            memory,
+            view,
+            format: ktx2.format,
+            extent: vk::Extent2D {
+                width: ktx2.width,
+                height: ktx2.height,
+            },
+            mip_levels,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        })
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 单层 4x4 BC1 纹理（一个 8 字节块），level 索引指向 offset/length
+    fn ktx2_bytes(offset: u64, length: u64) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        let header = [
+            vk::Format::BC1_RGBA_UNORM_BLOCK.as_raw() as u32,
+            1, // typeSize
+            4, // pixelWidth
+            4, // pixelHeight
+            0, // pixelDepth
+            0, // layerCount
+            1, // faceCount
+            1, // levelCount
+            0, // supercompressionScheme
+        ];
+        for value in header {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.resize(KTX2_LEVEL_INDEX_OFFSET, 0);
+        for value in [offset, length, length] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0x5A; 8]);
+        bytes
+    }
+
+    #[test]
+    fn parses_a_single_bc1_level() {
+        let data_offset = (KTX2_LEVEL_INDEX_OFFSET + 3 * 8) as u64;
+        let image = parse_ktx2(&ktx2_bytes(data_offset, 8)).unwrap();
+        assert_eq!(image.format, vk::Format::BC1_RGBA_UNORM_BLOCK);
+        assert_eq!((image.width, image.height), (4, 4));
+        assert_eq!(image.levels, vec![vec![0x5A; 8]]);
+    }
+
+    #[test]
+    fn overflowing_level_range_is_invalid() {
+        for (offset, length) in [(u64::MAX - 4, 8), (8, u64::MAX), (1 << 20, 8)] {
+            assert!(matches!(
+                parse_ktx2(&ktx2_bytes(offset, length)),
+                Err(RtError::InvalidTexture(_))
+            ));
+        }
+    }
+}
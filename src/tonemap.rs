@@ -0,0 +1,478 @@
+use ash::{Device, vk};
+use bytemuck::{Pod, Zeroable};
+
+use crate::buffer::BufferResource;
+use crate::error::RtError;
+use crate::image_utils::{ExportOptions, RenderTargetImage, RenderTargetUsage};
+use crate::pipeline::{create_compute_pipeline, create_shader_module};
+
+/// tonemap.comp 与 luminance_histogram.comp 以 rgba32f 读取的累积图像格式
+pub const ACCUMULATION_FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+
+/// HDR 辐射度到 [0, 1] 的色调映射算子
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TonemapOperator {
+    /// 不做映射，超出 1 的部分直接截断
+    #[default]
+    Clamp,
+    /// x / (1 + x)
+    Reinhard,
+    /// Narkowicz 的 ACES 拟合曲线
+    Aces,
+}
+
+impl TonemapOperator {
+    /// 对单个通道应用算子，与 shaders/tonemap.comp 的实现一致
+    pub fn apply(self, x: f32) -> f32 {
+        match self {
+            TonemapOperator::Clamp => x,
+            TonemapOperator::Reinhard => x / (1.0 + x),
+            TonemapOperator::Aces => {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        }
+    }
+
+    /// 着色器中 tonemap_operator 的取值
+    pub fn shader_id(self) -> u32 {
+        match self {
+            TonemapOperator::Clamp => 0,
+            TonemapOperator::Reinhard => 1,
+            TonemapOperator::Aces => 2,
+        }
+    }
+}
+
+/// tonemap.comp 的 push constant，布局与着色器中的 TonemapConstants 一致
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct TonemapConstants {
+    pub tonemap_operator: u32,
+    /// 1 / 累积采样数
+    pub sample_scale: f32,
+    /// 非 0 时按 sRGB 分段曲线编码，目标已按 sRGB 编码时为 0
+    pub encode_srgb: u32,
+    /// <= 0 表示不限制
+    pub firefly_clamp: f32,
+}
+
+impl TonemapConstants {
+    /// 由导出选项构造，使 GPU 输出与 save_image_to_png 的结果一致（不包括 flip_vertical）
+    pub fn from_options(options: &ExportOptions, n_samples: u32) -> Self {
+        Self {
+            tonemap_operator: options.tonemap.shader_id(),
+            sample_scale: 1.0 / n_samples.max(1) as f32,
+            encode_srgb: u32::from(!options.output_is_srgb),
+            firefly_clamp: options.firefly_clamp.unwrap_or(0.0),
+        }
+    }
+}
+
+/// 曝光状态，布局与 tonemap.comp 中的 Exposure uniform 一致
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct ExposureState {
+    /// 自动曝光统计得到的场景平均亮度，固定曝光时不使用
+    pub average_luminance: f32,
+    /// 色调映射前乘到辐射度上的曝光系数
+    pub exposure: f32,
+}
+
+/// 存放 ExposureState 的 host 可见缓冲，可同时作为 uniform 与 storage buffer 绑定
+pub struct ExposureBuffer {
+    pub buffer: BufferResource,
+}
+
+impl ExposureBuffer {
+    pub fn new(
+        device: &Device,
+        exposure: f32,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Self {
+        let mut buffer = BufferResource::new(
+            std::mem::size_of::<ExposureState>() as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+            device_memory_properties,
+        );
+        buffer.store(
+            &[ExposureState {
+                average_luminance: 0.0,
+                exposure,
+            }],
+            device,
+        );
+        Self { buffer }
+    }
+
+    /// 读取 GPU 最近写入的曝光状态，调用前需等待写入的命令执行完成
+    pub fn read(&self, device: &Device) -> Result<ExposureState, vk::Result> {
+        let size = std::mem::size_of::<ExposureState>() as vk::DeviceSize;
+        let data = self.buffer.map(0, vk::WHOLE_SIZE, device);
+        let result = self
+            .buffer
+            .invalidate(device, 0, size)
+            .map(|()| unsafe { std::ptr::read_unaligned(data as *const ExposureState) });
+        self.buffer.unmap(device);
+        result
+    }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer.buffer)
+            .offset(0)
+            .range(std::mem::size_of::<ExposureState>() as vk::DeviceSize)
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            self.buffer.destroy(device);
+        }
+    }
+}
+
+/// 色调映射计算管线（shaders/tonemap.comp）
+///
+/// 描述符集布局：binding 0 为 R32G32B32A32_SFLOAT 累积图像，binding 1 为 OUTPUT_FORMAT 输出图像，
+/// 两者都以 STORAGE_IMAGE 绑定且处于 GENERAL 布局；binding 2 为 ExposureBuffer（UNIFORM_BUFFER）。输出可直接用 present_render_target 拷贝到交换链。
+pub struct TonemapPass {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+}
+
+impl TonemapPass {
+    const WORKGROUP_SIZE: u32 = 8;
+
+    /// 输出图像的格式
+    pub const OUTPUT_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+    /// 输出图像的用途：计算着色器写入，之后拷贝到交换链或回读
+    pub const OUTPUT_USAGE: RenderTargetUsage =
+        RenderTargetUsage::Custom(vk::ImageUsageFlags::from_raw(
+            vk::ImageUsageFlags::STORAGE.as_raw() | vk::ImageUsageFlags::TRANSFER_SRC.as_raw(),
+        ));
+
+    pub fn new(
+        device: &Device,
+        shader_code: &[u32],
+        cache: vk::PipelineCache,
+    ) -> Result<Self, vk::Result> {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = [
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::DescriptorType::UNIFORM_BUFFER,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(binding, descriptor_type)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding as u32)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        })
+        .collect();
+
+        let descriptor_set_layout = unsafe {
+            device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+                None,
+            )
+        }?;
+
+        let set_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(std::mem::size_of::<TonemapConstants>() as u32)];
+        let pipeline_layout = unsafe {
+            device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&set_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )
+        }?;
+
+        let shader_module = create_shader_module(device, shader_code)?;
+        let pipeline = create_compute_pipeline(device, pipeline_layout, shader_module, cache, &[]);
+        unsafe { device.destroy_shader_module(shader_module, None) };
+
+        Ok(Self {
+            descriptor_set_layout,
+            pipeline_layout,
+            pipeline: pipeline?,
+        })
+    }
+
+    /// 创建与 accumulation 同尺寸的输出图像
+    pub fn create_output(
+        device: &Device,
+        accumulation: &RenderTargetImage,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<RenderTargetImage, vk::Result> {
+        RenderTargetImage::new(
+            device,
+            accumulation.extent.width,
+            accumulation.extent.height,
+            Self::OUTPUT_FORMAT,
+            Self::OUTPUT_USAGE,
+            device_memory_properties,
+        )
+    }
+
+    /// 把累积图像、输出图像与曝光缓冲写入 descriptor_set
+    ///
+    /// 着色器以 rgba32f 读取累积图像，其他格式返回 RtError::UnsupportedFormat。
+    pub fn write_descriptor_set(
+        &self,
+        device: &Device,
+        descriptor_set: vk::DescriptorSet,
+        accumulation: &RenderTargetImage,
+        output: &RenderTargetImage,
+        exposure: &ExposureBuffer,
+    ) -> Result<(), RtError> {
+        if accumulation.format != ACCUMULATION_FORMAT {
+            return Err(RtError::UnsupportedFormat(accumulation.format));
+        }
+
+        let infos = [accumulation.view, output.view].map(|view| {
+            [vk::DescriptorImageInfo::default()
+                .image_view(view)
+                .image_layout(vk::ImageLayout::GENERAL)]
+        });
+
+        let exposure_info = [exposure.descriptor_info()];
+
+        let mut writes: Vec<vk::WriteDescriptorSet> = infos
+            .iter()
+            .enumerate()
+            .map(|(binding, info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(info)
+            })
+            .collect();
+        writes.push(
+            vk::WriteDescriptorSet::default()
+                .dst_set(descriptor_set)
+                .dst_binding(2)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&exposure_info),
+        );
+
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+        Ok(())
+    }
+
+    /// 录制色调映射 dispatch
+    ///
+    /// 调用方需保证累积图像的光追写入已通过屏障对计算着色器可见，且两张图像都处于 GENERAL 布局。
+    pub fn record(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+        constants: &TonemapConstants,
+        extent: vk::Extent2D,
+    ) {
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(constants),
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                extent.width.div_ceil(Self::WORKGROUP_SIZE),
+                extent.height.div_ceil(Self::WORKGROUP_SIZE),
+                1,
+            );
+        }
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::{read_texel, transition_image_to_general};
+    use crate::test_support::test_context;
+
+    #[test]
+    fn constant_hdr_input_maps_to_the_expected_ldr_value() {
+        let Some(context) = test_context("constant_hdr_input_maps_to_the_expected_ldr_value")
+        else {
+            return;
+        };
+        let spv_path = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/tonemap.comp.spv");
+        let Ok(spv) = std::fs::read(spv_path) else {
+            eprintln!(
+                "skipping constant_hdr_input_maps_to_the_expected_ldr_value: {} not compiled",
+                spv_path
+            );
+            return;
+        };
+        let shader_code = ash::util::read_spv(&mut std::io::Cursor::new(spv)).unwrap();
+        let device = &context.device;
+        let properties = context.device_memory_properties;
+        let command_pool = context.command_pool();
+
+        let pass = TonemapPass::new(device, &shader_code, vk::PipelineCache::null()).unwrap();
+        let mut accumulation = RenderTargetImage::new(
+            device,
+            8,
+            8,
+            ACCUMULATION_FORMAT,
+            RenderTargetUsage::Accumulation,
+            properties,
+        )
+        .unwrap();
+        let mut output = TonemapPass::create_output(device, &accumulation, properties).unwrap();
+        for image in [&mut accumulation, &mut output] {
+            transition_image_to_general(device, command_pool.pool, context.queue, image).unwrap();
+        }
+        let exposure = ExposureBuffer::new(device, 1.0, properties);
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: 2,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+            },
+        ];
+        let descriptor_pool = unsafe {
+            device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )
+        }
+        .unwrap();
+        let set_layouts = [pass.descriptor_set_layout];
+        let descriptor_set = unsafe {
+            device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&set_layouts),
+            )
+        }
+        .unwrap()[0];
+        pass.write_descriptor_set(device, descriptor_set, &accumulation, &output, &exposure)
+            .unwrap();
+
+        // 两个采样累积到 4.0，平均后的辐射度为 2.0；输出按线性值量化，便于直接和算子比较
+        let n_samples = 2;
+        let radiance = 2.0;
+        let accumulated = radiance * n_samples as f32;
+        let range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1);
+        for tonemap in [TonemapOperator::Reinhard, TonemapOperator::Aces] {
+            let options = ExportOptions {
+                output_is_srgb: true,
+                tonemap,
+                ..ExportOptions::default()
+            };
+            let command_buffer = command_pool.begin_one_time(device).unwrap();
+            unsafe {
+                device.cmd_clear_color_image(
+                    command_buffer,
+                    accumulation.image,
+                    vk::ImageLayout::GENERAL,
+                    &vk::ClearColorValue {
+                        float32: [accumulated, accumulated, accumulated, 1.0],
+                    },
+                    &[range],
+                );
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[vk::ImageMemoryBarrier::default()
+                        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                        .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                        .old_layout(vk::ImageLayout::GENERAL)
+                        .new_layout(vk::ImageLayout::GENERAL)
+                        .image(accumulation.image)
+                        .subresource_range(range)],
+                );
+            }
+            pass.record(
+                device,
+                command_buffer,
+                descriptor_set,
+                &TonemapConstants::from_options(&options, n_samples),
+                output.extent,
+            );
+            command_pool
+                .end_one_time(device, context.queue, command_buffer)
+                .unwrap();
+
+            let texel: [u8; 4] = read_texel(
+                device,
+                command_pool.pool,
+                context.queue,
+                &output,
+                3,
+                5,
+                properties,
+            )
+            .unwrap();
+            let expected = (tonemap.apply(radiance) * 255.0).round() as i32;
+            for channel in &texel[..3] {
+                assert!(
+                    (*channel as i32 - expected).abs() <= 1,
+                    "{:?}: got {:?}, expected {}",
+                    tonemap,
+                    texel,
+                    expected
+                );
+            }
+            assert_eq!(texel[3], 255);
+        }
+
+        unsafe {
+            device.destroy_descriptor_pool(descriptor_pool, None);
+            exposure.destroy(device);
+            output.destroy(device);
+            accumulation.destroy(device);
+            pass.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use ash::{Device, khr, vk};
+use bytemuck::{Pod, Zeroable};
+
+use crate::command::{CommandPoolManager, map_submit_error};
+use crate::error::RtError;
+
+/// 一次 vkCmdTraceRaysKHR 调用所需的管线、描述符集与 SBT 区域
+#[derive(Clone, Copy)]
+pub struct TraceDispatch {
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub descriptor_set: vk::DescriptorSet,
+    pub raygen_region: vk::StridedDeviceAddressRegionKHR,
+    pub miss_region: vk::StridedDeviceAddressRegionKHR,
+    pub hit_region: vk::StridedDeviceAddressRegionKHR,
+    pub callable_region: vk::StridedDeviceAddressRegionKHR,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TraceDispatch {
+    /// 绑定管线和描述符集并录制 trace rays
+    pub fn record(
+        &self,
+        device: &Device,
+        rt_loader: &khr::ray_tracing_pipeline::Device,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::RAY_TRACING_KHR,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::RAY_TRACING_KHR,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            rt_loader.cmd_trace_rays(
+                command_buffer,
+                &self.raygen_region,
+                &self.miss_region,
+                &self.hit_region,
+                &self.callable_region,
+                self.width,
+                self.height,
+                1,
+            );
+        }
+    }
+}
+
+/// 分块 dispatch 时通过 push constant 传给 raygen 着色器的块偏移与整幅图像尺寸，
+/// 着色器中像素坐标为 gl_LaunchIDEXT.xy + tile_offset
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct TileConstants {
+    pub tile_offset: [u32; 2],
+    /// 整幅图像的宽高；分块时 gl_LaunchSizeEXT 只是块的大小，计算 UV 与宽高比要用这个值
+    pub image_extent: [u32; 2],
+}
+
+impl TileConstants {
+    pub fn new(tile: &TileRect, width: u32, height: u32) -> Self {
+        Self {
+            tile_offset: tile.offset,
+            image_extent: [width, height],
+        }
+    }
+
+    /// 在 offset 处声明的 push constant 范围，与其他 push constant 共用管线布局时放在它们之后
+    pub fn push_constant_range(offset: u32) -> vk::PushConstantRange {
+        vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR)
+            .offset(offset)
+            .size(size_of::<Self>() as u32)
+    }
+}
+
+/// 图像中的一个矩形块
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileRect {
+    pub offset: [u32; 2],
+    pub extent: [u32; 2],
+}
+
+/// 按行优先把 width x height 切成边长 tile_size 的块，右侧与底部的块可能更小
+pub fn tile_rects(width: u32, height: u32, tile_size: u32) -> Vec<TileRect> {
+    assert!(tile_size > 0, "tile_size must be non-zero");
+
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(tile_size as usize) {
+        for x in (0..width).step_by(tile_size as usize) {
+            tiles.push(TileRect {
+                offset: [x, y],
+                extent: [tile_size.min(width - x), tile_size.min(height - y)],
+            });
+        }
+    }
+    tiles
+}
+
+/// 把一次大图像的 trace rays 拆成多个块，每 tiles_per_submit 块单独提交并等待 fence，避免单次提交过长触发 TDR
+///
+/// 管线布局需包含 `TileConstants::push_constant_range(constants_offset)`。各块写入互不重叠的像素，
+/// 提交之间不需要屏障；函数返回时所有块都已执行完成。返回 dispatch 次数。
+#[allow(clippy::too_many_arguments)]
+pub fn trace_rays_tiled(
+    device: &Device,
+    rt_loader: &khr::ray_tracing_pipeline::Device,
+    command_pool: &CommandPoolManager,
+    queue: vk::Queue,
+    dispatch: &TraceDispatch,
+    constants_offset: u32,
+    tile_size: u32,
+    tiles_per_submit: usize,
+) -> Result<usize, RtError> {
+    if tile_size == 0 || tiles_per_submit == 0 {
+        return Err(RtError::InvalidConfiguration(format!(
+            "tile_size ({}) and tiles_per_submit ({}) must be non-zero",
+            tile_size, tiles_per_submit
+        )));
+    }
+    let tiles = tile_rects(dispatch.width, dispatch.height, tile_size);
+
+    let fence = unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None) }?;
+    let result = (|| -> Result<(), RtError> {
+        for batch in tiles.chunks(tiles_per_submit) {
+            let command_buffer = command_pool.begin_one_time(device)?;
+            record_tiles(
+                device,
+                rt_loader,
+                command_buffer,
+                dispatch,
+                constants_offset,
+                batch,
+            );
+
+            let command_buffers = [command_buffer];
+            let submitted = unsafe {
+                device.end_command_buffer(command_buffer).and_then(|()| {
+                    device.queue_submit(
+                        queue,
+                        &[vk::SubmitInfo::default().command_buffers(&command_buffers)],
+                        fence,
+                    )
+                })
+            }
+            .and_then(|()| unsafe { device.wait_for_fences(&[fence], true, u64::MAX) })
+            .and_then(|()| unsafe { device.reset_fences(&[fence]) });
+            unsafe { device.free_command_buffers(command_pool.pool, &command_buffers) };
+            submitted.map_err(|err| map_submit_error(err, command_pool.fault_loader.as_ref()))?;
+        }
+        Ok(())
+    })();
+    unsafe { device.destroy_fence(fence, None) };
+    result?;
+
+    Ok(tiles.len())
+}
+
+/// 在 command_buffer 中为 tiles 中的每个块录制一次 trace rays，TileConstants 写在 constants_offset 处
+pub fn record_tiles(
+    device: &Device,
+    rt_loader: &khr::ray_tracing_pipeline::Device,
+    command_buffer: vk::CommandBuffer,
+    dispatch: &TraceDispatch,
+    constants_offset: u32,
+    tiles: &[TileRect],
+) {
+    unsafe {
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::RAY_TRACING_KHR,
+            dispatch.pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::RAY_TRACING_KHR,
+            dispatch.pipeline_layout,
+            0,
+            &[dispatch.descriptor_set],
+            &[],
+        );
+
+        for tile in tiles {
+            let constants = TileConstants::new(tile, dispatch.width, dispatch.height);
+            device.cmd_push_constants(
+                command_buffer,
+                dispatch.pipeline_layout,
+                vk::ShaderStageFlags::RAYGEN_KHR,
+                constants_offset,
+                bytemuck::bytes_of(&constants),
+            );
+            rt_loader.cmd_trace_rays(
+                command_buffer,
+                &dispatch.raygen_region,
+                &dispatch.miss_region,
+                &dispatch.hit_region,
+                &dispatch.callable_region,
+                tile.extent[0],
+                tile.extent[1],
+                1,
+            );
+        }
+    }
+}
+
+/// 一次 traceRayEXT 使用的 rayFlags 与 cullMask，按 pass（主光线、阴影、反射）通过 push constant 传入，
+/// 切换 pass 时无需重新编译着色器
+///
+/// 着色器端对应 `layout(push_constant) uniform RayPass { uint rayFlags; uint cullMask; }`，
+/// 取值与 GLSL 的 `gl_RayFlags*EXT` 相同。
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Pod, Zeroable)]
+pub struct RayPassConstants {
+    pub ray_flags: u32,
+    /// 只有低 8 位有效，与实例的 mask 按位与为 0 时该实例被跳过
+    pub cull_mask: u32,
+}
+
+impl RayPassConstants {
+    pub const FLAG_NONE: u32 = 0;
+    pub const FLAG_OPAQUE: u32 = 0x01;
+    pub const FLAG_NO_OPAQUE: u32 = 0x02;
+    pub const FLAG_TERMINATE_ON_FIRST_HIT: u32 = 0x04;
+    pub const FLAG_SKIP_CLOSEST_HIT_SHADER: u32 = 0x08;
+    pub const FLAG_CULL_BACK_FACING_TRIANGLES: u32 = 0x10;
+    pub const FLAG_CULL_FRONT_FACING_TRIANGLES: u32 = 0x20;
+    pub const FLAG_CULL_OPAQUE: u32 = 0x40;
+    pub const FLAG_CULL_NO_OPAQUE: u32 = 0x80;
+
+    /// 命中所有实例
+    pub const CULL_MASK_ALL: u32 = 0xFF;
+
+    pub fn new(ray_flags: u32, cull_mask: u32) -> Self {
+        assert!(
+            cull_mask <= Self::CULL_MASK_ALL,
+            "cull mask 0x{:x} has bits above the low 8",
+            cull_mask
+        );
+        Self {
+            ray_flags,
+            cull_mask,
+        }
+    }
+
+    /// 主光线：不加额外标志，命中所有实例
+    pub fn primary() -> Self {
+        Self::new(Self::FLAG_NONE, Self::CULL_MASK_ALL)
+    }
+
+    /// 阴影光线：只关心是否被遮挡，首次命中即终止并跳过 closest-hit
+    pub fn shadow(cull_mask: u32) -> Self {
+        Self::new(
+            Self::FLAG_OPAQUE
+                | Self::FLAG_TERMINATE_ON_FIRST_HIT
+                | Self::FLAG_SKIP_CLOSEST_HIT_SHADER,
+            cull_mask,
+        )
+    }
+
+    /// 在 offset 处声明的 push constant 范围，raygen 与 closest-hit（发射次级光线）都可读取
+    pub fn push_constant_range(offset: u32) -> vk::PushConstantRange {
+        vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR | vk::ShaderStageFlags::CLOSEST_HIT_KHR)
+            .offset(offset)
+            .size(size_of::<Self>() as u32)
+    }
+
+    /// 录制 push constant，offset 需与 push_constant_range 一致
+    pub fn record(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        offset: u32,
+    ) {
+        unsafe {
+            device.cmd_push_constants(
+                command_buffer,
+                pipeline_layout,
+                vk::ShaderStageFlags::RAYGEN_KHR | vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                offset,
+                bytemuck::bytes_of(self),
+            );
+        }
+    }
+}
+
+/// 只追踪渲染目标的一个子矩形时通过 push constant 传给 raygen 的视口，
+/// 像素坐标为 `viewport_offset + gl_LaunchIDEXT.xy`，宽高比按 viewport_extent 计算
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct ViewportConstants {
+    pub viewport_offset: [u32; 2],
+    pub viewport_extent: [u32; 2],
+}
+
+impl ViewportConstants {
+    pub fn from_rect(region: vk::Rect2D) -> Self {
+        assert!(
+            region.offset.x >= 0 && region.offset.y >= 0,
+            "viewport offset must be non-negative: {:?}",
+            region.offset
+        );
+        Self {
+            viewport_offset: [region.offset.x as u32, region.offset.y as u32],
+            viewport_extent: [region.extent.width, region.extent.height],
+        }
+    }
+
+    /// 创建管线布局时需要声明的 push constant 范围
+    pub fn push_constant_range() -> vk::PushConstantRange {
+        vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR)
+            .offset(0)
+            .size(size_of::<Self>() as u32)
+    }
+}
+
+/// 只对 region 范围录制 trace rays，配合 present_render_target_region 显示到 swapchain 的对应区域
+///
+/// 管线布局需包含 `ViewportConstants::push_constant_range()`。
+pub fn trace_rays_region(
+    device: &Device,
+    rt_loader: &khr::ray_tracing_pipeline::Device,
+    command_buffer: vk::CommandBuffer,
+    dispatch: &TraceDispatch,
+    region: vk::Rect2D,
+) -> ViewportConstants {
+    let constants = ViewportConstants::from_rect(region);
+    assert!(
+        constants.viewport_offset[0] + region.extent.width <= dispatch.width
+            && constants.viewport_offset[1] + region.extent.height <= dispatch.height,
+        "viewport {:?} exceeds the {}x{} render target",
+        region,
+        dispatch.width,
+        dispatch.height
+    );
+
+    unsafe {
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::RAY_TRACING_KHR,
+            dispatch.pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::RAY_TRACING_KHR,
+            dispatch.pipeline_layout,
+            0,
+            &[dispatch.descriptor_set],
+            &[],
+        );
+        device.cmd_push_constants(
+            command_buffer,
+            dispatch.pipeline_layout,
+            vk::ShaderStageFlags::RAYGEN_KHR,
+            0,
+            bytemuck::bytes_of(&constants),
+        );
+        rt_loader.cmd_trace_rays(
+            command_buffer,
+            &dispatch.raygen_region,
+            &dispatch.miss_region,
+            &dispatch.hit_region,
+            &dispatch.callable_region,
+            region.extent.width,
+            region.extent.height,
+            1,
+        );
+    }
+
+    constants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_an_8k_image_in_row_major_order() {
+        let tiles = tile_rects(8192, 8192, 2048);
+        assert_eq!(tiles.len(), 16);
+        for (index, tile) in tiles.iter().enumerate() {
+            let (column, row) = (index as u32 % 4, index as u32 / 4);
+            assert_eq!(tile.offset, [column * 2048, row * 2048]);
+            assert_eq!(tile.extent, [2048, 2048]);
+        }
+    }
+
+    #[test]
+    fn tile_constants_carry_the_full_image_extent() {
+        let tiles = tile_rects(8192, 8192, 2048);
+        let last = TileConstants::new(&tiles[15], 8192, 8192);
+        assert_eq!(last.tile_offset, [6144, 6144]);
+        assert_eq!(last.image_extent, [8192, 8192]);
+        assert_eq!(size_of::<TileConstants>(), 16);
+
+        // 与 ViewportConstants 共用布局时放在其后，两个范围不重叠
+        let viewport = ViewportConstants::push_constant_range();
+        let tile = TileConstants::push_constant_range(viewport.offset + viewport.size);
+        assert_eq!(tile.offset, 16);
+        assert_eq!(tile.size, 16);
+    }
+
+    #[test]
+    fn ray_pass_constants_match_the_shader_block_layout() {
+        // layout(push_constant) uniform RayPass { uint rayFlags; uint cullMask; }
+        let shadow = RayPassConstants::shadow(0x0F);
+        assert_eq!(size_of::<RayPassConstants>(), 8);
+        assert_eq!(
+            bytemuck::bytes_of(&shadow),
+            [
+                (RayPassConstants::FLAG_OPAQUE
+                    | RayPassConstants::FLAG_TERMINATE_ON_FIRST_HIT
+                    | RayPassConstants::FLAG_SKIP_CLOSEST_HIT_SHADER)
+                    .to_ne_bytes(),
+                0x0Fu32.to_ne_bytes(),
+            ]
+            .concat()
+        );
+        assert_eq!(shadow.ray_flags, 0x0D);
+
+        let primary = RayPassConstants::primary();
+        assert_eq!((primary.ray_flags, primary.cull_mask), (0, 0xFF));
+
+        let range = RayPassConstants::push_constant_range(16);
+        assert_eq!((range.offset, range.size), (16, 8));
+        assert!(
+            range
+                .stage_flags
+                .contains(vk::ShaderStageFlags::CLOSEST_HIT_KHR)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cull mask")]
+    fn cull_mask_above_eight_bits_is_rejected() {
+        RayPassConstants::new(RayPassConstants::FLAG_NONE, 0x100);
+    }
+
+    #[test]
+    fn edge_tiles_are_clipped_to_the_image() {
+        let tiles = tile_rects(5, 3, 2);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(
+            tiles[2],
+            TileRect {
+                offset: [4, 0],
+                extent: [1, 2],
+            }
+        );
+        assert_eq!(
+            tiles[5],
+            TileRect {
+                offset: [4, 2],
+                extent: [1, 1],
+            }
+        );
+        let covered: u32 = tiles.iter().map(|t| t.extent[0] * t.extent[1]).sum();
+        assert_eq!(covered, 15);
+    }
+}
@@ -1,24 +1,89 @@
 use ash::prelude::VkResult;
 use ash::{Device, Entry, Instance, ext, khr, vk};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString, c_void};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::os::raw::c_char;
+use std::sync::{Mutex, OnceLock};
+
+use crate::address_registry::lookup_device_address;
+use crate::error::RtError;
 
 pub struct ValidationLayerConfig {
     pub layers: Vec<CString>,
     pub enabled: bool,
+    /// 启用着色器 debugPrintfEXT，输出以 INFO 级别经调试回调打印
+    ///
+    /// 验证层需要插桩每个着色器并回读缓冲区，帧时间会明显增加，只在调试时开启。
+    pub debug_printf: bool,
+    /// 启用 GPU 辅助验证（GPU-AV），检查着色器中越界的描述符与缓冲区访问
+    ///
+    /// 性能影响远大于 debug_printf。同时会预留一个描述符集槽位（RESERVE_BINDING_SLOT），
+    /// 管线布局最多只能使用 maxBoundDescriptorSets - 1 个描述符集。
+    pub gpu_assisted: bool,
 }
 
 impl ValidationLayerConfig {
-    /// 创建验证层配置（debug 模式启用，release 模式禁用）
+    /// 覆盖默认验证层开关的环境变量，`RT_VALIDATION=0` 关闭、`RT_VALIDATION=1` 开启
+    pub const ENV_VAR: &'static str = "RT_VALIDATION";
+
+    /// 创建验证层配置（默认 debug 模式启用，release 模式禁用，可由 RT_VALIDATION 覆盖）
     pub fn new() -> Self {
-        #[cfg(debug_assertions)]
-        let layers = vec![CString::new("VK_LAYER_KHRONOS_validation").unwrap()];
-        #[cfg(not(debug_assertions))]
-        let layers = Vec::new();
+        let enabled = Self::enabled_from_env(std::env::var(Self::ENV_VAR).ok().as_deref())
+            .unwrap_or(cfg!(debug_assertions));
+
+        let layers = if enabled {
+            vec![CString::new("VK_LAYER_KHRONOS_validation").unwrap()]
+        } else {
+            Vec::new()
+        };
 
-        let enabled = !layers.is_empty();
-        Self { layers, enabled }
+        Self {
+            layers,
+            enabled,
+            debug_printf: false,
+            gpu_assisted: false,
+        }
+    }
+
+    /// 需要通过 vk::ValidationFeaturesEXT 链入实例创建信息的验证特性，未启用验证层时为空
+    ///
+    /// 规范不允许同时启用 GPU_ASSISTED 与 DEBUG_PRINTF（VUID-VkValidationFeaturesEXT-pEnabledValidationFeatures-02968），
+    /// 两者都打开时返回 RtError::InvalidConfiguration。
+    pub fn enabled_features(&self) -> Result<Vec<vk::ValidationFeatureEnableEXT>, RtError> {
+        let mut features = Vec::new();
+        if !self.enabled {
+            return Ok(features);
+        }
+        if self.debug_printf && self.gpu_assisted {
+            return Err(RtError::InvalidConfiguration(
+                "debug_printf and gpu_assisted validation cannot be enabled together".to_string(),
+            ));
+        }
+        if self.debug_printf {
+            features.push(vk::ValidationFeatureEnableEXT::DEBUG_PRINTF);
+        }
+        if self.gpu_assisted {
+            features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED);
+            features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT);
+        }
+        Ok(features)
+    }
+
+    /// 解析 RT_VALIDATION 的值，未设置或无法识别时返回 None
+    fn enabled_from_env(value: Option<&str>) -> Option<bool> {
+        match value?.trim() {
+            "1" | "true" | "on" => Some(true),
+            "0" | "false" | "off" => Some(false),
+            other => {
+                println!(
+                    "[Warning] Ignoring unrecognized {}={:?}",
+                    Self::ENV_VAR,
+                    other
+                );
+                None
+            }
+        }
     }
 
     /// 获取层名称指针列表
@@ -41,6 +106,151 @@ impl Default for ValidationLayerConfig {
     }
 }
 
+/// 验证消息去重的处理结果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DedupDecision {
+    /// 正常打印
+    Emit,
+    /// 已超过重复上限，不打印
+    Suppress,
+    /// 不打印本条，但打印一次汇总：目前共抑制的条数与涉及的不同消息数
+    Summary { suppressed: u64, distinct: usize },
+}
+
+/// 按 messageIdNumber + 消息内容哈希去重，每种消息最多打印 max_repeats 次
+///
+/// 每抑制 summary_interval 条打印一次汇总，summary_interval 为 0 时不打印汇总。
+#[derive(Debug)]
+pub struct MessageDeduplicator {
+    pub max_repeats: u32,
+    pub summary_interval: u64,
+    counts: HashMap<(i32, u64), u32>,
+    suppressed: u64,
+}
+
+impl MessageDeduplicator {
+    pub fn new(max_repeats: u32, summary_interval: u64) -> Self {
+        Self {
+            max_repeats,
+            summary_interval,
+            counts: HashMap::new(),
+            suppressed: 0,
+        }
+    }
+
+    pub fn filter(&mut self, message_id: i32, message: &str) -> DedupDecision {
+        let mut hasher = DefaultHasher::new();
+        message.hash(&mut hasher);
+
+        let count = self
+            .counts
+            .entry((message_id, hasher.finish()))
+            .or_insert(0);
+        *count = count.saturating_add(1);
+        if *count <= self.max_repeats {
+            return DedupDecision::Emit;
+        }
+
+        self.suppressed += 1;
+        if self.summary_interval > 0 && self.suppressed.is_multiple_of(self.summary_interval) {
+            let distinct = self
+                .counts
+                .values()
+                .filter(|&&count| count > self.max_repeats)
+                .count();
+            DedupDecision::Summary {
+                suppressed: self.suppressed,
+                distinct,
+            }
+        } else {
+            DedupDecision::Suppress
+        }
+    }
+}
+
+/// 调试信使的消息级别、类型与按消息 ID 的过滤规则
+///
+/// - deny_ids / deny_names 命中的消息总是被丢弃
+/// - allow_ids / allow_names 非空时，只输出命中其中之一的消息
+///
+/// names 按子串匹配 `pMessageIdName`（如 `"BestPractices-"`）。
+#[derive(Clone, Debug)]
+pub struct DebugMessengerConfig {
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+    pub allow_ids: Vec<i32>,
+    pub allow_names: Vec<String>,
+    pub deny_ids: Vec<i32>,
+    pub deny_names: Vec<String>,
+}
+
+impl Default for DebugMessengerConfig {
+    fn default() -> Self {
+        Self {
+            severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            message_types: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+            allow_ids: Vec::new(),
+            allow_names: Vec::new(),
+            deny_ids: Vec::new(),
+            deny_names: Vec::new(),
+        }
+    }
+}
+
+impl DebugMessengerConfig {
+    /// 按 allow/deny 规则判断是否输出该消息
+    pub fn should_emit(&self, message_id: i32, message_id_name: &str) -> bool {
+        let matches = |ids: &[i32], names: &[String]| {
+            ids.contains(&message_id)
+                || names
+                    .iter()
+                    .any(|name| message_id_name.contains(name.as_str()))
+        };
+
+        if matches(&self.deny_ids, &self.deny_names) {
+            return false;
+        }
+        if self.allow_ids.is_empty() && self.allow_names.is_empty() {
+            return true;
+        }
+        matches(&self.allow_ids, &self.allow_names)
+    }
+}
+
+static DEBUG_MESSENGER_CONFIG: OnceLock<DebugMessengerConfig> = OnceLock::new();
+
+/// 设置调试信使配置，需在 create_instance 之前调用
+///
+/// 配置只能设置一次；重复调用，或在 create_instance 已使用默认配置之后调用，
+/// 返回 RtError::InvalidConfiguration，已生效的配置保持不变。
+pub fn set_debug_messenger_config(config: DebugMessengerConfig) -> Result<(), RtError> {
+    DEBUG_MESSENGER_CONFIG.set(config).map_err(|_| {
+        RtError::InvalidConfiguration("debug messenger config is already set".to_string())
+    })
+}
+
+/// 当前生效的调试信使配置，未设置时为默认值
+pub fn debug_messenger_config() -> &'static DebugMessengerConfig {
+    DEBUG_MESSENGER_CONFIG.get_or_init(DebugMessengerConfig::default)
+}
+
+static MESSAGE_DEDUP: OnceLock<Mutex<MessageDeduplicator>> = OnceLock::new();
+
+/// 为 default_vulkan_debug_utils_callback 启用去重，需在 create_instance 之前调用
+///
+/// 去重器只能设置一次，重复调用返回 RtError::InvalidConfiguration，已生效的参数保持不变。
+pub fn enable_message_dedup(max_repeats: u32, summary_interval: u64) -> Result<(), RtError> {
+    MESSAGE_DEDUP
+        .set(Mutex::new(MessageDeduplicator::new(
+            max_repeats,
+            summary_interval,
+        )))
+        .map_err(|_| RtError::InvalidConfiguration("message dedup is already enabled".to_string()))
+}
+
 pub unsafe extern "system" fn default_vulkan_debug_utils_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
@@ -62,6 +272,42 @@ pub unsafe extern "system" fn default_vulkan_debug_utils_callback(
             _ => "[Unknown]",
         };
         let message = CStr::from_ptr((*p_callback_data).p_message);
+
+        let message_id_name = if (*p_callback_data).p_message_id_name.is_null() {
+            std::borrow::Cow::Borrowed("")
+        } else {
+            CStr::from_ptr((*p_callback_data).p_message_id_name).to_string_lossy()
+        };
+        if !debug_messenger_config()
+            .should_emit((*p_callback_data).message_id_number, &message_id_name)
+        {
+            return vk::FALSE;
+        }
+
+        if let Some(dedup) = MESSAGE_DEDUP.get() {
+            let decision = dedup
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .filter(
+                    (*p_callback_data).message_id_number,
+                    &message.to_string_lossy(),
+                );
+            match decision {
+                DedupDecision::Emit => {}
+                DedupDecision::Suppress => return vk::FALSE,
+                DedupDecision::Summary {
+                    suppressed,
+                    distinct,
+                } => {
+                    println!(
+                        "[Debug] Suppressed {} repeated messages ({} distinct)",
+                        suppressed, distinct
+                    );
+                    return vk::FALSE;
+                }
+            }
+        }
+
         println!("[Debug]{}{}{:?}", severity, types, message);
 
         vk::FALSE
@@ -102,33 +348,143 @@ pub fn get_instance_extensions(headless_mode: bool) -> Vec<*const i8> {
     instance_extensions
 }
 
+/// 从期望的实例扩展列表中去掉 loader 不支持的扩展，每个被去掉的扩展打印一条警告
+///
+/// 缺少 VK_EXT_debug_utils 时不会中止，只是无法创建调试信使；
+/// 用 `has_debug_utils` 检查过滤后的列表决定是否启用信使。
+pub fn filter_instance_extensions(
+    available: &[vk::ExtensionProperties],
+    desired: &[*const i8],
+) -> Vec<*const i8> {
+    desired
+        .iter()
+        .copied()
+        .filter(|&name_ptr| {
+            let name = unsafe { CStr::from_ptr(name_ptr) };
+            let supported = available
+                .iter()
+                .any(|extension| extension.extension_name_as_c_str() == Ok(name));
+            if !supported {
+                println!(
+                    "[Warning] Instance extension {:?} is not available and will be skipped",
+                    name
+                );
+            }
+            supported
+        })
+        .collect()
+}
+
+/// 按 loader 实际支持的扩展过滤 desired，枚举失败时原样返回
+pub fn get_available_instance_extensions(entry: &Entry, desired: &[*const i8]) -> Vec<*const i8> {
+    match unsafe { entry.enumerate_instance_extension_properties(None) } {
+        Ok(available) => filter_instance_extensions(&available, desired),
+        Err(_) => desired.to_vec(),
+    }
+}
+
+/// 扩展列表中是否包含 VK_EXT_debug_utils
+pub fn has_debug_utils(instance_extensions: &[*const i8]) -> bool {
+    instance_extensions
+        .iter()
+        .any(|&name| unsafe { CStr::from_ptr(name) } == ext::debug_utils::NAME)
+}
+
+/// 可选的实例扩展：当前只有窗口模式下的 VK_EXT_swapchain_colorspace（HDR 色彩空间）
+///
+/// 仅返回 loader 实际支持的扩展，结果追加到 get_instance_extensions 的列表后即可。
+pub fn get_optional_instance_extensions(entry: &Entry, headless_mode: bool) -> Vec<*const i8> {
+    if headless_mode {
+        return Vec::new();
+    }
+
+    let available = unsafe { entry.enumerate_instance_extension_properties(None) }
+        .unwrap_or_default();
+
+    [ext::swapchain_colorspace::NAME]
+        .into_iter()
+        .filter(|name| {
+            available
+                .iter()
+                .any(|extension| extension.extension_name_as_c_str() == Ok(*name))
+        })
+        .map(|name| name.as_ptr())
+        .collect()
+}
+
+/// 传给驱动的应用与引擎标识，部分驱动会按应用名匹配优化配置
+#[derive(Clone, Copy, Debug)]
+pub struct ApplicationInfo<'a> {
+    pub name: &'a str,
+    /// (major, minor, patch)
+    pub version: (u32, u32, u32),
+    pub engine_name: &'a str,
+    /// (major, minor, patch)
+    pub engine_version: (u32, u32, u32),
+}
+
+impl Default for ApplicationInfo<'_> {
+    fn default() -> Self {
+        Self {
+            name: "Vulkan Ray Tracing",
+            version: (1, 0, 0),
+            engine_name: "No Engine",
+            engine_version: (1, 0, 0),
+        }
+    }
+}
+
+/// 创建实例
+///
+/// validation_features 非空时链入 vk::ValidationFeaturesEXT（需要同时启用 VK_EXT_validation_features）；
+/// 其中包含 DEBUG_PRINTF 时调试信使额外接收 INFO 级别消息，以便打印着色器输出。
+/// 应用名或引擎名中含有 NUL 字节时返回 RtError::InvalidConfiguration。
 pub fn create_instance(
     entry: &Entry,
+    app_info: &ApplicationInfo,
     validation_layers: &[*const i8],
     instance_extensions: &[*const i8],
     enable_validation: bool,
-) -> VkResult<Instance> {
-    let application_name =
-        CString::new("Vulkan Ray Tracing").expect("Failed to create application name");
-    let engine_name = CString::new("No Engine").expect("Failed to create engine name");
+    validation_features: &[vk::ValidationFeatureEnableEXT],
+) -> Result<Instance, RtError> {
+    let c_name = |field: &str, value: &str| {
+        CString::new(value).map_err(|_| {
+            RtError::InvalidConfiguration(format!("{} {:?} contains a NUL byte", field, value))
+        })
+    };
+    let application_name = c_name("application name", app_info.name)?;
+    let engine_name = c_name("engine name", app_info.engine_name)?;
 
+    let messenger_config = debug_messenger_config();
+    let mut severity = messenger_config.severity;
+    if validation_features.contains(&vk::ValidationFeatureEnableEXT::DEBUG_PRINTF) {
+        severity |= vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+    }
+    if validation_features.contains(&vk::ValidationFeatureEnableEXT::GPU_ASSISTED) {
+        println!(
+            "[Warning] GPU-assisted validation is enabled: expect a large slowdown and one reserved descriptor set slot"
+        );
+    }
     let mut debug_utils_create_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
-        .message_severity(
-            vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
-        )
-        .message_type(
-            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
-                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
-        )
+        .message_severity(severity)
+        .message_type(messenger_config.message_types)
         .pfn_user_callback(Some(default_vulkan_debug_utils_callback));
 
     let application_info = vk::ApplicationInfo::default()
         .application_name(application_name.as_c_str())
-        .application_version(vk::make_api_version(0, 1, 0, 0))
+        .application_version(vk::make_api_version(
+            0,
+            app_info.version.0,
+            app_info.version.1,
+            app_info.version.2,
+        ))
         .engine_name(engine_name.as_c_str())
-        .engine_version(vk::make_api_version(0, 1, 0, 0))
+        .engine_version(vk::make_api_version(
+            0,
+            app_info.engine_version.0,
+            app_info.engine_version.1,
+            app_info.engine_version.2,
+        ))
         .api_version(vk::API_VERSION_1_3);
 
     let instance_create_info = vk::InstanceCreateInfo::default()
@@ -142,7 +498,15 @@ pub fn create_instance(
         instance_create_info
     };
 
-    unsafe { entry.create_instance(&instance_create_info, None) }
+    let mut validation_features_info =
+        vk::ValidationFeaturesEXT::default().enabled_validation_features(validation_features);
+    let instance_create_info = if validation_features.is_empty() {
+        instance_create_info
+    } else {
+        instance_create_info.push_next(&mut validation_features_info)
+    };
+
+    Ok(unsafe { entry.create_instance(&instance_create_info, None) }?)
 }
 
 /// 队列族索引
@@ -184,83 +548,134 @@ impl QueueFamilyIndices {
     }
 }
 
+/// 设备选择用到的物理设备与 surface 查询，抽象出来以便在没有 GPU 时用 mock 实现测试选择逻辑
+pub trait PhysicalDeviceQueries {
+    fn physical_devices(&self) -> VkResult<Vec<vk::PhysicalDevice>>;
+    fn device_extension_properties(
+        &self,
+        physical_device: vk::PhysicalDevice,
+    ) -> VkResult<Vec<vk::ExtensionProperties>>;
+    fn queue_family_properties(
+        &self,
+        physical_device: vk::PhysicalDevice,
+    ) -> Vec<vk::QueueFamilyProperties>;
+    fn device_properties(
+        &self,
+        physical_device: vk::PhysicalDevice,
+    ) -> vk::PhysicalDeviceProperties;
+    /// queue_family_index 对应的队列族能否向 surface 呈现
+    fn surface_support(
+        &self,
+        physical_device: vk::PhysicalDevice,
+        queue_family_index: u32,
+        surface: vk::SurfaceKHR,
+    ) -> VkResult<bool>;
+}
+
+/// 基于 ash Instance 与可选 surface loader 的 PhysicalDeviceQueries 实现
+///
+/// 没有 surface loader（无头模式）时 surface_support 总是返回 false。
+#[derive(Clone, Copy)]
+pub struct InstanceQueries<'a> {
+    pub instance: &'a Instance,
+    pub surface_loader: Option<&'a khr::surface::Instance>,
+}
+
+impl<'a> InstanceQueries<'a> {
+    pub fn new(instance: &'a Instance, surface_loader: Option<&'a khr::surface::Instance>) -> Self {
+        Self {
+            instance,
+            surface_loader,
+        }
+    }
+}
+
+impl PhysicalDeviceQueries for InstanceQueries<'_> {
+    fn physical_devices(&self) -> VkResult<Vec<vk::PhysicalDevice>> {
+        unsafe { self.instance.enumerate_physical_devices() }
+    }
+
+    fn device_extension_properties(
+        &self,
+        physical_device: vk::PhysicalDevice,
+    ) -> VkResult<Vec<vk::ExtensionProperties>> {
+        unsafe {
+            self.instance
+                .enumerate_device_extension_properties(physical_device)
+        }
+    }
+
+    fn queue_family_properties(
+        &self,
+        physical_device: vk::PhysicalDevice,
+    ) -> Vec<vk::QueueFamilyProperties> {
+        unsafe {
+            self.instance
+                .get_physical_device_queue_family_properties(physical_device)
+        }
+    }
+
+    fn device_properties(
+        &self,
+        physical_device: vk::PhysicalDevice,
+    ) -> vk::PhysicalDeviceProperties {
+        unsafe {
+            self.instance
+                .get_physical_device_properties(physical_device)
+        }
+    }
+
+    fn surface_support(
+        &self,
+        physical_device: vk::PhysicalDevice,
+        queue_family_index: u32,
+        surface: vk::SurfaceKHR,
+    ) -> VkResult<bool> {
+        match self.surface_loader {
+            Some(loader) => unsafe {
+                loader.get_physical_device_surface_support(
+                    physical_device,
+                    queue_family_index,
+                    surface,
+                )
+            },
+            None => Ok(false),
+        }
+    }
+}
+
+/// 选择第一个支持全部 extensions 且队列族满足要求的物理设备
+///
+/// surface 为 Some 时还要求存在可呈现的队列族，此时 instance 需能查询 surface 支持。
 pub fn pick_physical_device_and_queue_family_indices(
-    instance: &Instance,
-    surface_loader: Option<&khr::surface::Instance>,
+    instance: &impl PhysicalDeviceQueries,
     surface: Option<vk::SurfaceKHR>,
     extensions: &[&CStr],
     need_compute: bool,
 ) -> VkResult<Option<(vk::PhysicalDevice, QueueFamilyIndices)>> {
     let need_present = surface.is_some();
 
-    Ok(unsafe { instance.enumerate_physical_devices() }?
+    Ok(instance
+        .physical_devices()?
         .into_iter()
         .find_map(|physical_device| {
             // 检查设备扩展支持
-            if unsafe { instance.enumerate_device_extension_properties(physical_device) }.map(
-                |exts| {
+            if instance
+                .device_extension_properties(physical_device)
+                .map(|exts| {
                     let set: HashSet<&CStr> = exts
                         .iter()
                         .map(|ext| unsafe { CStr::from_ptr(&ext.extension_name as *const c_char) })
                         .collect();
 
                     extensions.iter().all(|ext| set.contains(ext))
-                },
-            ) != Ok(true)
-            {
-                return None;
-            }
-
-            let queue_families =
-                unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
-
-            let mut indices = QueueFamilyIndices::default();
-
-            // 查找图形队列族
-            if let Some(graphics_index) = queue_families
-                .iter()
-                .enumerate()
-                .find(|(_, properties)| {
-                    properties.queue_count > 0
-                        && properties.queue_flags.contains(vk::QueueFlags::GRAPHICS)
                 })
-                .map(|(i, _)| i as u32)
+                != Ok(true)
             {
-                indices.graphics_family = Some(graphics_index);
-            }
-
-            // 查找计算队列族
-            if need_compute {
-                if let Some(compute_index) = queue_families
-                    .iter()
-                    .enumerate()
-                    .find(|(_, properties)| {
-                        properties.queue_count > 0
-                            && properties.queue_flags.contains(vk::QueueFlags::COMPUTE)
-                    })
-                    .map(|(i, _)| i as u32)
-                {
-                    indices.compute_family = Some(compute_index);
-                }
+                return None;
             }
 
-            // 查找呈现队列族
-            if let (Some(loader), Some(surf)) = (surface_loader, surface) {
-                if let Some(present_index) = queue_families
-                    .iter()
-                    .enumerate()
-                    .find(|(i, _)| {
-                        unsafe {
-                            loader
-                                .get_physical_device_surface_support(physical_device, *i as u32, surf)
-                                .unwrap_or(false)
-                        }
-                    })
-                    .map(|(i, _)| i as u32)
-                {
-                    indices.present_family = Some(present_index);
-                }
-            }
+            let indices = find_queue_families(instance, physical_device, surface, need_compute);
 
             // 检查是否满足要求
             if indices.is_complete(need_compute, need_present) {
@@ -271,33 +686,387 @@ pub fn pick_physical_device_and_queue_family_indices(
         }))
 }
 
-pub fn create_device(
+/// 在 physical_device 上查找 graphics、compute（need_compute 时）与 present（有 surface 时）队列族
+fn find_queue_families(
+    instance: &impl PhysicalDeviceQueries,
+    physical_device: vk::PhysicalDevice,
+    surface: Option<vk::SurfaceKHR>,
+    need_compute: bool,
+) -> QueueFamilyIndices {
+    let queue_families = instance.queue_family_properties(physical_device);
+
+    let mut indices = QueueFamilyIndices::default();
+
+    // 查找图形队列族
+    if let Some(graphics_index) = queue_families
+        .iter()
+        .enumerate()
+        .find(|(_, properties)| {
+            properties.queue_count > 0
+                && properties.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+        .map(|(i, _)| i as u32)
+    {
+        indices.graphics_family = Some(graphics_index);
+    }
+
+    // 查找计算队列族
+    if need_compute {
+        if let Some(compute_index) = queue_families
+            .iter()
+            .enumerate()
+            .find(|(_, properties)| {
+                properties.queue_count > 0
+                    && properties.queue_flags.contains(vk::QueueFlags::COMPUTE)
+            })
+            .map(|(i, _)| i as u32)
+        {
+            indices.compute_family = Some(compute_index);
+        }
+    }
+
+    // 查找呈现队列族
+    if let Some(surf) = surface {
+        if let Some(present_index) = queue_families
+            .iter()
+            .enumerate()
+            .find(|(i, _)| {
+                instance
+                    .surface_support(physical_device, *i as u32, surf)
+                    .unwrap_or(false)
+            })
+            .map(|(i, _)| i as u32)
+        {
+            indices.present_family = Some(present_index);
+        }
+    }
+
+    indices
+}
+
+/// pick_physical_device_with_fallback 选出的设备
+#[derive(Clone, Copy, Debug)]
+pub struct PickedDevice {
+    pub physical_device: vk::PhysicalDevice,
+    pub queue_indices: QueueFamilyIndices,
+    /// 设备是否支持 rt_extensions 中的全部扩展；为 false 时调用方应跳过光线追踪相关的工作
+    pub rt_supported: bool,
+}
+
+/// 先按 rt_extensions 选择支持光线追踪的设备，找不到时放宽扩展要求
+///
+/// 用于没有 RT 硬件的 CI（lavapipe、SwiftShader 等 CPU 实现）：此时仍返回一个满足队列要求的设备，
+/// 优先选择 CPU 类型的设备，并把 rt_supported 置为 false，而不是直接返回 None。
+pub fn pick_physical_device_with_fallback(
+    instance: &impl PhysicalDeviceQueries,
+    surface: Option<vk::SurfaceKHR>,
+    rt_extensions: &[&CStr],
+    need_compute: bool,
+) -> VkResult<Option<PickedDevice>> {
+    if let Some((physical_device, queue_indices)) = pick_physical_device_and_queue_family_indices(
+        instance,
+        surface,
+        rt_extensions,
+        need_compute,
+    )? {
+        return Ok(Some(PickedDevice {
+            physical_device,
+            queue_indices,
+            rt_supported: true,
+        }));
+    }
+
+    let need_present = surface.is_some();
+    let mut candidates = instance.physical_devices()?;
+    candidates.sort_by_key(|&physical_device| {
+        instance.device_properties(physical_device).device_type != vk::PhysicalDeviceType::CPU
+    });
+
+    for physical_device in candidates {
+        let queue_indices = find_queue_families(instance, physical_device, surface, need_compute);
+        if queue_indices.is_complete(need_compute, need_present) {
+            println!(
+                "[Warning] No ray tracing capable device found, falling back to a device without ray tracing support"
+            );
+            return Ok(Some(PickedDevice {
+                physical_device,
+                queue_indices,
+                rt_supported: false,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// 各队列的调度优先级（0.0 ~ 1.0），默认均为 1.0
+///
+/// 多个角色共用同一队列族时，按 graphics、compute、present 的顺序取第一个匹配角色的优先级。
+#[derive(Clone, Copy, Debug)]
+pub struct QueuePriorities {
+    pub graphics: f32,
+    pub compute: f32,
+    pub present: f32,
+}
+
+impl Default for QueuePriorities {
+    fn default() -> Self {
+        Self {
+            graphics: 1.0,
+            compute: 1.0,
+            present: 1.0,
+        }
+    }
+}
+
+impl QueuePriorities {
+    /// 队列族 family 使用的优先级
+    pub fn for_family(&self, queue_indices: &QueueFamilyIndices, family: u32) -> f32 {
+        if queue_indices.graphics_family == Some(family) {
+            self.graphics
+        } else if queue_indices.compute_family == Some(family) {
+            self.compute
+        } else if queue_indices.present_family == Some(family) {
+            self.present
+        } else {
+            1.0
+        }
+    }
+}
+
+/// 逻辑设备创建配置
+#[derive(Default, Clone, Copy, Debug)]
+pub struct DeviceConfig {
+    /// 无头模式下不启用 swapchain 扩展
+    pub headless_mode: bool,
+    /// 着色器中使用 64 位整数（buffer_reference 地址运算常用）
+    pub shader_int64: bool,
+    /// 允许捕获/回放 buffer device address，便于 RenderDoc 等工具回放光线追踪
+    pub buffer_device_address_capture_replay: bool,
+    /// 启用 VK_EXT_memory_budget，用于 query_memory_budget
+    pub memory_budget: bool,
+    /// 启用 VK_EXT_device_fault，设备丢失后可用 query_device_fault 获取诊断信息
+    pub device_fault: bool,
+    /// 各队列族的优先级，例如让异步 compute 低于 graphics
+    pub queue_priorities: QueuePriorities,
+    /// 从 graphics 队列族请求的队列数，0 视为 1，超过 queue_count 时截断
+    pub graphics_queue_count: u32,
+    /// 从 compute 队列族请求的队列数，0 视为 1，超过 queue_count 时截断
+    pub compute_queue_count: u32,
+    /// 启用 robustBufferAccess，越界的 buffer 访问变为有定义的结果，调试属性读取时更安全
+    pub robust_buffer_access: bool,
+    /// 启用 VK_EXT_robustness2 的 nullDescriptor，允许绑定 VK_NULL_HANDLE 描述符
+    pub null_descriptor: bool,
+    /// 启用 textureCompressionBC，用于直接上传 BCn 压缩纹理（Texture::from_ktx2）
+    pub texture_compression_bc: bool,
+    /// 启用 accelerationStructureHostCommands，允许用 BottomLevelAS::new_on_host 在 CPU 上构建
+    pub acceleration_structure_host_commands: bool,
+    /// 启用 VK_NV_ray_tracing_motion_blur，用于 MotionTopLevelAS 与 traceRayMotionNV
+    pub ray_tracing_motion_blur: bool,
+}
+
+impl DeviceConfig {
+    pub fn new(headless_mode: bool) -> Self {
+        Self {
+            headless_mode,
+            ..Default::default()
+        }
+    }
+}
+
+/// 某个队列族实际创建的队列数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FamilyQueueCount {
+    pub family_index: u32,
+    pub count: u32,
+}
+
+/// 计算每个唯一队列族要创建的队列数
+///
+/// graphics 与 compute 共用一个队列族时取两者请求的较大值，结果不超过该族的 queue_count。
+pub fn resolve_queue_counts(
     instance: &Instance,
     physical_device: vk::PhysicalDevice,
     queue_indices: &QueueFamilyIndices,
-    headless_mode: bool,
-) -> VkResult<Device> {
-    let priorities = [1.0];
+    config: &DeviceConfig,
+) -> Vec<FamilyQueueCount> {
+    let family_properties =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    clamp_queue_counts(queue_indices, config, &family_properties)
+}
 
-    // 为每个唯一的队列族创建 QueueCreateInfo
-    let queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = queue_indices
+/// resolve_queue_counts 的计算部分，family_properties 按队列族索引排列
+fn clamp_queue_counts(
+    queue_indices: &QueueFamilyIndices,
+    config: &DeviceConfig,
+    family_properties: &[vk::QueueFamilyProperties],
+) -> Vec<FamilyQueueCount> {
+    queue_indices
         .unique_families()
+        .into_iter()
+        .map(|family_index| {
+            let mut requested = 1;
+            if queue_indices.graphics_family == Some(family_index) {
+                requested = requested.max(config.graphics_queue_count);
+            }
+            if queue_indices.compute_family == Some(family_index) {
+                requested = requested.max(config.compute_queue_count);
+            }
+            let available = family_properties[family_index as usize].queue_count;
+            FamilyQueueCount {
+                family_index,
+                count: requested.min(available),
+            }
+        })
+        .collect()
+}
+
+/// 获取队列族 family_index 上创建的全部队列，count 来自 resolve_queue_counts
+pub fn get_family_queues(device: &Device, family_index: u32, count: u32) -> Vec<vk::Queue> {
+    (0..count)
+        .map(|queue_index| unsafe { device.get_device_queue(family_index, queue_index) })
+        .collect()
+}
+
+/// 每个队列族中各队列的优先级，顺序与 families 一致
+fn family_queue_priorities(
+    families: &[FamilyQueueCount],
+    queue_indices: &QueueFamilyIndices,
+    priorities: &QueuePriorities,
+) -> Vec<Vec<f32>> {
+    families
         .iter()
-        .map(|&index| {
+        .map(|family| {
+            let priority = priorities.for_family(queue_indices, family.family_index);
+            vec![priority; family.count as usize]
+        })
+        .collect()
+}
+
+/// 为每个唯一的队列族创建 QueueCreateInfo，priorities 来自 family_queue_priorities
+fn queue_create_infos<'a>(
+    families: &[FamilyQueueCount],
+    priorities: &'a [Vec<f32>],
+) -> Vec<vk::DeviceQueueCreateInfo<'a>> {
+    families
+        .iter()
+        .zip(priorities)
+        .map(|(family, priorities)| {
             vk::DeviceQueueCreateInfo::default()
-                .queue_family_index(index)
-                .queue_priorities(&priorities)
+                .queue_family_index(family.family_index)
+                .queue_priorities(priorities)
         })
-        .collect();
+        .collect()
+}
 
-    let mut features2 = vk::PhysicalDeviceFeatures2::default();
+/// config 中需要在 PhysicalDeviceFeatures2::features 上开启的核心功能
+fn enabled_core_features(config: &DeviceConfig) -> vk::PhysicalDeviceFeatures {
+    vk::PhysicalDeviceFeatures::default()
+        .shader_int64(config.shader_int64)
+        .robust_buffer_access(config.robust_buffer_access)
+        .texture_compression_bc(config.texture_compression_bc)
+}
+
+/// 由扩展提供的可选功能在设备上的支持情况，只查询 config 请求了的功能
+#[derive(Clone, Copy, Debug, Default)]
+struct ExtensionFeatureSupport {
+    null_descriptor: bool,
+    device_fault: bool,
+    ray_tracing_motion_blur: bool,
+}
+
+impl ExtensionFeatureSupport {
+    fn query(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        config: &DeviceConfig,
+    ) -> Self {
+        let mut robustness2 = vk::PhysicalDeviceRobustness2FeaturesEXT::default();
+        let mut fault = vk::PhysicalDeviceFaultFeaturesEXT::default();
+        let mut motion_blur = vk::PhysicalDeviceRayTracingMotionBlurFeaturesNV::default();
+        let mut features = vk::PhysicalDeviceFeatures2::default();
+        if config.null_descriptor {
+            features = features.push_next(&mut robustness2);
+        }
+        if config.device_fault {
+            features = features.push_next(&mut fault);
+        }
+        if config.ray_tracing_motion_blur {
+            features = features.push_next(&mut motion_blur);
+        }
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+
+        Self {
+            null_descriptor: robustness2.null_descriptor == vk::TRUE,
+            device_fault: fault.device_fault == vk::TRUE,
+            ray_tracing_motion_blur: motion_blur.ray_tracing_motion_blur == vk::TRUE,
+        }
+    }
+
+    /// config 请求了设备不支持的功能时返回 MissingRequiredFeature
+    fn check(&self, config: &DeviceConfig) -> Result<(), RtError> {
+        if config.null_descriptor && !self.null_descriptor {
+            return Err(RtError::MissingRequiredFeature("nullDescriptor"));
+        }
+        if config.device_fault && !self.device_fault {
+            return Err(RtError::MissingRequiredFeature("deviceFault"));
+        }
+        if config.ray_tracing_motion_blur && !self.ray_tracing_motion_blur {
+            return Err(RtError::MissingRequiredFeature("rayTracingMotionBlur"));
+        }
+        Ok(())
+    }
+}
+
+pub fn create_device(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    queue_indices: &QueueFamilyIndices,
+    config: &DeviceConfig,
+) -> Result<Device, RtError> {
+    // 光线追踪依赖 buffer device address，不支持时直接报错而不是在分配内存时失败
+    let mut supported_features12 = vk::PhysicalDeviceVulkan12Features::default();
+    let mut supported_as_features = vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default();
+    let mut supported_features = vk::PhysicalDeviceFeatures2::default()
+        .push_next(&mut supported_features12)
+        .push_next(&mut supported_as_features);
+    unsafe { instance.get_physical_device_features2(physical_device, &mut supported_features) };
+    if config.robust_buffer_access && supported_features.features.robust_buffer_access == vk::FALSE
+    {
+        return Err(RtError::MissingRequiredFeature("robustBufferAccess"));
+    }
+    if config.texture_compression_bc
+        && supported_features.features.texture_compression_bc == vk::FALSE
+    {
+        return Err(RtError::MissingRequiredFeature("textureCompressionBC"));
+    }
+    if config.acceleration_structure_host_commands
+        && supported_as_features.acceleration_structure_host_commands == vk::FALSE
+    {
+        return Err(RtError::MissingRequiredFeature(
+            "accelerationStructureHostCommands",
+        ));
+    }
+    if supported_features12.buffer_device_address == vk::FALSE {
+        return Err(RtError::MissingRequiredFeature("bufferDeviceAddress"));
+    }
+    ExtensionFeatureSupport::query(instance, physical_device, config).check(config)?;
+
+    let families = resolve_queue_counts(instance, physical_device, queue_indices, config);
+    let priorities = family_queue_priorities(&families, queue_indices, &config.queue_priorities);
+    let queue_create_infos = queue_create_infos(&families, &priorities);
+
+    let mut features2 =
+        vk::PhysicalDeviceFeatures2::default().features(enabled_core_features(config));
 
     let mut features12 = vk::PhysicalDeviceVulkan12Features::default()
         .buffer_device_address(true)
+        .buffer_device_address_capture_replay(config.buffer_device_address_capture_replay)
         .scalar_block_layout(true);
 
     let mut as_feature = vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default()
-        .acceleration_structure(true);
+        .acceleration_structure(true)
+        .acceleration_structure_host_commands(config.acceleration_structure_host_commands);
 
     let mut raytracing_pipeline =
         vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default().ray_tracing_pipeline(true);
@@ -310,12 +1079,33 @@ pub fn create_device(
         vk::EXT_SCALAR_BLOCK_LAYOUT_NAME.as_ptr(),
     ];
 
+    if config.memory_budget {
+        enabled_extension_names.push(vk::EXT_MEMORY_BUDGET_NAME.as_ptr());
+    }
+
+    let mut fault_features = vk::PhysicalDeviceFaultFeaturesEXT::default().device_fault(true);
+    if config.device_fault {
+        enabled_extension_names.push(vk::EXT_DEVICE_FAULT_NAME.as_ptr());
+    }
+
+    let mut robustness2_features =
+        vk::PhysicalDeviceRobustness2FeaturesEXT::default().null_descriptor(true);
+    if config.null_descriptor {
+        enabled_extension_names.push(vk::EXT_ROBUSTNESS2_NAME.as_ptr());
+    }
+
+    let mut motion_blur_features =
+        vk::PhysicalDeviceRayTracingMotionBlurFeaturesNV::default().ray_tracing_motion_blur(true);
+    if config.ray_tracing_motion_blur {
+        enabled_extension_names.push(vk::NV_RAY_TRACING_MOTION_BLUR_NAME.as_ptr());
+    }
+
     // 窗口模式需要 swapchain 扩展
-    if !headless_mode {
+    if !config.headless_mode {
         enabled_extension_names.push(vk::KHR_SWAPCHAIN_NAME.as_ptr());
     }
 
-    let device_create_info = vk::DeviceCreateInfo::default()
+    let mut device_create_info = vk::DeviceCreateInfo::default()
         .push_next(&mut features2)
         .push_next(&mut features12)
         .push_next(&mut as_feature)
@@ -323,5 +1113,971 @@ pub fn create_device(
         .queue_create_infos(&queue_create_infos)
         .enabled_extension_names(&enabled_extension_names);
 
-    unsafe { instance.create_device(physical_device, &device_create_info, None) }
-}
\ No newline at end of file
+    if config.device_fault {
+        device_create_info = device_create_info.push_next(&mut fault_features);
+    }
+
+    if config.null_descriptor {
+        device_create_info = device_create_info.push_next(&mut robustness2_features);
+    }
+
+    if config.ray_tracing_motion_blur {
+        device_create_info = device_create_info.push_next(&mut motion_blur_features);
+    }
+
+    Ok(unsafe { instance.create_device(physical_device, &device_create_info, None) }?)
+}
+
+/// 单个内存堆的预算与当前用量（字节）
+#[derive(Clone, Copy, Debug)]
+pub struct HeapBudget {
+    pub heap_index: u32,
+    pub budget: vk::DeviceSize,
+    pub usage: vk::DeviceSize,
+}
+
+/// 通过 VK_EXT_memory_budget 查询各内存堆的可用预算，需要设备支持该扩展
+pub fn query_memory_budget(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Vec<HeapBudget> {
+    let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+    let mut memory_properties2 =
+        vk::PhysicalDeviceMemoryProperties2::default().push_next(&mut budget_properties);
+
+    unsafe {
+        instance.get_physical_device_memory_properties2(physical_device, &mut memory_properties2)
+    };
+
+    let heap_count = memory_properties2.memory_properties.memory_heap_count;
+
+    (0..heap_count)
+        .map(|i| HeapBudget {
+            heap_index: i,
+            budget: budget_properties.heap_budget[i as usize],
+            usage: budget_properties.heap_usage[i as usize],
+        })
+        .collect()
+}
+
+/// 设备丢失后通过 VK_EXT_device_fault 查询故障描述，需要以 DeviceConfig::device_fault 创建设备
+pub fn query_device_fault(fault_loader: &ext::device_fault::Device) -> Option<String> {
+    let get_device_fault_info = fault_loader.fp().get_device_fault_info_ext;
+
+    let mut counts = vk::DeviceFaultCountsEXT::default();
+    let result =
+        unsafe { get_device_fault_info(fault_loader.device(), &mut counts, std::ptr::null_mut()) };
+    if result != vk::Result::SUCCESS {
+        return None;
+    }
+
+    let mut address_infos =
+        vec![vk::DeviceFaultAddressInfoEXT::default(); counts.address_info_count as usize];
+    let mut vendor_infos =
+        vec![vk::DeviceFaultVendorInfoEXT::default(); counts.vendor_info_count as usize];
+    counts.vendor_binary_size = 0;
+
+    let mut fault_info = vk::DeviceFaultInfoEXT {
+        p_address_infos: address_infos.as_mut_ptr(),
+        p_vendor_infos: vendor_infos.as_mut_ptr(),
+        ..Default::default()
+    };
+    let result =
+        unsafe { get_device_fault_info(fault_loader.device(), &mut counts, &mut fault_info) };
+    if result != vk::Result::SUCCESS && result != vk::Result::INCOMPLETE {
+        return None;
+    }
+
+    let mut report = fault_info
+        .description_as_c_str()
+        .map(|description| description.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    for address_info in &address_infos[..counts.address_info_count as usize] {
+        report.push_str(&format!(
+            "\n  address {:?}: 0x{:x} (precision {})",
+            address_info.address_type,
+            address_info.reported_address,
+            address_info.address_precision
+        ));
+        if let Some((label, offset)) = lookup_device_address(address_info.reported_address) {
+            report.push_str(&format!(" -> {} + 0x{:x}", label, offset));
+        }
+    }
+    for vendor_info in &vendor_infos[..counts.vendor_info_count as usize] {
+        report.push_str(&format!(
+            "\n  vendor fault 0x{:x} data 0x{:x}: {}",
+            vendor_info.vendor_fault_code,
+            vendor_info.vendor_fault_data,
+            vendor_info
+                .description_as_c_str()
+                .map(|d| d.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        ));
+    }
+
+    Some(report)
+}
+
+/// 单个队列族的能力描述，用于调试设备与队列族的选择
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueFamilyDescription {
+    pub family_index: u32,
+    pub graphics: bool,
+    pub compute: bool,
+    pub transfer: bool,
+    pub sparse_binding: bool,
+    pub queue_count: u32,
+    /// 时间戳查询的有效位数，0 表示该队列族不支持时间戳
+    pub timestamp_valid_bits: u32,
+}
+
+impl QueueFamilyDescription {
+    /// 支持 compute 但不支持 graphics，可用于异步计算
+    pub fn is_dedicated_compute(&self) -> bool {
+        self.compute && !self.graphics
+    }
+}
+
+impl std::fmt::Display for QueueFamilyDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let capabilities: Vec<&str> = [
+            (self.graphics, "graphics"),
+            (self.compute, "compute"),
+            (self.transfer, "transfer"),
+            (self.sparse_binding, "sparse"),
+        ]
+        .into_iter()
+        .filter(|(supported, _)| *supported)
+        .map(|(_, name)| name)
+        .collect();
+        write!(
+            f,
+            "[{}] {} queue(s), {}, timestampValidBits {}",
+            self.family_index,
+            self.queue_count,
+            capabilities.join("|"),
+            self.timestamp_valid_bits
+        )
+    }
+}
+
+/// 列出物理设备所有队列族的能力
+pub fn describe_queue_families(
+    instance: &impl PhysicalDeviceQueries,
+    physical_device: vk::PhysicalDevice,
+) -> Vec<QueueFamilyDescription> {
+    instance
+        .queue_family_properties(physical_device)
+        .iter()
+        .enumerate()
+        .map(|(index, properties)| QueueFamilyDescription {
+            family_index: index as u32,
+            graphics: properties.queue_flags.contains(vk::QueueFlags::GRAPHICS),
+            compute: properties.queue_flags.contains(vk::QueueFlags::COMPUTE),
+            transfer: properties.queue_flags.contains(vk::QueueFlags::TRANSFER),
+            sparse_binding: properties
+                .queue_flags
+                .contains(vk::QueueFlags::SPARSE_BINDING),
+            queue_count: properties.queue_count,
+            timestamp_valid_bits: properties.timestamp_valid_bits,
+        })
+        .collect()
+}
+
+/// 生成设备能力报告：名称、类型、驱动版本、光追管线属性、加速结构限制与内存堆
+///
+/// 设备支持 VK_EXT_memory_budget 时同时列出各堆的预算与用量，便于附在 bug 报告中。
+pub fn device_report(instance: &Instance, physical_device: vk::PhysicalDevice) -> String {
+    use std::fmt::Write;
+
+    let mut rt_properties = vk::PhysicalDeviceRayTracingPipelinePropertiesKHR::default();
+    let mut as_properties = vk::PhysicalDeviceAccelerationStructurePropertiesKHR::default();
+    let mut properties2 = vk::PhysicalDeviceProperties2::default()
+        .push_next(&mut rt_properties)
+        .push_next(&mut as_properties);
+    unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+    let properties = properties2.properties;
+
+    let device_name = properties
+        .device_name_as_c_str()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut report = String::new();
+    let _ = writeln!(report, "Device: {}", device_name);
+    let _ = writeln!(report, "Type: {:?}", properties.device_type);
+    let _ = writeln!(
+        report,
+        "API version: {}.{}.{}",
+        vk::api_version_major(properties.api_version),
+        vk::api_version_minor(properties.api_version),
+        vk::api_version_patch(properties.api_version)
+    );
+    let _ = writeln!(report, "Driver version: {:#x}", properties.driver_version);
+
+    let _ = writeln!(report, "Ray tracing pipeline:");
+    let _ = writeln!(
+        report,
+        "  shaderGroupHandleSize: {}",
+        rt_properties.shader_group_handle_size
+    );
+    let _ = writeln!(
+        report,
+        "  maxRayRecursionDepth: {}",
+        rt_properties.max_ray_recursion_depth
+    );
+    let _ = writeln!(
+        report,
+        "  shaderGroupBaseAlignment: {}",
+        rt_properties.shader_group_base_alignment
+    );
+
+    let _ = writeln!(report, "Acceleration structure:");
+    let _ = writeln!(
+        report,
+        "  maxGeometryCount: {}",
+        as_properties.max_geometry_count
+    );
+    let _ = writeln!(
+        report,
+        "  maxInstanceCount: {}",
+        as_properties.max_instance_count
+    );
+    let _ = writeln!(
+        report,
+        "  maxPrimitiveCount: {}",
+        as_properties.max_primitive_count
+    );
+    let _ = writeln!(
+        report,
+        "  minAccelerationStructureScratchOffsetAlignment: {}",
+        as_properties.min_acceleration_structure_scratch_offset_alignment
+    );
+
+    let _ = writeln!(report, "Queue families:");
+    for family in describe_queue_families(&InstanceQueries::new(instance, None), physical_device) {
+        let _ = writeln!(report, "  {}", family);
+    }
+
+    let memory_properties =
+        unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let has_memory_budget =
+        unsafe { instance.enumerate_device_extension_properties(physical_device) }
+            .unwrap_or_default()
+            .iter()
+            .any(|extension| extension.extension_name_as_c_str() == Ok(ext::memory_budget::NAME));
+    let budgets = if has_memory_budget {
+        query_memory_budget(instance, physical_device)
+    } else {
+        Vec::new()
+    };
+
+    let _ = writeln!(report, "Memory heaps:");
+    for (i, heap) in memory_properties.memory_heaps_as_slice().iter().enumerate() {
+        let _ = write!(
+            report,
+            "  [{}] {} MiB {:?}",
+            i,
+            heap.size / (1024 * 1024),
+            heap.flags
+        );
+        if let Some(budget) = budgets.get(i) {
+            let _ = write!(
+                report,
+                ", budget {} MiB, usage {} MiB",
+                budget.budget / (1024 * 1024),
+                budget.usage / (1024 * 1024)
+            );
+        }
+        let _ = writeln!(report);
+    }
+
+    report
+}
+
+/// 打印 device_report 的内容
+pub fn print_device_report(instance: &Instance, physical_device: vk::PhysicalDevice) {
+    print!("{}", device_report(instance, physical_device));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TestContext, test_context};
+    use ash::vk::Handle;
+
+    fn validation_config(debug_printf: bool, gpu_assisted: bool) -> ValidationLayerConfig {
+        ValidationLayerConfig {
+            layers: Vec::new(),
+            enabled: true,
+            debug_printf,
+            gpu_assisted,
+        }
+    }
+
+    #[test]
+    fn validation_env_var_values() {
+        for value in ["1", "true", "on", " 1 "] {
+            assert_eq!(
+                ValidationLayerConfig::enabled_from_env(Some(value)),
+                Some(true)
+            );
+        }
+        for value in ["0", "false", "off"] {
+            assert_eq!(
+                ValidationLayerConfig::enabled_from_env(Some(value)),
+                Some(false)
+            );
+        }
+        assert_eq!(ValidationLayerConfig::enabled_from_env(Some("maybe")), None);
+        assert_eq!(ValidationLayerConfig::enabled_from_env(None), None);
+    }
+
+    #[test]
+    fn gpu_assisted_and_debug_printf_are_mutually_exclusive() {
+        assert!(matches!(
+            validation_config(true, true).enabled_features(),
+            Err(RtError::InvalidConfiguration(_))
+        ));
+        assert_eq!(
+            validation_config(true, false).enabled_features().unwrap(),
+            [vk::ValidationFeatureEnableEXT::DEBUG_PRINTF]
+        );
+        assert_eq!(
+            validation_config(false, true).enabled_features().unwrap(),
+            [
+                vk::ValidationFeatureEnableEXT::GPU_ASSISTED,
+                vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT,
+            ]
+        );
+    }
+
+    thread_local! {
+        static CHAINED_VALIDATION_FEATURES: std::cell::RefCell<Option<Vec<vk::ValidationFeatureEnableEXT>>> =
+            const { std::cell::RefCell::new(None) };
+    }
+
+    /// 只记录 pNext 链中 ValidationFeaturesEXT 的 vkCreateInstance，随后返回失败避免真正创建实例
+    unsafe extern "system" fn recording_create_instance(
+        create_info: *const vk::InstanceCreateInfo<'_>,
+        _allocator: *const vk::AllocationCallbacks<'_>,
+        _instance: *mut vk::Instance,
+    ) -> vk::Result {
+        let mut next = unsafe { (*create_info).p_next } as *const vk::BaseInStructure<'_>;
+        let mut features = None;
+        while let Some(header) = unsafe { next.as_ref() } {
+            if header.s_type == vk::StructureType::VALIDATION_FEATURES_EXT {
+                let info = unsafe { &*(next as *const vk::ValidationFeaturesEXT<'_>) };
+                features = Some(
+                    unsafe {
+                        std::slice::from_raw_parts(
+                            info.p_enabled_validation_features,
+                            info.enabled_validation_feature_count as usize,
+                        )
+                    }
+                    .to_vec(),
+                );
+            }
+            next = header.p_next;
+        }
+        CHAINED_VALIDATION_FEATURES.with(|chained| *chained.borrow_mut() = features);
+        vk::Result::ERROR_INITIALIZATION_FAILED
+    }
+
+    unsafe extern "system" fn recording_get_instance_proc_addr(
+        _instance: vk::Instance,
+        name: *const c_char,
+    ) -> vk::PFN_vkVoidFunction {
+        if unsafe { CStr::from_ptr(name) } == c"vkCreateInstance" {
+            Some(unsafe {
+                std::mem::transmute::<vk::PFN_vkCreateInstance, unsafe extern "system" fn()>(
+                    recording_create_instance,
+                )
+            })
+        } else {
+            None
+        }
+    }
+
+    /// 用假的 vkCreateInstance 调用 create_instance，返回链入的验证特性
+    fn chained_validation_features(
+        features: &[vk::ValidationFeatureEnableEXT],
+    ) -> Option<Vec<vk::ValidationFeatureEnableEXT>> {
+        let entry = unsafe {
+            Entry::from_static_fn(ash::StaticFn {
+                get_instance_proc_addr: recording_get_instance_proc_addr,
+            })
+        };
+        let result = create_instance(
+            &entry,
+            &ApplicationInfo::default(),
+            &[],
+            &[],
+            true,
+            features,
+        );
+        assert!(matches!(
+            result,
+            Err(RtError::Vulkan(vk::Result::ERROR_INITIALIZATION_FAILED))
+        ));
+        CHAINED_VALIDATION_FEATURES.with(|chained| chained.borrow_mut().take())
+    }
+
+    #[test]
+    fn debug_printf_chains_validation_features_into_the_instance() {
+        let features = validation_config(true, false).enabled_features().unwrap();
+        assert_eq!(
+            chained_validation_features(&features),
+            Some(vec![vk::ValidationFeatureEnableEXT::DEBUG_PRINTF])
+        );
+        assert_eq!(chained_validation_features(&[]), None);
+    }
+
+    #[test]
+    fn application_name_with_nul_is_an_error() {
+        let Ok(entry) = (unsafe { Entry::load() }) else {
+            eprintln!("skipping application_name_with_nul_is_an_error: no Vulkan loader");
+            return;
+        };
+        let app_info = ApplicationInfo {
+            name: "bad\0name",
+            ..ApplicationInfo::default()
+        };
+        assert!(matches!(
+            create_instance(&entry, &app_info, &[], &[], false, &[]),
+            Err(RtError::InvalidConfiguration(_))
+        ));
+    }
+
+    #[test]
+    fn shader_int64_is_enabled_only_when_requested() {
+        let default_features = enabled_core_features(&DeviceConfig::new(true));
+        assert_eq!(default_features.shader_int64, vk::FALSE);
+
+        let config = DeviceConfig {
+            shader_int64: true,
+            ..DeviceConfig::new(true)
+        };
+        let features = enabled_core_features(&config);
+        assert_eq!(features.shader_int64, vk::TRUE);
+        assert_eq!(features.robust_buffer_access, vk::FALSE);
+    }
+
+    #[test]
+    fn device_with_shader_int64_creates_when_supported() {
+        let Some(context) = test_context("device_with_shader_int64_creates_when_supported") else {
+            return;
+        };
+        let supported = unsafe {
+            context
+                .instance
+                .get_physical_device_features(context.physical_device)
+        }
+        .shader_int64;
+        if supported == vk::FALSE {
+            eprintln!("skipping device_with_shader_int64_creates_when_supported: no shaderInt64");
+            return;
+        }
+        drop(context);
+
+        let context = TestContext::with_config(|config| config.shader_int64 = true);
+        assert!(context.is_some());
+    }
+
+    #[test]
+    fn memory_budget_reports_positive_budgets_above_usage() {
+        let Some(context) = test_context("memory_budget_reports_positive_budgets_above_usage")
+        else {
+            return;
+        };
+        let supported = unsafe {
+            context
+                .instance
+                .enumerate_device_extension_properties(context.physical_device)
+        }
+        .unwrap()
+        .iter()
+        .any(|extension| extension.extension_name_as_c_str() == Ok(ext::memory_budget::NAME));
+        if !supported {
+            eprintln!(
+                "skipping memory_budget_reports_positive_budgets_above_usage: no VK_EXT_memory_budget"
+            );
+            return;
+        }
+
+        let budgets = query_memory_budget(&context.instance, context.physical_device);
+        assert!(!budgets.is_empty());
+        for heap in &budgets {
+            assert!(heap.budget > 0, "heap {} has no budget", heap.heap_index);
+            assert!(
+                heap.usage <= heap.budget,
+                "heap {} uses {} of {}",
+                heap.heap_index,
+                heap.usage,
+                heap.budget
+            );
+        }
+    }
+
+    #[test]
+    fn device_report_names_the_device_and_its_handle_size() {
+        let Some(context) = test_context("device_report_names_the_device_and_its_handle_size")
+        else {
+            return;
+        };
+        let properties = unsafe {
+            context
+                .instance
+                .get_physical_device_properties(context.physical_device)
+        };
+        let device_name = properties
+            .device_name_as_c_str()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+
+        let report = device_report(&context.instance, context.physical_device);
+        assert!(report.contains(&device_name), "{}", report);
+        assert!(
+            report
+                .lines()
+                .any(|line| line.trim_start().starts_with("shaderGroupHandleSize: ")),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn compute_priority_reaches_the_compute_queue_create_info() {
+        let queue_indices = QueueFamilyIndices {
+            graphics_family: Some(0),
+            compute_family: Some(2),
+            ..Default::default()
+        };
+        let priorities = QueuePriorities {
+            compute: 0.5,
+            ..Default::default()
+        };
+        let families = [
+            FamilyQueueCount {
+                family_index: 0,
+                count: 1,
+            },
+            FamilyQueueCount {
+                family_index: 2,
+                count: 2,
+            },
+        ];
+
+        let family_priorities = family_queue_priorities(&families, &queue_indices, &priorities);
+        let infos = queue_create_infos(&families, &family_priorities);
+        assert_eq!(infos.len(), 2);
+        let priorities_of = |info: &vk::DeviceQueueCreateInfo| unsafe {
+            std::slice::from_raw_parts(info.p_queue_priorities, info.queue_count as usize).to_vec()
+        };
+        assert_eq!(infos[0].queue_family_index, 0);
+        assert_eq!(priorities_of(&infos[0]), [1.0]);
+        assert_eq!(infos[1].queue_family_index, 2);
+        assert_eq!(priorities_of(&infos[1]), [0.5, 0.5]);
+    }
+
+    #[test]
+    fn queue_counts_are_clamped_to_each_family() {
+        let family = |queue_count| vk::QueueFamilyProperties {
+            queue_count,
+            ..Default::default()
+        };
+        let family_properties = [family(2), family(8)];
+        let config = DeviceConfig {
+            graphics_queue_count: 4,
+            compute_queue_count: 3,
+            ..DeviceConfig::new(true)
+        };
+
+        // graphics 族只有 2 个队列，请求 4 个时截断；compute 族容量足够
+        let separate = QueueFamilyIndices {
+            graphics_family: Some(0),
+            compute_family: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            clamp_queue_counts(&separate, &config, &family_properties),
+            [
+                FamilyQueueCount {
+                    family_index: 0,
+                    count: 2
+                },
+                FamilyQueueCount {
+                    family_index: 1,
+                    count: 3
+                },
+            ]
+        );
+
+        // 共用队列族时取较大的请求数，0 视为 1
+        let shared = QueueFamilyIndices {
+            graphics_family: Some(1),
+            compute_family: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            clamp_queue_counts(&shared, &config, &family_properties),
+            [FamilyQueueCount {
+                family_index: 1,
+                count: 4
+            }]
+        );
+        assert_eq!(
+            clamp_queue_counts(&shared, &DeviceConfig::new(true), &family_properties),
+            [FamilyQueueCount {
+                family_index: 1,
+                count: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn requested_extension_features_must_be_supported() {
+        let requested = DeviceConfig {
+            null_descriptor: true,
+            device_fault: true,
+            ..DeviceConfig::new(true)
+        };
+        let supported = ExtensionFeatureSupport {
+            null_descriptor: true,
+            device_fault: true,
+            ray_tracing_motion_blur: false,
+        };
+        assert!(supported.check(&requested).is_ok());
+        assert!(
+            ExtensionFeatureSupport::default()
+                .check(&DeviceConfig::new(true))
+                .is_ok()
+        );
+
+        let without_null_descriptor = ExtensionFeatureSupport {
+            null_descriptor: false,
+            ..supported
+        };
+        assert!(matches!(
+            without_null_descriptor.check(&requested),
+            Err(RtError::MissingRequiredFeature("nullDescriptor"))
+        ));
+        let without_fault = ExtensionFeatureSupport {
+            device_fault: false,
+            ..supported
+        };
+        assert!(matches!(
+            without_fault.check(&requested),
+            Err(RtError::MissingRequiredFeature("deviceFault"))
+        ));
+    }
+
+    #[test]
+    fn queried_extension_features_gate_device_creation() {
+        let Some(context) = test_context("queried_extension_features_gate_device_creation") else {
+            return;
+        };
+        let config = DeviceConfig {
+            null_descriptor: true,
+            device_fault: true,
+            ..DeviceConfig::new(true)
+        };
+        let support =
+            ExtensionFeatureSupport::query(&context.instance, context.physical_device, &config);
+        let queue_indices = QueueFamilyIndices {
+            graphics_family: Some(context.queue_family_index),
+            ..Default::default()
+        };
+
+        match create_device(
+            &context.instance,
+            context.physical_device,
+            &queue_indices,
+            &config,
+        ) {
+            Ok(device) => {
+                assert!(support.null_descriptor && support.device_fault);
+                unsafe { device.destroy_device(None) };
+            }
+            Err(RtError::MissingRequiredFeature(feature)) => {
+                assert!(matches!(feature, "nullDescriptor" | "deviceFault"));
+                assert!(support.check(&config).is_err());
+            }
+            Err(err) => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn missing_debug_utils_is_filtered_out_and_disables_the_messenger() {
+        let available: Vec<vk::ExtensionProperties> = [khr::surface::NAME, khr::xlib_surface::NAME]
+            .into_iter()
+            .map(|name| {
+                vk::ExtensionProperties::default()
+                    .extension_name(name)
+                    .unwrap()
+            })
+            .collect();
+        let desired = [
+            ext::debug_utils::NAME.as_ptr(),
+            khr::surface::NAME.as_ptr(),
+            khr::xlib_surface::NAME.as_ptr(),
+        ];
+        assert!(has_debug_utils(&desired));
+
+        let filtered = filter_instance_extensions(&available, &desired);
+        assert_eq!(filtered, desired[1..]);
+        assert!(!has_debug_utils(&filtered));
+        assert!(filter_instance_extensions(&[], &desired).is_empty());
+    }
+
+    #[test]
+    fn enabling_message_dedup_twice_is_an_error() {
+        // 去重器是进程级全局状态，只检查第二次调用一定失败
+        let _ = enable_message_dedup(5, 100);
+        assert!(matches!(
+            enable_message_dedup(1, 0),
+            Err(RtError::InvalidConfiguration(_))
+        ));
+    }
+
+    #[test]
+    fn setting_debug_messenger_config_twice_is_an_error() {
+        let _ = set_debug_messenger_config(DebugMessengerConfig::default());
+        let denying = DebugMessengerConfig {
+            deny_ids: vec![42],
+            ..DebugMessengerConfig::default()
+        };
+        assert!(matches!(
+            set_debug_messenger_config(denying),
+            Err(RtError::InvalidConfiguration(_))
+        ));
+        assert!(debug_messenger_config().should_emit(42, ""));
+    }
+
+    /// 一个 mock 物理设备：支持的扩展、队列族，以及每个队列族能否呈现
+    struct MockDevice {
+        device_type: vk::PhysicalDeviceType,
+        extensions: Vec<&'static CStr>,
+        queue_flags: Vec<vk::QueueFlags>,
+        present: Vec<bool>,
+    }
+
+    struct MockInstance {
+        devices: Vec<MockDevice>,
+    }
+
+    impl MockInstance {
+        fn device(&self, physical_device: vk::PhysicalDevice) -> &MockDevice {
+            &self.devices[physical_device.as_raw() as usize - 1]
+        }
+    }
+
+    impl PhysicalDeviceQueries for MockInstance {
+        fn physical_devices(&self) -> VkResult<Vec<vk::PhysicalDevice>> {
+            Ok((1..=self.devices.len() as u64)
+                .map(vk::PhysicalDevice::from_raw)
+                .collect())
+        }
+
+        fn device_extension_properties(
+            &self,
+            physical_device: vk::PhysicalDevice,
+        ) -> VkResult<Vec<vk::ExtensionProperties>> {
+            Ok(self
+                .device(physical_device)
+                .extensions
+                .iter()
+                .map(|name| {
+                    vk::ExtensionProperties::default()
+                        .extension_name(name)
+                        .unwrap()
+                })
+                .collect())
+        }
+
+        fn queue_family_properties(
+            &self,
+            physical_device: vk::PhysicalDevice,
+        ) -> Vec<vk::QueueFamilyProperties> {
+            self.device(physical_device)
+                .queue_flags
+                .iter()
+                .map(|&queue_flags| vk::QueueFamilyProperties {
+                    queue_flags,
+                    queue_count: 1,
+                    ..Default::default()
+                })
+                .collect()
+        }
+
+        fn device_properties(
+            &self,
+            physical_device: vk::PhysicalDevice,
+        ) -> vk::PhysicalDeviceProperties {
+            vk::PhysicalDeviceProperties {
+                device_type: self.device(physical_device).device_type,
+                ..Default::default()
+            }
+        }
+
+        fn surface_support(
+            &self,
+            physical_device: vk::PhysicalDevice,
+            queue_family_index: u32,
+            _surface: vk::SurfaceKHR,
+        ) -> VkResult<bool> {
+            Ok(self.device(physical_device).present[queue_family_index as usize])
+        }
+    }
+
+    const RT_EXTENSIONS: [&CStr; 2] = [
+        khr::acceleration_structure::NAME,
+        khr::ray_tracing_pipeline::NAME,
+    ];
+
+    fn mock_device(
+        device_type: vk::PhysicalDeviceType,
+        extensions: &[&'static CStr],
+        families: &[(vk::QueueFlags, bool)],
+    ) -> MockDevice {
+        MockDevice {
+            device_type,
+            extensions: extensions.to_vec(),
+            queue_flags: families.iter().map(|&(flags, _)| flags).collect(),
+            present: families.iter().map(|&(_, present)| present).collect(),
+        }
+    }
+
+    #[test]
+    fn selection_skips_devices_missing_extensions() {
+        let graphics = vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE;
+        let instance = MockInstance {
+            devices: vec![
+                mock_device(
+                    vk::PhysicalDeviceType::INTEGRATED_GPU,
+                    &RT_EXTENSIONS[..1],
+                    &[(graphics, false)],
+                ),
+                mock_device(
+                    vk::PhysicalDeviceType::DISCRETE_GPU,
+                    &RT_EXTENSIONS,
+                    &[(graphics, false)],
+                ),
+            ],
+        };
+
+        let (physical_device, indices) =
+            pick_physical_device_and_queue_family_indices(&instance, None, &RT_EXTENSIONS, true)
+                .unwrap()
+                .unwrap();
+        assert_eq!(physical_device.as_raw(), 2);
+        assert_eq!(indices.graphics_family, Some(0));
+        assert_eq!(indices.compute_family, Some(0));
+    }
+
+    #[test]
+    fn selection_uses_surface_support_for_present_family() {
+        let surface = vk::SurfaceKHR::from_raw(7);
+        let instance = MockInstance {
+            devices: vec![
+                // 没有任何队列族能呈现
+                mock_device(
+                    vk::PhysicalDeviceType::DISCRETE_GPU,
+                    &RT_EXTENSIONS,
+                    &[(vk::QueueFlags::GRAPHICS, false)],
+                ),
+                mock_device(
+                    vk::PhysicalDeviceType::DISCRETE_GPU,
+                    &RT_EXTENSIONS,
+                    &[
+                        (vk::QueueFlags::GRAPHICS, false),
+                        (vk::QueueFlags::TRANSFER, true),
+                    ],
+                ),
+            ],
+        };
+
+        let (physical_device, indices) = pick_physical_device_and_queue_family_indices(
+            &instance,
+            Some(surface),
+            &RT_EXTENSIONS,
+            false,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(physical_device.as_raw(), 2);
+        assert_eq!(indices.graphics_family, Some(0));
+        assert_eq!(indices.present_family, Some(1));
+    }
+
+    #[test]
+    fn fallback_prefers_a_cpu_device_without_ray_tracing() {
+        let families = [(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE, false)];
+        let instance = MockInstance {
+            devices: vec![
+                mock_device(vk::PhysicalDeviceType::DISCRETE_GPU, &[], &families),
+                mock_device(vk::PhysicalDeviceType::CPU, &[], &families),
+            ],
+        };
+
+        let picked = pick_physical_device_with_fallback(&instance, None, &RT_EXTENSIONS, true)
+            .unwrap()
+            .unwrap();
+        assert_eq!(picked.physical_device.as_raw(), 2);
+        assert!(!picked.rt_supported);
+    }
+
+    #[test]
+    fn queue_family_descriptions_map_the_queue_flags() {
+        let instance = MockInstance {
+            devices: vec![mock_device(
+                vk::PhysicalDeviceType::DISCRETE_GPU,
+                &RT_EXTENSIONS,
+                &[
+                    (
+                        vk::QueueFlags::GRAPHICS
+                            | vk::QueueFlags::COMPUTE
+                            | vk::QueueFlags::TRANSFER,
+                        false,
+                    ),
+                    (
+                        vk::QueueFlags::COMPUTE | vk::QueueFlags::SPARSE_BINDING,
+                        false,
+                    ),
+                ],
+            )],
+        };
+
+        let families = describe_queue_families(&instance, vk::PhysicalDevice::from_raw(1));
+        assert_eq!(families.len(), 2);
+        assert!(families[0].graphics && families[0].compute && families[0].transfer);
+        assert!(!families[0].is_dedicated_compute());
+        assert_eq!(families[1].family_index, 1);
+        assert!(families[1].sparse_binding && !families[1].transfer);
+        assert!(families[1].is_dedicated_compute());
+        assert_eq!(
+            families[1].to_string(),
+            "[1] 1 queue(s), compute|sparse, timestampValidBits 0"
+        );
+    }
+
+    #[test]
+    fn device_reports_a_graphics_family_with_queues() {
+        let Some(context) = test_context("device_reports_a_graphics_family_with_queues") else {
+            return;
+        };
+        let families = describe_queue_families(
+            &InstanceQueries::new(&context.instance, None),
+            context.physical_device,
+        );
+        assert!(
+            families
+                .iter()
+                .any(|family| family.graphics && family.queue_count > 0),
+            "no graphics family in {:?}",
+            families
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use ash::{Device, vk};
+
+use crate::buffer::{BufferResource, fill_buffer};
+use crate::pipeline::{create_compute_pipeline, create_shader_module};
+
+/// 波前式路径追踪的一级光线队列
+///
+/// - origins: 每条光线一个 vec4（xyz 为起点，w 为 tmin）
+/// - directions: 每条光线一个 vec4（xyz 为方向，w 为 tmax，<= 0 表示光线已终止）
+/// - counter: 队列中的光线数，着色器通过 atomicAdd 追加光线
+pub struct RayQueueBuffers {
+    pub origins: BufferResource,
+    pub directions: BufferResource,
+    pub counter: BufferResource,
+    pub capacity: u32,
+}
+
+impl RayQueueBuffers {
+    pub fn new(
+        capacity: u32,
+        device: &Device,
+        device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Self {
+        let ray_buffer_size = capacity as vk::DeviceSize * std::mem::size_of::<[f32; 4]>() as u64;
+        let new_buffer = |size, usage| {
+            BufferResource::new(
+                size,
+                vk::BufferUsageFlags::STORAGE_BUFFER | usage,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                device,
+                device_memory_properties,
+            )
+        };
+
+        Self {
+            origins: new_buffer(ray_buffer_size, vk::BufferUsageFlags::empty()),
+            directions: new_buffer(ray_buffer_size, vk::BufferUsageFlags::empty()),
+            counter: new_buffer(
+                std::mem::size_of::<u32>() as vk::DeviceSize,
+                vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::TRANSFER_SRC,
+            ),
+            capacity,
+        }
+    }
+
+    /// 录制把计数器清零的命令，并保证之后的着色器读写能看到清零结果
+    pub fn reset_counter(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        fill_buffer(device, command_buffer, &self.counter, 0, vk::WHOLE_SIZE, 0);
+
+        unsafe {
+            let barrier = vk::BufferMemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                .buffer(self.counter.buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE);
+
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[barrier],
+                &[],
+            );
+        }
+    }
+
+    /// 按 origins、directions、counter 的顺序返回描述符信息
+    pub fn descriptor_infos(&self) -> [vk::DescriptorBufferInfo; 3] {
+        [&self.origins, &self.directions, &self.counter].map(|buffer| {
+            vk::DescriptorBufferInfo::default()
+                .buffer(buffer.buffer)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)
+        })
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            self.origins.destroy(device);
+            self.directions.destroy(device);
+            self.counter.destroy(device);
+        }
+    }
+}
+
+/// 压缩光线队列的计算管线（shaders/compact_ray_queue.comp）
+///
+/// 描述符集布局：binding 0..=2 为输入队列，3..=5 为输出队列，顺序同 RayQueueBuffers::descriptor_infos
+pub struct RayQueueCompactor {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+}
+
+impl RayQueueCompactor {
+    const WORKGROUP_SIZE: u32 = 256;
+
+    pub fn new(
+        device: &Device,
+        shader_code: &[u32],
+        cache: vk::PipelineCache,
+    ) -> Result<Self, vk::Result> {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..6)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            })
+            .collect();
+
+        let descriptor_set_layout = unsafe {
+            device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+                None,
+            )
+        }?;
+
+        let set_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(std::mem::size_of::<u32>() as u32)];
+        let pipeline_layout = unsafe {
+            device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&set_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )
+        }?;
+
+        let shader_module = create_shader_module(device, shader_code)?;
+        let pipeline = create_compute_pipeline(device, pipeline_layout, shader_module, cache, &[]);
+        unsafe { device.destroy_shader_module(shader_module, None) };
+
+        Ok(Self {
+            descriptor_set_layout,
+            pipeline_layout,
+            pipeline: pipeline?,
+        })
+    }
+
+    /// 把 input/output 两个队列写入 descriptor_set
+    pub fn write_descriptor_set(
+        &self,
+        device: &Device,
+        descriptor_set: vk::DescriptorSet,
+        input: &RayQueueBuffers,
+        output: &RayQueueBuffers,
+    ) {
+        let infos: Vec<[vk::DescriptorBufferInfo; 1]> = input
+            .descriptor_infos()
+            .into_iter()
+            .chain(output.descriptor_infos())
+            .map(|info| [info])
+            .collect();
+
+        let writes: Vec<vk::WriteDescriptorSet> = infos
+            .iter()
+            .enumerate()
+            .map(|(binding, info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(info)
+            })
+            .collect();
+
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    /// 录制压缩 dispatch；调用前需已对 output 调用 reset_counter
+    ///
+    /// 超出 output_capacity 的光线被丢弃，但 output 的计数器仍会累加，
+    /// 读取方应使用 min(counter, output_capacity)。
+    pub fn record(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+        input_capacity: u32,
+        output_capacity: u32,
+    ) {
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &output_capacity.to_ne_bytes(),
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                input_capacity.div_ceil(Self::WORKGROUP_SIZE),
+                1,
+                1,
+            );
+        }
+    }
+
+    pub unsafe fn destroy(self, device: &Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_context;
+
+    #[test]
+    fn full_hd_queue_counter_resets_to_zero() {
+        let Some(context) = test_context("full_hd_queue_counter_resets_to_zero") else {
+            return;
+        };
+        let device = &context.device;
+        let command_pool = context.command_pool();
+
+        let capacity = 1920 * 1080;
+        let queue = RayQueueBuffers::new(capacity, device, context.device_memory_properties);
+        assert_eq!(queue.capacity, capacity);
+        assert_eq!(queue.origins.size, capacity as vk::DeviceSize * 16);
+        assert_eq!(queue.directions.size, capacity as vk::DeviceSize * 16);
+
+        // 先写入一个非零计数，再在同一个命令缓冲中清零
+        let command_buffer = command_pool.begin_one_time(device).unwrap();
+        fill_buffer(
+            device,
+            command_buffer,
+            &queue.counter,
+            0,
+            vk::WHOLE_SIZE,
+            1234,
+        );
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)],
+                &[],
+                &[],
+            );
+        }
+        queue.reset_counter(device, command_buffer);
+        command_pool
+            .end_one_time(device, context.queue, command_buffer)
+            .unwrap();
+
+        assert_eq!(context.read_buffer::<u32>(&queue.counter), [0]);
+
+        unsafe {
+            queue.destroy(device);
+            command_pool.destroy(device);
+        }
+    }
+}
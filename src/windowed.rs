@@ -1,80 +1,274 @@
 use ash::{khr, vk};
 
+use crate::buffer::BufferResource;
+use crate::command::CommandPoolManager;
+use crate::error::RtError;
+use crate::frame_sync::FrameSync;
+use crate::image_utils::{RenderTargetImage, debug_assert_layout, is_srgb_format};
+use crate::vulkan_base::QueueFamilyIndices;
+
+/// 列出 surface 支持的全部格式与色彩空间组合
+pub fn list_surface_formats(
+    surface_loader: &khr::surface::Instance,
+    physical_device: vk::PhysicalDevice,
+    surface: vk::SurfaceKHR,
+) -> Result<Vec<vk::SurfaceFormatKHR>, vk::Result> {
+    unsafe { surface_loader.get_physical_device_surface_formats(physical_device, surface) }
+}
+
+/// 选择 surface 格式：
+/// - 指定 preferred_color_space（如 HDR10_ST2084_EXT）时优先取该色彩空间的第一个格式
+/// - 否则优先 B8G8R8A8_SRGB + SRGB_NONLINEAR，再否则取第一个；列表为空时返回 None
+///
+/// SRGB_NONLINEAR 以外的色彩空间需要实例启用 VK_EXT_swapchain_colorspace。
+pub fn choose_surface_format(
+    formats: &[vk::SurfaceFormatKHR],
+    preferred_color_space: Option<vk::ColorSpaceKHR>,
+) -> Option<vk::SurfaceFormatKHR> {
+    preferred_color_space
+        .and_then(|color_space| formats.iter().find(|f| f.color_space == color_space))
+        .or_else(|| {
+            formats.iter().find(|f| {
+                f.format == vk::Format::B8G8R8A8_SRGB
+                    && f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+            })
+        })
+        .or_else(|| formats.first())
+        .copied()
+}
+
+/// 选择呈现模式：优先 MAILBOX，否则使用必定支持的 FIFO；列表为空时返回 None
+pub fn choose_present_mode(present_modes: &[vk::PresentModeKHR]) -> Option<vk::PresentModeKHR> {
+    if present_modes.is_empty() {
+        return None;
+    }
+    Some(
+        present_modes
+            .iter()
+            .copied()
+            .find(|&m| m == vk::PresentModeKHR::MAILBOX)
+            .unwrap_or(vk::PresentModeKHR::FIFO),
+    )
+}
+
+/// 选择 swapchain 图像的共享模式
+///
+/// 图形队列与呈现队列属于不同队列族时使用 CONCURRENT 并列出两个队列族，
+/// 这样呈现前不需要做队列族所有权转移（release/acquire 两次 barrier 与额外的 semaphore），
+/// 代价是部分驱动在 CONCURRENT 模式下会关闭图像压缩。两者相同时使用 EXCLUSIVE。
+pub fn choose_sharing_mode(queue_indices: &QueueFamilyIndices) -> (vk::SharingMode, Vec<u32>) {
+    match (queue_indices.graphics_family, queue_indices.present_family) {
+        (Some(graphics), Some(present)) if graphics != present => {
+            (vk::SharingMode::CONCURRENT, vec![graphics, present])
+        }
+        _ => (vk::SharingMode::EXCLUSIVE, Vec::new()),
+    }
+}
+
+/// 选择 swapchain 图像数量：比最小值多一张以避免等待驱动，max_image_count 为 0 表示没有上限
+pub fn choose_image_count(capabilities: &vk::SurfaceCapabilitiesKHR) -> u32 {
+    let desired = capabilities.min_image_count + 1;
+    if capabilities.max_image_count > 0 {
+        desired.min(capabilities.max_image_count)
+    } else {
+        desired
+    }
+}
+
+/// 选择 surface 支持的合成 alpha 模式：优先 OPAQUE，其次 INHERIT，再依次尝试预乘/后乘
+///
+/// 部分移动端或模拟的 surface 不支持 OPAQUE，硬编码会导致创建 swapchain 失败。
+pub fn choose_composite_alpha(
+    capabilities: &vk::SurfaceCapabilitiesKHR,
+) -> vk::CompositeAlphaFlagsKHR {
+    [
+        vk::CompositeAlphaFlagsKHR::OPAQUE,
+        vk::CompositeAlphaFlagsKHR::INHERIT,
+        vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+        vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+    ]
+    .into_iter()
+    .find(|&mode| capabilities.supported_composite_alpha.contains(mode))
+    .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE)
+}
+
+/// 选择 pre_transform：current_transform 受支持时使用它，否则退回 IDENTITY 或最低的受支持位
+pub fn choose_pre_transform(
+    capabilities: &vk::SurfaceCapabilitiesKHR,
+) -> vk::SurfaceTransformFlagsKHR {
+    let supported = capabilities.supported_transforms;
+    if supported.contains(capabilities.current_transform)
+        && !capabilities.current_transform.is_empty()
+    {
+        capabilities.current_transform
+    } else if supported.contains(vk::SurfaceTransformFlagsKHR::IDENTITY) {
+        vk::SurfaceTransformFlagsKHR::IDENTITY
+    } else {
+        let raw = supported.as_raw();
+        vk::SurfaceTransformFlagsKHR::from_raw(raw & raw.wrapping_neg())
+    }
+}
+
+/// 选择 swapchain 尺寸：surface 指定了 current_extent 时直接使用，否则把窗口尺寸限制在允许范围内
+pub fn choose_extent(
+    capabilities: &vk::SurfaceCapabilitiesKHR,
+    width: u32,
+    height: u32,
+) -> vk::Extent2D {
+    if capabilities.current_extent.width != u32::MAX {
+        capabilities.current_extent
+    } else {
+        vk::Extent2D {
+            width: width.clamp(
+                capabilities.min_image_extent.width,
+                capabilities.max_image_extent.width,
+            ),
+            height: height.clamp(
+                capabilities.min_image_extent.height,
+                capabilities.max_image_extent.height,
+            ),
+        }
+    }
+}
+
+/// 渲染目标到 swapchain 图像的传输方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentCopy {
+    /// 格式不同（如 R32G32B32A32_SFLOAT → B8G8R8A8_SRGB），只能用会做格式转换的 vkCmdBlitImage
+    Blit,
+    /// 格式相同，直接 vkCmdCopyImage
+    Copy,
+}
+
+/// 根据源与目标格式选择 blit 或 copy；不同格式之间 cmd_copy_image 是非法的
+pub fn choose_present_copy(src_format: vk::Format, dst_format: vk::Format) -> PresentCopy {
+    if src_format == dst_format {
+        PresentCopy::Copy
+    } else {
+        PresentCopy::Blit
+    }
+}
+
 pub struct Swapchain {
     pub swapchain: vk::SwapchainKHR,
     pub images: Vec<vk::Image>,
     pub image_views: Vec<vk::ImageView>,
     pub format: vk::Format,
+    pub color_space: vk::ColorSpaceKHR,
     pub extent: vk::Extent2D,
+    /// swapchain 图像实际的用途，TRANSFER_SRC 仅在 surface 支持时启用（用于截图）
+    pub usage: vk::ImageUsageFlags,
+    /// 每张 swapchain 图像一个渲染完成信号量，按 image_index 取用
+    ///
+    /// 呈现引擎何时释放信号量与帧下标无关，按帧复用会在图像数多于 frames in flight 时
+    /// 对仍被上一次呈现等待的信号量再次 signal。
+    pub render_finished: Vec<vk::Semaphore>,
     pub loader: khr::swapchain::Device,
 }
 
 impl Swapchain {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         instance: &ash::Instance,
         device: &ash::Device,
         physical_device: vk::PhysicalDevice,
+        queue_indices: &QueueFamilyIndices,
+        surface: vk::SurfaceKHR,
+        surface_loader: &khr::surface::Instance,
+        width: u32,
+        height: u32,
+        preferred_color_space: Option<vk::ColorSpaceKHR>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::create(
+            instance,
+            device,
+            physical_device,
+            queue_indices,
+            surface,
+            surface_loader,
+            width,
+            height,
+            preferred_color_space,
+            vk::SwapchainKHR::null(),
+        )
+    }
+
+    /// 以新的窗口尺寸重建 swapchain，保持当前的色彩空间
+    ///
+    /// 旧 swapchain 作为 old_swapchain 传给驱动以便复用资源，随后被销毁，
+    /// 调用前需确保 GPU 已不再使用旧的 swapchain 图像（例如 FrameSync::drain 或 device_wait_idle）。
+    #[allow(clippy::too_many_arguments)]
+    pub fn recreate(
+        &mut self,
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        queue_indices: &QueueFamilyIndices,
+        surface: vk::SurfaceKHR,
+        surface_loader: &khr::surface::Instance,
+        width: u32,
+        height: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let new = Self::create(
+            instance,
+            device,
+            physical_device,
+            queue_indices,
+            surface,
+            surface_loader,
+            width,
+            height,
+            Some(self.color_space),
+            self.swapchain,
+        )?;
+        let old = std::mem::replace(self, new);
+        old.destroy(device);
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        queue_indices: &QueueFamilyIndices,
         surface: vk::SurfaceKHR,
         surface_loader: &khr::surface::Instance,
         width: u32,
         height: u32,
+        preferred_color_space: Option<vk::ColorSpaceKHR>,
+        old_swapchain: vk::SwapchainKHR,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let surface_capabilities = unsafe {
             surface_loader
                 .get_physical_device_surface_capabilities(physical_device, surface)
         }?;
 
-        let surface_formats = unsafe {
-            surface_loader
-                .get_physical_device_surface_formats(physical_device, surface)
-        }?;
-
-        if surface_formats.is_empty() {
-            return Err("No surface formats available".into());
-        }
+        let surface_formats = list_surface_formats(surface_loader, physical_device, surface)?;
 
-        let surface_format = surface_formats
-            .iter()
-            .find(|f| {
-                f.format == vk::Format::B8G8R8A8_SRGB
-                    && f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-            })
-            .unwrap_or(&surface_formats[0]);
+        let surface_format = choose_surface_format(&surface_formats, preferred_color_space)
+            .ok_or("No surface formats available")?;
 
         let present_modes = unsafe {
             surface_loader
                 .get_physical_device_surface_present_modes(physical_device, surface)
         }?;
 
-        if present_modes.is_empty() {
-            return Err("No present modes available".into());
-        }
+        let present_mode =
+            choose_present_mode(&present_modes).ok_or("No present modes available")?;
 
-        let present_mode = present_modes
-            .iter()
-            .find(|&&m| m == vk::PresentModeKHR::MAILBOX)
-            .unwrap_or(&vk::PresentModeKHR::FIFO);
+        let image_count = choose_image_count(&surface_capabilities);
 
-        let image_count = (surface_capabilities.min_image_count + 1)
-            .min(surface_capabilities.max_image_count.max(surface_capabilities.min_image_count + 1));
+        let extent = choose_extent(&surface_capabilities, width, height);
 
-        let extent = if surface_capabilities.current_extent.width != u32::MAX {
-            surface_capabilities.current_extent
-        } else {
-            vk::Extent2D {
-                width: width.clamp(
-                    surface_capabilities.min_image_extent.width,
-                    surface_capabilities.max_image_extent.width,
-                ),
-                height: height.clamp(
-                    surface_capabilities.min_image_extent.height,
-                    surface_capabilities.max_image_extent.height,
-                ),
-            }
-        };
+        let (sharing_mode, sharing_queue_families) = choose_sharing_mode(queue_indices);
 
         let swapchain_loader = khr::swapchain::Device::new(instance, device);
 
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (surface_capabilities.supported_usage_flags
+                & (vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC));
+
         let swapchain_create_info = vk::SwapchainCreateInfoKHR::default()
             .surface(surface)
             .min_image_count(image_count)
@@ -82,12 +276,14 @@ impl Swapchain {
             .image_color_space(surface_format.color_space)
             .image_extent(extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .pre_transform(surface_capabilities.current_transform)
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(*present_mode)
-            .clipped(true);
+            .image_usage(usage)
+            .image_sharing_mode(sharing_mode)
+            .queue_family_indices(&sharing_queue_families)
+            .pre_transform(choose_pre_transform(&surface_capabilities))
+            .composite_alpha(choose_composite_alpha(&surface_capabilities))
+            .present_mode(present_mode)
+            .clipped(true)
+            .old_swapchain(old_swapchain);
 
         let swapchain = unsafe {
             swapchain_loader
@@ -113,22 +309,727 @@ impl Swapchain {
             image_views.push(view);
         }
 
+        let mut render_finished = Vec::with_capacity(images.len());
+        for _ in &images {
+            render_finished.push(unsafe {
+                device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+            }?);
+        }
+
         Ok(Self {
             swapchain,
             images,
             image_views,
             format: surface_format.format,
+            color_space: surface_format.color_space,
             extent,
+            usage,
+            render_finished,
             loader: swapchain_loader,
         })
     }
 
+    /// swapchain 图像写入时是否由硬件做 sRGB 编码，为 true 时上游应跳过手动 gamma
+    pub fn output_is_srgb(&self) -> bool {
+        is_srgb_format(self.format)
+    }
+
+    /// 提交当前帧的 command_buffer 并呈现第 image_index 张图像，返回 swapchain 是否需要重建
+    ///
+    /// - 提交等待 frame_sync 当前帧的 image_available（在 COLOR_ATTACHMENT_OUTPUT 与 TRANSFER 阶段，
+    ///   后者覆盖 present_render_target 的拷贝），完成后触发第 image_index 张图像的 render_finished
+    ///   与当前帧的 in_flight_fence
+    /// - 呈现等待同一个 render_finished
+    ///
+    /// image_index 需由以同一 image_available 信号量调用的 acquire_next_image 得到，
+    /// 调用前需已对当前帧调用 wait_and_reset。SUBOPTIMAL 与 ERROR_OUT_OF_DATE_KHR 都返回 Ok(true)。
+    pub fn submit_and_present(
+        &self,
+        device: &ash::Device,
+        graphics_queue: vk::Queue,
+        present_queue: vk::Queue,
+        command_buffer: vk::CommandBuffer,
+        frame_sync: &mut FrameSync,
+        image_index: u32,
+    ) -> Result<bool, RtError> {
+        let frame = frame_sync.frame_index();
+        let wait_semaphores = [frame_sync.image_available[frame]];
+        let wait_stages =
+            [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER];
+        let signal_semaphores = [self.render_finished[image_index as usize]];
+        let command_buffers = [command_buffer];
+
+        let submit_info = vk::SubmitInfo::default()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+
+        frame_sync.submit(device, graphics_queue, submit_info)?;
+
+        let swapchains = [self.swapchain];
+        let image_indices = [image_index];
+        let present_info = vk::PresentInfoKHR::default()
+            .wait_semaphores(&signal_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
+
+        match unsafe { self.loader.queue_present(present_queue, &present_info) } {
+            Ok(suboptimal) => Ok(suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(true),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     pub fn destroy(&self, device: &ash::Device) {
         unsafe {
             for &view in &self.image_views {
                 device.destroy_image_view(view, None);
             }
+            for &semaphore in &self.render_finished {
+                device.destroy_semaphore(semaphore, None);
+            }
             self.loader.destroy_swapchain(self.swapchain, None);
         }
     }
-}
\ No newline at end of file
+}
+
+/// 录制把渲染目标传到 swapchain 第 image_index 张图像的命令，结束后该图像处于 PRESENT_SRC_KHR
+///
+/// src 必须处于 GENERAL 布局（光线追踪写入后的状态），swapchain 需要支持 TRANSFER_DST 用途。
+/// blit 到 `_SRGB` 格式时硬件会做 gamma 编码：若 `swapchain.output_is_srgb()` 为 true，
+/// 渲染目标中应保存线性值，色调映射阶段不能再手动应用 gamma。
+/// 格式不同时使用 blit，相同时使用 copy（尺寸取两者的较小值）。
+pub fn present_render_target(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    src: &RenderTargetImage,
+    swapchain: &Swapchain,
+    image_index: u32,
+) -> PresentCopy {
+    present_render_target_region(
+        device,
+        command_buffer,
+        src,
+        vk::Rect2D::default().extent(src.extent),
+        swapchain,
+        image_index,
+        vk::Rect2D::default().extent(swapchain.extent),
+    )
+}
+
+/// 只把渲染目标的 src_region 传到 swapchain 图像的 dst_region，用于视口内嵌在编辑器 UI 中的布局
+///
+/// 与 trace_rays_region 搭配，只追踪并显示子矩形。swapchain 图像从 UNDEFINED 转换，
+/// dst_region 以外的内容是未定义的，需要在之后由 UI 绘制覆盖。
+#[allow(clippy::too_many_arguments)]
+pub fn present_render_target_region(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    src: &RenderTargetImage,
+    src_region: vk::Rect2D,
+    swapchain: &Swapchain,
+    image_index: u32,
+    dst_region: vk::Rect2D,
+) -> PresentCopy {
+    debug_assert_layout(src, vk::ImageLayout::GENERAL, "present_render_target");
+
+    let dst_image = swapchain.images[image_index as usize];
+    let range = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .level_count(1)
+        .layer_count(1);
+
+    let barriers = [
+        vk::ImageMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .old_layout(vk::ImageLayout::GENERAL)
+            .new_layout(vk::ImageLayout::GENERAL)
+            .image(src.image)
+            .subresource_range(range),
+        vk::ImageMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .image(dst_image)
+            .subresource_range(range),
+    ];
+
+    let mode = choose_present_copy(src.format, swapchain.format);
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR | vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &barriers,
+        );
+
+        match mode {
+            PresentCopy::Blit => {
+                device.cmd_blit_image(
+                    command_buffer,
+                    src.image,
+                    vk::ImageLayout::GENERAL,
+                    dst_image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region_blit(src_region, dst_region)],
+                    vk::Filter::NEAREST,
+                );
+            }
+            PresentCopy::Copy => {
+                device.cmd_copy_image(
+                    command_buffer,
+                    src.image,
+                    vk::ImageLayout::GENERAL,
+                    dst_image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region_copy(src_region, dst_region)],
+                );
+            }
+        }
+
+        let present_barrier = vk::ImageMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::empty())
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .image(dst_image)
+            .subresource_range(range);
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[present_barrier],
+        );
+    }
+
+    mode
+}
+
+fn color_layers() -> vk::ImageSubresourceLayers {
+    vk::ImageSubresourceLayers::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .layer_count(1)
+}
+
+/// 矩形的 [左上, 右下) 两个角，用作 vk::ImageBlit 的 offsets
+fn rect_corners(rect: vk::Rect2D) -> [vk::Offset3D; 2] {
+    [
+        vk::Offset3D {
+            x: rect.offset.x,
+            y: rect.offset.y,
+            z: 0,
+        },
+        vk::Offset3D {
+            x: rect.offset.x + rect.extent.width as i32,
+            y: rect.offset.y + rect.extent.height as i32,
+            z: 1,
+        },
+    ]
+}
+
+/// 把 src_region 缩放到 dst_region 的 blit 区域
+pub fn region_blit(src_region: vk::Rect2D, dst_region: vk::Rect2D) -> vk::ImageBlit {
+    vk::ImageBlit::default()
+        .src_subresource(color_layers())
+        .src_offsets(rect_corners(src_region))
+        .dst_subresource(color_layers())
+        .dst_offsets(rect_corners(dst_region))
+}
+
+/// 从 src_region 左上角拷贝到 dst_region 左上角的 copy 区域，尺寸取两者的较小值
+pub fn region_copy(src_region: vk::Rect2D, dst_region: vk::Rect2D) -> vk::ImageCopy {
+    vk::ImageCopy::default()
+        .src_subresource(color_layers())
+        .src_offset(rect_corners(src_region)[0])
+        .dst_subresource(color_layers())
+        .dst_offset(rect_corners(dst_region)[0])
+        .extent(vk::Extent3D {
+            width: src_region.extent.width.min(dst_region.extent.width),
+            height: src_region.extent.height.min(dst_region.extent.height),
+            depth: 1,
+        })
+}
+
+/// 把紧密排列的 4 字节像素就地转换为 RGBA 顺序，返回 format 是否受支持
+///
+/// B8G8R8A8 交换 R/B 通道，R8G8B8A8 保持不变；其它格式返回 false 且不修改数据。
+pub fn swizzle_to_rgba8(pixels: &mut [u8], format: vk::Format) -> bool {
+    match format {
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => true,
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// 回读 swapchain 第 image_index 张图像，返回逐行紧密排列的 RGBA8 数据（行序自上而下）
+///
+/// 截取的是实际呈现的内容（经过色调映射与 sRGB 编码），而不是浮点渲染目标。
+/// 图像需已被 acquire、处于 PRESENT_SRC_KHR 布局且尚未 queue_present，例如在 present_render_target
+/// 的命令提交完成之后；函数返回时图像已恢复为 PRESENT_SRC_KHR。swapchain 需带有 TRANSFER_SRC 用途。
+pub fn capture_swapchain_image(
+    device: &ash::Device,
+    command_pool: &CommandPoolManager,
+    queue: vk::Queue,
+    swapchain: &Swapchain,
+    image_index: u32,
+    device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+) -> Result<Vec<u8>, RtError> {
+    if !swapchain.usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+        return Err(RtError::MissingRequiredFeature(
+            "swapchain TRANSFER_SRC usage",
+        ));
+    }
+    let mut probe = [0u8; 4];
+    if !swizzle_to_rgba8(&mut probe, swapchain.format) {
+        return Err(RtError::UnsupportedFormat(swapchain.format));
+    }
+
+    let image = swapchain.images[image_index as usize];
+    let extent = swapchain.extent;
+    let size = extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4;
+    let readback_buffer = BufferResource::new(
+        size,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_VISIBLE,
+        device,
+        device_memory_properties,
+    );
+
+    let range = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .level_count(1)
+        .layer_count(1);
+    let to_transfer = vk::ImageMemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::empty())
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .old_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .image(image)
+        .subresource_range(range);
+    let to_present = vk::ImageMemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .dst_access_mask(vk::AccessFlags::empty())
+        .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+        .image(image)
+        .subresource_range(range);
+    let region = vk::BufferImageCopy::default()
+        .image_subresource(color_layers())
+        .image_extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        });
+
+    let result = (|| -> Result<Vec<u8>, RtError> {
+        let command_buffer = command_pool.begin_one_time(device)?;
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer],
+            );
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback_buffer.buffer,
+                &[region],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::HOST_READ)],
+                &[],
+                &[to_present],
+            );
+        }
+        command_pool.end_one_time(device, queue, command_buffer)?;
+
+        let data = readback_buffer.map(0, vk::WHOLE_SIZE, device);
+        readback_buffer.invalidate(device, 0, size)?;
+        let mut pixels =
+            unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) }.to_vec();
+        readback_buffer.unmap(device);
+
+        swizzle_to_rgba8(&mut pixels, swapchain.format);
+        Ok(pixels)
+    })();
+
+    unsafe { readback_buffer.destroy(device) };
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surface_format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space,
+        }
+    }
+
+    fn capabilities() -> vk::SurfaceCapabilitiesKHR {
+        vk::SurfaceCapabilitiesKHR {
+            min_image_count: 2,
+            max_image_count: 3,
+            current_extent: vk::Extent2D {
+                width: u32::MAX,
+                height: u32::MAX,
+            },
+            min_image_extent: vk::Extent2D {
+                width: 16,
+                height: 16,
+            },
+            max_image_extent: vk::Extent2D {
+                width: 1024,
+                height: 768,
+            },
+            supported_transforms: vk::SurfaceTransformFlagsKHR::IDENTITY,
+            current_transform: vk::SurfaceTransformFlagsKHR::IDENTITY,
+            supported_composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn surface_format_prefers_srgb_and_falls_back_to_first() {
+        let unorm = surface_format(
+            vk::Format::B8G8R8A8_UNORM,
+            vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        );
+        let srgb = surface_format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR);
+
+        assert_eq!(
+            choose_surface_format(&[unorm, srgb], None).unwrap().format,
+            vk::Format::B8G8R8A8_SRGB
+        );
+        assert_eq!(
+            choose_surface_format(&[unorm], None).unwrap().format,
+            vk::Format::B8G8R8A8_UNORM
+        );
+        assert!(choose_surface_format(&[], None).is_none());
+    }
+
+    #[test]
+    fn surface_format_prefers_the_requested_color_space() {
+        let unorm = surface_format(
+            vk::Format::B8G8R8A8_UNORM,
+            vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        );
+        let srgb = surface_format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR);
+        let hdr = surface_format(
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        );
+        let formats = [unorm, srgb, hdr];
+
+        let chosen = |color_space| choose_surface_format(&formats, color_space).unwrap().format;
+        assert_eq!(chosen(None), vk::Format::B8G8R8A8_SRGB);
+        assert_eq!(
+            chosen(Some(vk::ColorSpaceKHR::HDR10_ST2084_EXT)),
+            vk::Format::A2B10G10R10_UNORM_PACK32
+        );
+        // 不支持请求的色彩空间时回退到 sRGB
+        assert_eq!(
+            chosen(Some(vk::ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT)),
+            vk::Format::B8G8R8A8_SRGB
+        );
+    }
+
+    #[test]
+    fn present_mode_prefers_mailbox_and_falls_back_to_fifo() {
+        assert_eq!(
+            choose_present_mode(&[vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX]),
+            Some(vk::PresentModeKHR::MAILBOX)
+        );
+        assert_eq!(
+            choose_present_mode(&[vk::PresentModeKHR::IMMEDIATE]),
+            Some(vk::PresentModeKHR::FIFO)
+        );
+        assert_eq!(choose_present_mode(&[]), None);
+    }
+
+    #[test]
+    fn sharing_mode_is_concurrent_only_for_distinct_families() {
+        let mut indices = QueueFamilyIndices {
+            graphics_family: Some(0),
+            present_family: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            choose_sharing_mode(&indices),
+            (vk::SharingMode::EXCLUSIVE, Vec::new())
+        );
+
+        indices.present_family = Some(2);
+        assert_eq!(
+            choose_sharing_mode(&indices),
+            (vk::SharingMode::CONCURRENT, vec![0, 2])
+        );
+    }
+
+    #[test]
+    fn image_count_respects_max_and_unbounded() {
+        let mut caps = capabilities();
+        caps.min_image_count = 2;
+
+        caps.max_image_count = 0;
+        assert_eq!(choose_image_count(&caps), 3);
+
+        caps.max_image_count = 2;
+        assert_eq!(choose_image_count(&caps), 2);
+
+        caps.max_image_count = 4;
+        assert_eq!(choose_image_count(&caps), 3);
+    }
+
+    #[test]
+    fn extent_uses_current_extent_or_clamps_window_size() {
+        let mut caps = capabilities();
+        assert_eq!(
+            choose_extent(&caps, 4096, 8),
+            vk::Extent2D {
+                width: 1024,
+                height: 16
+            }
+        );
+
+        caps.current_extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+        assert_eq!(
+            choose_extent(&caps, 4096, 8),
+            vk::Extent2D {
+                width: 800,
+                height: 600
+            }
+        );
+    }
+
+    #[test]
+    fn present_copy_requires_matching_formats() {
+        assert_eq!(
+            choose_present_copy(vk::Format::B8G8R8A8_SRGB, vk::Format::B8G8R8A8_SRGB),
+            PresentCopy::Copy
+        );
+        assert_eq!(
+            choose_present_copy(vk::Format::R32G32B32A32_SFLOAT, vk::Format::B8G8R8A8_SRGB),
+            PresentCopy::Blit
+        );
+    }
+
+    #[test]
+    fn subregion_constants_and_blit_target_the_same_rectangle() {
+        use crate::trace::ViewportConstants;
+
+        let viewport = vk::Rect2D {
+            offset: vk::Offset2D { x: 200, y: 40 },
+            extent: vk::Extent2D {
+                width: 640,
+                height: 360,
+            },
+        };
+        let constants = ViewportConstants::from_rect(viewport);
+        assert_eq!(constants.viewport_offset, [200, 40]);
+        assert_eq!(constants.viewport_extent, [640, 360]);
+        assert_eq!(
+            bytemuck::cast::<_, [u32; 4]>(constants),
+            [200, 40, 640, 360]
+        );
+
+        let blit = region_blit(viewport, viewport);
+        let corners = [
+            vk::Offset3D {
+                x: 200,
+                y: 40,
+                z: 0,
+            },
+            vk::Offset3D {
+                x: 840,
+                y: 400,
+                z: 1,
+            },
+        ];
+        assert_eq!(blit.src_offsets, corners);
+        assert_eq!(blit.dst_offsets, corners);
+
+        // 源区域比目标大时，copy 按较小的尺寸截断
+        let copy = region_copy(
+            viewport,
+            vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: vk::Extent2D {
+                    width: 320,
+                    height: 720,
+                },
+            },
+        );
+        assert_eq!(copy.src_offset, corners[0]);
+        assert_eq!(copy.dst_offset, vk::Offset3D::default());
+        assert_eq!((copy.extent.width, copy.extent.height), (320, 360));
+    }
+
+    #[test]
+    fn composite_alpha_falls_back_to_supported_mode() {
+        let mut caps = capabilities();
+        assert_eq!(
+            choose_composite_alpha(&caps),
+            vk::CompositeAlphaFlagsKHR::OPAQUE
+        );
+
+        caps.supported_composite_alpha =
+            vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED | vk::CompositeAlphaFlagsKHR::INHERIT;
+        assert_eq!(
+            choose_composite_alpha(&caps),
+            vk::CompositeAlphaFlagsKHR::INHERIT
+        );
+    }
+
+    #[test]
+    fn pre_transform_uses_current_then_identity_then_lowest_bit() {
+        let mut caps = capabilities();
+        caps.supported_transforms =
+            vk::SurfaceTransformFlagsKHR::IDENTITY | vk::SurfaceTransformFlagsKHR::ROTATE_90;
+        caps.current_transform = vk::SurfaceTransformFlagsKHR::ROTATE_90;
+        assert_eq!(
+            choose_pre_transform(&caps),
+            vk::SurfaceTransformFlagsKHR::ROTATE_90
+        );
+
+        caps.current_transform = vk::SurfaceTransformFlagsKHR::ROTATE_180;
+        assert_eq!(
+            choose_pre_transform(&caps),
+            vk::SurfaceTransformFlagsKHR::IDENTITY
+        );
+
+        caps.supported_transforms =
+            vk::SurfaceTransformFlagsKHR::ROTATE_270 | vk::SurfaceTransformFlagsKHR::ROTATE_90;
+        assert_eq!(
+            choose_pre_transform(&caps),
+            vk::SurfaceTransformFlagsKHR::ROTATE_90
+        );
+    }
+
+    #[test]
+    fn swizzle_swaps_red_and_blue_for_bgra() {
+        let mut pixels = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert!(swizzle_to_rgba8(&mut pixels, vk::Format::B8G8R8A8_SRGB));
+        assert_eq!(pixels, [3, 2, 1, 4, 7, 6, 5, 8]);
+
+        assert!(swizzle_to_rgba8(&mut pixels, vk::Format::R8G8B8A8_UNORM));
+        assert_eq!(pixels, [3, 2, 1, 4, 7, 6, 5, 8]);
+
+        assert!(!swizzle_to_rgba8(
+            &mut pixels,
+            vk::Format::R16G16B16A16_SFLOAT
+        ));
+        assert_eq!(pixels, [3, 2, 1, 4, 7, 6, 5, 8]);
+    }
+
+    #[test]
+    fn submit_and_present_uses_the_render_finished_semaphore_of_the_image() {
+        use crate::test_support::{
+            MockPresent, MockSubmit, mock_device, mock_swapchain_loader, with_mock,
+        };
+        use ash::vk::Handle;
+
+        let device = mock_device(None);
+        let mut frame_sync = FrameSync::new(&device, 2).unwrap();
+        let render_finished: Vec<vk::Semaphore> = (0..3)
+            .map(|_| {
+                unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None) }
+                    .unwrap()
+            })
+            .collect();
+        let swapchain = Swapchain {
+            swapchain: vk::SwapchainKHR::from_raw(0x50),
+            images: vec![vk::Image::null(); 3],
+            image_views: Vec::new(),
+            format: vk::Format::B8G8R8A8_UNORM,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            extent: vk::Extent2D {
+                width: 4,
+                height: 4,
+            },
+            usage: vk::ImageUsageFlags::TRANSFER_DST,
+            render_finished: render_finished.clone(),
+            loader: mock_swapchain_loader(&device),
+        };
+
+        // 图像数多于 frames in flight：第 0 帧拿到图像 2，第 1 帧拿到图像 0
+        let frames = [(0, 2), (1, 0)];
+        for (_, image_index) in frames {
+            frame_sync.wait_and_reset(&device).unwrap();
+            let needs_recreate = swapchain
+                .submit_and_present(
+                    &device,
+                    vk::Queue::null(),
+                    vk::Queue::null(),
+                    vk::CommandBuffer::null(),
+                    &mut frame_sync,
+                    image_index,
+                )
+                .unwrap();
+            assert!(!needs_recreate);
+            frame_sync.advance();
+        }
+
+        let expected_submits: Vec<MockSubmit> = frames
+            .iter()
+            .map(|&(frame, image_index)| MockSubmit {
+                wait_semaphores: vec![frame_sync.image_available[frame]],
+                wait_stages: vec![
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::TRANSFER,
+                ],
+                signal_semaphores: vec![render_finished[image_index as usize]],
+                fence: frame_sync.in_flight_fences[frame],
+            })
+            .collect();
+        let expected_presents: Vec<MockPresent> = frames
+            .iter()
+            .map(|&(_, image_index)| MockPresent {
+                wait_semaphores: vec![render_finished[image_index as usize]],
+                image_indices: vec![image_index],
+            })
+            .collect();
+        with_mock(|mock| {
+            assert_eq!(mock.submits, expected_submits);
+            assert_eq!(mock.presents, expected_presents);
+        });
+
+        unsafe {
+            for semaphore in render_finished {
+                device.destroy_semaphore(semaphore, None);
+            }
+            frame_sync.destroy(&device);
+        }
+    }
+}